@@ -1,34 +1,2037 @@
 //! Mutable application state and utils
-use ssh2::{Session, Sftp};
+use crossterm::event::{KeyCode, KeyEvent};
+use ssh2::{FileStat, Sftp};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tracing::info;
+use tui::widgets::ListState;
 
-use crate::app_utils::{AppBuf, AppContent, AppState};
+use crate::app_utils::{
+  self, ActiveState, AppBuf, AppContent, AppState, Comparison, Entry, EntryKind, Sort,
+};
+use crate::backend::{self, FsBackend, Local};
+use crate::bookmarks::Bookmarks;
+use crate::config;
+use crate::connection::{self, Connection};
+use crate::error::GsftpError;
+use crate::file_transfer::{ConfirmThreshold, TransferOptions, TransferStatus};
+use crate::health::Health;
+use crate::i18n;
+use crate::keymap::{Action, Keymap};
+use crate::register::Register;
+use crate::remote_path::{self, Flavor};
+use crate::scheduler::Start;
+use crate::settings::Settings;
+use crate::split;
+use crate::theme::{Icons, Theme};
+use crate::transform::Encryption;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What the text typed into a prompt is going to be used for
+pub enum PromptKind {
+  /// When a pending transfer should start
+  Schedule,
+  /// A command typed after `:`, e.g. `connect user@host`
+  Command,
+  /// Which host to copy the highlighted remote item to
+  Relay,
+  /// Text the active pane's names have to contain, applied as it's typed
+  Filter,
+  /// The start of a name in the active pane to jump to, as it's typed
+  Find,
+  /// The name of a directory to create in the active pane
+  Mkdir,
+  /// The name of an empty file to create in the active pane
+  Touch,
+  /// A new name for an item in the active pane
+  Rename,
+  /// New permissions for an item in the active pane
+  Chmod,
+  /// A new owner for an item in the remote pane
+  Chown,
+  /// A pattern, e.g. `*.log`, for the names in the active pane to mark
+  Mark,
+  /// A pattern for names to search for under the remote directory
+  Search,
+  /// The passphrase transfers are encrypted and decrypted with, which isn't shown as it's typed
+  Passphrase,
+}
+
+impl PromptKind {
+  /// Whether the prompt is typed into the status bar, leaving the panes in view
+  pub fn inline(&self) -> bool {
+    matches!(self, Self::Filter | Self::Find)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single line of text being typed into a modal dialog
+pub struct Prompt {
+  pub kind: PromptKind,
+  pub question: String,
+  pub input: String,
+  /// The item being renamed, or having its permissions or owner changed
+  pub target: Option<String>,
+}
+
+impl Prompt {
+  pub fn new(kind: PromptKind, question: &str) -> Self {
+    Self {
+      kind,
+      question: question.to_string(),
+      input: String::new(),
+      target: None,
+    }
+  }
+
+  /// The input as it's drawn; a passphrase only shows how long it is
+  pub fn shown(&self) -> Cow<'_, str> {
+    match self.kind {
+      PromptKind::Passphrase => Cow::Owned("*".repeat(self.input.chars().count())),
+      _ => Cow::Borrowed(&self.input),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Whatever the keyboard is going to: the panes, or one of the dialogs drawn over them
+pub enum InputMode {
+  Normal,
+  Info,
+  Picker,
+  Viewer,
+  Confirm,
+  Prompt,
+  Help,
+  Queue,
+  Log,
+}
+
+impl InputMode {
+  /// e.g. `CONFIRM`, for the status bar; the normal mode goes without saying
+  pub fn describe(&self) -> Option<&'static str> {
+    match self {
+      Self::Normal => None,
+      Self::Info => Some("INFO"),
+      Self::Picker => Some("PICK"),
+      Self::Viewer => Some("VIEW"),
+      Self::Confirm => Some("CONFIRM"),
+      Self::Prompt => Some("INPUT"),
+      Self::Help => Some("HELP"),
+      Self::Queue => Some("TRANSFERS"),
+      Self::Log => Some("MESSAGES"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What became of an action given to `App::handle`
+pub enum Handled {
+  /// It's done, with nothing to say about it
+  Done,
+  /// It's done, and the status bar says so
+  Message(String),
+  /// It was refused, or it failed, and the status bar says why
+  Error(String),
+  /// It needs more than the app: the connections, the terminal or a thread of its own, so it's
+  /// up to the event loop
+  Effect(Effect),
+}
+
+impl From<Result<(), String>> for Handled {
+  /// Nothing to say if it worked, and why if it didn't
+  fn from(result: Result<(), String>) -> Self {
+    match result {
+      Ok(()) => Self::Done,
+      Err(e) => Self::Error(e),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What's left of a key or an action once the app has done what it can with it: whatever needs
+/// the connections, the tabs, the terminal, the clipboard or a thread of its own
+pub enum Effect {
+  Quit,
+  /// Look up everything there is to know about the named item in the active pane
+  Details(String),
+  /// Edit the file at this path in the active pane, uploading it again afterwards if it's remote
+  OpenEditor(PathBuf),
+  /// Put this text on the clipboard
+  SetClipboard(String),
+  /// Copy what was yanked into the active pane's directory
+  Paste(Register),
+  /// Add up the size of the named directory in the active pane
+  MeasureUsage(String),
+  /// Compare the named file with its namesake in the other pane
+  Verify(String),
+  /// Search the remote directory and everything under it for names matching the pattern
+  Search(String),
+  /// Write every message this session to the file
+  SaveLog(PathBuf),
+  /// Upload or download the highlighted item
+  Transfer,
+  /// Copy each of these over to the other pane
+  CopyDifferences(Vec<Entry>),
+  /// Transfer the highlighted item at this time
+  Schedule(Start),
+  /// Copy the highlighted remote item to the host with this index
+  Relay(usize),
+  /// Log in to the destination, in a new tab or in this one
+  Connect {
+    destination: String,
+    new_tab: bool,
+  },
+  /// Show the host with this index in the remote pane
+  SwitchHost(usize),
+  /// Show the next host in the remote pane, wrapping around
+  NextHost,
+  /// Give the named remote item to the owner, e.g. `deploy:www`, once it's been looked up
+  Chown {
+    name: String,
+    owner: String,
+  },
+  /// Read the config files again, and apply them
+  ReloadConfig,
+  /// Open a tab on the same directories
+  NewTab,
+  CloseTab,
+  /// Start the transfers that the confirmation dialog asked about
+  StartPending,
+  /// Forget them instead, since the dialog about them was cancelled
+  DropPending,
+  /// Try the transfer with this id again
+  Retry(usize),
+  /// Show the tab this many along, wrapping around
+  CycleTab(isize),
+  /// Show the tab with this index
+  SwitchTab(usize),
+}
+
+#[derive(Debug, Default)]
+/// The keyboard help, listing what each key does. Typing after `/` narrows it down to the lines
+/// with the text in them.
+pub struct Help {
+  pub query: String,
+  /// Whether keys go into the query, rather than scrolling
+  pub searching: bool,
+  /// How many rows are scrolled past
+  pub scroll: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What the items in a picker are
+pub enum PickerKind {
+  /// Bookmarked directories for the active pane
+  Bookmarks,
+  /// Remote paths that a search turned up
+  Found,
+}
+
+#[derive(Debug)]
+/// A list to choose one item from in a modal dialog
+pub struct Picker {
+  pub kind: PickerKind,
+  pub title: String,
+  pub items: Vec<String>,
+  pub selected: usize,
+}
+
+impl Picker {
+  pub fn new(kind: PickerKind, title: &str, items: Vec<String>) -> Self {
+    Self {
+      kind,
+      title: title.to_string(),
+      items,
+      selected: 0,
+    }
+  }
+
+  /// Moves the highlight `step` items along, wrapping around at either end
+  pub fn step(&mut self, step: isize) {
+    if !self.items.is_empty() {
+      let len = self.items.len() as isize;
+      self.selected = (self.selected as isize + step).rem_euclid(len) as usize;
+    }
+  }
+
+  pub fn selected(&self) -> Option<&str> {
+    self.items.get(self.selected).map(String::as_str)
+  }
+}
+
+/// How much of a file is shown in the viewer
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+// How far `<` and `>` move the divider between the panes, in percent of the width
+const SPLIT_STEP: i16 = 5;
+
+// How many columns Shift+⬅ and Shift+➡ scroll a whole name along in the status bar
+const NAME_SCROLL_STEP: usize = 8;
+
+#[derive(Debug)]
+/// The start of a file, shown read-only in a scrollable modal dialog
+pub struct Viewer {
+  pub title: String,
+  pub lines: Vec<String>,
+  /// How many lines have been scrolled past
+  pub scroll: usize,
+}
+
+impl Viewer {
+  /// Text is shown as it is (tabs expanded, other control characters dropped); anything that
+  /// doesn't look like text just gets a note saying so
+  pub fn new(name: &str, head: &[u8], size: Option<u64>) -> Self {
+    let truncated = size.map_or(head.len() as u64 >= PREVIEW_BYTES, |size| {
+      size > head.len() as u64
+    });
+    let title = match (truncated, size) {
+      (true, Some(size)) => format!(
+        "{name} - first {} of {}",
+        app_utils::human_size(head.len() as u64),
+        app_utils::human_size(size)
+      ),
+      _ => name.to_string(),
+    };
+    let lines = match looks_binary(head) {
+      true => vec![format!(
+        "{name} looks like a binary file, so it isn't shown{}",
+        size.map_or(String::new(), |size| format!(
+          " ({})",
+          app_utils::human_size(size)
+        ))
+      )],
+      false => String::from_utf8_lossy(head)
+        .lines()
+        .map(|line| {
+          line
+            .replace('\t', "    ")
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect()
+        })
+        .collect(),
+    };
+    Self {
+      title,
+      lines,
+      scroll: 0,
+    }
+  }
+
+  /// Scrolls `step` lines down (or up, if negative), stopping at either end
+  pub fn scroll_by(&mut self, step: isize) {
+    let last = self.lines.len().saturating_sub(1) as isize;
+    self.scroll = (self.scroll as isize).saturating_add(step).clamp(0, last) as usize;
+  }
+}
+
+// What transfers do, and when to ask before starting them, unless the transfer dialog says otherwise
+fn transfer_defaults(args: &clap::ArgMatches) -> (TransferOptions, ConfirmThreshold) {
+  let mut transfer_opts = TransferOptions::default();
+  if let Some(policy) = args.value_of("symlinks") {
+    transfer_opts.symlinks = policy.parse().unwrap_or(transfer_opts.symlinks);
+  }
+  if let Some(policy) = args.value_of("on-conflict") {
+    transfer_opts.conflicts = policy.parse().unwrap_or(transfer_opts.conflicts);
+  }
+  transfer_opts.compress = args.is_present("gzip");
+  transfer_opts.keep_gz = args.is_present("keep-gz");
+  transfer_opts.chunk_size = args
+    .value_of("chunk-size")
+    .and_then(config::parse_size)
+    .filter(|&size| size > 0);
+  let mut confirm_threshold = ConfirmThreshold::default();
+  if let Some(bytes) = args.value_of("confirm-size").and_then(config::parse_size) {
+    confirm_threshold.bytes = bytes;
+  }
+  if let Some(Ok(files)) = args.value_of("confirm-files").map(str::parse) {
+    confirm_threshold.files = files;
+  }
+  (transfer_opts, confirm_threshold)
+}
+
+// Whether there's a directory at `path` in `fs`, or a link to one
+fn is_dir(fs: &dyn FsBackend, path: &Path) -> bool {
+  fs.stat(path)
+    .is_ok_and(|entry| entry.kind == EntryKind::Dir)
+}
+
+// Creates the empty file `path` in `fs`, refusing to empty anything already called that
+fn create_empty(fs: &dyn FsBackend, path: &Path) -> Result<(), GsftpError> {
+  if fs.lstat(path).is_ok() {
+    return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+  }
+  Ok(fs.write(path, 0o644)?.finish()?)
+}
+
+// NUL bytes, or lots of bytes that aren't UTF-8, mean there's no point showing it as text
+fn looks_binary(head: &[u8]) -> bool {
+  if head.contains(&0) {
+    return true;
+  }
+  // The last character may have been cut in half
+  let sample = &head[..head.len().saturating_sub(3)];
+  let text = String::from_utf8_lossy(sample);
+  let invalid = text
+    .chars()
+    .filter(|&c| c == char::REPLACEMENT_CHARACTER)
+    .count();
+  invalid * 10 > text.chars().count().max(1)
+}
 
 #[derive(Debug)]
 /// Static, mutable application configuration
 pub struct App {
   pub buf: AppBuf,
+  /// The local pane's files: the disk, or `backend::Memory` when trying the app out
+  pub local_fs: Box<dyn FsBackend>,
   pub content: AppContent,
   pub state: AppState,
-  pub show_help: bool,
+  /// The keyboard help, while it's open
+  pub help: Option<Help>,
+  pub keymap: Keymap,
+  /// Whether everything's drawn in plain ASCII, for consoles that garble anything else
+  pub ascii: bool,
+  /// Whether everything's drawn in the terminal's own colors, for monochrome terminals and
+  /// anyone who finds the colors hard to tell apart
+  pub no_color: bool,
   pub show_hidden: bool,
+  /// Whether listings show permissions, sizes and modification times next to the names
+  pub show_details: bool,
+  /// Whether the status bar shows the highlighted item's whole name instead, and how many
+  /// columns along it's been scrolled
+  pub show_full_name: bool,
+  pub name_scroll: usize,
+  /// Whether listings are coloured by how they compare with the other pane's directory
+  pub compare: bool,
+  /// How much of the width the local pane gets, in percent; the remote pane gets the rest
+  pub split: u16,
+  /// Whether the active pane has the whole width to itself for now
+  pub zoomed: bool,
+  pub theme: Theme,
+  /// Whether entering a symlink to a directory goes to the directory it points to
+  pub follow_symlinks: bool,
+  /// Whether deleted local items go to the trash rather than being removed for good
+  pub use_trash: bool,
+  /// Whether anything that changes files is refused (see `Action::writes`)
+  pub read_only: bool,
+  pub transfer_opts: TransferOptions,
+  pub confirm_threshold: ConfirmThreshold,
+  pub encryption: Encryption,
+  /// Question shown in a modal dialog, waiting for a yes/no answer
+  pub confirm: Option<String>,
+  /// Text being typed into a modal dialog
+  pub prompt: Option<Prompt>,
+  /// A title and some details, e.g. about the server or the highlighted item, shown in a modal
+  /// dialog until any key is pressed
+  pub info: Option<(String, String)>,
+  /// Bookmarked directories or search results to choose from, in a modal dialog
+  pub picker: Option<Picker>,
+  /// A file being read, in a modal dialog
+  pub viewer: Option<Viewer>,
+  /// Which of the connected hosts the remote pane is showing, counting from 0, and how many
+  /// there are
+  pub hosts: (usize, usize),
+  /// What that host said about itself, and how it's connected to (see `ServerInfo::describe`)
+  pub server_info: String,
+  /// This machine's name, for the local pane's title
+  pub local_host: String,
+  /// Who the remote pane is logged in as, and where, e.g. `deploy@example.com:22`
+  pub remote_id: String,
+  /// Whether that host's paths look like Windows ones
+  pub remote_flavor: Flavor,
+  /// How the connection to that host is doing, for the status bar
+  pub health: Health,
+  /// Directories being added up in the background, shown with a spinner in the status bar
+  pub measuring: Vec<String>,
+  /// The pattern being searched for under the remote directory, if a search is running
+  pub searching: Option<String>,
+  /// Free space where each pane's directory is, along with which directory that was
+  pub local_free: (PathBuf, Option<u64>),
+  pub remote_free: (PathBuf, Option<u64>),
+  /// Every transfer started this session, indexed by transfer id
+  pub transfers: Vec<TransferStatus>,
+  /// The highlighted row of the transfer queue panel, while it's open
+  pub queue: Option<ListState>,
+  /// The highlighted row of the message log panel, while it's open
+  pub log: Option<ListState>,
+  /// Directories the user bookmarked, this session or before
+  pub bookmarks: Bookmarks,
+  /// The title of every tab (see `tabs::title`), once there's more than one to show
+  pub tabs: Vec<String>,
+  /// Which of them is showing, counting from 0
+  pub tab: usize,
+  // The item the confirmation dialog asks whether to delete
+  pending_delete: Option<String>,
+  // Items yanked to be pasted elsewhere on the same side
+  register: Option<Register>,
+  // Whether the last key was `g`, which `t` or `T` can follow to switch tabs
+  g_pressed: bool,
 }
 
 impl App {
-  /// Create new app using SFTP session and CLI args
-  pub fn from(sess: &Session, sftp: &Sftp, args: clap::ArgMatches) -> Self {
-    let buf = AppBuf::from(sess);
+  /// Create new app using the first remote connection and CLI args, starting in the current
+  /// directory, which it's an error not to be able to read
+  pub fn from(connection: &Connection, args: clap::ArgMatches) -> Result<Self, String> {
+    let buf = AppBuf::from(connection.cwd.clone())
+      .map_err(|e| format!("Couldn't read the current directory: {e}"))?;
+    let mut app = Self::new(buf, Box::new(Local), &*connection.sftp, args);
+    app.remote_id = connection.id.clone();
+    app.remote_flavor = connection.flavor;
+    app.server_info = connection.info.describe();
+    app.bookmarks = Bookmarks::load();
+    Ok(app)
+  }
+
+  /// Create new app showing the directories in `buf`, whose files are in `local_fs` and
+  /// `remote_fs`, with the options in the CLI args. Which host is on the remote side is
+  /// left for the caller to fill in, and there are no bookmarks until the caller loads them.
+  pub fn new(
+    buf: AppBuf,
+    local_fs: Box<dyn FsBackend>,
+    remote_fs: &dyn FsBackend,
+    args: clap::ArgMatches,
+  ) -> Self {
     let state = AppState::default();
-    let show_help = args.is_present("shortcuts");
+    let help = args.is_present("shortcuts").then(Help::default);
     let show_hidden = args.is_present("all");
-    let content = AppContent::from(&buf, sftp, show_hidden);
+    let mut content = AppContent::from(&buf, &*local_fs, remote_fs, show_hidden);
+    if let Some(key) = args.value_of("sort") {
+      content.sort.key = key.parse().unwrap_or_default();
+      content.refilter();
+    }
+    let (transfer_opts, confirm_threshold) = transfer_defaults(&args);
+    let encryption = Encryption {
+      recipients: args
+        .values_of("encrypt-to")
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default(),
+      identity: args.value_of("decrypt-with").map(PathBuf::from),
+      with_passphrase: args.is_present("encrypt-passphrase"),
+      passphrase: None,
+    };
 
     Self {
       buf,
+      local_fs,
       content,
       state,
-      show_help,
+      help,
+      keymap: Keymap::default(),
+      ascii: args.is_present("ascii"),
+      // https://no-color.org: set to anything but an empty string
+      no_color: args.is_present("no-color")
+        || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
       show_hidden,
+      show_details: false,
+      show_full_name: false,
+      name_scroll: 0,
+      compare: false,
+      split: split::load().unwrap_or(split::DEFAULT),
+      zoomed: false,
+      theme: Theme::default(),
+      follow_symlinks: true,
+      use_trash: args.is_present("trash"),
+      read_only: args.is_present("read-only"),
+      transfer_opts,
+      confirm_threshold,
+      encryption,
+      confirm: None,
+      prompt: None,
+      info: None,
+      picker: None,
+      viewer: None,
+      hosts: (0, 1),
+      server_info: String::new(),
+      local_host: app_utils::local_hostname(),
+      remote_id: String::new(),
+      remote_flavor: Flavor::default(),
+      health: Health::Unknown,
+      measuring: Vec::new(),
+      searching: None,
+      local_free: (PathBuf::new(), None),
+      remote_free: (PathBuf::new(), None),
+      transfers: Vec::new(),
+      queue: None,
+      log: None,
+      bookmarks: Bookmarks::default(),
+      tabs: Vec::new(),
+      tab: 0,
+      pending_delete: None,
+      register: None,
+      g_pressed: false,
+    }
+  }
+
+  /// Takes the theme and the keys from the config files, or neither if either is wrong
+  pub fn configure(&mut self, settings: &Settings) -> Result<(), String> {
+    let mut theme = Theme::load(settings)?;
+    // Nerd Font icons are anything but ASCII
+    if self.ascii && theme.icons == Icons::Nerd {
+      theme.icons = Icons::Ascii;
     }
+    self.keymap = Keymap::load(settings)?;
+    self.theme = theme;
+    Ok(())
+  }
+
+  /// Applies the config files as they are now, for `:reload-config`: the theme, the keys, and
+  /// the transfer defaults that the command line `args`, filled in from the files again, give
+  pub fn reload_config(
+    &mut self,
+    args: &clap::ArgMatches,
+    settings: &Settings,
+  ) -> Result<(), String> {
+    self.configure(settings)?;
+    (self.transfer_opts, self.confirm_threshold) = transfer_defaults(args);
+    Ok(())
+  }
+
+  /// Asks for the passphrase that `--encrypt-passphrase` encrypts and decrypts transfers with
+  pub fn ask_passphrase(&mut self) {
+    self.prompt = Some(Prompt::new(
+      PromptKind::Passphrase,
+      i18n::tr("Passphrase to encrypt and decrypt transfers with:"),
+    ));
+  }
+
+  /// The keyboard help's lines that match what's been searched for, as `(keys, what they do)`
+  pub fn help_lines(&self) -> Vec<(String, &'static str)> {
+    let query = self
+      .help
+      .as_ref()
+      .map_or(String::new(), |help| help.query.to_lowercase());
+    self
+      .keymap
+      .help(self.ascii)
+      .into_iter()
+      .filter(|(keys, description)| {
+        keys.to_lowercase().contains(&query) || description.to_lowercase().contains(&query)
+      })
+      .collect()
+  }
+
+  /// Opens the transfer queue panel at the newest transfer, or closes it
+  pub fn toggle_queue(&mut self) {
+    self.queue = match self.queue {
+      Some(_) => None,
+      None => {
+        let mut state = ListState::default();
+        state.select(self.transfers.len().checked_sub(1));
+        Some(state)
+      }
+    };
+  }
+
+  /// Moves the queue panel's highlight `step` rows along, stopping at either end
+  pub fn step_queue(&mut self, step: isize) {
+    let last = self.transfers.len().saturating_sub(1);
+    if let Some(state) = self.queue.as_mut() {
+      let i = (state.selected().unwrap_or(0) as isize).saturating_add(step);
+      state.select(Some(i.clamp(0, last as isize) as usize));
+    }
+  }
+
+  /// The transfer highlighted in the queue panel
+  pub fn queued(&self) -> Option<usize> {
+    let i = self.queue.as_ref()?.selected()?;
+    (i < self.transfers.len()).then_some(i)
+  }
+
+  /// Opens the message log panel at the newest message, or closes it
+  pub fn toggle_log(&mut self) {
+    self.log = match self.log {
+      Some(_) => None,
+      None => {
+        // drawing it stops at the last message there is
+        let mut state = ListState::default();
+        state.select(Some(usize::MAX));
+        Some(state)
+      }
+    };
+  }
+
+  /// Moves the message log's highlight `step` rows along, stopping at the top (and, once it's
+  /// drawn, at the bottom)
+  pub fn step_log(&mut self, step: isize) {
+    if let Some(state) = self.log.as_mut() {
+      let i = state.selected().unwrap_or(0).saturating_add_signed(step);
+      state.select(Some(i));
+    }
+  }
+
+  /// Which dialog takes the next key, if any: when several are open, the first of
+  /// info, picker, viewer, confirmation, prompt, keyboard help, transfer queue and message log
+  /// does
+  pub fn input_mode(&self) -> InputMode {
+    if self.info.is_some() {
+      InputMode::Info
+    } else if self.picker.is_some() {
+      InputMode::Picker
+    } else if self.viewer.is_some() {
+      InputMode::Viewer
+    } else if self.confirm.is_some() {
+      InputMode::Confirm
+    } else if self.prompt.is_some() {
+      InputMode::Prompt
+    } else if self.help.is_some() {
+      InputMode::Help
+    } else if self.queue.is_some() {
+      InputMode::Queue
+    } else if self.log.is_some() {
+      InputMode::Log
+    } else {
+      InputMode::Normal
+    }
+  }
+
+  /// Applies `action` as far as the app can by itself: moving around, opening prompts and
+  /// changing what's shown. `remote_fs` is the active connection's files, for reading remote
+  /// directories.
+  /// Whatever's left is handed back, and the screen shows the result the next time it's drawn.
+  pub fn handle(&mut self, action: Action, remote_fs: &dyn FsBackend) -> Handled {
+    // in read-only mode, nothing that changes files gets any further
+    // (the keys that make directories and files still step through a filter's matches)
+    let stepping = matches!(action, Action::NewDir | Action::NewFile) && !self.filter().is_empty();
+    if self.read_only && action.writes(self.state.active) && !stepping {
+      let refusal = "Read-only: start gsftp without --read-only to make changes";
+      return Handled::Error(i18n::tr(refusal).to_string());
+    }
+    match action {
+      Action::Help => self.help = Some(Help::default()),
+      // type a command, e.g. `connect user@host`
+      Action::Command => self.prompt = Some(Prompt::new(PromptKind::Command, ":")),
+      Action::Hidden => {
+        self.show_hidden = !self.show_hidden;
+        self.list_local();
+        self.list_remote(remote_fs);
+      }
+      // filter the active pane as it's typed, Esc to clear it
+      Action::Filter => {
+        let mut prompt = Prompt::new(PromptKind::Filter, "/");
+        prompt.input = self.filter().to_string();
+        self.prompt = Some(prompt);
+      }
+      // the next or previous match of the filter, or else a new directory or file
+      Action::NewDir if stepping => self.step_selection(1),
+      Action::NewFile if stepping => self.step_selection(-1),
+      Action::NewDir => {
+        let question = i18n::tr("New directory name:");
+        self.prompt = Some(Prompt::new(PromptKind::Mkdir, question));
+      }
+      Action::NewFile => {
+        self.prompt = Some(Prompt::new(PromptKind::Touch, i18n::tr("New file name:")))
+      }
+      // jump to the first name starting with what's typed next
+      Action::Find => self.prompt = Some(Prompt::new(PromptKind::Find, i18n::tr("find: "))),
+      Action::Sort => self.set_sort(self.content.sort.next_key()),
+      Action::Reverse => self.set_sort(self.content.sort.reversed()),
+      Action::Mark => self.toggle_mark(),
+      Action::InvertMarks => self.invert_marks(),
+      Action::MarkAll => self.mark_all(),
+      Action::MarkPattern => {
+        let question = i18n::tr("Mark names matching (e.g. *.log):");
+        self.prompt = Some(Prompt::new(PromptKind::Mark, question));
+      }
+      // colour both panes by how they compare with each other
+      Action::Compare => {
+        self.compare = !self.compare;
+        return Handled::Message(String::from(match self.compare {
+          true => "Comparing: green only here, red differs, magenta newer here",
+          false => "Stopped comparing",
+        }));
+      }
+      // move the divider between the panes, and remember where it was left for next time
+      Action::SplitLeft | Action::SplitRight => {
+        let step = match action {
+          Action::SplitLeft => -SPLIT_STEP,
+          _ => SPLIT_STEP,
+        };
+        if let Err(e) = split::save(self.resize_split(step)) {
+          return Handled::Error(format!("Couldn't save the split: {e}"));
+        }
+      }
+      Action::Zoom => self.zoomed = !self.zoomed,
+      // read the start of the highlighted file
+      Action::View => {
+        if let Some(name) = self.selected_name() {
+          if let Err(e) = self.preview(&name, remote_fs) {
+            return Handled::Error(e);
+          }
+        }
+      }
+      Action::Down => self.move_selection(1),
+      Action::Up => self.move_selection(-1),
+      Action::Top => self.select_first(),
+      Action::Bottom => self.select_last(),
+      Action::SwitchWindow => {
+        self.state.active = match self.state.active {
+          ActiveState::Local => ActiveState::Remote,
+          ActiveState::Remote => ActiveState::Local,
+        }
+      }
+      Action::EnterDir => match self.state.active {
+        ActiveState::Local => self.cd_into_local(),
+        ActiveState::Remote => self.cd_into_remote(remote_fs),
+      },
+      Action::LeaveDir => match self.state.active {
+        ActiveState::Local => self.cd_out_of_local(),
+        ActiveState::Remote => self.cd_out_of_remote(remote_fs),
+      },
+      // list both panes again right now
+      Action::Refresh => {
+        self.list_local();
+        self.list_remote(remote_fs);
+        return Handled::Message(String::from("Refreshed"));
+      }
+      Action::Queue => self.toggle_queue(),
+      // show the whole of the highlighted name in the status bar, and scroll along it
+      Action::WholeName => {
+        self.show_full_name = !self.show_full_name;
+        self.name_scroll = 0;
+      }
+      Action::NameLeft if self.show_full_name => {
+        self.name_scroll = self.name_scroll.saturating_sub(NAME_SCROLL_STEP)
+      }
+      Action::NameRight if self.show_full_name => self.name_scroll += NAME_SCROLL_STEP,
+      Action::NameLeft | Action::NameRight => {}
+      // whether symlinks to directories can be entered
+      Action::FollowSymlinks => {
+        self.follow_symlinks = !self.follow_symlinks;
+        return Handled::Message(String::from(match self.follow_symlinks {
+          true => "Following symlinks",
+          false => "Not following symlinks",
+        }));
+      }
+      Action::FileDetails => self.show_details = !self.show_details,
+      // search the remote directory and everything under it
+      Action::Search if self.searching.is_some() => {
+        return Handled::Error(String::from("Wait for the search that's running to finish"))
+      }
+      Action::Search => {
+        let dir = self.buf.remote.display().to_string();
+        let question = i18n::tr_args("Find under {} (e.g. *.log):", &[&dir]);
+        self.prompt = Some(Prompt::new(PromptKind::Search, &question));
+      }
+      // change the highlighted item's permissions
+      Action::Chmod => {
+        if let Some(name) = self.selected_name() {
+          let perm = self.entry_named(&name).and_then(|e| e.perm).unwrap_or(0);
+          let question = i18n::tr_args(
+            "Permissions for {}, now {} (e.g. 644, rw-r--r--, g+w):",
+            &[&name, &app_utils::rwx(perm)],
+          );
+          let mut prompt = Prompt::new(PromptKind::Chmod, &question);
+          prompt.input = format!("{perm:03o}");
+          prompt.target = Some(name);
+          self.prompt = Some(prompt);
+        }
+      }
+      Action::Rename => {
+        if let Some(name) = self.selected_name() {
+          let question = i18n::tr_args("Rename {} to:", &[&name]);
+          let mut prompt = Prompt::new(PromptKind::Rename, &question);
+          prompt.input = name.clone();
+          prompt.target = Some(name);
+          self.prompt = Some(prompt);
+        }
+      }
+      // delete the highlighted item, once the user confirms
+      Action::Delete => {
+        if let Some(name) = self.selected_name() {
+          self.confirm = Some(self.describe_delete(&name));
+          self.pending_delete = Some(name);
+        }
+      }
+      // change who owns the highlighted remote item
+      Action::Chown if !matches!(self.state.active, ActiveState::Remote) => {
+        return Handled::Error(String::from(
+          "Owners can only be changed in the remote window",
+        ))
+      }
+      Action::Chown => {
+        if let Some(name) = self.selected_name() {
+          let current = match self.entry_named(&name).and_then(|e| e.owner) {
+            Some((uid, gid)) => format!("{uid}:{gid}"),
+            None => String::from("?"),
+          };
+          let question = i18n::tr_args(
+            "Owner for {}, now {} (user, user:group or :group):",
+            &[&name, &current],
+          );
+          let mut prompt = Prompt::new(PromptKind::Chown, &question);
+          prompt.target = Some(name);
+          self.prompt = Some(prompt);
+        }
+      }
+      Action::Quit => return Handled::Effect(Effect::Quit),
+      // show everything there is to know about the highlighted item
+      Action::Details => {
+        if let Some(name) = self.selected_name() {
+          return Handled::Effect(Effect::Details(name));
+        }
+      }
+      // edit the highlighted file, uploading it again afterwards if it's remote
+      Action::Edit => match self.selected_name() {
+        Some(name)
+          if self
+            .entry_named(&name)
+            .is_some_and(|e| e.kind == EntryKind::Dir) =>
+        {
+          return Handled::Error(format!("{name} is a directory"))
+        }
+        Some(name) => return Handled::Effect(Effect::OpenEditor(self.path_of(&name))),
+        None => {}
+      },
+      // copy the highlighted item's full path to the clipboard
+      Action::CopyPath => {
+        if let Some(name) = self.selected_name() {
+          let path = self.path_of(&name);
+          return Handled::Effect(Effect::SetClipboard(match self.state.active {
+            ActiveState::Local => path.display().to_string(),
+            ActiveState::Remote => connection::scp_path(&self.remote_id, &path),
+          }));
+        }
+      }
+      // yank the marked items, or the highlighted one, to paste in another directory
+      Action::Yank => {
+        let names = self.chosen();
+        if !names.is_empty() {
+          let yanked = Register {
+            host: self.host(),
+            dir: self.dir().to_path_buf(),
+            names: names.iter().map(|name| self.file_name(name)).collect(),
+          };
+          let text = format!("Yanked {} - p pastes it", yanked.describe());
+          self.register = Some(yanked);
+          return Handled::Message(text);
+        }
+      }
+      // paste a copy of what was yanked into the active pane's directory
+      Action::Paste => {
+        return match &self.register {
+          None => Handled::Error(String::from(
+            "Nothing's been yanked yet - x yanks the highlighted item",
+          )),
+          Some(yanked) if yanked.host != self.host() => Handled::Error(String::from(
+            "That was yanked on the other side; y transfers between the windows",
+          )),
+          Some(yanked) => Handled::Effect(Effect::Paste(yanked.clone())),
+        }
+      }
+      // add up the size of the highlighted directory
+      Action::Usage => {
+        if let Some(name) = self.selected_name() {
+          self.measuring.push(name.clone());
+          return Handled::Effect(Effect::MeasureUsage(name));
+        }
+      }
+      // bookmark the current directory
+      Action::Bookmark => {
+        let (host, dir) = (self.host(), self.dir().to_path_buf());
+        return match self.bookmarks.add(host.as_deref(), &dir) {
+          Ok(true) => Handled::Message(format!("Bookmarked {}", dir.display())),
+          Ok(false) => Handled::Message(format!("{} is already bookmarked", dir.display())),
+          Err(e) => Handled::Error(format!("Couldn't save bookmarks: {e}")),
+        };
+      }
+      // choose from the bookmarks for this pane
+      Action::Bookmarks => {
+        let items = self
+          .bookmarks
+          .on(self.host().as_deref())
+          .iter()
+          .map(|path| path.display().to_string())
+          .collect();
+        self.picker = Some(Picker::new(PickerKind::Bookmarks, "Bookmarks", items));
+      }
+      Action::Transfer => return Handled::Effect(Effect::Transfer),
+      // transfer the highlighted item later on
+      Action::Schedule => {
+        if self.selected_name().is_some() {
+          self.prompt = Some(Prompt::new(
+            PromptKind::Schedule,
+            i18n::tr("Start at (HH:MM, +10m, or after the queue):"),
+          ));
+        }
+      }
+      // copy the highlighted remote item to another connected host, asking which if there's
+      // more than one
+      Action::Relay => {
+        let (active, count) = self.hosts;
+        let refusal = match (self.state.active, count) {
+          (ActiveState::Local, _) => {
+            "Highlight something in the remote window to copy it to another host"
+          }
+          (_, 0 | 1) => "Connect to another host first, with :connect user@host",
+          (_, 2) => return Handled::Effect(Effect::Relay(1 - active)),
+          _ => {
+            let question = i18n::tr_args("Copy to host number (1-{}):", &[&count.to_string()]);
+            self.prompt = Some(Prompt::new(PromptKind::Relay, &question));
+            return Handled::Done;
+          }
+        };
+        return Handled::Error(i18n::tr(refusal).to_string());
+      }
+      // copy whatever differs from the other pane over to it
+      Action::CopyDifferences => {
+        let differences = self.differences();
+        if differences.is_empty() {
+          let text = i18n::tr("Nothing differs from the other side");
+          return Handled::Message(text.to_string());
+        }
+        return Handled::Effect(Effect::CopyDifferences(differences));
+      }
+      // show who the remote pane is connected to, and how
+      Action::ServerInfo => self.info = Some((String::from("Server"), self.server_info.clone())),
+      // compare the highlighted file with its namesake in the other pane
+      Action::Verify => {
+        if let Some(name) = self.selected_name() {
+          return Handled::Effect(Effect::Verify(name));
+        }
+      }
+      Action::NextHost => return Handled::Effect(Effect::NextHost),
+      Action::NewTab => return Handled::Effect(Effect::NewTab),
+      // show every message this session
+      Action::Log => self.toggle_log(),
+    }
+    Handled::Done
+  }
+
+  /// Applies a key to whichever dialog takes it (see `input_mode`), or else to the panes as
+  /// the action it's bound to, the way `handle` does
+  pub fn handle_key(&mut self, key: KeyEvent, remote_fs: &dyn FsBackend) -> Handled {
+    match self.input_mode() {
+      // the server info dialog goes away on any key
+      InputMode::Info => {
+        self.info = None;
+        Handled::Done
+      }
+      InputMode::Picker => self.pick(key, remote_fs),
+      InputMode::Viewer => {
+        self.read_viewer(key);
+        Handled::Done
+      }
+      InputMode::Confirm => self.answer(key, remote_fs),
+      InputMode::Prompt => self.type_into_prompt(key, remote_fs),
+      InputMode::Help => {
+        self.read_help(key);
+        Handled::Done
+      }
+      InputMode::Queue => self.manage_queue(key),
+      InputMode::Log => self.read_log(key),
+      InputMode::Normal => self.press(key, remote_fs),
+    }
+  }
+
+  // The picker, until something's chosen or it's cancelled
+  fn pick(&mut self, key: KeyEvent, remote_fs: &dyn FsBackend) -> Handled {
+    let host = self.host();
+    let Some(picker) = self.picker.as_mut() else {
+      return Handled::Done;
+    };
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => picker.step(1),
+      KeyCode::Char('k') | KeyCode::Up => picker.step(-1),
+      KeyCode::Char('x') | KeyCode::Delete if picker.kind == PickerKind::Bookmarks => {
+        if let Some(path) = picker.selected().map(String::from) {
+          picker.items.retain(|item| *item != path);
+          picker.selected = picker.selected.min(picker.items.len().saturating_sub(1));
+          if let Err(e) = self.bookmarks.remove(host.as_deref(), path.as_ref()) {
+            return Handled::Error(format!("Couldn't save bookmarks: {e}"));
+          }
+        }
+      }
+      KeyCode::Enter => {
+        let (kind, path) = (picker.kind, picker.selected().map(String::from));
+        self.picker = None;
+        if let Some(path) = path {
+          return match kind {
+            PickerKind::Bookmarks => self.cd_to(&path, remote_fs),
+            PickerKind::Found => self.reveal_remote(&path, remote_fs),
+          }
+          .into();
+        }
+      }
+      KeyCode::Esc | KeyCode::Char('q') => self.picker = None,
+      _ => {}
+    }
+    Handled::Done
+  }
+
+  // The file viewer, until it's closed
+  fn read_viewer(&mut self, key: KeyEvent) {
+    let Some(viewer) = self.viewer.as_mut() else {
+      return;
+    };
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => viewer.scroll_by(1),
+      KeyCode::Char('k') | KeyCode::Up => viewer.scroll_by(-1),
+      KeyCode::PageDown | KeyCode::Char(' ') => viewer.scroll_by(20),
+      KeyCode::PageUp => viewer.scroll_by(-20),
+      KeyCode::Char('g') | KeyCode::Home => viewer.scroll = 0,
+      KeyCode::Char('G') | KeyCode::End => viewer.scroll_by(isize::MAX),
+      KeyCode::Char('q') | KeyCode::Char('v') | KeyCode::Esc => self.viewer = None,
+      _ => {}
+    }
+  }
+
+  // The confirmation dialog, until it's answered. An item is deleted here, while transfers
+  // are started or forgotten by the event loop, which holds them.
+  fn answer(&mut self, key: KeyEvent, remote_fs: &dyn FsBackend) -> Handled {
+    match key.code {
+      KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+        self.confirm = None;
+        match self.pending_delete.take() {
+          Some(name) => match self.delete(&name, remote_fs) {
+            Ok(()) => Handled::Message(format!("Deleted {name}")),
+            Err(e) => Handled::Error(e),
+          },
+          None => Handled::Effect(Effect::StartPending),
+        }
+      }
+      KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc | KeyCode::Char('q') => {
+        self.confirm = None;
+        self.pending_delete = None;
+        Handled::Effect(Effect::DropPending)
+      }
+      _ => Handled::Done,
+    }
+  }
+
+  // A text prompt, until it's submitted or cancelled
+  fn type_into_prompt(&mut self, key: KeyEvent, remote_fs: &dyn FsBackend) -> Handled {
+    let Some(prompt) = self.prompt.as_mut() else {
+      return Handled::Done;
+    };
+    match key.code {
+      KeyCode::Char(c) => {
+        prompt.input.push(c);
+        self.apply_typed();
+      }
+      KeyCode::Backspace => {
+        prompt.input.pop();
+        self.apply_typed();
+      }
+      // complete the path after `cd`
+      KeyCode::Tab if prompt.kind == PromptKind::Command => {
+        if let Some(partial) = prompt.input.strip_prefix("cd ").map(str::to_string) {
+          let completed = self.complete_path(partial.trim_start(), remote_fs);
+          if let Some(prompt) = &mut self.prompt {
+            prompt.input = format!("cd {completed}");
+          }
+        }
+      }
+      KeyCode::Esc => {
+        if prompt.kind == PromptKind::Filter {
+          self.set_filter("");
+        }
+        self.prompt = None;
+      }
+      KeyCode::Enter => {
+        if let Some(prompt) = self.prompt.take() {
+          return self.submit(prompt, remote_fs);
+        }
+      }
+      _ => {}
+    }
+    Handled::Done
+  }
+
+  // A filter or a find is applied as it's typed
+  fn apply_typed(&mut self) {
+    let Some(prompt) = &self.prompt else {
+      return;
+    };
+    let (kind, query) = (prompt.kind, prompt.input.clone());
+    match kind {
+      PromptKind::Filter => self.set_filter(&query),
+      PromptKind::Find => self.find_prefix(&query),
+      _ => {}
+    }
+  }
+
+  // What's typed into `prompt` is put to use once it's submitted
+  fn submit(&mut self, prompt: Prompt, remote_fs: &dyn FsBackend) -> Handled {
+    match prompt.kind {
+      // the filter was applied as it was typed, and stays until it's cleared,
+      // and the highlight already moved to the name
+      PromptKind::Filter | PromptKind::Find => Handled::Done,
+      PromptKind::Command => self.run_command(prompt, remote_fs),
+      PromptKind::Mkdir => self.make_dir(&prompt.input, remote_fs).into(),
+      PromptKind::Touch => self.make_file(&prompt.input, remote_fs).into(),
+      PromptKind::Rename => match &prompt.target {
+        Some(from) => self.rename(from, &prompt.input, remote_fs).into(),
+        None => Handled::Done,
+      },
+      PromptKind::Chmod => {
+        let Some(name) = prompt.target else {
+          return Handled::Done;
+        };
+        let current = self.entry_named(&name).and_then(|e| e.perm).unwrap_or(0);
+        match app_utils::parse_mode(&prompt.input, current) {
+          Some(mode) => match self.chmod(&name, mode, remote_fs) {
+            Ok(()) => Handled::Message(format!("{name} is now {}", app_utils::rwx(mode))),
+            Err(e) => Handled::Error(e),
+          },
+          None => Handled::Error(String::from(
+            "Permissions should look like 644, rw-r--r-- or g+w",
+          )),
+        }
+      }
+      PromptKind::Mark => {
+        let pattern = prompt.input.trim();
+        Handled::Message(match self.mark_matching(pattern) {
+          0 => format!("Nothing else matches {pattern}"),
+          1 => String::from("Marked 1 item"),
+          n => format!("Marked {n} items"),
+        })
+      }
+      // kept for the rest of the session, and never shown or logged
+      PromptKind::Passphrase if prompt.input.is_empty() => {
+        self.ask_passphrase();
+        Handled::Error(i18n::tr("The passphrase can't be empty").to_string())
+      }
+      PromptKind::Passphrase => {
+        self.encryption.passphrase = Some(prompt.input);
+        let text = i18n::tr("Transfers will be encrypted with the passphrase");
+        Handled::Message(text.to_string())
+      }
+      PromptKind::Relay => {
+        let (active, count) = self.hosts;
+        let target = prompt
+          .input
+          .trim()
+          .parse::<usize>()
+          .ok()
+          .and_then(|n| n.checked_sub(1));
+        match target {
+          Some(i) if i != active && i < count => Handled::Effect(Effect::Relay(i)),
+          _ => {
+            Handled::Error(i18n::tr("That's not the number of another connected host").to_string())
+          }
+        }
+      }
+      // the owner's name is looked up on the server
+      PromptKind::Chown => match prompt.target {
+        Some(name) => Handled::Effect(Effect::Chown {
+          name,
+          owner: prompt.input.trim().to_string(),
+        }),
+        None => Handled::Done,
+      },
+      PromptKind::Search => {
+        let pattern = prompt.input.trim().to_string();
+        if pattern.is_empty() {
+          return Handled::Done;
+        }
+        self.searching = Some(pattern.clone());
+        Handled::Effect(Effect::Search(pattern))
+      }
+      PromptKind::Schedule => match Start::parse(&prompt.input) {
+        Some(start) => Handled::Effect(Effect::Schedule(start)),
+        None => Handled::Error(String::from(
+          "Start time should look like 02:00, +10m, or after",
+        )),
+      },
+    }
+  }
+
+  // A command typed after `:`. Going somewhere and making things are done here, and whatever
+  // has to do with the connections, the tabs or the config files is handed back.
+  fn run_command(&mut self, prompt: Prompt, remote_fs: &dyn FsBackend) -> Handled {
+    let input = prompt.input.trim();
+    let mut words = input.split_whitespace();
+    match (words.next(), words.next()) {
+      (Some("cd"), _) => {
+        // the path may well have spaces in it
+        let path = input.strip_prefix("cd").unwrap_or_default().trim();
+        let path = if path.is_empty() { "~" } else { path };
+        self.cd_to(path, remote_fs).into()
+      }
+      (Some("mkdir" | "touch"), Some(_)) if self.read_only => {
+        let refusal = "Read-only: start gsftp without --read-only to make changes";
+        Handled::Error(i18n::tr(refusal).to_string())
+      }
+      (Some("mkdir"), Some(_)) => {
+        let name = input.strip_prefix("mkdir").unwrap_or_default();
+        self.make_dir(name, remote_fs).into()
+      }
+      (Some("touch"), Some(_)) => {
+        let name = input.strip_prefix("touch").unwrap_or_default();
+        self.make_file(name, remote_fs).into()
+      }
+      (Some("passphrase"), None) if self.encryption.with_passphrase => {
+        self.ask_passphrase();
+        Handled::Done
+      }
+      (Some("passphrase"), None) => Handled::Error(
+        i18n::tr("Start gsftp with --encrypt-passphrase to encrypt with a passphrase").to_string(),
+      ),
+      // the new host gets a tab of its own with `tabnew`, rather than taking this one's place
+      (Some(command @ ("connect" | "tabnew")), Some(destination)) => {
+        Handled::Effect(Effect::Connect {
+          destination: destination.to_string(),
+          new_tab: command == "tabnew",
+        })
+      }
+      (Some("switch"), Some(n)) => match n.parse::<usize>().ok().and_then(|n| n.checked_sub(1)) {
+        Some(i) if i < self.hosts.1 => Handled::Effect(Effect::SwitchHost(i)),
+        _ => Handled::Error(i18n::tr_args("No connection number {}", &[n])),
+      },
+      (Some("reload-config"), None) => Handled::Effect(Effect::ReloadConfig),
+      (Some("tabnew"), None) => Handled::Effect(Effect::NewTab),
+      (Some("tabclose"), None) if self.tabs.len() < 2 => {
+        Handled::Error(i18n::tr("The last tab can't be closed; q quits").to_string())
+      }
+      (Some("tabclose"), None) => Handled::Effect(Effect::CloseTab),
+      _ => Handled::Error(i18n::tr(
+        "Commands are `cd <path>`, `mkdir <name>`, `touch <name>`, `connect user@host`, `switch <number>`, `tabnew [user@host]`, `tabclose`, `reload-config` and `passphrase`",
+      ).to_string()),
+    }
+  }
+
+  // The keyboard help, which is searched after `/`
+  fn read_help(&mut self, key: KeyEvent) {
+    let closing = self.keymap.action(&key) == Some(Action::Help);
+    let Some(help) = self.help.as_mut() else {
+      return;
+    };
+    if help.searching {
+      match key.code {
+        KeyCode::Char(c) => {
+          help.query.push(c);
+          help.scroll = 0;
+        }
+        KeyCode::Backspace => {
+          help.query.pop();
+        }
+        KeyCode::Enter => help.searching = false,
+        KeyCode::Esc => {
+          help.query.clear();
+          help.searching = false;
+        }
+        _ => {}
+      }
+      return;
+    }
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => help.scroll += 1,
+      KeyCode::Char('k') | KeyCode::Up => help.scroll = help.scroll.saturating_sub(1),
+      KeyCode::Char('g') | KeyCode::Home => help.scroll = 0,
+      // the help is drawn no further than its last row
+      KeyCode::Char('G') | KeyCode::End => help.scroll = usize::MAX,
+      KeyCode::Char('/') => {
+        help.query.clear();
+        help.searching = true;
+      }
+      KeyCode::Char('?') | KeyCode::Char('q') | KeyCode::Esc => self.help = None,
+      _ if closing => self.help = None,
+      _ => {}
+    }
+  }
+
+  // The message log panel, while it's open
+  fn read_log(&mut self, key: KeyEvent) -> Handled {
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => self.step_log(1),
+      KeyCode::Char('k') | KeyCode::Up => self.step_log(-1),
+      // nothing highlighted is drawn as the first message
+      KeyCode::Char('g') | KeyCode::Home => self.log = Some(ListState::default()),
+      KeyCode::Char('G') | KeyCode::End => self.step_log(isize::MAX),
+      // saved next to whatever's in the local pane
+      KeyCode::Char('s') => {
+        let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let path = self.buf.local.join(format!("gsftp-messages-{time}.log"));
+        return Handled::Effect(Effect::SaveLog(path));
+      }
+      KeyCode::Char('M') | KeyCode::Char('q') | KeyCode::Esc => self.log = None,
+      _ if self.keymap.action(&key) == Some(Action::Log) => self.log = None,
+      _ => {}
+    }
+    Handled::Done
+  }
+
+  // The transfer queue panel, while it's open
+  fn manage_queue(&mut self, key: KeyEvent) -> Handled {
+    match key.code {
+      KeyCode::Char('j') | KeyCode::Down => self.step_queue(1),
+      KeyCode::Char('k') | KeyCode::Up => self.step_queue(-1),
+      KeyCode::Char('g') | KeyCode::Home => self.step_queue(isize::MIN),
+      KeyCode::Char('G') | KeyCode::End => self.step_queue(isize::MAX),
+      KeyCode::Char('x') | KeyCode::Delete => match self.queued() {
+        Some(i) if self.transfers[i].is_running() || self.transfers[i].is_scheduled() => {
+          self.transfers[i].cancel();
+          return Handled::Message(format!("Cancelled {}", self.transfers[i].name));
+        }
+        Some(_) => return Handled::Error(String::from("That transfer isn't running")),
+        None => {}
+      },
+      KeyCode::Char('r') => match self.queued() {
+        Some(i) if self.transfers[i].can_retry() => return Handled::Effect(Effect::Retry(i)),
+        Some(_) => {
+          let refusal = "Only failed or cancelled transfers can be retried";
+          return Handled::Error(String::from(refusal));
+        }
+        None => {}
+      },
+      KeyCode::Char('T') | KeyCode::Char('q') | KeyCode::Esc => self.queue = None,
+      _ if self.keymap.action(&key) == Some(Action::Queue) => self.queue = None,
+      _ => {}
+    }
+    Handled::Done
+  }
+
+  // A key for the panes, once no dialog wants it
+  fn press(&mut self, key: KeyEvent, remote_fs: &dyn FsBackend) -> Handled {
+    // `gt` and `gT` go to the next and previous tab, the way they do in vim
+    let g = key.code == KeyCode::Char('g') && key.modifiers.is_empty();
+    let after_g = std::mem::replace(&mut self.g_pressed, g);
+    if after_g && matches!(key.code, KeyCode::Char('t') | KeyCode::Char('T')) {
+      let step = if key.code == KeyCode::Char('t') {
+        1
+      } else {
+        -1
+      };
+      return Handled::Effect(Effect::CycleTab(step));
+    }
+    // clear the filter, if there is one, before Esc does anything else
+    if key.code == KeyCode::Esc && !self.filter().is_empty() {
+      self.set_filter("");
+      return Handled::Done;
+    }
+    match (self.keymap.action(&key), key.code) {
+      (Some(action), _) => self.handle(action, remote_fs),
+      // show tab 1 to 9
+      (None, KeyCode::Char(digit @ '1'..='9')) => {
+        Handled::Effect(Effect::SwitchTab(digit as usize - '1' as usize))
+      }
+      (None, _) => Handled::Done,
+    }
+  }
+
+  /// Moves the divider between the panes `step` percent of the width to the right,
+  /// leaving each pane at least `split::MIN` percent. Yields the new split.
+  pub fn resize_split(&mut self, step: i16) -> u16 {
+    let percent = (self.split as i16 + step).clamp(split::MIN as i16, (100 - split::MIN) as i16);
+    self.split = percent as u16;
+    self.zoomed = false;
+    self.split
+  }
+
+  /// How much of the width the local and remote panes each get right now, in percent
+  pub fn pane_widths(&self) -> (u16, u16) {
+    match (self.zoomed, &self.state.active) {
+      (true, ActiveState::Local) => (100, 0),
+      (true, ActiveState::Remote) => (0, 100),
+      (false, _) => (self.split, 100 - self.split),
+    }
+  }
+
+  /// Whether any transfers are still in progress
+  pub fn transfers_running(&self) -> bool {
+    self.transfers.iter().any(TransferStatus::is_running)
+  }
+
+  /// Name of the highlighted item in the active pane, if there is one
+  pub fn selected_name(&self) -> Option<String> {
+    let (state, content) = match self.state.active {
+      ActiveState::Local => (&self.state.local, &self.content.local),
+      ActiveState::Remote => (&self.state.remote, &self.content.remote),
+    };
+    content
+      .get(state.selected()?)
+      .map(|entry| entry.name.clone())
+  }
+
+  /// Orders both panes by `sort` instead, keeping the same items highlighted
+  pub fn set_sort(&mut self, sort: Sort) {
+    self.content.sort = sort;
+    let panes = [
+      (&mut self.content.local, &mut self.state.local),
+      (&mut self.content.remote, &mut self.state.remote),
+    ];
+    for (entries, state) in panes {
+      let selected = state
+        .selected()
+        .and_then(|i| entries.get(i))
+        .map(|entry| entry.name.clone());
+      sort.apply(entries);
+      if let Some(name) = selected {
+        state.select(entries.iter().position(|entry| entry.name == name));
+      }
+    }
+  }
+
+  /// Lists only the entries in the active pane whose names contain `query` (ignoring case),
+  /// highlighting the first of them
+  pub fn set_filter(&mut self, query: &str) {
+    let (filter, state) = match self.state.active {
+      ActiveState::Local => (&mut self.content.local_filter, &mut self.state.local),
+      ActiveState::Remote => (&mut self.content.remote_filter, &mut self.state.remote),
+    };
+    *filter = query.to_string();
+    state.select(Some(0));
+    self.content.refilter();
+  }
+
+  /// The filter on the active pane, if it has one
+  pub fn filter(&self) -> &str {
+    match self.state.active {
+      ActiveState::Local => &self.content.local_filter,
+      ActiveState::Remote => &self.content.remote_filter,
+    }
+  }
+
+  /// Changes the active pane's directory to `path`: absolute, relative to the current directory,
+  /// or starting with `~` for the home directory
+  pub fn cd_to(&mut self, path: &str, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    let dir = self.resolve(path, remote_fs);
+    match self.state.active {
+      ActiveState::Local => {
+        let dir = self
+          .local_fs
+          .realpath(&dir)
+          .map_err(|e| format!("{}: {e}", dir.display()))?;
+        if !is_dir(&*self.local_fs, &dir) {
+          return Err(format!("{} isn't a directory", dir.display()));
+        }
+        self.buf.local = dir;
+        self.content.local_filter.clear();
+        self.content.local_marks.clear();
+        self.list_local();
+        self.state.local.select(Some(0));
+      }
+      ActiveState::Remote => {
+        let dir = remote_fs
+          .realpath(&dir)
+          .map_err(|e| format!("{}: {e}", dir.display()))?;
+        if !is_dir(remote_fs, &dir) {
+          return Err(format!("{} isn't a directory", dir.display()));
+        }
+        self.buf.remote = dir;
+        self.content.remote_filter.clear();
+        self.content.remote_marks.clear();
+        self.list_remote(remote_fs);
+        self.state.remote.select(Some(0));
+      }
+    }
+    Ok(())
+  }
+
+  /// Completes the last part of `partial`, a path being typed for the active pane, as far as the
+  /// names it could be agree. A directory that's the only match gets a trailing `/`.
+  pub fn complete_path(&self, partial: &str, remote_fs: &dyn FsBackend) -> String {
+    let (dir, stem) = match partial.rfind('/') {
+      Some(i) => partial.split_at(i + 1),
+      None => ("", partial),
+    };
+    let listing = match (dir, &self.state.active) {
+      ("", ActiveState::Local) => self.content.local.clone(),
+      ("", ActiveState::Remote) => self.content.remote.clone(),
+      (dir, ActiveState::Local) => {
+        backend::ls(&*self.local_fs, &self.resolve(dir, remote_fs), true).unwrap_or_default()
+      }
+      (dir, ActiveState::Remote) => {
+        backend::ls(remote_fs, &self.resolve(dir, remote_fs), true).unwrap_or_default()
+      }
+    };
+    let matches: Vec<&Entry> = listing
+      .iter()
+      .filter(|entry| entry.name.starts_with(stem))
+      .collect();
+    match matches.as_slice() {
+      [] => partial.to_string(),
+      [only] => {
+        let slash = if only.kind == EntryKind::Dir { "/" } else { "" };
+        format!("{dir}{}{slash}", only.name)
+      }
+      [first, rest @ ..] => {
+        let mut common = first.name.clone();
+        for entry in rest {
+          let len = common
+            .chars()
+            .zip(entry.name.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+          common.truncate(len);
+        }
+        format!("{dir}{common}")
+      }
+    }
+  }
+
+  /// Creates the directory `name` in the active pane's directory, then highlights it
+  pub fn make_dir(&mut self, name: &str, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+      return Err(format!("{name:?} isn't a valid directory name"));
+    }
+    match self.state.active {
+      ActiveState::Local => {
+        let dir = self.buf.local.join(name);
+        self
+          .local_fs
+          .mkdir(&dir, 0o755)
+          .map_err(|e| format!("{}: {e}", dir.display()))?;
+        self.list_local();
+      }
+      ActiveState::Remote => {
+        let dir = remote_path::join(&self.buf.remote, name);
+        remote_fs
+          .mkdir(&dir, 0o755)
+          .map_err(|e| format!("{}: {e}", dir.display()))?;
+        info!(path = %dir.display(), "mkdir");
+        self.list_remote(remote_fs);
+      }
+    }
+    self.select_named(name);
+    Ok(())
+  }
+
+  /// Creates the empty file `name` in the active pane's directory, then highlights it.
+  /// Anything already called that is left alone.
+  pub fn make_file(&mut self, name: &str, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+      return Err(format!("{name:?} isn't a valid file name"));
+    }
+    match self.state.active {
+      ActiveState::Local => {
+        let file = self.buf.local.join(name);
+        create_empty(&*self.local_fs, &file).map_err(|e| format!("{}: {e}", file.display()))?;
+        self.list_local();
+      }
+      ActiveState::Remote => {
+        let file = remote_path::join(&self.buf.remote, name);
+        create_empty(remote_fs, &file).map_err(|e| format!("{}: {e}", file.display()))?;
+        info!(path = %file.display(), "create");
+        self.list_remote(remote_fs);
+      }
+    }
+    self.select_named(name);
+    Ok(())
+  }
+
+  /// Renames `from` in the active pane's directory to `to`, refusing to replace anything already
+  /// called that, then highlights it under its new name
+  pub fn rename(&mut self, from: &str, to: &str, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    let to = to.trim();
+    if to.is_empty() || to.contains('/') || to == "." || to == ".." {
+      return Err(format!("{to:?} isn't a valid name"));
+    }
+    if to == from {
+      return Ok(());
+    }
+    match self.state.active {
+      ActiveState::Local => {
+        let (old, new) = (
+          self.buf.local.join(self.file_name(from)),
+          self.buf.local.join(to),
+        );
+        if self.local_fs.lstat(&new).is_ok() {
+          return Err(format!("{to} already exists"));
+        }
+        self
+          .local_fs
+          .rename(&old, &new)
+          .map_err(|e| format!("{}: {e}", old.display()))?;
+        self.list_local();
+      }
+      ActiveState::Remote => {
+        let (old, new) = (
+          remote_path::join(&self.buf.remote, self.file_name(from)),
+          remote_path::join(&self.buf.remote, to),
+        );
+        if remote_fs.lstat(&new).is_ok() {
+          return Err(format!("{to} already exists"));
+        }
+        remote_fs
+          .rename(&old, &new)
+          .map_err(|e| format!("{}: {e}", old.display()))?;
+        info!(from = %old.display(), to = %new.display(), "rename");
+        self.list_remote(remote_fs);
+      }
+    }
+    self.select_named(to);
+    Ok(())
+  }
+
+  /// The real name of the entry shown as `name` in the active pane, which may not be UTF-8
+  pub fn file_name(&self, name: &str) -> OsString {
+    self
+      .entry_named(name)
+      .map_or_else(|| OsString::from(name), |e| e.file_name.clone())
+  }
+
+  /// Where `name` in the active pane is, on whichever side that is
+  pub fn path_of(&self, name: &str) -> PathBuf {
+    match self.state.active {
+      ActiveState::Local => self.buf.local.join(self.file_name(name)),
+      ActiveState::Remote => remote_path::join(&self.buf.remote, self.file_name(name)),
+    }
+  }
+
+  // The active pane's directory
+  fn dir(&self) -> &Path {
+    match self.state.active {
+      ActiveState::Local => &self.buf.local,
+      ActiveState::Remote => &self.buf.remote,
+    }
+  }
+
+  // The id of the host the active pane is on, or `None` for this machine
+  fn host(&self) -> Option<String> {
+    match self.state.active {
+      ActiveState::Local => None,
+      ActiveState::Remote => Some(self.remote_id.clone()),
+    }
+  }
+
+  /// The entry called `name` in the active pane, if it's listed
+  pub fn entry_named(&self, name: &str) -> Option<&Entry> {
+    let content = match self.state.active {
+      ActiveState::Local => &self.content.local,
+      ActiveState::Remote => &self.content.remote,
+    };
+    content.iter().find(|e| e.name == name)
+  }
+
+  /// Gives `name` in the remote pane's directory to the user `uid` and group `gid`, leaving
+  /// whichever is `None` as it is
+  pub fn chown(
+    &mut self,
+    name: &str,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    sftp: &Sftp,
+  ) -> Result<(), String> {
+    let path = remote_path::join(&self.buf.remote, self.file_name(name));
+    let stat = FileStat {
+      size: None,
+      uid,
+      gid,
+      perm: None,
+      atime: None,
+      mtime: None,
+    };
+    sftp
+      .setstat(&path, stat)
+      .map_err(|e| format!("{}: {e}", path.display()))?;
+    info!(path = %path.display(), ?uid, ?gid, "chown");
+    self.list_remote(sftp);
+    Ok(())
+  }
+
+  /// Sets the permission bits of `name` in the active pane's directory to `mode`
+  pub fn chmod(&mut self, name: &str, mode: u32, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    match self.state.active {
+      ActiveState::Local => {
+        let path = self.buf.local.join(self.file_name(name));
+        self
+          .local_fs
+          .chmod(&path, mode)
+          .map_err(|e| format!("{}: {e}", path.display()))?;
+        self.list_local();
+      }
+      ActiveState::Remote => {
+        let path = remote_path::join(&self.buf.remote, self.file_name(name));
+        remote_fs
+          .chmod(&path, mode)
+          .map_err(|e| format!("{}: {e}", path.display()))?;
+        info!(path = %path.display(), mode = format!("{mode:04o}"), "chmod");
+        self.list_remote(remote_fs);
+      }
+    }
+    Ok(())
+  }
+
+  /// Opens the start of the file `name` in the active pane in the viewer
+  pub fn preview(&mut self, name: &str, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    if self
+      .entry_named(name)
+      .is_some_and(|e| e.kind == EntryKind::Dir)
+    {
+      return Err(format!("{name} is a directory"));
+    }
+    let size = self.entry_named(name).and_then(|e| e.size);
+    let (fs, path) = match self.state.active {
+      ActiveState::Local => (&*self.local_fs, self.buf.local.join(self.file_name(name))),
+      ActiveState::Remote => (
+        remote_fs,
+        remote_path::join(&self.buf.remote, self.file_name(name)),
+      ),
+    };
+    let mut head = Vec::new();
+    fs.read(&path)
+      .and_then(|file| Ok(file.take(PREVIEW_BYTES).read_to_end(&mut head)?))
+      .map_err(|e| format!("{}: {e}", path.display()))?;
+    self.viewer = Some(Viewer::new(name, &head, size));
+    Ok(())
+  }
+
+  /// What deleting `name` in the active pane will do, for asking the user first
+  pub fn describe_delete(&self, name: &str) -> String {
+    let content = match self.state.active {
+      ActiveState::Local => &self.content.local,
+      ActiveState::Remote => &self.content.remote,
+    };
+    let dir = content
+      .iter()
+      .any(|e| e.name == name && e.kind == EntryKind::Dir);
+    let path = match self.state.active {
+      ActiveState::Local => self.buf.local.join(self.file_name(name)),
+      ActiveState::Remote => remote_path::join(&self.buf.remote, self.file_name(name)),
+    };
+    let what = match dir {
+      true => format!("the directory {} and everything in it", path.display()),
+      false => path.display().to_string(),
+    };
+    match (&self.state.active, self.use_trash) {
+      (ActiveState::Local, true) => format!("Move {what} to the trash?"),
+      _ => format!("Delete {what}? This can't be undone."),
+    }
+  }
+
+  /// Deletes `name` from the active pane's directory, along with everything in it if it's a
+  /// directory. Local items go to the trash instead if `use_trash` is set.
+  pub fn delete(&mut self, name: &str, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    match self.state.active {
+      ActiveState::Local => {
+        let path = self.buf.local.join(self.file_name(name));
+        let removed = match self.use_trash {
+          true => trash::delete(&path).map_err(|e| e.to_string()),
+          false => backend::remove_all(&*self.local_fs, &path).map_err(|e| e.to_string()),
+        };
+        removed.map_err(|e| format!("{}: {e}", path.display()))?;
+        self.list_local();
+      }
+      ActiveState::Remote => {
+        let path = remote_path::join(&self.buf.remote, self.file_name(name));
+        backend::remove_all(remote_fs, &path).map_err(|e| format!("{}: {e}", path.display()))?;
+        info!(path = %path.display(), "delete");
+        self.list_remote(remote_fs);
+      }
+    }
+    // The highlight may have been on the last item
+    let (state, len) = match self.state.active {
+      ActiveState::Local => (&mut self.state.local, self.content.local.len()),
+      ActiveState::Remote => (&mut self.state.remote, self.content.remote.len()),
+    };
+    if state.selected().is_some_and(|i| i >= len) {
+      state.select(Some(len.saturating_sub(1)));
+    }
+    Ok(())
+  }
+
+  /// Goes to the directory holding the remote `path` and highlights it there
+  pub fn reveal_remote(&mut self, path: &str, remote_fs: &dyn FsBackend) -> Result<(), String> {
+    let path = Path::new(path);
+    let name = path.file_name().ok_or("Nothing to go to")?;
+    self.state.active = ActiveState::Remote;
+    self.cd_to(
+      &remote_path::parent(path, self.remote_flavor).to_string_lossy(),
+      remote_fs,
+    )?;
+    self.select_named(&name.to_string_lossy());
+    Ok(())
+  }
+
+  /// Lists the local pane's directory again
+  pub fn list_local(&mut self) {
+    self
+      .content
+      .update_local(&*self.local_fs, &self.buf.local, self.show_hidden);
+  }
+
+  /// Lists the remote pane's directory again, from `remote_fs`
+  pub fn list_remote(&mut self, remote_fs: &dyn FsBackend) {
+    self
+      .content
+      .update_remote(remote_fs, &self.buf.remote, self.show_hidden);
+  }
+
+  // Highlights the entry called `name` in the active pane, if it's listed
+  fn select_named(&mut self, name: &str) {
+    let (state, content) = match self.state.active {
+      ActiveState::Local => (&mut self.state.local, &self.content.local),
+      ActiveState::Remote => (&mut self.state.remote, &self.content.remote),
+    };
+    if let Some(i) = content.iter().position(|e| e.name == name) {
+      state.select(Some(i));
+    }
+  }
+
+  // `path` in the active pane, with `~` expanded and relative paths joined to the current directory
+  fn resolve(&self, path: &str, remote_fs: &dyn FsBackend) -> PathBuf {
+    let (home, cwd) = match self.state.active {
+      ActiveState::Local => (dirs::home_dir(), &self.buf.local),
+      ActiveState::Remote => (remote_fs.realpath(Path::new(".")).ok(), &self.buf.remote),
+    };
+    let join = |dir: &Path, path: &str| match self.state.active {
+      ActiveState::Local => dir.join(path),
+      ActiveState::Remote if path.starts_with('/') => PathBuf::from(path),
+      ActiveState::Remote => remote_path::join(dir, path),
+    };
+    match path.strip_prefix('~') {
+      Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+        join(&home.unwrap_or_default(), rest.trim_start_matches('/'))
+      }
+      _ => join(cwd, path),
+    }
+  }
+
+  /// Highlights the first entry in the active pane whose name starts with `prefix`, ignoring case.
+  /// Stays put if there isn't one.
+  pub fn find_prefix(&mut self, prefix: &str) {
+    let (state, content) = match self.state.active {
+      ActiveState::Local => (&mut self.state.local, &self.content.local),
+      ActiveState::Remote => (&mut self.state.remote, &self.content.remote),
+    };
+    let prefix = prefix.to_lowercase();
+    if let Some(i) = content
+      .iter()
+      .position(|e| e.name.to_lowercase().starts_with(&prefix))
+    {
+      state.select(Some(i));
+    }
+  }
+
+  /// Marks the highlighted item in the active pane, or unmarks it, then moves on to the next one
+  pub fn toggle_mark(&mut self) {
+    let (state, content, marks) = match self.state.active {
+      ActiveState::Local => (
+        &mut self.state.local,
+        &self.content.local,
+        &mut self.content.local_marks,
+      ),
+      ActiveState::Remote => (
+        &mut self.state.remote,
+        &self.content.remote,
+        &mut self.content.remote_marks,
+      ),
+    };
+    let Some(i) = state.selected() else { return };
+    let Some(entry) = content.get(i) else { return };
+    if !marks.remove(&entry.name) {
+      marks.insert(entry.name.clone());
+    }
+    state.select(Some((i + 1).min(content.len() - 1)));
+  }
+
+  /// Names of the marked items in the active pane, in the order they're listed, or the
+  /// highlighted one's if nothing's marked
+  pub fn chosen(&self) -> Vec<String> {
+    let (content, marks) = match self.state.active {
+      ActiveState::Local => (&self.content.local, &self.content.local_marks),
+      ActiveState::Remote => (&self.content.remote, &self.content.remote_marks),
+    };
+    let marked: Vec<String> = content
+      .iter()
+      .filter(|e| marks.contains(&e.name))
+      .map(|e| e.name.clone())
+      .collect();
+    match marked.is_empty() {
+      true => self.selected_name().into_iter().collect(),
+      false => marked,
+    }
+  }
+
+  /// The items listed in the active pane which are missing from the other pane's directory, or
+  /// differ from what's there
+  pub fn differences(&self) -> Vec<Entry> {
+    let (content, others) = match self.state.active {
+      ActiveState::Local => (&self.content.local, self.content.all_remote()),
+      ActiveState::Remote => (&self.content.remote, self.content.all_local()),
+    };
+    let others = app_utils::by_name(others);
+    content
+      .iter()
+      .filter(|e| Comparison::of(e, &others) != Comparison::Same)
+      .cloned()
+      .collect()
+  }
+
+  /// Marks everything in the active pane
+  pub fn mark_all(&mut self) {
+    let (content, marks) = self.marks_mut();
+    marks.extend(content.iter().map(|e| e.name.clone()));
+  }
+
+  /// Marks whatever in the active pane isn't marked, and unmarks the rest
+  pub fn invert_marks(&mut self) {
+    let (content, marks) = self.marks_mut();
+    for entry in content {
+      if !marks.remove(&entry.name) {
+        marks.insert(entry.name.clone());
+      }
+    }
+  }
+
+  /// Marks the names in the active pane that fit `pattern`, e.g. `*.log`.
+  /// Yields how many were newly marked.
+  pub fn mark_matching(&mut self, pattern: &str) -> usize {
+    let (content, marks) = self.marks_mut();
+    content
+      .iter()
+      .filter(|e| app_utils::wildcard(pattern.as_bytes(), e.name.as_bytes()))
+      .filter(|e| marks.insert(e.name.clone()))
+      .count()
+  }
+
+  // The active pane's entries and the names marked among them
+  fn marks_mut(&mut self) -> (&[Entry], &mut HashSet<String>) {
+    match self.state.active {
+      ActiveState::Local => (&self.content.local, &mut self.content.local_marks),
+      ActiveState::Remote => (&self.content.remote, &mut self.content.remote_marks),
+    }
+  }
+
+  /// Moves the highlight in the active pane `step` entries along, stopping at either end
+  pub fn move_selection(&mut self, step: isize) {
+    let (state, len) = self.active_selection();
+    if len > 0 {
+      let current = state.selected().unwrap_or(0);
+      state.select(Some(current.saturating_add_signed(step).min(len - 1)));
+    }
+  }
+
+  /// Highlights the first entry in the active pane
+  pub fn select_first(&mut self) {
+    self.active_selection().0.select(Some(0));
+  }
+
+  /// Highlights the last entry in the active pane
+  pub fn select_last(&mut self) {
+    let (state, len) = self.active_selection();
+    state.select(Some(len.saturating_sub(1)));
+  }
+
+  // The active pane's highlight, and how many entries it has to choose from
+  fn active_selection(&mut self) -> (&mut ListState, usize) {
+    match self.state.active {
+      ActiveState::Local => (&mut self.state.local, self.content.local.len()),
+      ActiveState::Remote => (&mut self.state.remote, self.content.remote.len()),
+    }
+  }
+
+  /// Moves the highlight in the active pane `step` entries along, wrapping around at either end
+  pub fn step_selection(&mut self, step: isize) {
+    let (state, len) = match self.state.active {
+      ActiveState::Local => (&mut self.state.local, self.content.local.len()),
+      ActiveState::Remote => (&mut self.state.remote, self.content.remote.len()),
+    };
+    if len == 0 {
+      return;
+    }
+    let current = state.selected().unwrap_or(0) as isize;
+    state.select(Some((current + step).rem_euclid(len as isize) as usize));
   }
 
   /// Updates the `AppBuf.local`, `AppContent.local` and `AppState.local`,
@@ -36,25 +2039,100 @@ impl App {
   /// be read into `AppContent.local` while the PathBuf itself will be saved as
   /// `AppBuf.local`. `AppState.local` is reset to `Some(0)`.
   pub fn cd_into_local(&mut self) {
-    let i = self.state.local.selected().unwrap_or(0);
-    // fix panic if you delete some of the items in your directory
-    if self.content.local.is_empty() {
+    // nothing to enter if the dir is empty (e.g. after deleting what was in it)
+    let Some(entry) = self
+      .state
+      .local
+      .selected()
+      .and_then(|i| self.content.local.get(i))
+    else {
       return;
-    }
-    self.buf.local.push(&self.content.local[i]);
-    if !self.buf.local.is_dir() {
-      self.buf.local.pop();
+    };
+    let dir = self.buf.local.join(entry);
+    if !is_dir(&*self.local_fs, &dir) {
       return;
     }
-    self.content.update_local(&self.buf.local, self.show_hidden);
+    self.buf.local = match entry.kind {
+      // Going where the link really leads means a link to one of its own parents can't
+      // make for an endless path
+      EntryKind::Symlink if self.follow_symlinks => match self.local_fs.realpath(&dir) {
+        Ok(dir) => dir,
+        Err(_) => return,
+      },
+      EntryKind::Symlink => return,
+      _ => dir,
+    };
+    // a filter only applies to the directory it was typed in
+    self.content.local_filter.clear();
+    self.content.local_marks.clear();
+    self.list_local();
     self.state.local.select(Some(0));
   }
 
+  /// Starts the panes in the directories given with `--local-dir` and `--remote-dir`.
+  /// Fails with a message saying which one isn't there.
+  pub fn start_in(
+    &mut self,
+    local: Option<&str>,
+    remote: Option<&str>,
+    remote_fs: &dyn FsBackend,
+  ) -> Result<(), String> {
+    // What comes after `~` or `~/`, for a path under the home directory
+    let under_home = |path: &str| {
+      path
+        .strip_prefix('~')
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .map(|rest| rest.trim_start_matches('/').to_string())
+    };
+    if let Some(path) = local {
+      let dir = match under_home(path) {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+      };
+      match self.local_fs.realpath(&dir) {
+        Ok(dir) if is_dir(&*self.local_fs, &dir) => self.buf.local = dir,
+        Ok(_) => return Err(format!("Local path {path} isn't a directory")),
+        Err(e) => return Err(format!("Local directory {path}: {e}")),
+      }
+      self.list_local();
+    }
+    if let Some(path) = remote {
+      let remote_home = remote_fs.realpath(Path::new(".")).unwrap_or_default();
+      let dir = match under_home(path) {
+        Some(rest) => remote_path::join(&remote_home, rest),
+        None if path.starts_with('/') => PathBuf::from(path),
+        None => remote_path::join(&remote_home, path),
+      };
+      match remote_fs.realpath(&dir) {
+        Ok(dir) if is_dir(remote_fs, &dir) => self.buf.remote = dir,
+        Ok(_) => return Err(format!("Remote path {path} isn't a directory")),
+        Err(e) => return Err(format!("Remote directory {path} not found: {e}")),
+      }
+      self.list_remote(remote_fs);
+    }
+    Ok(())
+  }
+
+  /// Goes back to `local` and `remote`, e.g. from where the last session left off.
+  /// Either is skipped if it isn't a directory any more.
+  pub fn restore_dirs(&mut self, local: PathBuf, remote: PathBuf, remote_fs: &dyn FsBackend) {
+    if is_dir(&*self.local_fs, &local) {
+      self.buf.local = local;
+      self.list_local();
+    }
+    if is_dir(remote_fs, &remote) {
+      self.buf.remote = remote;
+      self.list_remote(remote_fs);
+    }
+  }
+
   /// Changes `AppBuf.local` to its parent, and reads the new `PathBuf`'s contents to
   /// `AppContent.local`.
   pub fn cd_out_of_local(&mut self) {
     self.buf.local.pop();
-    self.content.update_local(&self.buf.local, self.show_hidden);
+    self.content.local_filter.clear();
+    self.content.local_marks.clear();
+    self.list_local();
     self.state.local.select(Some(0));
   }
 
@@ -62,33 +2140,488 @@ impl App {
   /// using the currently selected item as a PathBuf, the contents of which will
   /// be read into `AppContent.remote` while the PathBuf itself will be saved as
   /// `AppBuf.remote`. `AppState.remote` is reset to `Some(0)`.
-  pub fn cd_into_remote(&mut self, sftp: &Sftp) {
-    // return if dir is empty, or push below will panic
-    if self.content.remote.is_empty() {
+  pub fn cd_into_remote(&mut self, remote_fs: &dyn FsBackend) {
+    // nothing to enter if the dir is empty, or nothing's selected
+    let Some(entry) = self
+      .state
+      .remote
+      .selected()
+      .and_then(|i| self.content.remote.get(i))
+    else {
       return;
-    }
-    // because this unwrap never fails ⬇
-    let i = self.state.remote.selected().unwrap();
-    self.buf.remote.push(&self.content.remote[i]);
-    // we have to make sure we don't treat files as if they're directories -
-    // this functions exactly like `if !self.buf.local.is_dir() {...}` in `cd_into_local`
-    if sftp.opendir(self.buf.remote.as_path()).is_err() {
-      self.buf.remote.pop();
+    };
+    let dir = remote_path::join(&self.buf.remote, entry);
+    // we have to make sure we don't treat files as if they're directories
+    if !is_dir(remote_fs, &dir) {
       return;
     }
-    self
-      .content
-      .update_remote(sftp, &self.buf.remote, self.show_hidden);
+    self.buf.remote = match entry.kind {
+      // as in `cd_into_local`, follow links to where they really lead
+      EntryKind::Symlink if self.follow_symlinks => match remote_fs.realpath(&dir) {
+        Ok(dir) => dir,
+        Err(_) => return,
+      },
+      EntryKind::Symlink => return,
+      _ => dir,
+    };
+    self.content.remote_filter.clear();
+    self.content.remote_marks.clear();
+    self.list_remote(remote_fs);
     self.state.remote.select(Some(0));
   }
 
   /// Changes `AppBuf.remote` to its parent, and reads the new `PathBuf`'s contents to
   /// `AppContent.remote`.
-  pub fn cd_out_of_remote(&mut self, sftp: &Sftp) {
-    self.buf.remote.pop();
-    self
-      .content
-      .update_remote(sftp, &self.buf.remote, self.show_hidden);
+  pub fn cd_out_of_remote(&mut self, remote_fs: &dyn FsBackend) {
+    self.buf.remote = remote_path::parent(&self.buf.remote, self.remote_flavor);
+    self.content.remote_filter.clear();
+    self.content.remote_marks.clear();
+    self.list_remote(remote_fs);
     self.state.remote.select(Some(0));
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::fixtures::{names, put};
+  use crate::backend::Memory;
+
+  // An app in `/home/me` on this side, with a couple of directories, a file and a link to one
+  // of the directories, and in `/srv` on the other side, which is `remote`
+  fn app(remote: &Memory) -> App {
+    let local = Memory::new();
+    put(&local, "/home/me/docs/a.txt", "ay");
+    put(&local, "/home/me/notes.txt", "some notes");
+    local
+      .symlink(Path::new("docs"), Path::new("/home/me/link"))
+      .unwrap();
+    put(remote, "/srv/www/index.html", "<html>");
+    put(remote, "/srv/data.bin", "\0\0\0");
+    let buf = AppBuf {
+      local: PathBuf::from("/home/me"),
+      remote: PathBuf::from("/srv"),
+    };
+    let args = config::command().get_matches_from(["gsftp", "me@example.com"]);
+    App::new(buf, Box::new(local), remote, args)
+  }
+
+  // Presses each key in turn, and yields what became of the last one
+  fn press(app: &mut App, remote: &Memory, keys: &[KeyCode]) -> Handled {
+    let mut handled = Handled::Done;
+    for &code in keys {
+      handled = app.handle_key(KeyEvent::from(code), remote);
+    }
+    handled
+  }
+
+  // Types `text` into whatever's taking the keys
+  fn type_in(app: &mut App, remote: &Memory, text: &str) {
+    for c in text.chars() {
+      app.handle_key(KeyEvent::from(KeyCode::Char(c)), remote);
+    }
+  }
+
+  #[test]
+  fn entering_and_leaving_directories_on_either_side() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    // directories come first
+    assert_eq!(names(&app.content.local), ["docs", "link", "notes.txt"]);
+    assert_eq!(names(&app.content.remote), ["www", "data.bin"]);
+    app.handle(Action::EnterDir, &remote);
+    assert_eq!(app.buf.remote, Path::new("/srv/www"));
+    assert_eq!(names(&app.content.remote), ["index.html"]);
+    app.handle(Action::LeaveDir, &remote);
+    assert_eq!(app.buf.remote, Path::new("/srv"));
+    // files can't be entered
+    app.handle(Action::Down, &remote);
+    app.handle(Action::EnterDir, &remote);
+    assert_eq!(app.buf.remote, Path::new("/srv"));
+    app.handle(Action::SwitchWindow, &remote);
+    app.handle(Action::EnterDir, &remote);
+    assert_eq!(app.buf.local, Path::new("/home/me/docs"));
+    assert_eq!(names(&app.content.local), ["a.txt"]);
+    app.handle(Action::LeaveDir, &remote);
+    assert_eq!(app.buf.local, Path::new("/home/me"));
+    // links lead where they really go
+    app.handle(Action::Down, &remote);
+    app.handle(Action::EnterDir, &remote);
+    assert_eq!(app.buf.local, Path::new("/home/me/docs"));
+  }
+
+  #[test]
+  fn going_to_a_path_resolves_it_against_the_pane() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.state.active = ActiveState::Local;
+    app.cd_to("link", &remote).unwrap();
+    assert_eq!(app.buf.local, Path::new("/home/me/docs"));
+    assert!(app.cd_to("../notes.txt", &remote).is_err());
+    assert!(app.cd_to("/nowhere", &remote).is_err());
+    assert_eq!(app.complete_path("../no", &remote), "../notes.txt");
+    app.state.active = ActiveState::Remote;
+    assert_eq!(app.complete_path("w", &remote), "www/");
+    app.cd_to("/srv/www", &remote).unwrap();
+    assert_eq!(names(&app.content.remote), ["index.html"]);
+    app.reveal_remote("/srv/data.bin", &remote).unwrap();
+    assert_eq!(app.selected_name().as_deref(), Some("data.bin"));
+  }
+
+  #[test]
+  fn making_renaming_and_deleting_in_the_active_pane() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.state.active = ActiveState::Local;
+    app.make_dir("new", &remote).unwrap();
+    assert_eq!(app.selected_name().as_deref(), Some("new"));
+    app.make_file("empty.txt", &remote).unwrap();
+    assert!(app.make_file("notes.txt", &remote).is_err());
+    assert!(app.make_dir("../up", &remote).is_err());
+    app.rename("empty.txt", "full.txt", &remote).unwrap();
+    assert!(app.rename("full.txt", "notes.txt", &remote).is_err());
+    app.delete("docs", &remote).unwrap();
+    assert_eq!(
+      names(&app.content.local),
+      ["new", "full.txt", "link", "notes.txt"]
+    );
+    app.state.active = ActiveState::Remote;
+    app.chmod("data.bin", 0o600, &remote).unwrap();
+    assert_eq!(
+      remote.stat(Path::new("/srv/data.bin")).unwrap().perm,
+      Some(0o600)
+    );
+    app.delete("www", &remote).unwrap();
+    assert_eq!(names(&app.content.remote), ["data.bin"]);
+    assert!(remote.lstat(Path::new("/srv/www")).is_err());
+  }
+
+  #[test]
+  fn viewing_reads_the_start_of_a_file() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.state.active = ActiveState::Local;
+    app.handle(Action::Bottom, &remote);
+    app.handle(Action::View, &remote);
+    let viewer = app.viewer.take().unwrap();
+    assert_eq!(viewer.lines, ["some notes"]);
+    app.handle(Action::Top, &remote);
+    assert!(matches!(
+      app.handle(Action::View, &remote),
+      Handled::Error(_)
+    ));
+  }
+
+  #[test]
+  fn commands_are_run_by_the_app_or_handed_back() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.handle(Action::Command, &remote);
+    type_in(&mut app, &remote, "cd w");
+    press(&mut app, &remote, &[KeyCode::Tab]);
+    assert_eq!(app.prompt.as_ref().unwrap().input, "cd www/");
+    assert_eq!(press(&mut app, &remote, &[KeyCode::Enter]), Handled::Done);
+    assert_eq!(app.buf.remote, Path::new("/srv/www"));
+    assert!(app.prompt.is_none());
+    // connecting is up to the event loop
+    app.handle(Action::Command, &remote);
+    type_in(&mut app, &remote, "tabnew me@example.org");
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Effect(Effect::Connect {
+        destination: String::from("me@example.org"),
+        new_tab: true,
+      })
+    );
+    // but there has to be a host or a tab to go to
+    app.hosts = (0, 2);
+    app.handle(Action::Command, &remote);
+    type_in(&mut app, &remote, "switch 2");
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Effect(Effect::SwitchHost(1))
+    );
+    for command in ["switch 3", "switch 0", "tabclose", "frobnicate"] {
+      app.handle(Action::Command, &remote);
+      type_in(&mut app, &remote, command);
+      assert!(matches!(
+        press(&mut app, &remote, &[KeyCode::Enter]),
+        Handled::Error(_)
+      ));
+    }
+    app.handle(Action::Command, &remote);
+    assert_eq!(press(&mut app, &remote, &[KeyCode::Esc]), Handled::Done);
+    assert!(app.prompt.is_none());
+  }
+
+  #[test]
+  fn prompts_hand_back_what_was_typed_once_it_makes_sense() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.handle(Action::Schedule, &remote);
+    type_in(&mut app, &remote, "soon");
+    assert!(matches!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Error(_)
+    ));
+    app.handle(Action::Schedule, &remote);
+    type_in(&mut app, &remote, "after");
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Effect(Effect::Schedule(Start::AfterQueue))
+    );
+    // a search is shown as running until the event loop says it's done
+    app.handle(Action::Search, &remote);
+    type_in(&mut app, &remote, " *.html ");
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Effect(Effect::Search(String::from("*.html")))
+    );
+    assert_eq!(app.searching.as_deref(), Some("*.html"));
+    assert!(matches!(
+      app.handle(Action::Search, &remote),
+      Handled::Error(_)
+    ));
+    // the owner is named, and looked up by the event loop
+    app.handle(Action::Chown, &remote);
+    assert!(app.prompt.as_ref().unwrap().question.contains("now ?"));
+    type_in(&mut app, &remote, "deploy:www");
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Effect(Effect::Chown {
+        name: String::from("www"),
+        owner: String::from("deploy:www"),
+      })
+    );
+  }
+
+  #[test]
+  fn relaying_asks_which_host_once_there_are_several() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    assert!(matches!(
+      app.handle(Action::Relay, &remote),
+      Handled::Error(_)
+    ));
+    app.hosts = (1, 2);
+    assert_eq!(
+      app.handle(Action::Relay, &remote),
+      Handled::Effect(Effect::Relay(0))
+    );
+    app.hosts = (1, 3);
+    assert_eq!(app.handle(Action::Relay, &remote), Handled::Done);
+    type_in(&mut app, &remote, "2");
+    assert!(matches!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Error(_)
+    ));
+    app.handle(Action::Relay, &remote);
+    type_in(&mut app, &remote, "3");
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Effect(Effect::Relay(2))
+    );
+    // only remote items can be relayed
+    app.handle(Action::SwitchWindow, &remote);
+    assert!(matches!(
+      app.handle(Action::Relay, &remote),
+      Handled::Error(_)
+    ));
+  }
+
+  #[test]
+  fn the_highlighted_item_is_handed_back_by_its_path() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.remote_id = String::from("me@example.com:22");
+    assert_eq!(
+      app.handle(Action::Edit, &remote),
+      Handled::Error(String::from("www is a directory"))
+    );
+    app.handle(Action::Down, &remote);
+    assert_eq!(
+      app.handle(Action::Edit, &remote),
+      Handled::Effect(Effect::OpenEditor(PathBuf::from("/srv/data.bin")))
+    );
+    assert_eq!(
+      app.handle(Action::CopyPath, &remote),
+      Handled::Effect(Effect::SetClipboard(String::from(
+        "me@example.com:/srv/data.bin"
+      )))
+    );
+    app.handle(Action::SwitchWindow, &remote);
+    assert_eq!(
+      app.handle(Action::CopyPath, &remote),
+      Handled::Effect(Effect::SetClipboard(String::from("/home/me/docs")))
+    );
+    // nothing here is on the other side
+    let Handled::Effect(Effect::CopyDifferences(differences)) =
+      app.handle(Action::CopyDifferences, &remote)
+    else {
+      panic!("copying is up to the event loop");
+    };
+    assert_eq!(names(&differences), ["docs", "link", "notes.txt"]);
+  }
+
+  #[test]
+  fn yanked_items_are_only_pasted_on_the_same_host() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.remote_id = String::from("me@example.com:22");
+    assert!(matches!(
+      app.handle(Action::Paste, &remote),
+      Handled::Error(_)
+    ));
+    app.handle(Action::Mark, &remote);
+    app.handle(Action::Mark, &remote);
+    assert_eq!(
+      app.handle(Action::Yank, &remote),
+      Handled::Message(String::from("Yanked 2 items - p pastes it"))
+    );
+    app.handle(Action::SwitchWindow, &remote);
+    assert!(matches!(
+      app.handle(Action::Paste, &remote),
+      Handled::Error(_)
+    ));
+    app.handle(Action::SwitchWindow, &remote);
+    app.remote_id = String::from("me@example.org:22");
+    assert!(matches!(
+      app.handle(Action::Paste, &remote),
+      Handled::Error(_)
+    ));
+    app.remote_id = String::from("me@example.com:22");
+    assert_eq!(
+      app.handle(Action::Paste, &remote),
+      Handled::Effect(Effect::Paste(Register {
+        host: Some(String::from("me@example.com:22")),
+        dir: PathBuf::from("/srv"),
+        names: vec![OsString::from("www"), OsString::from("data.bin")],
+      }))
+    );
+  }
+
+  #[test]
+  fn bookmarks_are_saved_picked_and_forgotten() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.remote_id = String::from("me@example.com:22");
+    let file = std::env::temp_dir().join(format!("gsftp-bookmarks-{}", std::process::id()));
+    app.bookmarks = Bookmarks::from_file(Some(file.clone()));
+    assert_eq!(
+      app.handle(Action::Bookmark, &remote),
+      Handled::Message(String::from("Bookmarked /srv"))
+    );
+    assert_eq!(
+      app.handle(Action::Bookmark, &remote),
+      Handled::Message(String::from("/srv is already bookmarked"))
+    );
+    // bookmarks on this side are kept apart from the other side's
+    app.handle(Action::SwitchWindow, &remote);
+    app.handle(Action::Bookmarks, &remote);
+    assert!(app.picker.as_ref().unwrap().items.is_empty());
+    press(&mut app, &remote, &[KeyCode::Esc]);
+    app.handle(Action::SwitchWindow, &remote);
+    app.handle(Action::Bookmarks, &remote);
+    assert_eq!(app.picker.as_ref().unwrap().items, ["/srv"]);
+    let saved = || Bookmarks::from_file(Some(file.clone())).on(Some("me@example.com:22"));
+    assert_eq!(saved(), [PathBuf::from("/srv")]);
+    press(&mut app, &remote, &[KeyCode::Char('x'), KeyCode::Esc]);
+    assert!(saved().is_empty());
+    std::fs::remove_file(file).unwrap();
+  }
+
+  #[test]
+  fn the_message_log_takes_keys_while_its_open() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.handle(Action::Log, &remote);
+    assert_eq!(app.input_mode(), InputMode::Log);
+    press(&mut app, &remote, &[KeyCode::Char('g'), KeyCode::Char('j')]);
+    assert_eq!(app.log.as_ref().unwrap().selected(), Some(1));
+    let Handled::Effect(Effect::SaveLog(path)) = press(&mut app, &remote, &[KeyCode::Char('s')])
+    else {
+      panic!("saving is up to the event loop, which has the messages");
+    };
+    assert_eq!(path.parent(), Some(Path::new("/home/me")));
+    // the panes get the keys again once it's closed
+    press(&mut app, &remote, &[KeyCode::Char('q'), KeyCode::Char('j')]);
+    assert!(app.log.is_none());
+    assert_eq!(app.selected_name().as_deref(), Some("data.bin"));
+  }
+
+  #[test]
+  fn renaming_and_deleting_go_through_their_dialogs() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    app.state.active = ActiveState::Local;
+    app.handle(Action::Bottom, &remote);
+    app.handle(Action::Rename, &remote);
+    assert_eq!(app.input_mode(), InputMode::Prompt);
+    press(&mut app, &remote, &[KeyCode::Backspace; 3]);
+    type_in(&mut app, &remote, "md");
+    assert_eq!(press(&mut app, &remote, &[KeyCode::Enter]), Handled::Done);
+    assert_eq!(names(&app.content.local), ["docs", "link", "notes.md"]);
+    // deleting asks first, and nothing goes if the answer's no
+    app.handle(Action::Delete, &remote);
+    assert_eq!(app.input_mode(), InputMode::Confirm);
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Char('n')]),
+      Handled::Effect(Effect::DropPending)
+    );
+    assert_eq!(names(&app.content.local), ["docs", "link", "notes.md"]);
+    app.handle(Action::Delete, &remote);
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Char('y')]),
+      Handled::Message(String::from("Deleted notes.md"))
+    );
+    assert_eq!(names(&app.content.local), ["docs", "link"]);
+    // with nothing to delete, yes is for the transfers waiting on the answer
+    app.confirm = Some(String::from("Copy 2 GB?"));
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Enter]),
+      Handled::Effect(Effect::StartPending)
+    );
+  }
+
+  #[test]
+  fn dialogs_take_keys_before_the_panes() {
+    let remote = Memory::new();
+    let mut app = app(&remote);
+    // any key closes the info dialog, and goes no further
+    app.info = Some((String::from("Server"), String::new()));
+    press(&mut app, &remote, &[KeyCode::Char('j')]);
+    assert!(app.info.is_none());
+    assert_eq!(app.selected_name().as_deref(), Some("www"));
+    // the help is searched after `/`, and closed with `?`
+    app.handle(Action::Help, &remote);
+    press(&mut app, &remote, &[KeyCode::Char('/')]);
+    type_in(&mut app, &remote, "quit");
+    let help = app.help.as_ref().unwrap();
+    assert!(help.searching);
+    assert_eq!(help.query, "quit");
+    press(&mut app, &remote, &[KeyCode::Enter, KeyCode::Char('?')]);
+    assert!(app.help.is_none());
+    // the filter is applied as it's typed, and Esc clears it
+    app.handle(Action::Filter, &remote);
+    type_in(&mut app, &remote, "dat");
+    press(&mut app, &remote, &[KeyCode::Enter]);
+    assert_eq!(names(&app.content.remote), ["data.bin"]);
+    press(&mut app, &remote, &[KeyCode::Esc]);
+    assert_eq!(names(&app.content.remote), ["www", "data.bin"]);
+    // a picked bookmark is gone to
+    let items = vec![String::from("/srv/www")];
+    app.picker = Some(Picker::new(PickerKind::Bookmarks, "Bookmarks", items));
+    press(&mut app, &remote, &[KeyCode::Enter]);
+    assert!(app.picker.is_none());
+    assert_eq!(app.buf.remote, Path::new("/srv/www"));
+    // and keys bound to nothing switch tabs
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Char('g'), KeyCode::Char('T')]),
+      Handled::Effect(Effect::CycleTab(-1))
+    );
+    assert_eq!(
+      press(&mut app, &remote, &[KeyCode::Char('3')]),
+      Handled::Effect(Effect::SwitchTab(2))
+    );
+  }
+}
@@ -1,11 +1,24 @@
 //! Utils to read the contents of local and remote directories
-use ssh2::{Session, Sftp};
+use chrono::{Local, TimeZone};
+use ssh2::{FileStat, Session, Sftp};
 use std::{
-  env, fs,
+  cmp::Ordering,
+  collections::{HashMap, HashSet},
+  env,
+  ffi::{OsStr, OsString},
+  fs, io,
   path::{Path, PathBuf},
+  process::Command,
+  str::FromStr,
+  time::{Instant, UNIX_EPOCH},
 };
 use tui::widgets::ListState;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+use crate::backend::{self, FsBackend};
+use crate::error::GsftpError;
+use crate::remote_path;
 use crate::sftp;
 
 #[derive(Debug)]
@@ -15,84 +28,740 @@ pub struct AppBuf {
   pub remote: PathBuf,
 }
 
-impl From<&Session> for AppBuf {
+impl AppBuf {
   /// Yields a `AppBuf` with the `local` field defaulting to the current working directory;
-  /// the `remote` field defaults to the remote connection's home directory (e.g. /home/$USER).
-  fn from(sess: &Session) -> Self {
-    let local = env::current_dir().unwrap_or_else(|e| {
-      eprintln!("Fatal error reading current directory: {e}");
-      std::process::exit(1);
+  /// the `remote` field is wherever the remote connection starts out (see `remote_dir`).
+  /// It's an error if the current directory can't be read, e.g. because it's been deleted.
+  pub fn from(remote: PathBuf) -> io::Result<Self> {
+    let local = env::current_dir()?;
+    Ok(Self { local, remote })
+  }
+}
+
+/// The remote home directory, or `start_dir` within it if that's a directory. If it isn't, the
+/// error says so, along with the home directory to start in instead.
+pub fn remote_dir(
+  sess: &Session,
+  sftp: &Sftp,
+  start_dir: Option<&Path>,
+) -> Result<PathBuf, (PathBuf, String)> {
+  let home = sftp::home_dir(sftp, sess);
+  match start_dir.map(|dir| remote_path::join(&home, dir)) {
+    Some(dir) if sftp.stat(&dir).is_ok_and(|stat| stat.is_dir()) => Ok(dir),
+    Some(dir) => {
+      let e = format!(
+        "Remote directory {} not found; starting in {}.",
+        dir.display(),
+        home.display()
+      );
+      Err((home, e))
+    }
+    None => Ok(home),
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What sort of thing a directory entry is
+pub enum EntryKind {
+  File,
+  Dir,
+  Symlink,
+  Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One item in a directory listing, along with whatever's known about it
+pub struct Entry {
+  /// The name to show, with anything that isn't valid UTF-8 replaced
+  pub name: String,
+  /// The name exactly as it is on disk, for building paths with
+  pub file_name: OsString,
+  pub kind: EntryKind,
+  pub size: Option<u64>,
+  /// Last modified, in seconds since the Unix epoch
+  pub mtime: Option<u64>,
+  /// Unix permission bits, e.g. `0o644`
+  pub perm: Option<u32>,
+  /// Who owns it, as `(uid, gid)`
+  pub owner: Option<(u32, u32)>,
+  /// Where a symlink points, as it's written in the link
+  pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What sort of thing an entry is, as far as coloring it and picking an icon go
+pub enum FileClass {
+  Dir,
+  Symlink,
+  Executable,
+  Archive,
+  Image,
+  Other,
+}
+
+impl Entry {
+  /// Directories and links by kind, then archives and images by their extensions, and
+  /// executables by their permissions
+  pub fn class(&self) -> FileClass {
+    const ARCHIVES: [&str; 11] = [
+      "zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "jar", "deb",
+    ];
+    const IMAGES: [&str; 10] = [
+      "png", "jpg", "jpeg", "gif", "bmp", "svg", "webp", "ico", "tif", "tiff",
+    ];
+    match self.kind {
+      EntryKind::Dir => return FileClass::Dir,
+      EntryKind::Symlink => return FileClass::Symlink,
+      EntryKind::File | EntryKind::Other => {}
+    }
+    let extension = Path::new(&self.name)
+      .extension()
+      .map(|ext| ext.to_string_lossy().to_lowercase())
+      .unwrap_or_default();
+    if ARCHIVES.contains(&extension.as_str()) {
+      FileClass::Archive
+    } else if IMAGES.contains(&extension.as_str()) {
+      FileClass::Image
+    } else if self.kind == EntryKind::File && self.perm.is_some_and(|perm| perm & 0o111 != 0) {
+      FileClass::Executable
+    } else {
+      FileClass::Other
+    }
+  }
+
+  /// A remote entry, from the `FileStat` that `readdir` yields alongside it.
+  /// A symlink's `target` has to be read separately.
+  pub fn from_stat(file_name: OsString, stat: &FileStat) -> Self {
+    let kind = match stat.file_type() {
+      ssh2::FileType::RegularFile => EntryKind::File,
+      ssh2::FileType::Directory => EntryKind::Dir,
+      ssh2::FileType::Symlink => EntryKind::Symlink,
+      _ => EntryKind::Other,
+    };
+    Self {
+      name: file_name.to_string_lossy().into_owned(),
+      file_name,
+      kind,
+      size: stat.size,
+      mtime: stat.mtime,
+      perm: stat.perm.map(|perm| perm & 0o7777),
+      owner: stat.uid.zip(stat.gid),
+      target: None,
+    }
+  }
+
+  /// A local entry; symlinks are described, not followed
+  pub fn from_path(path: &Path) -> Option<Self> {
+    let file_name = path.file_name()?.to_os_string();
+    let meta = fs::symlink_metadata(path).ok();
+    let mut entry = Self::from_metadata(file_name, meta.as_ref());
+    if entry.kind == EntryKind::Symlink {
+      entry.target = fs::read_link(path)
+        .ok()
+        .map(|target| target.display().to_string());
+    }
+    Some(entry)
+  }
+
+  /// A local entry called `file_name`, with whatever `meta` says about it
+  pub fn from_metadata(file_name: OsString, meta: Option<&fs::Metadata>) -> Self {
+    let kind = match meta.map(fs::Metadata::file_type) {
+      Some(t) if t.is_symlink() => EntryKind::Symlink,
+      Some(t) if t.is_dir() => EntryKind::Dir,
+      Some(t) if t.is_file() => EntryKind::File,
+      _ => EntryKind::Other,
+    };
+    let mtime = meta
+      .and_then(|meta| meta.modified().ok())
+      .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+      .map(|age| age.as_secs());
+    #[cfg(unix)]
+    let perm = {
+      use std::os::unix::fs::PermissionsExt;
+      meta.map(|meta| meta.permissions().mode() & 0o7777)
+    };
+    #[cfg(not(unix))]
+    let perm = None;
+    #[cfg(unix)]
+    let owner = {
+      use std::os::unix::fs::MetadataExt;
+      meta.map(|meta| (meta.uid(), meta.gid()))
+    };
+    #[cfg(not(unix))]
+    let owner = None;
+    Self {
+      name: file_name.to_string_lossy().into_owned(),
+      file_name,
+      kind,
+      size: meta.map(fs::Metadata::len),
+      mtime,
+      perm,
+      owner,
+      target: None,
+    }
+  }
+
+  /// Permissions, size and modification time, like `ls -l`: `-rw-r--r--   1.2 MB 2024-05-01 13:37`
+  pub fn details(&self) -> String {
+    let kind = match self.kind {
+      EntryKind::File => '-',
+      EntryKind::Dir => 'd',
+      EntryKind::Symlink => 'l',
+      EntryKind::Other => '?',
+    };
+    let perm = match self.perm {
+      Some(perm) => rwx(perm),
+      None => "?".repeat(9),
+    };
+    let size = match (self.kind, self.size) {
+      (EntryKind::File, Some(size)) => human_size(size),
+      _ => String::new(),
+    };
+    let mtime = self
+      .mtime
+      .and_then(|secs| Local.timestamp_opt(secs as i64, 0).single())
+      .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+      .unwrap_or_default();
+    format!("{kind}{perm} {size:>9} {mtime:>16}")
+  }
+
+  /// Just the size, for when there isn't room for all the details
+  pub fn short_details(&self) -> String {
+    match (self.kind, self.size) {
+      (EntryKind::File, Some(size)) => format!("{:>9}", human_size(size)),
+      _ => String::new(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How an entry compares with the one of the same name in the other pane's directory
+pub enum Comparison {
+  /// There's nothing by that name over there
+  Missing,
+  /// Files of different sizes, or a file on one side and a directory on the other
+  Differs,
+  /// Files of the same size, but this one was modified later
+  Newer,
+  /// Nothing to tell them apart. Directories on both sides always count as the same, since
+  /// what's inside them isn't compared.
+  Same,
+}
+
+impl Comparison {
+  /// Compares `entry` with its namesake among `others`, which are indexed by `by_name`
+  pub fn of(entry: &Entry, others: &HashMap<&OsStr, &Entry>) -> Self {
+    let Some(other) = others.get(entry.file_name.as_os_str()) else {
+      return Self::Missing;
+    };
+    match (entry.kind, other.kind) {
+      (EntryKind::Dir, EntryKind::Dir) => Self::Same,
+      (EntryKind::Dir, _) | (_, EntryKind::Dir) => Self::Differs,
+      _ if entry.size != other.size => Self::Differs,
+      // Transfers don't keep modification times, so only a newer copy counts
+      _ if entry.mtime > other.mtime => Self::Newer,
+      _ => Self::Same,
+    }
+  }
+}
+
+/// `entries` looked up by their names, for comparing them with another directory's
+pub fn by_name(entries: &[Entry]) -> HashMap<&OsStr, &Entry> {
+  entries
+    .iter()
+    .map(|e| (e.file_name.as_os_str(), e))
+    .collect()
+}
+
+impl AsRef<Path> for Entry {
+  fn as_ref(&self) -> &Path {
+    Path::new(&self.file_name)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What listings are ordered by
+pub enum SortKey {
+  #[default]
+  Name,
+  /// By name, but with runs of digits compared as numbers, so `file2` comes before `file10`
+  Natural,
+  Size,
+  Modified,
+  /// Directories, then symlinks, then files, each grouped by extension
+  Type,
+}
+
+impl FromStr for SortKey {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "name" => Ok(Self::Name),
+      "natural" => Ok(Self::Natural),
+      "size" => Ok(Self::Size),
+      "modified" => Ok(Self::Modified),
+      "type" => Ok(Self::Type),
+      _ => Err(format!("Unknown sort key: {s}")),
+    }
+  }
+}
+
+// Compares names ignoring case, with each run of digits compared by its value
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+  let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+  loop {
+    match (a.peek().copied(), b.peek().copied()) {
+      (None, None) => return Ordering::Equal,
+      (None, Some(_)) => return Ordering::Less,
+      (Some(_), None) => return Ordering::Greater,
+      (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+        let digits = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+          let mut run = String::new();
+          while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            run.push(c);
+          }
+          run
+        };
+        let (x, y) = (digits(&mut a), digits(&mut b));
+        // Leading zeros don't change a number, but a longer number is a bigger one
+        let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+        let order = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+        if order != Ordering::Equal {
+          return order;
+        }
+      }
+      (Some(x), Some(y)) => {
+        let order = x.to_lowercase().cmp(y.to_lowercase());
+        if order != Ordering::Equal {
+          return order;
+        }
+        a.next();
+        b.next();
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How both panes are ordered: by name, A to Z, unless the user picks otherwise
+pub struct Sort {
+  pub key: SortKey,
+  pub descending: bool,
+}
+
+impl Sort {
+  /// The next key in the cycle name, natural, size, modified, type, keeping the direction
+  pub fn next_key(self) -> Self {
+    let key = match self.key {
+      SortKey::Name => SortKey::Natural,
+      SortKey::Natural => SortKey::Size,
+      SortKey::Size => SortKey::Modified,
+      SortKey::Modified => SortKey::Type,
+      SortKey::Type => SortKey::Name,
+    };
+    Self { key, ..self }
+  }
+
+  pub fn reversed(self) -> Self {
+    Self {
+      descending: !self.descending,
+      ..self
+    }
+  }
+
+  /// Sorts `entries` by the key, falling back to the name for ties. Directories always come first.
+  pub fn apply(&self, entries: &mut [Entry]) {
+    let rank = |kind: EntryKind| match kind {
+      EntryKind::Dir => 0,
+      EntryKind::Symlink => 1,
+      EntryKind::File => 2,
+      EntryKind::Other => 3,
+    };
+    let extension = |e: &Entry| {
+      Path::new(&e.name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+    };
+    entries.sort_by(|a, b| {
+      let order = match self.key {
+        SortKey::Name => std::cmp::Ordering::Equal,
+        SortKey::Natural => natural_cmp(&a.name, &b.name),
+        SortKey::Size => a.size.cmp(&b.size),
+        SortKey::Modified => a.mtime.cmp(&b.mtime),
+        SortKey::Type => rank(a.kind)
+          .cmp(&rank(b.kind))
+          .then_with(|| extension(a).cmp(&extension(b))),
+      };
+      let order = order.then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+      let order = match self.descending {
+        true => order.reverse(),
+        false => order,
+      };
+      let is_file = |e: &Entry| e.kind != EntryKind::Dir;
+      is_file(a).cmp(&is_file(b)).then(order)
     });
-    let remote = sftp::home_dir(sess);
-    Self { local, remote }
+  }
+
+  /// e.g. `size ↓`, for the pane titles
+  pub fn describe(&self) -> String {
+    let key = match self.key {
+      SortKey::Name => "name",
+      SortKey::Natural => "natural",
+      SortKey::Size => "size",
+      SortKey::Modified => "modified",
+      SortKey::Type => "type",
+    };
+    let arrow = if self.descending { "↓" } else { "↑" };
+    format!("{key} {arrow}")
   }
 }
 
 #[derive(Debug)]
-/// Contains the contents of our current working directories as `Vec<String>`.
+/// Contains the contents of our current working directories, one `Entry` per item.
+/// Only the entries matching each pane's filter are listed.
 pub struct AppContent {
-  pub local: Vec<String>,
-  pub remote: Vec<String>,
+  pub local: Vec<Entry>,
+  pub remote: Vec<Entry>,
+  /// How both listings are ordered
+  pub sort: Sort,
+  /// Case-insensitive text the names in each pane have to contain; empty lists everything
+  pub local_filter: String,
+  pub remote_filter: String,
+  /// Names of the items marked in each pane, which only last until it changes directory
+  pub local_marks: HashSet<String>,
+  pub remote_marks: HashSet<String>,
+  /// When the remote directory was last listed
+  pub remote_listed: Instant,
+  /// Why each pane's directory couldn't be read, if it couldn't, e.g. `permission denied`
+  pub local_unreadable: Option<String>,
+  pub remote_unreadable: Option<String>,
+  // Everything in the directories, before filtering
+  all_local: Vec<Entry>,
+  all_remote: Vec<Entry>,
 }
 
 impl AppContent {
   /// The `AppContent` struct holds two vectors which contain the contents of the local and remote
   /// directories contained by the `PathBuf` directories in the `AppBuf` struct
   /// the `remote` field defaults to the remote connection's home directory (e.g. /home/$USER).
-  pub fn from(buf: &AppBuf, sftp: &Sftp, show_hidden: bool) -> Self {
-    let local = sort_and_stringify(read_dir_contents(&buf.local), show_hidden);
-    let remote = sftp::ls(sftp, &buf.remote, show_hidden);
-    Self { local, remote }
+  pub fn from(
+    buf: &AppBuf,
+    local_fs: &dyn FsBackend,
+    remote_fs: &dyn FsBackend,
+    show_hidden: bool,
+  ) -> Self {
+    let (local, local_unreadable) = read_dir(local_fs, &buf.local, show_hidden);
+    let (remote, remote_unreadable) = read_dir(remote_fs, &buf.remote, show_hidden);
+    Self {
+      local: local.clone(),
+      remote: remote.clone(),
+      sort: Sort::default(),
+      local_filter: String::new(),
+      remote_filter: String::new(),
+      local_marks: HashSet::new(),
+      remote_marks: HashSet::new(),
+      remote_listed: Instant::now(),
+      local_unreadable,
+      remote_unreadable,
+      all_local: local,
+      all_remote: remote,
+    }
   }
 
   /// Given the current `AppBuf.local`, updates the `AppContent.local`
   /// to reflect the current local dir's contents.
-  pub fn update_local(&mut self, path: &Path, show_hidden: bool) {
-    self.local = sort_and_stringify(read_dir_contents(path), show_hidden);
+  pub fn update_local(&mut self, fs: &dyn FsBackend, path: &Path, show_hidden: bool) {
+    (self.all_local, self.local_unreadable) = read_dir(fs, path, show_hidden);
+    self.refilter();
   }
 
   /// Given the current `AppBuf.remote`, updates the `AppContent.remote`
   /// to reflect the current remote dir's contents.
-  pub fn update_remote(&mut self, sftp: &Sftp, buf: &Path, show_hidden: bool) {
-    self.remote = sftp::ls(sftp, buf, show_hidden);
+  pub fn update_remote(&mut self, fs: &dyn FsBackend, buf: &Path, show_hidden: bool) {
+    (self.all_remote, self.remote_unreadable) = read_dir(fs, buf, show_hidden);
+    self.remote_listed = Instant::now();
+    self.refilter();
+  }
+
+  /// Everything in the local directory, whatever the filter
+  pub fn all_local(&self) -> &[Entry] {
+    &self.all_local
+  }
+
+  /// Everything in the remote directory, whatever the filter
+  pub fn all_remote(&self) -> &[Entry] {
+    &self.all_remote
+  }
+
+  /// Lists the entries matching the filters again, e.g. after one of them changed
+  pub fn refilter(&mut self) {
+    self.local = filtered(&self.all_local, &self.local_filter, self.sort);
+    self.remote = filtered(&self.all_remote, &self.remote_filter, self.sort);
   }
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// How much is listed in a pane, and how much of it is marked
+pub struct Totals {
+  pub items: usize,
+  pub bytes: u64,
+  pub marked: usize,
+  pub marked_bytes: u64,
+}
+
+impl Totals {
+  /// Adds up the files in `entries`; directories count as items but not towards the size
+  pub fn of(entries: &[Entry], marks: &HashSet<String>) -> Self {
+    let mut totals = Self::default();
+    for entry in entries {
+      let bytes = match entry.kind {
+        EntryKind::File => entry.size.unwrap_or_default(),
+        _ => 0,
+      };
+      totals.items += 1;
+      totals.bytes += bytes;
+      if marks.contains(&entry.name) {
+        totals.marked += 1;
+        totals.marked_bytes += bytes;
+      }
+    }
+    totals
+  }
+
+  /// e.g. `12 items, 4.5 MB, 3 selected (1.2 MB)`
+  pub fn describe(&self) -> String {
+    let items = match self.items {
+      1 => String::from("1 item"),
+      n => format!("{} items", thousands(n as u64)),
+    };
+    let mut text = format!("{items}, {}", human_size(self.bytes));
+    if self.marked > 0 {
+      text.push_str(&format!(
+        ", {} selected ({})",
+        thousands(self.marked as u64),
+        human_size(self.marked_bytes)
+      ));
+    }
+    text
+  }
+}
+
+fn filtered(entries: &[Entry], filter: &str, sort: Sort) -> Vec<Entry> {
+  let filter = filter.to_lowercase();
+  let mut entries: Vec<Entry> = entries
+    .iter()
+    .filter(|e| e.name.to_lowercase().contains(&filter))
+    .cloned()
+    .collect();
+  sort.apply(&mut entries);
+  entries
+}
+
 pub fn read_dir_contents(buf: &Path) -> Vec<PathBuf> {
-  match fs::read_dir(buf) {
-    Ok(rd) => rd
+  try_read_dir_contents(buf).unwrap_or_default()
+}
+
+/// Like `read_dir_contents`, but fails if the directory can't be read
+pub fn try_read_dir_contents(buf: &Path) -> io::Result<Vec<PathBuf>> {
+  Ok(
+    fs::read_dir(buf)?
       .map(|res| res.map(|e| e.path()))
       .map(|res| res.unwrap_or_default())
       .filter(|buf| buf.exists())
       .collect(),
-    Err(_) => vec![],
+  )
+}
+
+// A pane's listing of `path` in `fs`, or nothing and the reason it couldn't be read
+fn read_dir(fs: &dyn FsBackend, path: &Path, show_hidden: bool) -> (Vec<Entry>, Option<String>) {
+  let denied = || (Vec::new(), Some(String::from("permission denied")));
+  match backend::ls(fs, path, show_hidden) {
+    Ok(entries) => (entries, None),
+    Err(GsftpError::Io(e)) if e.kind() == io::ErrorKind::PermissionDenied => denied(),
+    // SSH_FX_PERMISSION_DENIED
+    Err(GsftpError::Sftp(e)) if e.code() == ssh2::ErrorCode::SFTP(3) => denied(),
+    Err(GsftpError::Sftp(e)) => (Vec::new(), Some(e.message().to_string())),
+    Err(e) => (Vec::new(), Some(e.to_string())),
   }
 }
 
-fn sort_and_stringify(bufs: Vec<PathBuf>, show_hidden: bool) -> Vec<String> {
-  let mut bufs: Vec<String> = bufs
-    .iter()
-    .map(|b| {
-      b.file_name()
-        .unwrap_or_default()
-        .to_str()
-        .unwrap_or_default()
+/// Permission bits the way `ls -l` shows them, e.g. `rw-r--r--`
+pub fn rwx(perm: u32) -> String {
+  (0..9)
+    .map(|bit| match perm & (0o400 >> bit) {
+      0 => '-',
+      _ => ['r', 'w', 'x'][bit % 3],
     })
-    .filter(|s| !s.is_empty())
-    .filter(|s| {
-      if !show_hidden {
-        !s.starts_with('.')
-      } else {
-        true
+    .collect()
+}
+
+/// New permission bits from what the user typed: octal (`644`), all nine bits (`rw-r--r--`), or
+/// changes to `current` like chmod's (`u+x`, `go-w`, `a=r`)
+pub fn parse_mode(text: &str, current: u32) -> Option<u32> {
+  let text = text.trim();
+  if !text.is_empty() && text.len() <= 4 && text.chars().all(|c| c.is_digit(8)) {
+    return u32::from_str_radix(text, 8).ok();
+  }
+  if text.len() == 9 && text.chars().all(|c| "rwx-".contains(c)) {
+    let mut mode = current & 0o7000;
+    for (bit, c) in text.chars().enumerate() {
+      match c {
+        '-' => {}
+        c if c == ['r', 'w', 'x'][bit % 3] => mode |= 0o400 >> bit,
+        _ => return None,
       }
-    })
-    .map(|s| s.to_string())
-    .collect();
-  bufs.sort_by(|s1, s2| s1.to_lowercase().partial_cmp(&s2.to_lowercase()).unwrap());
-  bufs
+    }
+    return Some(mode);
+  }
+  let mut mode = current;
+  for clause in text.split(',') {
+    let op = clause.find(['+', '-', '='])?;
+    let (who, perms) = clause.split_at(op);
+    let mut mask = 0;
+    for c in who.chars() {
+      mask |= match c {
+        'u' => 0o700,
+        'g' => 0o070,
+        'o' => 0o007,
+        'a' => 0o777,
+        _ => return None,
+      };
+    }
+    if who.is_empty() {
+      mask = 0o777;
+    }
+    let mut bits = 0;
+    for c in perms[1..].chars() {
+      bits |= match c {
+        'r' => 0o444,
+        'w' => 0o222,
+        'x' => 0o111,
+        _ => return None,
+      };
+    }
+    match &perms[..1] {
+      "+" => mode |= bits & mask,
+      "-" => mode &= !(bits & mask),
+      _ => mode = (mode & !mask) | (bits & mask),
+    }
+  }
+  Some(mode)
 }
 
-#[derive(Debug)]
+/// Whether `text` fits `pattern`, ignoring case: `*` matches any run of characters, `?` exactly one
+pub fn wildcard(pattern: &[u8], text: &[u8]) -> bool {
+  match (pattern.first(), text.first()) {
+    (None, None) => true,
+    (Some(b'*'), _) => {
+      wildcard(&pattern[1..], text) || (!text.is_empty() && wildcard(pattern, &text[1..]))
+    }
+    (Some(b'?'), Some(_)) => wildcard(&pattern[1..], &text[1..]),
+    (Some(p), Some(t)) if p.eq_ignore_ascii_case(t) => wildcard(&pattern[1..], &text[1..]),
+    _ => false,
+  }
+}
+
+/// `text` cut down to at most `width` columns on screen, ending in `…` if anything had to go.
+/// Wide characters (e.g. CJK and emoji) take two columns, and accented letters built from
+/// several characters are never split up.
+pub fn truncate(text: &str, width: usize) -> String {
+  if text.width() <= width {
+    return text.to_string();
+  }
+  let mut truncated = String::new();
+  let mut used = 0;
+  for grapheme in text.graphemes(true) {
+    // Leaving a column for the ellipsis
+    if used + grapheme.width() + 1 > width {
+      break;
+    }
+    truncated.push_str(grapheme);
+    used += grapheme.width();
+  }
+  if width > 0 {
+    truncated.push('…');
+  }
+  truncated
+}
+
+/// `text` cut down to at most `width` columns on screen by taking out its middle, e.g.
+/// `/home/me/…/project/src`, so that both ends of a long path stay in view
+pub fn truncate_middle(text: &str, width: usize) -> String {
+  if text.width() <= width {
+    return text.to_string();
+  }
+  if width == 0 {
+    return String::new();
+  }
+  let graphemes: Vec<&str> = text.graphemes(true).collect();
+  // Leaving a column for the ellipsis, the end gets any column the start can't use
+  let room = width - 1;
+  let (mut head, mut head_width) = (0, 0);
+  while head < graphemes.len() && head_width + graphemes[head].width() <= room / 2 {
+    head_width += graphemes[head].width();
+    head += 1;
+  }
+  let (mut tail, mut tail_width) = (graphemes.len(), 0);
+  while tail > head && head_width + tail_width + graphemes[tail - 1].width() <= room {
+    tail -= 1;
+    tail_width += graphemes[tail].width();
+  }
+  format!(
+    "{}…{}",
+    graphemes[..head].concat(),
+    graphemes[tail..].concat()
+  )
+}
+
+/// `text` followed by enough spaces to take up `width` columns on screen
+pub fn pad(text: &str, width: usize) -> String {
+  let padding = width.saturating_sub(text.width());
+  format!("{text}{}", " ".repeat(padding))
+}
+
+/// This machine's name, as `hostname` gives it, or `localhost` if it won't say
+pub fn local_hostname() -> String {
+  Command::new("hostname")
+    .output()
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    .filter(|name| !name.is_empty())
+    .unwrap_or_else(|| String::from("localhost"))
+}
+
+/// Formats a number of bytes for humans, e.g. `1.2 GB`
+pub fn human_size(bytes: u64) -> String {
+  const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+  let mut size = bytes as f64;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{bytes} B")
+  } else {
+    format!("{size:.1} {}", UNITS[unit])
+  }
+}
+
+/// Formats an integer with thousands separators, e.g. `3,402`
+pub fn thousands(n: u64) -> String {
+  let digits = n.to_string();
+  let mut groups: Vec<&str> = Vec::new();
+  let mut end = digits.len();
+  while end > 3 {
+    groups.push(&digits[end - 3..end]);
+    end -= 3;
+  }
+  groups.push(&digits[..end]);
+  groups.reverse();
+  groups.join(",")
+}
+
+#[derive(Debug, Clone, Copy)]
 /// Whichever connection is 'active' (either the local or remote connections) will respond
 /// to user input. The other will be in a quiescent state.
 pub enum ActiveState {
@@ -124,3 +793,87 @@ impl Default for AppState {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn modes_are_octal_nine_bits_or_chmod_changes() {
+    assert_eq!(parse_mode("644", 0), Some(0o644));
+    assert_eq!(parse_mode(" 4755 ", 0), Some(0o4755));
+    assert_eq!(parse_mode("rw-r--r--", 0o4777), Some(0o4644));
+    assert_eq!(parse_mode("u+x", 0o644), Some(0o744));
+    assert_eq!(parse_mode("go-w", 0o666), Some(0o644));
+    assert_eq!(parse_mode("a=r", 0o755), Some(0o444));
+    assert_eq!(parse_mode("+x,o-r", 0o644), Some(0o751));
+    assert_eq!(parse_mode("u=", 0o755), Some(0o055));
+    for nonsense in [
+      "",
+      "888",
+      "12345",
+      "rw-r--r-x-",
+      "wr-r--r--",
+      "u",
+      "z+x",
+      "u+q",
+    ] {
+      assert_eq!(parse_mode(nonsense, 0o644), None, "{nonsense}");
+    }
+  }
+
+  #[test]
+  fn wildcards_match_runs_and_single_characters_ignoring_case() {
+    assert!(wildcard(b"*.rs", b"main.RS"));
+    assert!(wildcard(b"*", b""));
+    assert!(wildcard(b"a*b*c", b"aXXbYc"));
+    assert!(wildcard(b"file?.txt", b"file1.txt"));
+    assert!(!wildcard(b"file?.txt", b"file.txt"));
+    assert!(!wildcard(b"*.rs", b"main.rs.bak"));
+    assert!(!wildcard(b"", b"a"));
+  }
+
+  #[test]
+  fn truncating_counts_columns_not_characters() {
+    assert_eq!(truncate("short", 10), "short");
+    assert_eq!(truncate("abcdefgh", 5), "abcd…");
+    // wide characters take two columns, so one that won't fit whole is left out
+    assert_eq!(truncate("日本語テキスト", 4), "日…");
+    assert_eq!(truncate("日本語テキスト", 5), "日本…");
+    // an accent built from a combining character stays with its letter
+    assert_eq!(truncate("cafe\u{301}s and more", 5), "cafe\u{301}…");
+    assert_eq!(truncate("abc", 1), "…");
+    assert_eq!(truncate("abc", 0), "");
+  }
+
+  #[test]
+  fn truncating_the_middle_keeps_both_ends() {
+    assert_eq!(truncate_middle("/home/me", 10), "/home/me");
+    assert_eq!(truncate_middle("abcdefghij", 5), "ab…ij");
+    assert_eq!(truncate_middle("日本語テキスト", 6), "日…ト");
+    assert_eq!(
+      truncate_middle("e\u{301}tude/re\u{301}sume\u{301}", 5),
+      "e\u{301}t…me\u{301}"
+    );
+    assert_eq!(truncate_middle("abc", 1), "…");
+    assert_eq!(truncate_middle("abc", 0), "");
+    for width in 0..16 {
+      assert!(truncate_middle("/srv/日本語/テキスト", width).width() <= width);
+    }
+  }
+
+  #[test]
+  fn sizes_and_counts_read_easily() {
+    assert_eq!(human_size(0), "0 B");
+    assert_eq!(human_size(1023), "1023 B");
+    assert_eq!(human_size(1024), "1.0 KB");
+    assert_eq!(human_size(1536), "1.5 KB");
+    assert_eq!(human_size(5 << 30), "5.0 GB");
+    assert_eq!(human_size(u64::MAX), "16777216.0 TB");
+    assert_eq!(thousands(0), "0");
+    assert_eq!(thousands(999), "999");
+    assert_eq!(thousands(1000), "1,000");
+    assert_eq!(thousands(1234567), "1,234,567");
+    assert_eq!(thousands(u64::MAX), "18,446,744,073,709,551,615");
+  }
+}
@@ -0,0 +1,37 @@
+//! How logging in asks the user what it has to (a password, a server's questions, or whether to
+//! trust a new host key): that's up to the program using gsftp, which the `gsftp` binary does on
+//! the terminal before its interface starts. Until a program says how, nothing is asked, and
+//! whatever needed an answer fails.
+use std::io;
+use std::sync::OnceLock;
+
+/// Somewhere to put questions to the user
+pub trait Ask: Send + Sync {
+  /// Shows `text`, e.g. a server's instructions or a host key's fingerprint
+  fn tell(&self, text: &str);
+  /// The user's answer to `question`, which isn't shown as it's typed if it's `secret`
+  fn ask(&self, question: &str, secret: bool) -> io::Result<String>;
+}
+
+static ASKER: OnceLock<Box<dyn Ask>> = OnceLock::new();
+
+/// Puts every question from now on to `asker`. Only the first one given is used.
+pub fn set(asker: Box<dyn Ask>) {
+  let _ = ASKER.set(asker);
+}
+
+pub(crate) fn tell(text: &str) {
+  if let Some(asker) = ASKER.get() {
+    asker.tell(text);
+  }
+}
+
+pub(crate) fn ask(question: &str, secret: bool) -> io::Result<String> {
+  match ASKER.get() {
+    Some(asker) => asker.ask(question, secret),
+    None => Err(io::Error::new(
+      io::ErrorKind::Unsupported,
+      "there's no one to ask",
+    )),
+  }
+}
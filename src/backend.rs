@@ -0,0 +1,820 @@
+//! Files wherever they are: on the local disk (`Local`), on an SFTP server (`ssh2::Sftp`), or in
+//! memory (`Memory`). Anything written against `FsBackend`, like `App`'s panes, transfers, and
+//! `ls`, `mkdir_all`, `remove_all` and `copy_all` here, works the same on each of them, so it can
+//! be tried out on `Memory` without a disk to clean up after or a server to log in to.
+use ssh2::{FileStat, OpenFlags, OpenType, Sftp};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{debug, trace, warn};
+
+use crate::app_utils::{Entry, EntryKind};
+use crate::error::GsftpError;
+use crate::remote_path;
+use crate::transform::Sink;
+
+/// What gsftp needs to do with files, on whichever side they're on
+pub trait FsBackend {
+  /// The entries in `dir`, in no particular order and without `.` and `..`. Symlinks are
+  /// described, not followed.
+  fn list(&self, dir: &Path) -> Result<Vec<Entry>, GsftpError>;
+  /// What's at `path`, following symlinks
+  fn stat(&self, path: &Path) -> Result<Entry, GsftpError>;
+  /// What's at `path`, describing a symlink rather than following it
+  fn lstat(&self, path: &Path) -> Result<Entry, GsftpError>;
+  fn read(&self, path: &Path) -> Result<Box<dyn Read + '_>, GsftpError>;
+  /// Like `read`, but starting `offset` bytes in, e.g. to retry whatever's left of a part
+  fn read_from(&self, path: &Path, offset: u64) -> Result<Box<dyn Read + '_>, GsftpError>;
+  /// Creates the file `path` with the permission bits `perm`, or empties it if it's there
+  /// already, keeping the permissions it has. Yields it to be written to (and finished, so no
+  /// failure goes unnoticed).
+  fn write(&self, path: &Path, perm: u32) -> Result<Box<dyn Sink>, GsftpError>;
+  /// Creates the directory `path`, whose parent has to be there already
+  fn mkdir(&self, path: &Path, perm: u32) -> Result<(), GsftpError>;
+  /// Creates `link`, pointing at `target`
+  fn symlink(&self, target: &Path, link: &Path) -> Result<(), GsftpError>;
+  fn rename(&self, from: &Path, to: &Path) -> Result<(), GsftpError>;
+  /// Removes a file, a symlink or an empty directory
+  fn remove(&self, path: &Path) -> Result<(), GsftpError>;
+  /// Where `path` really is: absolute, with every symlink along the way followed
+  fn realpath(&self, path: &Path) -> Result<PathBuf, GsftpError>;
+  /// Sets the permission bits of `path`, following a symlink
+  fn chmod(&self, path: &Path, perm: u32) -> Result<(), GsftpError>;
+
+  /// `dir` with `name` on the end, the way this side writes paths
+  fn join(&self, dir: &Path, name: &OsStr) -> PathBuf {
+    dir.join(name)
+  }
+}
+
+// `App` keeps its local side as one of these, and has to be `Debug`
+impl fmt::Debug for dyn FsBackend {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("FsBackend")
+  }
+}
+
+/// The entries in `dir` the way `ls` shows them: ordered by name whatever the case, and
+/// without hidden ones unless `show_hidden`
+pub fn ls(fs: &dyn FsBackend, dir: &Path, show_hidden: bool) -> Result<Vec<Entry>, GsftpError> {
+  let mut entries: Vec<Entry> = fs
+    .list(dir)?
+    .into_iter()
+    .filter(|e| show_hidden || !e.name.starts_with('.'))
+    .collect();
+  entries.sort_by_key(|e| e.name.to_lowercase());
+  Ok(entries)
+}
+
+/// Like `mkdir -p`: creates `path` along with any missing parent directories
+pub fn mkdir_all(fs: &dyn FsBackend, path: &Path) -> Result<(), GsftpError> {
+  debug!(path = %path.display(), "mkdir -p");
+  let mut dir = PathBuf::new();
+  for component in path.components() {
+    dir.push(component);
+    match fs.stat(&dir) {
+      Ok(entry) if entry.kind == EntryKind::Dir => {}
+      Ok(_) => return Err(format!("{} exists and is not a directory", dir.display()).into()),
+      Err(_) => fs.mkdir(&dir, 0o755)?,
+    }
+  }
+
+  Ok(())
+}
+
+/// Like `rm -r`: removes `path`, and everything in it if it's a directory.
+/// Symbolic links are removed themselves, never followed.
+pub fn remove_all(fs: &dyn FsBackend, path: &Path) -> Result<(), GsftpError> {
+  debug!(path = %path.display(), "remove");
+  if fs.lstat(path)?.kind == EntryKind::Dir {
+    for child in fs.list(path)? {
+      remove_all(fs, &fs.join(path, &child.file_name))?;
+    }
+  }
+  fs.remove(path)
+}
+
+/// Copies `from` on one side to `to` on another (or the same) side, like `cp -Rp`: directories
+/// with everything in them, symlinks as links, and each with its permissions
+pub fn copy_all(
+  from_fs: &dyn FsBackend,
+  from: &Path,
+  to_fs: &dyn FsBackend,
+  to: &Path,
+) -> Result<(), GsftpError> {
+  let entry = from_fs.lstat(from)?;
+  let perm = entry.perm.unwrap_or(match entry.kind {
+    EntryKind::Dir => 0o755,
+    _ => 0o644,
+  });
+  match entry.kind {
+    EntryKind::Symlink => {
+      let target = entry.target.unwrap_or_default();
+      to_fs.symlink(Path::new(&target), to)?;
+    }
+    EntryKind::Dir => {
+      to_fs.mkdir(to, perm)?;
+      for child in from_fs.list(from)? {
+        copy_all(
+          from_fs,
+          &from_fs.join(from, &child.file_name),
+          to_fs,
+          &to_fs.join(to, &child.file_name),
+        )?;
+      }
+    }
+    _ => {
+      let mut reader = from_fs.read(from)?;
+      let mut writer = to_fs.write(to, perm)?;
+      io::copy(&mut reader, &mut writer)?;
+      writer.finish()?;
+    }
+  }
+  Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// The local disk
+pub struct Local;
+
+impl FsBackend for Local {
+  fn list(&self, dir: &Path) -> Result<Vec<Entry>, GsftpError> {
+    let mut entries = Vec::new();
+    for child in fs::read_dir(dir)? {
+      entries.extend(Entry::from_path(&child?.path()));
+    }
+    Ok(entries)
+  }
+
+  fn stat(&self, path: &Path) -> Result<Entry, GsftpError> {
+    let meta = fs::metadata(path)?;
+    Ok(Entry::from_metadata(file_name(path), Some(&meta)))
+  }
+
+  fn lstat(&self, path: &Path) -> Result<Entry, GsftpError> {
+    // for the error, if there's nothing there
+    fs::symlink_metadata(path)?;
+    Entry::from_path(path).ok_or_else(|| format!("{} has no name", path.display()).into())
+  }
+
+  fn read(&self, path: &Path) -> Result<Box<dyn Read + '_>, GsftpError> {
+    Ok(Box::new(fs::File::open(path)?))
+  }
+
+  fn read_from(&self, path: &Path, offset: u64) -> Result<Box<dyn Read + '_>, GsftpError> {
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    Ok(Box::new(file))
+  }
+
+  fn write(&self, path: &Path, perm: u32) -> Result<Box<dyn Sink>, GsftpError> {
+    let new = fs::metadata(path).is_err();
+    let file = fs::File::create(path)?;
+    if new {
+      if let Err(e) = set_local_perm(path, perm) {
+        warn!(path = %path.display(), "couldn't set permissions: {e}");
+      }
+    }
+    Ok(Box::new(file))
+  }
+
+  fn mkdir(&self, path: &Path, perm: u32) -> Result<(), GsftpError> {
+    fs::create_dir(path)?;
+    set_local_perm(path, perm)?;
+    Ok(())
+  }
+
+  fn symlink(&self, target: &Path, link: &Path) -> Result<(), GsftpError> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link)?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_file(target, link)?;
+    Ok(())
+  }
+
+  fn rename(&self, from: &Path, to: &Path) -> Result<(), GsftpError> {
+    Ok(fs::rename(from, to)?)
+  }
+
+  fn remove(&self, path: &Path) -> Result<(), GsftpError> {
+    match fs::symlink_metadata(path)?.is_dir() {
+      true => fs::remove_dir(path)?,
+      false => fs::remove_file(path)?,
+    }
+    Ok(())
+  }
+
+  fn realpath(&self, path: &Path) -> Result<PathBuf, GsftpError> {
+    Ok(fs::canonicalize(path)?)
+  }
+
+  #[cfg(unix)]
+  fn chmod(&self, path: &Path, perm: u32) -> Result<(), GsftpError> {
+    Ok(set_local_perm(path, perm)?)
+  }
+
+  #[cfg(not(unix))]
+  fn chmod(&self, _path: &Path, _perm: u32) -> Result<(), GsftpError> {
+    Err("permission bits can only be set on Unix".into())
+  }
+}
+
+#[cfg(unix)]
+fn set_local_perm(path: &Path, perm: u32) -> io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+  fs::set_permissions(path, fs::Permissions::from_mode(perm))
+}
+
+// Windows has no permission bits to speak of
+#[cfg(not(unix))]
+fn set_local_perm(_path: &Path, _perm: u32) -> io::Result<()> {
+  Ok(())
+}
+
+fn file_name(path: &Path) -> OsString {
+  path.file_name().unwrap_or_default().to_os_string()
+}
+
+// The SFTP server, whose paths are always separated by `/`
+impl FsBackend for Sftp {
+  fn list(&self, dir: &Path) -> Result<Vec<Entry>, GsftpError> {
+    trace!(path = %dir.display(), "readdir");
+    Ok(
+      self
+        .readdir(dir)?
+        .iter()
+        .map(|(path, stat)| remote_entry(self, path, stat))
+        .collect(),
+    )
+  }
+
+  fn stat(&self, path: &Path) -> Result<Entry, GsftpError> {
+    Ok(Entry::from_stat(file_name(path), &Sftp::stat(self, path)?))
+  }
+
+  fn lstat(&self, path: &Path) -> Result<Entry, GsftpError> {
+    Ok(remote_entry(self, path, &Sftp::lstat(self, path)?))
+  }
+
+  fn read(&self, path: &Path) -> Result<Box<dyn Read + '_>, GsftpError> {
+    Ok(Box::new(self.open(path)?))
+  }
+
+  fn read_from(&self, path: &Path, offset: u64) -> Result<Box<dyn Read + '_>, GsftpError> {
+    let mut file = self.open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    Ok(Box::new(file))
+  }
+
+  fn write(&self, path: &Path, perm: u32) -> Result<Box<dyn Sink>, GsftpError> {
+    let new = Sftp::stat(self, path).is_err();
+    let flags = OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE;
+    let file = self.open_mode(path, flags, perm as i32, OpenType::File)?;
+    // The server's umask would have its way with the mode given when opening. Some servers
+    // refuse SETSTAT, though, and the file's no less written for it.
+    if new {
+      if let Err(e) = FsBackend::chmod(self, path, perm) {
+        warn!(path = %path.display(), "couldn't set permissions: {e}");
+      }
+    }
+    Ok(Box::new(file))
+  }
+
+  fn mkdir(&self, path: &Path, perm: u32) -> Result<(), GsftpError> {
+    Ok(Sftp::mkdir(self, path, perm as i32)?)
+  }
+
+  fn symlink(&self, target: &Path, link: &Path) -> Result<(), GsftpError> {
+    // ssh2 accounts for OpenSSH swapping the arguments
+    Ok(Sftp::symlink(self, target, link)?)
+  }
+
+  fn rename(&self, from: &Path, to: &Path) -> Result<(), GsftpError> {
+    Ok(Sftp::rename(self, from, to, None)?)
+  }
+
+  fn remove(&self, path: &Path) -> Result<(), GsftpError> {
+    match Sftp::lstat(self, path)?.file_type().is_dir() {
+      true => self.rmdir(path)?,
+      false => self.unlink(path)?,
+    }
+    Ok(())
+  }
+
+  fn realpath(&self, path: &Path) -> Result<PathBuf, GsftpError> {
+    Ok(Sftp::realpath(self, path)?)
+  }
+
+  fn chmod(&self, path: &Path, perm: u32) -> Result<(), GsftpError> {
+    let stat = FileStat {
+      size: None,
+      uid: None,
+      gid: None,
+      perm: Some(perm),
+      atime: None,
+      mtime: None,
+    };
+    Ok(self.setstat(path, stat)?)
+  }
+
+  fn join(&self, dir: &Path, name: &OsStr) -> PathBuf {
+    remote_path::join(dir, name)
+  }
+}
+
+// An entry for what `readdir` or `lstat` said about the remote `path`, with a symlink's target
+fn remote_entry(sftp: &Sftp, path: &Path, stat: &FileStat) -> Entry {
+  let mut entry = Entry::from_stat(file_name(path), stat);
+  if entry.kind == EntryKind::Symlink {
+    entry.target = sftp
+      .readlink(path)
+      .ok()
+      .map(|target| target.display().to_string());
+  }
+  entry
+}
+
+#[derive(Debug, Clone)]
+// What's kept at each path in `Memory`
+enum Node {
+  File { data: Vec<u8>, perm: u32 },
+  Dir { perm: u32 },
+  Symlink { target: PathBuf },
+}
+
+#[derive(Debug)]
+/// Files kept in memory, under `/` and separated by `/` as on a server, for trying out code
+/// written against `FsBackend`. Modification times aren't kept.
+pub struct Memory {
+  nodes: Arc<Mutex<BTreeMap<PathBuf, Node>>>,
+}
+
+impl Default for Memory {
+  fn default() -> Self {
+    let root = (PathBuf::from("/"), Node::Dir { perm: 0o755 });
+    Self {
+      nodes: Arc::new(Mutex::new(BTreeMap::from([root]))),
+    }
+  }
+}
+
+impl Memory {
+  /// Nothing but an empty `/`
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // Everything there is, for one thing at a time to look at or change
+  fn nodes(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+    self.nodes.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  // Where `path` really is, with the symlinks along the way followed (and the one it ends in, if
+  // `follow`), giving up on loops like the OS does. Relative paths are taken from `/`.
+  fn resolve(&self, path: &Path, follow: bool) -> Result<PathBuf, GsftpError> {
+    let nodes = self.nodes();
+    let mut resolved = PathBuf::from("/");
+    // what's left to walk, last first
+    let mut rest: Vec<OsString> = path
+      .iter()
+      .rev()
+      .filter(|name| *name != "/")
+      .map(OsStr::to_os_string)
+      .collect();
+    let mut links = 0;
+    while let Some(name) = rest.pop() {
+      if name == "." {
+        continue;
+      }
+      if name == ".." {
+        resolved.pop();
+        continue;
+      }
+      let next = remote_path::join(&resolved, &name);
+      match nodes.get(&next) {
+        Some(Node::Symlink { target }) if follow || !rest.is_empty() => {
+          links += 1;
+          if links > 40 {
+            return Err(format!("Too many levels of symbolic links: {}", path.display()).into());
+          }
+          if target.is_absolute() {
+            resolved = PathBuf::from("/");
+          }
+          rest.extend(
+            target
+              .iter()
+              .rev()
+              .filter(|name| *name != "/")
+              .map(OsStr::to_os_string),
+          );
+        }
+        _ => resolved = next,
+      }
+    }
+    Ok(resolved)
+  }
+
+  // What's at the resolved `path`, described as an entry called by the last part of `name`
+  fn entry(&self, name: &Path, path: &Path) -> Result<Entry, GsftpError> {
+    let nodes = self.nodes();
+    let file_name = file_name(name);
+    let (kind, size, perm, target) = match nodes.get(path).ok_or_else(|| not_found(name))? {
+      Node::File { data, perm } => (EntryKind::File, Some(data.len() as u64), *perm, None),
+      Node::Dir { perm } => (EntryKind::Dir, None, *perm, None),
+      Node::Symlink { target } => (
+        EntryKind::Symlink,
+        None,
+        0o777,
+        Some(target.display().to_string()),
+      ),
+    };
+    Ok(Entry {
+      name: file_name.to_string_lossy().into_owned(),
+      file_name,
+      kind,
+      size,
+      mtime: None,
+      perm: Some(perm),
+      owner: None,
+      target,
+    })
+  }
+
+  // Whether the resolved `path` can have something put there: its directory is, and it isn't
+  fn check_vacant(&self, path: &Path) -> Result<(), GsftpError> {
+    let nodes = self.nodes();
+    let parent = path.parent().ok_or_else(|| already_exists(path))?;
+    if !matches!(nodes.get(parent), Some(Node::Dir { .. })) {
+      return Err(format!("{} is not a directory", parent.display()).into());
+    }
+    if nodes.contains_key(path) {
+      return Err(already_exists(path));
+    }
+    Ok(())
+  }
+
+  // Puts `node` at `path`, which mustn't be there yet, in a directory which must be
+  fn create(&self, path: &Path, node: Node) -> Result<PathBuf, GsftpError> {
+    let path = self.resolve(path, false)?;
+    self.check_vacant(&path)?;
+    self.nodes().insert(path.clone(), node);
+    Ok(path)
+  }
+}
+
+fn not_found(path: &Path) -> GsftpError {
+  io::Error::new(io::ErrorKind::NotFound, path.display().to_string()).into()
+}
+
+fn already_exists(path: &Path) -> GsftpError {
+  io::Error::new(io::ErrorKind::AlreadyExists, path.display().to_string()).into()
+}
+
+// A file in `Memory` being written, a write at a time
+struct MemoryFile {
+  nodes: Arc<Mutex<BTreeMap<PathBuf, Node>>>,
+  path: PathBuf,
+}
+
+impl Write for MemoryFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let mut nodes = self.nodes.lock().unwrap_or_else(|e| e.into_inner());
+    match nodes.get_mut(&self.path) {
+      Some(Node::File { data, .. }) => {
+        data.extend_from_slice(buf);
+        Ok(buf.len())
+      }
+      _ => Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        self.path.display().to_string(),
+      )),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Sink for MemoryFile {
+  fn finish(self: Box<Self>) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+impl FsBackend for Memory {
+  fn list(&self, dir: &Path) -> Result<Vec<Entry>, GsftpError> {
+    let dir = self.resolve(dir, true)?;
+    if !matches!(self.nodes().get(&dir), Some(Node::Dir { .. })) {
+      return Err(format!("{} is not a directory", dir.display()).into());
+    }
+    let children: Vec<PathBuf> = self
+      .nodes()
+      .keys()
+      .filter(|path| path.parent() == Some(dir.as_path()))
+      .cloned()
+      .collect();
+    children.iter().map(|path| self.entry(path, path)).collect()
+  }
+
+  fn stat(&self, path: &Path) -> Result<Entry, GsftpError> {
+    self.entry(path, &self.resolve(path, true)?)
+  }
+
+  fn lstat(&self, path: &Path) -> Result<Entry, GsftpError> {
+    self.entry(path, &self.resolve(path, false)?)
+  }
+
+  fn read(&self, path: &Path) -> Result<Box<dyn Read + '_>, GsftpError> {
+    self.read_from(path, 0)
+  }
+
+  fn read_from(&self, path: &Path, offset: u64) -> Result<Box<dyn Read + '_>, GsftpError> {
+    let real = self.resolve(path, true)?;
+    match self.nodes().get(&real) {
+      Some(Node::File { data, .. }) => {
+        let mut reader = Cursor::new(data.clone());
+        reader.set_position(offset);
+        Ok(Box::new(reader))
+      }
+      Some(_) => Err(format!("{} is not a file", path.display()).into()),
+      None => Err(not_found(path)),
+    }
+  }
+
+  fn write(&self, path: &Path, perm: u32) -> Result<Box<dyn Sink>, GsftpError> {
+    let real = self.resolve(path, true)?;
+    let existing = self.nodes().get(&real).cloned();
+    match existing {
+      Some(Node::File { perm, .. }) => {
+        let empty = Node::File {
+          data: Vec::new(),
+          perm,
+        };
+        self.nodes().insert(real.clone(), empty);
+      }
+      Some(_) => return Err(format!("{} is not a file", path.display()).into()),
+      None => {
+        let empty = Node::File {
+          data: Vec::new(),
+          perm,
+        };
+        self.create(&real, empty)?;
+      }
+    }
+    Ok(Box::new(MemoryFile {
+      nodes: Arc::clone(&self.nodes),
+      path: real,
+    }))
+  }
+
+  fn mkdir(&self, path: &Path, perm: u32) -> Result<(), GsftpError> {
+    self.create(path, Node::Dir { perm }).map(|_| ())
+  }
+
+  fn symlink(&self, target: &Path, link: &Path) -> Result<(), GsftpError> {
+    let target = target.to_path_buf();
+    self.create(link, Node::Symlink { target }).map(|_| ())
+  }
+
+  fn rename(&self, from: &Path, to: &Path) -> Result<(), GsftpError> {
+    let (from, to) = (self.resolve(from, false)?, self.resolve(to, false)?);
+    if !self.nodes().contains_key(&from) {
+      return Err(not_found(&from));
+    }
+    if to.starts_with(&from) {
+      return Err(format!("Can't move {} into itself", from.display()).into());
+    }
+    self.check_vacant(&to)?;
+    let mut nodes = self.nodes();
+    let moved: Vec<PathBuf> = nodes
+      .keys()
+      .filter(|path| path.starts_with(&from))
+      .cloned()
+      .collect();
+    for path in moved {
+      if let Some(node) = nodes.remove(&path) {
+        let rest = path.strip_prefix(&from).unwrap_or(Path::new(""));
+        nodes.insert(remote_path::join(&to, rest), node);
+      }
+    }
+    Ok(())
+  }
+
+  fn remove(&self, path: &Path) -> Result<(), GsftpError> {
+    let path = self.resolve(path, false)?;
+    let mut nodes = self.nodes();
+    match nodes.get(&path) {
+      None => return Err(not_found(&path)),
+      Some(Node::Dir { .. }) if nodes.keys().any(|p| p.parent() == Some(&path)) => {
+        return Err(format!("{} is not empty", path.display()).into())
+      }
+      Some(_) => {}
+    }
+    nodes.remove(&path);
+    Ok(())
+  }
+
+  fn realpath(&self, path: &Path) -> Result<PathBuf, GsftpError> {
+    let real = self.resolve(path, true)?;
+    match self.nodes().contains_key(&real) {
+      true => Ok(real),
+      false => Err(not_found(path)),
+    }
+  }
+
+  fn chmod(&self, path: &Path, perm: u32) -> Result<(), GsftpError> {
+    let real = self.resolve(path, true)?;
+    match self.nodes().get_mut(&real) {
+      Some(Node::File { perm: bits, .. } | Node::Dir { perm: bits }) => *bits = perm,
+      // a link that's still a link here leads nowhere
+      Some(Node::Symlink { .. }) | None => return Err(not_found(path)),
+    }
+    Ok(())
+  }
+
+  fn join(&self, dir: &Path, name: &OsStr) -> PathBuf {
+    remote_path::join(dir, name)
+  }
+}
+
+#[cfg(test)]
+// Files for tests here and in the modules written against `FsBackend` to try things out on
+pub(crate) mod fixtures {
+  use super::*;
+
+  // Writes `text` to `path`, making the directories above it first
+  pub(crate) fn put(fs: &dyn FsBackend, path: &str, text: &str) {
+    let path = Path::new(path);
+    mkdir_all(fs, path.parent().unwrap()).unwrap();
+    let mut file = fs.write(path, 0o644).unwrap();
+    file.write_all(text.as_bytes()).unwrap();
+    file.finish().unwrap();
+  }
+
+  pub(crate) fn contents(fs: &dyn FsBackend, path: &str) -> String {
+    let mut text = String::new();
+    fs.read(Path::new(path))
+      .unwrap()
+      .read_to_string(&mut text)
+      .unwrap();
+    text
+  }
+
+  pub(crate) fn names(entries: &[Entry]) -> Vec<&str> {
+    entries.iter().map(|e| e.name.as_str()).collect()
+  }
+
+  // `/src` with a couple of files, a hidden one, a subdirectory, a link to it and a link from
+  // it back up to `/src`
+  pub(crate) fn tree() -> Memory {
+    let fs = Memory::new();
+    put(&fs, "/src/A.txt", "ay");
+    put(&fs, "/src/b.txt", "bee");
+    put(&fs, "/src/.hidden", "shh");
+    put(&fs, "/src/lib/c.rs", "fn main() {}");
+    fs.symlink(Path::new("lib"), Path::new("/src/link"))
+      .unwrap();
+    fs.symlink(Path::new(".."), Path::new("/src/lib/up"))
+      .unwrap();
+    fs.chmod(Path::new("/src"), 0o750).unwrap();
+    fs.chmod(Path::new("/src/b.txt"), 0o640).unwrap();
+    fs
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::fixtures::{contents, names, tree};
+  use super::*;
+
+  #[test]
+  fn ls_sorts_by_name_and_hides_dotfiles() {
+    let fs = tree();
+    let shown = ls(&fs, Path::new("/src"), false).unwrap();
+    assert_eq!(names(&shown), ["A.txt", "b.txt", "lib", "link"]);
+    let all = ls(&fs, Path::new("/src"), true).unwrap();
+    assert_eq!(names(&all), [".hidden", "A.txt", "b.txt", "lib", "link"]);
+    assert!(ls(&fs, Path::new("/missing"), true).is_err());
+    assert!(ls(&fs, Path::new("/src/b.txt"), true).is_err());
+  }
+
+  #[test]
+  fn entries_say_what_they_are() {
+    let fs = tree();
+    let file = fs.lstat(Path::new("/src/b.txt")).unwrap();
+    assert_eq!(
+      (file.kind, file.size, file.perm),
+      (EntryKind::File, Some(3), Some(0o640))
+    );
+    let link = fs.lstat(Path::new("/src/link")).unwrap();
+    assert_eq!(link.kind, EntryKind::Symlink);
+    assert_eq!(link.target.as_deref(), Some("lib"));
+    // the link is followed into the directory it points at
+    assert_eq!(
+      fs.stat(Path::new("/src/link")).unwrap().kind,
+      EntryKind::Dir
+    );
+    let through = ls(&fs, Path::new("/src/link"), true).unwrap();
+    assert_eq!(names(&through), ["c.rs", "up"]);
+  }
+
+  #[test]
+  fn mkdir_all_makes_the_missing_parents() {
+    let fs = tree();
+    mkdir_all(&fs, Path::new("/src/lib/deep/er")).unwrap();
+    assert_eq!(
+      fs.stat(Path::new("/src/lib/deep/er")).unwrap().kind,
+      EntryKind::Dir
+    );
+    // already there, or through a link to a directory, is fine
+    mkdir_all(&fs, Path::new("/src/lib")).unwrap();
+    mkdir_all(&fs, Path::new("/src/link/new")).unwrap();
+    assert!(fs.stat(Path::new("/src/lib/new")).is_ok());
+    // but not through a file
+    assert!(mkdir_all(&fs, Path::new("/src/b.txt/sub")).is_err());
+  }
+
+  #[test]
+  fn remove_all_takes_everything_under_it_but_not_what_links_point_to() {
+    let fs = tree();
+    fs.symlink(Path::new("/src/lib"), Path::new("/outside"))
+      .unwrap();
+    remove_all(&fs, Path::new("/outside")).unwrap();
+    assert!(fs.lstat(Path::new("/src/lib/c.rs")).is_ok());
+    assert!(fs.remove(Path::new("/src")).is_err());
+    remove_all(&fs, Path::new("/src")).unwrap();
+    assert!(ls(&fs, Path::new("/"), true).unwrap().is_empty());
+  }
+
+  #[test]
+  fn copy_all_copies_a_tree_between_backends() {
+    let (from, to) = (tree(), Memory::new());
+    copy_all(&from, Path::new("/src"), &to, Path::new("/dest")).unwrap();
+    let copied = ls(&to, Path::new("/dest"), true).unwrap();
+    assert_eq!(names(&copied), [".hidden", "A.txt", "b.txt", "lib", "link"]);
+    assert_eq!(contents(&to, "/dest/lib/c.rs"), "fn main() {}");
+    assert_eq!(to.stat(Path::new("/dest")).unwrap().perm, Some(0o750));
+    assert_eq!(to.stat(Path::new("/dest/b.txt")).unwrap().perm, Some(0o640));
+    // links stay links, pointing the same way
+    let link = to.lstat(Path::new("/dest/link")).unwrap();
+    assert_eq!(link.target.as_deref(), Some("lib"));
+    // nothing's replaced
+    assert!(copy_all(&from, Path::new("/src"), &to, Path::new("/dest")).is_err());
+  }
+
+  #[test]
+  fn rename_moves_what_is_inside() {
+    let fs = tree();
+    fs.rename(Path::new("/src/lib"), Path::new("/lib")).unwrap();
+    assert_eq!(contents(&fs, "/lib/c.rs"), "fn main() {}");
+    assert!(fs.lstat(Path::new("/src/lib")).is_err());
+    assert!(fs
+      .rename(Path::new("/src/b.txt"), Path::new("/src/A.txt"))
+      .is_err());
+    assert!(fs
+      .rename(Path::new("/src/b.txt"), Path::new("/nowhere/b.txt"))
+      .is_err());
+  }
+
+  #[test]
+  fn writing_again_replaces_the_contents_but_not_the_permissions() {
+    let fs = tree();
+    fs.chmod(Path::new("/src/b.txt"), 0o755).unwrap();
+    fs.write(Path::new("/src/b.txt"), 0o600)
+      .unwrap()
+      .write_all(b"new")
+      .unwrap();
+    assert_eq!(contents(&fs, "/src/b.txt"), "new");
+    let overwritten = fs.stat(Path::new("/src/b.txt")).unwrap();
+    assert_eq!(overwritten.perm, Some(0o755));
+    fs.write(Path::new("/src/new.txt"), 0o600).unwrap();
+    let created = fs.stat(Path::new("/src/new.txt")).unwrap();
+    assert_eq!(created.perm, Some(0o600));
+    assert!(fs.write(Path::new("/src/lib"), 0o600).is_err());
+    assert!(fs.write(Path::new("/missing/file"), 0o600).is_err());
+  }
+
+  #[test]
+  fn local_disk_does_the_same() {
+    let dir = std::env::temp_dir().join(format!("gsftp-backend-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    let memory = tree();
+    copy_all(&memory, Path::new("/src"), &Local, &dir.join("src")).unwrap();
+    let copied = ls(&Local, &dir.join("src"), false).unwrap();
+    assert_eq!(names(&copied), ["A.txt", "b.txt", "lib", "link"]);
+    assert_eq!(
+      contents(&Local, &dir.join("src/lib/c.rs").to_string_lossy()),
+      "fn main() {}"
+    );
+    mkdir_all(&Local, &dir.join("src/link/deeper")).unwrap();
+    assert!(dir.join("src/lib/deeper").is_dir());
+    remove_all(&Local, &dir).unwrap();
+    assert!(!dir.exists());
+  }
+}
@@ -0,0 +1,296 @@
+//! `gsftp batch`: runs the commands in a file on one connection, without the interface, the
+//! way `sftp -b` does, e.g.
+//!
+//! ```text
+//! # deploy the site
+//! lcd build
+//! cd /var/www
+//! -mkdir html
+//! sync . html
+//! rm html/.DS_Store
+//! ```
+//!
+//! One command to a line, with blank lines and `#` comments skipped; names with spaces in them
+//! go in quotes. The first command that fails stops the rest, unless it starts with `-`.
+//! Relative remote paths are under the remote directory, which starts out wherever the
+//! destination says, and local ones are under the local directory, which starts out as the
+//! current one.
+use clap::ArgMatches;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use gsftp::app_utils::{self, Comparison, Entry, EntryKind};
+use gsftp::backend;
+use gsftp::config::Config;
+use gsftp::file_transfer::{Transfer, TransferKind, TransferOptions};
+use gsftp::headless::{self, FAILED};
+use gsftp::remote_path;
+use gsftp::session::{PooledSftp, SessionPool};
+use gsftp::settings::Settings;
+use gsftp::sftp;
+
+use crate::get_put;
+
+/// Every command, and what it takes
+#[rustfmt::skip]
+pub const COMMANDS: [(&str, &str); 8] = [
+  ("cd",    "cd <remote dir>"),
+  ("lcd",   "lcd <local dir>"),
+  ("get",   "get <remote path> [local path]"),
+  ("put",   "put <local path> [remote path]"),
+  ("mkdir", "mkdir <remote dir>"),
+  ("rm",    "rm [-r] <remote path>"),
+  ("sync",  "sync [local dir] [remote dir]"),
+  ("pwd",   "pwd"),
+];
+
+// Where the commands have got to, on the connection they run on
+struct Batch {
+  pool: Arc<SessionPool>,
+  sftp: PooledSftp,
+  remote: PathBuf,
+  local: PathBuf,
+  opts: TransferOptions,
+  quiet: bool,
+  progress: bool,
+}
+
+/// Runs `gsftp batch` with the subcommand's `args`, and yields the exit status: 0 if every
+/// command worked, or else the same as `gsftp get`'s
+pub fn run(args: &ArgMatches, settings: &Settings) -> i32 {
+  match batch(args, settings) {
+    Ok(()) => 0,
+    Err((status, e)) => {
+      eprintln!("{e}");
+      status
+    }
+  }
+}
+
+fn batch(args: &ArgMatches, settings: &Settings) -> Result<(), (i32, String)> {
+  // `-` is standard input, as for `sftp -b`
+  let file = args.value_of("BATCHFILE").unwrap();
+  let mut text = String::new();
+  match file {
+    "-" => io::stdin().read_to_string(&mut text).map(|_| ()),
+    _ => fs::read_to_string(file).map(|file_text| text = file_text),
+  }
+  .map_err(|e| (FAILED, format!("Couldn't read batch file {file}: {e}")))?;
+  let destination = args.value_of("DESTINATION").unwrap();
+  let conf =
+    Config::new(settings.destination(destination), args).map_err(|e| (FAILED, e.to_string()))?;
+  if args.is_present("no-exec") {
+    sftp::disable_exec();
+  }
+  if args.is_present("forward-agent") {
+    sftp::forward_agent();
+  }
+  let pool = headless::connect(conf.clone())?;
+  let sftp = pool.get().map_err(|e| (FAILED, e.to_string()))?;
+  let home = sftp::home_dir(&sftp, &pool.session());
+  let mut batch = Batch {
+    pool,
+    sftp,
+    remote: conf
+      .start_dir
+      .as_deref()
+      .map_or_else(|| home.clone(), |dir| headless::resolve(&home, dir)),
+    local: env::current_dir().unwrap_or_default(),
+    opts: headless::options(args),
+    quiet: args.is_present("quiet"),
+    progress: args.is_present("progress"),
+  };
+  for (i, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let (line, may_fail) = match line.strip_prefix('-') {
+      Some(line) => (line.trim_start(), true),
+      None => (line, false),
+    };
+    if !batch.quiet {
+      println!("gsftp> {line}");
+    }
+    if let Err(e) = words(line).and_then(|words| batch.command(&words)) {
+      let e = format!("{file} line {}: {e}", i + 1);
+      match may_fail {
+        true => eprintln!("{e}"),
+        false => return Err((FAILED, e)),
+      }
+    }
+  }
+  Ok(())
+}
+
+// The words of a command, split at spaces except inside single or double quotes
+fn words(line: &str) -> Result<Vec<String>, String> {
+  let mut words = Vec::new();
+  let mut word: Option<String> = None;
+  let mut quote = None;
+  for c in line.chars() {
+    match (quote, c) {
+      (Some(q), c) if c == q => quote = None,
+      (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+      (None, '"' | '\'') => {
+        quote = Some(c);
+        word.get_or_insert_with(String::new);
+      }
+      (None, c) if c.is_whitespace() => words.extend(word.take()),
+      (None, c) => word.get_or_insert_with(String::new).push(c),
+    }
+  }
+  if quote.is_some() {
+    return Err(String::from("unterminated quote"));
+  }
+  words.extend(word);
+  Ok(words)
+}
+
+impl Batch {
+  fn command(&mut self, words: &[String]) -> Result<(), String> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    match words.as_slice() {
+      ["cd", dir] => {
+        let dir = self.remote(dir);
+        match self.sftp.stat(&dir) {
+          Ok(stat) if stat.is_dir() => {}
+          Ok(_) => return Err(format!("{} is not a directory", dir.display())),
+          Err(e) => return Err(format!("{}: {e}", dir.display())),
+        }
+        self.remote = self.sftp.realpath(&dir).unwrap_or(dir);
+      }
+      ["lcd", dir] => {
+        let dir = self.local.join(dir);
+        self.local = dir
+          .canonicalize()
+          .ok()
+          .filter(|dir| dir.is_dir())
+          .ok_or_else(|| format!("{} is not a directory", dir.display()))?;
+      }
+      ["get", from] | ["get", from, _] => {
+        let from = self.remote(from);
+        let name = from
+          .file_name()
+          .ok_or_else(|| format!("Can't download {}", from.display()))?;
+        let to = match words.get(2) {
+          Some(to) => self.local.join(to),
+          None => self.local.clone(),
+        };
+        let to = match to.is_dir() {
+          true => to.join(name),
+          false => to,
+        };
+        self.transfer(TransferKind::Download, from, to)?;
+      }
+      ["put", from] | ["put", from, _] => {
+        let from = self.local.join(from);
+        let name = from
+          .file_name()
+          .ok_or_else(|| format!("Can't upload {}", from.display()))?
+          .to_os_string();
+        let to = match words.get(2) {
+          Some(to) => self.remote(to),
+          None => self.remote.clone(),
+        };
+        let to = match self.sftp.stat(&to) {
+          Ok(stat) if stat.is_dir() => remote_path::join(&to, name),
+          _ => to,
+        };
+        self.transfer(TransferKind::Upload, from, to)?;
+      }
+      ["mkdir", dir] => {
+        let dir = self.remote(dir);
+        self
+          .sftp
+          .mkdir(&dir, 0o755)
+          .map_err(|e| format!("{}: {e}", dir.display()))?;
+      }
+      ["rm", path] if *path != "-r" => {
+        let path = self.remote(path);
+        match self.sftp.lstat(&path) {
+          Ok(stat) if stat.file_type().is_dir() => {
+            return Err(format!("{} is a directory (use rm -r)", path.display()))
+          }
+          _ => self
+            .sftp
+            .unlink(&path)
+            .map_err(|e| format!("{}: {e}", path.display()))?,
+        }
+      }
+      ["rm", "-r", path] => {
+        let path = self.remote(path);
+        backend::remove_all(&*self.sftp, &path).map_err(|e| format!("{}: {e}", path.display()))?;
+      }
+      ["sync", dirs @ ..] if dirs.len() <= 2 => {
+        let local = dirs
+          .first()
+          .map_or(self.local.clone(), |dir| self.local.join(dir));
+        let remote = dirs
+          .get(1)
+          .map_or(self.remote.clone(), |dir| self.remote(dir));
+        let copied = self.sync(&local, &remote)?;
+        if !self.quiet && copied == 0 {
+          println!("Nothing differs");
+        }
+      }
+      ["pwd"] => println!("{}", self.remote.display()),
+      [name, ..] => {
+        return Err(match COMMANDS.iter().find(|(command, _)| command == name) {
+          Some((_, usage)) => format!("usage: {usage}"),
+          None => format!("unknown command {name}"),
+        })
+      }
+      [] => {}
+    }
+    Ok(())
+  }
+
+  // `path` under the remote directory, unless it's absolute
+  fn remote(&self, path: &str) -> PathBuf {
+    headless::resolve(&self.remote, path)
+  }
+
+  // Copies whatever in the local `dir` is missing from the remote one, or differs from it (as
+  // the panes compare them), going into directories that are on both sides.
+  // Yields how many items were copied.
+  fn sync(&self, local: &Path, remote: &Path) -> Result<usize, String> {
+    let locals: Vec<Entry> = app_utils::try_read_dir_contents(local)
+      .map_err(|e| format!("{}: {e}", local.display()))?
+      .iter()
+      .filter_map(|path| Entry::from_path(path))
+      .collect();
+    if self.sftp.stat(remote).is_err() {
+      backend::mkdir_all(&*self.sftp, remote).map_err(|e| format!("{}: {e}", remote.display()))?;
+    }
+    let remotes =
+      sftp::try_ls(&self.sftp, remote, true).map_err(|e| format!("{}: {e}", remote.display()))?;
+    let others = app_utils::by_name(&remotes);
+    let mut copied = 0;
+    for entry in &locals {
+      let (from, to) = (local.join(entry), remote_path::join(remote, entry));
+      match Comparison::of(entry, &others) {
+        Comparison::Same if entry.kind == EntryKind::Dir => copied += self.sync(&from, &to)?,
+        Comparison::Same => {}
+        _ => {
+          self.transfer(TransferKind::Upload, from, to)?;
+          copied += 1;
+        }
+      }
+    }
+    Ok(copied)
+  }
+
+  fn transfer(&self, kind: TransferKind, from: PathBuf, to: PathBuf) -> Result<(), String> {
+    let transfer =
+      Transfer::between(from, to, kind, self.opts, &self.pool).map_err(|e| e.to_string())?;
+    let status = get_put::execute(transfer, self.progress).map_err(|(_, e)| e)?;
+    if !self.quiet {
+      println!("{}", status.describe_success().unwrap_or_default());
+    }
+    Ok(())
+  }
+}
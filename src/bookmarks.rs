@@ -0,0 +1,107 @@
+//! Bookmarked directories, kept in gsftp's config directory between sessions.
+//! Local directories are shared by every session; remote ones belong to the host they're on.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Stands in for the host of local bookmarks in the file
+const LOCAL: &str = "local";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One bookmarked directory
+pub struct Bookmark {
+  /// The connection's id (e.g. `deploy@example.com:22`), or `None` for a local directory
+  pub host: Option<String>,
+  pub path: PathBuf,
+}
+
+#[derive(Debug, Default)]
+/// Every bookmark, in the order they were added
+pub struct Bookmarks {
+  list: Vec<Bookmark>,
+  file: Option<PathBuf>,
+}
+
+impl Bookmarks {
+  /// The saved bookmarks, or none at all if there aren't any (or they can't be read)
+  pub fn load() -> Self {
+    Self::from_file(
+      dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("bookmarks")),
+    )
+  }
+
+  /// The bookmarks saved in `file`, which they're saved back to. Without a file there are none,
+  /// and adding or removing one fails to save.
+  pub fn from_file(file: Option<PathBuf>) -> Self {
+    let text = file
+      .as_ref()
+      .and_then(|file| fs::read_to_string(file).ok())
+      .unwrap_or_default();
+    // One `host<TAB>path` per line
+    let list = text
+      .lines()
+      .filter_map(|line| line.split_once('\t'))
+      .map(|(host, path)| Bookmark {
+        host: (host != LOCAL).then(|| host.to_string()),
+        path: PathBuf::from(path),
+      })
+      .collect();
+    Self { list, file }
+  }
+
+  /// The bookmarked directories on `host`, or local ones for `None`
+  pub fn on(&self, host: Option<&str>) -> Vec<PathBuf> {
+    self
+      .list
+      .iter()
+      .filter(|b| b.host.as_deref() == host)
+      .map(|b| b.path.clone())
+      .collect()
+  }
+
+  /// Bookmark `path` on `host` and save. Yields false if it was already bookmarked.
+  pub fn add(&mut self, host: Option<&str>, path: &Path) -> io::Result<bool> {
+    let bookmark = Bookmark {
+      host: host.map(String::from),
+      path: path.to_path_buf(),
+    };
+    if self.list.contains(&bookmark) {
+      return Ok(false);
+    }
+    self.list.push(bookmark);
+    self.save()?;
+    Ok(true)
+  }
+
+  /// Forget the bookmark for `path` on `host`, and save
+  pub fn remove(&mut self, host: Option<&str>, path: &Path) -> io::Result<()> {
+    self
+      .list
+      .retain(|b| b.host.as_deref() != host || b.path != path);
+    self.save()
+  }
+
+  fn save(&self) -> io::Result<()> {
+    let file = self.file.as_ref().ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::NotFound,
+        "no config directory to save bookmarks in",
+      )
+    })?;
+    if let Some(dir) = file.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let text: String = self
+      .list
+      .iter()
+      .map(|b| {
+        format!(
+          "{}\t{}\n",
+          b.host.as_deref().unwrap_or(LOCAL),
+          b.path.display()
+        )
+      })
+      .collect();
+    fs::write(file, text)
+  }
+}
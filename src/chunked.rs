@@ -0,0 +1,298 @@
+//! Splitting very large uploads into fixed-size parts that can be retried individually
+use ssh2::Session;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::backend::FsBackend;
+use crate::error::GsftpError;
+use crate::file_transfer::{copy_with_progress, Progress};
+use crate::sftp;
+
+/// How many times a single part is attempted before the whole transfer gives up
+const ATTEMPTS: usize = 3;
+/// First line of every manifest, so we never mistake some other file for one
+const MAGIC: &str = "gsftp-chunks 1";
+/// A manifest has a line per part, so even a huge file's fits in this many bytes
+const MAX_MANIFEST: u64 = 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+/// Describes a file that was uploaded as parts: `name.part0000`, `name.part0001`, ...
+/// alongside `name.manifest`, which holds this description.
+pub struct Manifest {
+  pub name: String,
+  pub size: u64,
+  pub chunk_size: u64,
+  pub parts: Vec<(String, u64)>,
+}
+
+impl Manifest {
+  fn new(name: &str, size: u64, chunk_size: u64) -> Self {
+    let count = size.div_ceil(chunk_size).max(1);
+    let parts = (0..count)
+      .map(|i| {
+        let len = chunk_size.min(size - i * chunk_size);
+        (format!("{name}.part{i:04}"), len)
+      })
+      .collect();
+    Self {
+      name: name.to_string(),
+      size,
+      chunk_size,
+      parts,
+    }
+  }
+
+  /// Reads a manifest back, yielding `None` if `text` isn't one. Since the names in it come
+  /// from the server, one that isn't a plain file name (like `../.bashrc`) means it isn't, and
+  /// so do parts that don't add up to the size.
+  pub fn parse(text: &str) -> Option<Self> {
+    let mut lines = text.lines();
+    if lines.next()? != MAGIC {
+      return None;
+    }
+    let mut field = |key: &str| lines.next()?.strip_prefix(key).map(str::to_string);
+    let name = field("name ")?;
+    let size = field("size ")?.parse().ok()?;
+    let chunk_size = field("chunk_size ")?.parse().ok()?;
+    let parts = lines
+      .map(|line| {
+        let (len, part) = line.split_once(' ')?;
+        Some((part.to_string(), len.parse().ok()?))
+      })
+      .collect::<Option<_>>()?;
+    let manifest = Self {
+      name,
+      size,
+      chunk_size,
+      parts,
+    };
+    let names_plain =
+      plain_name(&manifest.name) && manifest.parts.iter().all(|(part, _)| plain_name(part));
+    let total = manifest.parts.iter().map(|(_, len)| len).sum::<u64>();
+    (names_plain && total == manifest.size).then_some(manifest)
+  }
+
+  fn render(&self) -> String {
+    let mut text = format!(
+      "{MAGIC}\nname {}\nsize {}\nchunk_size {}\n",
+      self.name, self.size, self.chunk_size
+    );
+    for (part, len) in &self.parts {
+      text.push_str(&format!("{len} {part}\n"));
+    }
+    text
+  }
+}
+
+/// `name.manifest`, next to the parts of `name`
+pub fn manifest_name(name: &str) -> String {
+  format!("{name}.manifest")
+}
+
+// Whether `name` names a file in the directory it's in, rather than somewhere else
+fn plain_name(name: &str) -> bool {
+  !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0'])
+}
+
+/// The manifest at `from`, or `None` if it isn't one: too big, not text, or not what
+/// `Manifest::parse` takes
+pub fn read_manifest(from: &Path, remote: &dyn FsBackend) -> Result<Option<Manifest>, GsftpError> {
+  let mut text = String::new();
+  let mut reader = remote.read(from)?.take(MAX_MANIFEST + 1);
+  if reader.read_to_string(&mut text).is_err() || text.len() as u64 > MAX_MANIFEST {
+    return Ok(None);
+  }
+  Ok(Manifest::parse(&text))
+}
+
+/// Uploads `from` to `to` as parts of at most `chunk_size` bytes plus a manifest.
+/// Parts already on the remote host with the right size are left alone, and each part is
+/// retried on its own if it fails. Once every part is up, the remote host stitches them
+/// back together with `cat`; if it can't, the parts stay put to be reassembled on download.
+pub fn upload(
+  from: &Path,
+  to: &Path,
+  chunk_size: u64,
+  sess: &Session,
+  local: &dyn FsBackend,
+  remote: &dyn FsBackend,
+  progress: &mut Progress,
+) -> Result<(), GsftpError> {
+  let name = to.file_name().unwrap_or_default().to_string_lossy();
+  let size = local.stat(from)?.size.unwrap_or_default();
+  let manifest = Manifest::new(&name, size, chunk_size);
+  for (i, (part, len)) in manifest.parts.iter().enumerate() {
+    let part_path = to.with_file_name(part);
+    if remote.stat(&part_path).ok().and_then(|entry| entry.size) == Some(*len) {
+      progress.add(*len);
+      continue;
+    }
+    let offset = i as u64 * chunk_size;
+    retry(|| {
+      let mut local_part = local.read_from(from, offset)?.take(*len);
+      let mut remote_part = remote.write(&part_path, 0o644)?;
+      copy_with_progress(&mut local_part, &mut remote_part, progress)?;
+      Ok(remote_part.finish()?)
+    })?;
+  }
+  let manifest_path = to.with_file_name(manifest_name(&name));
+  let mut manifest_file = remote.write(&manifest_path, 0o644)?;
+  io::Write::write_all(&mut manifest_file, manifest.render().as_bytes())?;
+  manifest_file.finish()?;
+
+  // Reassembly is best-effort: the manifest lets a later download finish the job
+  let paths: Option<Vec<String>> = manifest
+    .parts
+    .iter()
+    .map(|(part, _)| to.with_file_name(part).to_str().map(String::from))
+    .collect();
+  if let (Some(parts), Some(to_str)) = (paths, to.to_str()) {
+    let script = r#"out="$1"; shift; cat -- "$@" > "$out""#;
+    let mut args = vec!["-c", script, "sh", to_str];
+    args.extend(parts.iter().map(String::as_str));
+    if sftp::exec(sess, "sh", &args).is_ok() {
+      for (part, _) in &manifest.parts {
+        remote.remove(&to.with_file_name(part))?;
+      }
+      remote.remove(&manifest_path)?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Downloads the parts `manifest` lists, which are next to `from`, one after the other into
+/// `to`. Each part is retried on its own from as far as it got, and has to be as long as the
+/// manifest says, so that nothing goes missing from the middle.
+pub fn download(
+  from: &Path,
+  manifest: &Manifest,
+  remote: &dyn FsBackend,
+  to: &mut dyn Write,
+  progress: &mut Progress,
+) -> Result<(), GsftpError> {
+  for (part, len) in &manifest.parts {
+    let part_path = from.with_file_name(part);
+    let size = remote.stat(&part_path)?.size.unwrap_or_default();
+    let mismatch = |size: u64| format!("{part} is {size} bytes, but the manifest says {len}");
+    if size != *len {
+      return Err(mismatch(size).into());
+    }
+    let mut written = Counted {
+      to: &mut *to,
+      count: 0,
+    };
+    retry(|| {
+      let done = written.count;
+      let mut remote_part = remote.read_from(&part_path, done)?.take(len - done);
+      copy_with_progress(&mut remote_part, &mut written, progress)?;
+      Ok(())
+    })?;
+    if written.count != *len {
+      return Err(mismatch(written.count).into());
+    }
+  }
+
+  Ok(())
+}
+
+// Passes writes on, counting the bytes that made it, so a retry knows where to carry on from
+struct Counted<'a> {
+  to: &'a mut dyn Write,
+  count: u64,
+}
+
+impl Write for Counted<'_> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let n = self.to.write(buf)?;
+    self.count += n as u64;
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.to.flush()
+  }
+}
+
+fn retry(mut attempt: impl FnMut() -> Result<(), GsftpError>) -> Result<(), GsftpError> {
+  let mut result = attempt();
+  for _ in 1..ATTEMPTS {
+    // Cancelling isn't something another attempt gets past
+    if matches!(result, Ok(()) | Err(GsftpError::Cancelled)) {
+      break;
+    }
+    result = attempt();
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::fixtures::put;
+  use crate::backend::Memory;
+
+  fn progress() -> Progress {
+    let (tx, _rx) = crossbeam_channel::unbounded();
+    Progress::new(0, tx)
+  }
+
+  #[test]
+  fn manifests_read_back_what_was_written() {
+    let manifest = Manifest::new("big.iso", 10, 4);
+    let lens: Vec<u64> = manifest.parts.iter().map(|(_, len)| *len).collect();
+    assert_eq!(lens, [4, 4, 2]);
+    assert_eq!(manifest.parts[2].0, "big.iso.part0002");
+    assert_eq!(Manifest::parse(&manifest.render()), Some(manifest));
+    // an empty file is a single empty part
+    assert_eq!(
+      Manifest::new("empty", 0, 4).parts,
+      [("empty.part0000".to_string(), 0)]
+    );
+    assert_eq!(Manifest::parse("some other file\n"), None);
+  }
+
+  #[test]
+  fn manifests_only_name_files_next_to_them() {
+    let with =
+      |name: &str, part: &str| format!("{MAGIC}\nname {name}\nsize 3\nchunk_size 4\n3 {part}\n");
+    assert!(Manifest::parse(&with("a.txt", "a.txt.part0000")).is_some());
+    for (name, part) in [
+      ("../../.bashrc", "a.part0000"),
+      ("/etc/passwd", "a.part0000"),
+      ("..", "a.part0000"),
+      ("", "a.part0000"),
+      ("a.txt", "../a.part0000"),
+      ("a.txt", "dir\\a.part0000"),
+    ] {
+      assert_eq!(Manifest::parse(&with(name, part)), None, "{name} {part}");
+    }
+    // nor parts that don't add up to the file
+    let short = format!("{MAGIC}\nname a\nsize 9\nchunk_size 4\n3 a.part0000\n");
+    assert_eq!(Manifest::parse(&short), None);
+  }
+
+  #[test]
+  fn parts_are_joined_only_if_each_is_whole() {
+    let remote = Memory::new();
+    put(&remote, "/up/a.part0000", "abcd");
+    put(&remote, "/up/a.part0001", "ef");
+    let manifest = Manifest::parse(&format!(
+      "{MAGIC}\nname a\nsize 6\nchunk_size 4\n4 a.part0000\n2 a.part0001\n"
+    ))
+    .unwrap();
+    put(&remote, "/up/a.manifest", &manifest.render());
+    let from = Path::new("/up/a.manifest");
+    assert_eq!(
+      read_manifest(from, &remote).unwrap().as_ref(),
+      Some(&manifest)
+    );
+    let mut joined = Vec::new();
+    download(from, &manifest, &remote, &mut joined, &mut progress()).unwrap();
+    assert_eq!(joined, b"abcdef");
+    // a part that's come up short would leave a hole
+    put(&remote, "/up/a.part0000", "abc");
+    let mut joined = Vec::new();
+    assert!(download(from, &manifest, &remote, &mut joined, &mut progress()).is_err());
+  }
+}
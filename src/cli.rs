@@ -0,0 +1,65 @@
+//! The command line gsftp was started with, filled in from `config.toml`, and read again when
+//! the file changes
+use clap::ArgMatches;
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use gsftp::config::command;
+use gsftp::settings::Settings;
+
+use crate::wizard;
+
+/// The command line, with anything it leaves out that `config.toml` (or `--config`) sets filled
+/// in from there, along with the rest of what the file says. Like clap, this exits after
+/// printing help or a usage error; the file being wrong is an error.
+pub fn args() -> Result<(ArgMatches, Settings), String> {
+  // With nothing at all on the command line, the wizard asks what would have been given
+  let argv: Vec<OsString> = match env::args_os().len() {
+    1 if io::stdin().is_terminal() => wizard::run()?,
+    _ => env::args_os().collect(),
+  };
+  let given = command().get_matches_from(&argv);
+  let settings = Settings::load(given.value_of("config").map(Path::new))?;
+  let args = command().get_matches_from(with_settings(&argv, &given, &settings));
+  let _ = ARGV.set(argv);
+  Ok((args, settings))
+}
+
+// The command line `args` gave, kept for reading the config file again
+static ARGV: OnceLock<Vec<OsString>> = OnceLock::new();
+
+/// The command line and settings again, as `args` gave them, but from the config file as it is
+/// now (for `:reload-config`)
+pub fn reload() -> Result<(ArgMatches, Settings), String> {
+  let argv = ARGV
+    .get()
+    .ok_or_else(|| String::from("The command line hasn't been read yet"))?;
+  // only the first line of clap's error, since it's shown in the status bar
+  let error = |e: clap::Error| {
+    let e = e.to_string();
+    e.lines().next().unwrap_or_default().to_string()
+  };
+  let given = command().try_get_matches_from(argv).map_err(error)?;
+  let settings = Settings::load(given.value_of("config").map(Path::new))?;
+  let args = command()
+    .try_get_matches_from(with_settings(argv, &given, &settings))
+    .map_err(error)?;
+  Ok((args, settings))
+}
+
+// `argv`, followed by options for whatever `settings` sets that it doesn't
+fn with_settings(argv: &[OsString], given: &ArgMatches, settings: &Settings) -> Vec<OsString> {
+  // `gsftp get`, `put` and `batch` only take the options they're given
+  let options = match given.subcommand_name() {
+    Some(_) => Vec::new(),
+    None => settings.options(given),
+  };
+  argv
+    .iter()
+    .cloned()
+    .chain(options.into_iter().map(OsString::from))
+    .collect()
+}
@@ -1,66 +1,376 @@
 //! SFTP configuration and argument parsing
-use clap::{arg, ArgMatches, Command};
+use clap::{arg, Arg, ArgMatches, Command};
 use dns_lookup::lookup_host;
 use ssh2::{KeyboardInteractivePrompt, Prompt};
+use std::fs;
+use std::io::{self, Read};
 use std::net::Ipv4Addr;
 use std::path::{Path, PathBuf};
-use std::process;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use std::{env, fmt};
+use tracing::warn;
+
+use crate::ask;
+use crate::credentials;
+use crate::error::GsftpError;
+use crate::proxy::Proxy;
+use crate::settings;
+use crate::ssh_config::HostConfig;
 
 const PROGRAM_NAME: &str = env!("CARGO_PKG_NAME");
 
-pub fn args() -> ArgMatches {
+/// The directory of the config file `args` reads (`--config`, or else `config.toml`)
+pub fn dir(args: &ArgMatches) -> Option<PathBuf> {
+  let file = args
+    .value_of("config")
+    .map(PathBuf::from)
+    .or_else(settings::file)?;
+  file.parent().map(Path::to_path_buf)
+}
+
+/// When the config file was last changed (`None` if it isn't there), to tell whether it has
+/// been since
+pub fn stamp(args: &ArgMatches) -> Option<SystemTime> {
+  let file = args
+    .value_of("config")
+    .map(PathBuf::from)
+    .or_else(settings::file)?;
+  fs::metadata(file).ok()?.modified().ok()
+}
+
+/// Every option gsftp takes, before any are filled in from `config.toml`
+pub fn command() -> Command<'static> {
   Command::new(PROGRAM_NAME)
     .author("benharmonics")
     .version("0.1.0")
     .about("Secure file transfer tool with graphical interface")
     .before_help("https://github.com/benharmonics/gsftp/")
-    .arg(arg!(<DESTINATION> "Required remote connection, e.g. username@host"))
+    .after_help(
+      "PASSWORDS:\n    \
+       A password is taken from the first of these that's given: --password=<pwd> (deprecated)\n    \
+       or --password-stdin, then $GSFTP_PASSWORD (unless -i or -A is used), then the system\n    \
+       keyring, and finally a prompt.",
+    )
+    .arg(arg!(<DESTINATION>... "Required remote connection, e.g. username@host[:port][/path], or a Host from ~/.ssh/config; give several to open a tab for each"))
+    .subcommand_negates_reqs(true)
+    .subcommand(transfer_command(
+      "get",
+      "Download a file or directory without the interface, e.g. gsftp get deploy@example.com:/var/log/app.log .",
+      "Remote file or directory, as username@host[:port]:path",
+      "Where to put it locally",
+    ))
+    .subcommand(transfer_command(
+      "put",
+      "Upload a file or directory without the interface, e.g. gsftp put site.tar.gz deploy@example.com:/srv/",
+      "Local file or directory",
+      "Where to put it, as username@host[:port]:path (a directory if it ends with /)",
+    ))
+    .subcommand(
+      Command::new("batch")
+        .about("Run the commands in a file without the interface, like sftp -b")
+        .after_help(
+          "COMMANDS:\n    \
+           cd, lcd, get, put, mkdir, rm [-r], sync [local dir] [remote dir] (uploads whatever differs)\n    \
+           and pwd, one to a line. A command starting with - can fail without stopping the rest.",
+        )
+        .arg(arg!(<BATCHFILE> "File of commands, or - for standard input"))
+        .arg(arg!(<DESTINATION> "Remote connection, e.g. username@host[:port][/path]"))
+        .args(output_args()),
+    )
+    .arg(
+      arg!(--config "Read settings from this file instead of config.toml in gsftp's config directory")
+        .takes_value(true)
+        .value_name("PATH")
+        .global(true),
+    )
+    .arg(
+      arg!(--"log-file" "Log what gsftp does to this file, for diagnosing problems afterwards")
+        .takes_value(true)
+        .value_name("PATH")
+        .global(true),
+    )
+    .arg(
+      arg!(-v --verbose "Log in more detail; -vv logs everything")
+        .multiple_occurrences(true)
+        .takes_value(false)
+        .global(true),
+    )
     .arg(arg!(-a --all "Show hidden files").takes_value(false))
     .arg(
-      arg!(-i --identity "Authenticate with identity file, i.e. private key (recommended)")
+      arg!(-i --identity "Authenticate with identity file, i.e. private key (recommended); repeat to try several")
+        .takes_value(true)
+        .value_name("FILE")
         .number_of_values(1)
-        .conflicts_with_all(&["password", "agent"]),
+        .multiple_occurrences(true)
+        .conflicts_with_all(&["password", "agent"])
+        .global(true),
     )
     .arg(
-      arg!(-A --agent "Authenticate with SSH agent")
-        .default_value("on")
+      arg!(-A --agent "Authenticate with SSH agent only, rather than trying each method in turn")
         .takes_value(false)
-        .conflicts_with_all(&["password", "identity"]),
+        .conflicts_with_all(&["password", "identity"])
+        .global(true),
     )
     .arg(
-      arg!(--password "Authenticate with password (not recommended)")
-        .number_of_values(1)
-        .conflicts_with_all(&["agent", "identity"]),
+      arg!(-p --password "Authenticate with password, which you'll be prompted for")
+        .takes_value(true)
+        .value_name("PWD")
+        .min_values(0)
+        .max_values(1)
+        // `--password <pwd>` would swallow the destination, so the deprecated form needs `=`
+        .require_equals(true)
+        .conflicts_with_all(&["agent", "identity"])
+        .global(true),
+    )
+    .arg(
+      arg!(--"password-stdin" "Authenticate with a password read from standard input, for scripts")
+        .takes_value(false)
+        .conflicts_with_all(&["password", "agent", "identity"])
+        .global(true),
+    )
+    .arg(
+      arg!(--"agent-socket" "Use the SSH agent at this socket (or named pipe on Windows) instead of $SSH_AUTH_SOCK")
+        .takes_value(true)
+        .value_name("PATH")
+        .conflicts_with_all(&["password", "identity"])
+        .global(true),
     )
     .arg(
       arg!(--pubkey "Public key file")
-        .number_of_values(1)
-        .requires("identity"),
+        .takes_value(true)
+        .value_name("FILE")
+        .requires("identity")
+        .global(true),
     )
     .arg(
       arg!(--passphrase "Additional passphrase")
-        .number_of_values(1)
-        .requires("identity"),
+        .takes_value(true)
+        .value_name("PASSPHRASE")
+        .requires("identity")
+        .global(true),
+    )
+    .arg(
+      arg!(--proxy "Connect through a proxy, e.g. socks5://localhost:1080 or http://proxy:3128")
+        .takes_value(true)
+        .value_name("URL")
+        .validator(|url| url.parse::<Proxy>())
+        .global(true),
+    )
+    .arg(
+      arg!(-C --compress "Compress all traffic with SSH's zlib compression, for slow links")
+        .takes_value(false)
+        .global(true),
+    )
+    .arg(arg!(--"no-keyring" "Don't look up or save passwords and passphrases in the system keyring").takes_value(false).global(true))
+    .arg(
+      arg!(--timeout "Give up on a server that hasn't answered in this many seconds while connecting")
+        .takes_value(true)
+        .value_name("SECONDS")
+        .validator(|secs| number::<u64>(secs, "a number of seconds"))
+        .global(true),
     )
     .arg(
       arg!(-P --port "SSH port")
         .default_value("22")
-        .takes_value(true),
+        .takes_value(true)
+        .value_name("PORT")
+        .validator(port)
+        .global(true),
     )
     // .arg(
     //     arg!(--manual "NOT IMPLEMENTED")
     //         .takes_value(false)
     //         .conflicts_with_all(&["password", "identity", "agent"]),
     // )
+    .arg(
+      arg!(--fps "How many times a second the screen is redrawn, from 1 to 120; fewer saves battery")
+        .default_value("60")
+        .takes_value(true)
+        .value_name("FPS")
+        .validator(|fps| within(fps, 1.0, 120.0)),
+    )
+    .arg(
+      arg!(--"refresh-interval" "How often the windows are read again, from 0.25 to 600 seconds; longer means less traffic")
+        .default_value("1")
+        .takes_value(true)
+        .value_name("SECONDS")
+        .validator(|secs| within(secs, 0.25, 600.0)),
+    )
     .arg(arg!(--shortcuts "Start with keyboard shortcut help panel open").takes_value(false))
-    .get_matches()
+    .arg(arg!(--"no-color" "Draw in the terminal's own colors, with bold and reverse video to pick things out (or set NO_COLOR)").takes_value(false))
+    .arg(arg!(--ascii "Draw with plain ASCII only, for consoles that garble box-drawing characters and arrows").takes_value(false))
+    .arg(arg!(--"local-dir" "Local directory to start in").takes_value(true).value_name("PATH"))
+    .arg(
+      arg!(--"remote-dir" "Remote directory to start in, absolute or relative to the home directory")
+        .takes_value(true)
+        .value_name("PATH"),
+    )
+    .arg(arg!(--resume "Start in the local and remote directories you were last in on this host").takes_value(false))
+    .arg(arg!(--"read-only" "Refuse to upload, delete, rename, chmod or make anything, for browsing production servers safely").takes_value(false))
+    .arg(arg!(--trash "Move deleted local files to the trash instead of removing them for good").takes_value(false))
+    .arg(
+      arg!(--sort "What both windows are sorted by at first: name, natural (file2 before file10), size, modified, or type")
+        .possible_values(["name", "natural", "size", "modified", "type"])
+        .default_value("name")
+        .takes_value(true),
+    )
+    .arg(
+      arg!(--symlinks "How symbolic links are transferred: skip, follow, or recreate")
+        .possible_values(["skip", "follow", "recreate"])
+        .default_value("skip")
+        .takes_value(true)
+        .global(true),
+    )
+    .arg(
+      arg!(--"on-conflict" "What to do when the destination exists: overwrite, skip, or rename (safe)")
+        .possible_values(["overwrite", "skip", "rename", "safe"])
+        .default_value("overwrite")
+        .takes_value(true)
+        .global(true),
+    )
+    .arg(
+      arg!(--"no-exec" "Never run commands on the remote host, for SFTP-only servers")
+        .takes_value(false)
+        .global(true),
+    )
+    .arg(
+      arg!(--"forward-agent" "Forward your SSH agent to the commands gsftp runs on the remote host")
+        .takes_value(false)
+        .global(true),
+    )
+    .arg(
+      arg!(-z --gzip "Gzip file payloads in transit (needs gzip on the remote host)")
+        .takes_value(false)
+        .conflicts_with("no-exec"),
+    )
+    .arg(
+      arg!(--"keep-gz" "Store gzipped payloads as .gz files instead of unpacking them")
+        .takes_value(false)
+        .requires("gzip"),
+    )
+    .arg(
+      arg!(--"chunk-size" "Upload files bigger than this as separately retried parts, e.g. 64M")
+        .takes_value(true)
+        .value_name("SIZE")
+        .validator(size),
+    )
+    .arg(
+      arg!(--"encrypt-to" "Encrypt uploads with age for this recipient or recipients file (repeatable)")
+        .takes_value(true)
+        .value_name("RECIPIENT")
+        .number_of_values(1)
+        .multiple_occurrences(true),
+    )
+    .arg(
+      arg!(--"decrypt-with" "Decrypt downloads with this age identity file")
+        .takes_value(true)
+        .value_name("FILE"),
+    )
+    .arg(
+      arg!(--"encrypt-passphrase" "Encrypt uploads and decrypt downloads with age and a passphrase, asked for once gsftp starts")
+        .takes_value(false)
+        .conflicts_with_all(&["encrypt-to", "decrypt-with"]),
+    )
+    .arg(
+      arg!(--"confirm-size" "Ask before starting transfers larger than this, e.g. 500M or 2G")
+        .default_value("1G")
+        .takes_value(true)
+        .value_name("SIZE")
+        .validator(size),
+    )
+    .arg(
+      arg!(--"confirm-files" "Ask before starting transfers of more files than this")
+        .default_value("1000")
+        .takes_value(true)
+        .value_name("COUNT")
+        .validator(|files| number::<u64>(files, "a number of files")),
+    )
+}
+
+// `gsftp get` or `gsftp put`, which take the connection's options from the main command
+fn transfer_command(
+  name: &'static str,
+  about: &'static str,
+  source: &'static str,
+  dest: &'static str,
+) -> Command<'static> {
+  Command::new(name)
+    .about(about)
+    .after_help(
+      "EXIT STATUS:\n    \
+       0 if the transfer worked, 1 if it didn't, and 255 if the server couldn't be reached or\n    \
+       wouldn't let you log in.",
+    )
+    .arg(arg!(<SOURCE>).help(source))
+    .arg(arg!(<DEST>).help(dest))
+    .args(output_args())
+}
+
+// How much `gsftp get`, `put` and `batch` have to say
+fn output_args() -> [Arg<'static>; 2] {
+  [
+    arg!(-q --quiet "Print nothing but errors").takes_value(false),
+    arg!(--progress "Show how far each transfer has got on standard error")
+      .takes_value(false)
+      .conflicts_with("quiet"),
+  ]
+}
+
+// Checks an option's value is a number, saying what it should be if it isn't
+fn number<T: FromStr>(value: &str, expected: &str) -> Result<T, String> {
+  value
+    .trim()
+    .parse()
+    .map_err(|_| format!("expected {expected}"))
+}
+
+// Checks an option's value is a number from `min` to `max`
+fn within(value: &str, min: f64, max: f64) -> Result<f64, String> {
+  let expected = format!("a number from {min} to {max}");
+  number::<f64>(value, &expected).and_then(|n| match (min..=max).contains(&n) {
+    true => Ok(n),
+    false => Err(format!("expected {expected}")),
+  })
+}
+
+// Checks an option's value is a port, which can't be 0
+fn port(value: &str) -> Result<u16, String> {
+  number(value, "a port number from 1 to 65535").and_then(|port| match port {
+    0 => Err(String::from("expected a port number from 1 to 65535")),
+    port => Ok(port),
+  })
+}
+
+// Checks an option's value is a size `parse_size` understands
+fn size(value: &str) -> Result<u64, String> {
+  parse_size(value).ok_or_else(|| String::from("expected a size such as 500K, 64M or 2G"))
+}
+
+/// Parses a human-friendly size such as `512`, `500K`, `1.5G` (binary units) into bytes
+pub fn parse_size(s: &str) -> Option<u64> {
+  let s = s.trim();
+  let split = s
+    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+    .unwrap_or(s.len());
+  let (number, unit) = s.split_at(split);
+  let number: f64 = number.parse().ok()?;
+  let multiplier: u64 = match unit.trim().to_uppercase().trim_end_matches('B') {
+    "" => 1,
+    "K" => 1 << 10,
+    "M" => 1 << 20,
+    "G" => 1 << 30,
+    "T" => 1 << 40,
+    _ => return None,
+  };
+  Some((number * multiplier as f64) as u64)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// There are several principle authentication methods for SSH.
-/// Implicitly, if all authentication methods fail, the program will default to asking the
-/// user to input their authentication details manually.
-/// ^^^ NOT IMPLEMENTED
+/// Unless one is asked for on the command line, `Manual` tries each method the server allows
+/// in turn, ending with asking the user to input their authentication details manually.
 pub enum AuthMethod {
   Password(String),
   PrivateKey(String),
@@ -68,53 +378,143 @@ pub enum AuthMethod {
   Manual,
 }
 
-#[derive(Debug)]
+impl fmt::Display for AuthMethod {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Password(_) => write!(f, "password"),
+      Self::PrivateKey(key) => write!(f, "identity file {key}"),
+      Self::Agent => write!(f, "SSH agent"),
+      Self::Manual => write!(f, "keyboard-interactive"),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+/// Which SSH agent to ask for keys
+pub enum AgentSocket {
+  /// `$SSH_AUTH_SOCK`; on Windows, Pageant or the OpenSSH agent's named pipe when that isn't set
+  Default,
+  Path(PathBuf),
+  /// Don't use an agent at all (`IdentityAgent none`)
+  Disabled,
+}
+
+#[derive(Debug, Clone)]
 /// Static, immutable SFTP configuration
 pub struct Config {
   pub user: String,
+  /// The server's name, after any ssh config `HostName`; `addr` is its IP address
+  pub host: String,
   pub addr: String,
   pub auth_method: AuthMethod,
   pub pubkey: Option<PathBuf>,
   pub passphrase: Option<String>,
   pub port: u16,
+  /// Private keys to offer, in order: those given with `-i`, then those from ~/.ssh/config, or
+  /// else the OpenSSH defaults
+  pub identity_files: Vec<PathBuf>,
+  /// The order `Manual` authentication tries methods in, e.g. `publickey`, `password`
+  pub preferred_auth: Vec<String>,
+  /// Remote directory to start in, from the destination or the ssh config; relative paths are
+  /// under the home directory
+  pub start_dir: Option<PathBuf>,
+  /// Whether passwords and passphrases are looked up in, and saved to, the system keyring
+  pub keyring: bool,
+  pub agent_socket: AgentSocket,
+  /// How to reach the server if it can't be connected to directly
+  pub proxy: Option<Proxy>,
+  /// Whether to ask the server for SSH compression
+  pub compress: bool,
+  /// How long to wait for the server while connecting, if not the usual few seconds
+  pub timeout: Option<Duration>,
 }
 
-impl From<&ArgMatches> for Config {
-  fn from(args: &ArgMatches) -> Self {
+impl Config {
+  /// Configuration for connecting to `destination` with every option left as it would be on
+  /// the command line, for programs using gsftp as a library; the fields can be changed after
+  pub fn from_destination(destination: &str) -> Result<Self, GsftpError> {
+    let args = command()
+      .try_get_matches_from([PROGRAM_NAME, "--", destination])
+      .map_err(|e| GsftpError::Config(e.to_string()))?;
+    Self::new(destination, &args)
+  }
+
+  /// Configuration for connecting to `destination`, with the rest of the options from `args`
+  pub fn new(destination: &str, args: &ArgMatches) -> Result<Self, GsftpError> {
     // The program takes a destination as input in the format username@host, typically something like
     // user@10.0.0.8 on a LAN. We parse this input as follows:
     // If the user input a hostname as an IP Address, we can just parse it as such - easy!
     // Otherwise, we're going to have to try to use DNS to resolve the hostname into an IP address.
     // If both of these options fail, we'll just have to yield an error message and close the program.
-    let conn: Vec<&str> = args.value_of("DESTINATION").unwrap().split('@').collect();
-    if conn.len() != 2 {
-      eprintln!("Invalid destination format. Destination should be in the form `user@host`,");
-      eprintln!("e.g. `someone@example.com` or `person@10.0.0.118`.");
-      process::exit(1);
-    }
-    let user = String::from(conn[0]);
-    let addr = if let Ok(ip) = conn[1].parse::<Ipv4Addr>() {
+    // The host may also be an alias from ~/.ssh/config, which can fill in the user, port and so on,
+    // and it may be followed by a port and/or a starting directory: user@host:2222/var/www
+    let destination = parse_destination(destination).map_err(|e| {
+      GsftpError::Config(format!(
+        "Invalid destination: {e}.\n\
+         Destination should be in the form `user@host[:port][/path]`, e.g. `someone@example.com`,\n\
+         `person@10.0.0.118:2222` or `deploy@example.com:/var/www`, or a Host from ~/.ssh/config."
+      ))
+    })?;
+    let (user, host) = (destination.user, destination.host.as_str());
+    let ssh = HostConfig::load(host);
+    // Like OpenSSH, with no `user@` we log in as the ssh config's User, or else as ourselves
+    let user = user
+      .or_else(|| ssh.user.clone())
+      .or_else(local_user)
+      .ok_or_else(|| {
+        GsftpError::Config(format!(
+          "No user given for {host}, and couldn't tell who you are locally.\n\
+           Destination should be in the form `user@host`."
+        ))
+      })?;
+    let hostname = ssh.hostname.as_deref().unwrap_or(host);
+    // --proxy beats the ssh config's ProxyCommand
+    let proxy = match args.value_of("proxy") {
+      Some(url) => Some(
+        url
+          .parse::<Proxy>()
+          .map_err(|e| GsftpError::Config(format!("Invalid proxy {url}: {e}")))?,
+      ),
+      None => ssh
+        .proxy_command
+        .filter(|command| command != "none")
+        .map(Proxy::Command),
+    };
+    let addr = if let Ok(ip) = hostname.parse::<Ipv4Addr>() {
       ip.to_string()
     } else {
-      lookup_host(conn[1])
-        .unwrap_or_default()
-        .get(1)
-        .unwrap_or_else(|| {
-          eprintln!("Couldn't resolve remote server {} via DNS.", conn[1]);
-          process::exit(1);
-        })
-        .to_string()
+      match lookup_host(hostname).unwrap_or_default().get(1) {
+        Some(ip) => ip.to_string(),
+        // Behind a proxy, it's the proxy that needs to know the name
+        None if proxy.is_some() => hostname.to_string(),
+        None => return Err(GsftpError::Dns(hostname.to_string())),
+      }
     };
 
     // TODO: change this to a match statement to catch all possible arms?
-    let auth_method = if args.is_present("password") {
-      AuthMethod::Password(String::from(args.value_of("password").unwrap()))
-    } else if args.is_present("identity") {
-      AuthMethod::PrivateKey(String::from(args.value_of("identity").unwrap()))
-    } else if args.is_present("manual") {
+    // `--password` is filled in once the rest of the config can find it in the keyring
+    let identities: Vec<PathBuf> = args
+      .values_of("identity")
+      .map(|keys| keys.map(PathBuf::from).collect())
+      .unwrap_or_default();
+    let auth_method = if let Some(key) = identities.first() {
+      AuthMethod::PrivateKey(key.to_string_lossy().into_owned())
+    } else if args.is_present("agent") {
+      AuthMethod::Agent
+    } else {
       AuthMethod::Manual
+    };
+    // Like OpenSSH, keys from -i come first, and the default keys are only tried if nothing names any
+    let identity_files = if !identities.is_empty() {
+      identities.into_iter().chain(ssh.identity_files).collect()
+    } else if ssh.identity_files.is_empty() {
+      let home = PathBuf::from(env::var_os("HOME").unwrap_or_default());
+      ["id_ed25519", "id_ecdsa", "id_rsa"]
+        .iter()
+        .map(|key| home.join(".ssh").join(key))
+        .collect()
     } else {
-      AuthMethod::Agent
+      ssh.identity_files
     };
 
     // other config options - none of which will crash the program at this point
@@ -124,41 +524,360 @@ impl From<&ArgMatches> for Config {
         if pk.exists() {
           Some(pk.to_owned())
         } else {
-          eprintln!("Public key not found.");
-          eprintln!("Attempting to authenticate with private key anyway.");
+          tracing::warn!("Public key {path} not found; authenticating with the private key anyway");
           None
         }
       }
       None => None,
     };
+    // --agent-socket beats the ssh config's IdentityAgent
+    let agent_socket = match args
+      .value_of("agent-socket")
+      .or(ssh.identity_agent.as_deref())
+    {
+      None | Some("SSH_AUTH_SOCK") => AgentSocket::Default,
+      Some("none") => AgentSocket::Disabled,
+      Some(path) => AgentSocket::Path(PathBuf::from(path)),
+    };
+    let keyring = !args.is_present("no-keyring");
+    let compress = args.is_present("compress") || ssh.compression.unwrap_or(false);
     let passphrase = args.value_of("passphrase").map(String::from);
-    let port: u16 = args.value_of("port").unwrap().parse().unwrap_or_else(|e| {
-      eprintln!("Invalid port number: {e}");
-      eprintln!("Using default port 22.");
-      22
-    });
+    let timeout = match args.value_of("timeout") {
+      Some(secs) => Some(Duration::from_secs(
+        number(secs, "a number of seconds")
+          .map_err(|e| GsftpError::Config(format!("Invalid timeout {secs}: {e}")))?,
+      )),
+      None => None,
+    };
+    // A port in the destination beats an explicit --port, which beats the ssh config and the default
+    let port: u16 = match (destination.port, ssh.port) {
+      (Some(port), _) => port,
+      (None, Some(port)) if args.occurrences_of("port") == 0 => port,
+      _ => {
+        let given = args.value_of("port").unwrap();
+        port(given).map_err(|e| GsftpError::Config(format!("Invalid port {given}: {e}")))?
+      }
+    };
 
-    Self {
+    let mut conf = Self {
       user,
+      host: hostname.to_string(),
       addr,
       auth_method,
       pubkey,
       passphrase,
       port,
+      identity_files,
+      preferred_auth: ssh.preferred_auth,
+      // A directory in the destination beats the ssh config's RemoteDirectory
+      start_dir: destination.path.or(ssh.remote_dir),
+      keyring,
+      agent_socket,
+      proxy,
+      compress,
+      timeout,
+    };
+    if args.is_present("password-stdin") {
+      conf.auth_method = AuthMethod::Password(stdin_password().map_err(GsftpError::Auth)?);
+    } else if args.is_present("password") {
+      let password = password(args.value_of("password"), &conf, host).map_err(GsftpError::Auth)?;
+      conf.auth_method = AuthMethod::Password(password);
+    } else if let (AuthMethod::Manual, Ok(pwd)) = (&conf.auth_method, env::var("GSFTP_PASSWORD")) {
+      conf.auth_method = AuthMethod::Password(pwd);
     }
+
+    Ok(conf)
   }
 }
 
-#[allow(unreachable_code, unused_variables, unused_mut)]
+/// The local username, from the environment ($USERNAME on Windows)
+pub fn local_user() -> Option<String> {
+  ["USER", "LOGNAME", "USERNAME"]
+    .iter()
+    .find_map(|var| env::var(var).ok().filter(|user| !user.is_empty()))
+}
+
+// The pieces of a `user@host:port/path` destination; everything but the host is optional
+struct Destination {
+  user: Option<String>,
+  host: String,
+  port: Option<u16>,
+  path: Option<PathBuf>,
+}
+
+// After the colon, leading digits are a port if a `/` or nothing follows them; anything else is a
+// path, like scp's `host:dir`, which is relative to the remote home directory unless it's absolute.
+fn parse_destination(destination: &str) -> Result<Destination, String> {
+  let (user, rest) = match destination.split_once('@') {
+    Some(("", _)) => return Err(String::from("the user before `@` is empty")),
+    Some((user, rest)) => (Some(user.to_string()), rest),
+    None => (None, destination),
+  };
+  let (host, suffix) = rest.split_once(':').unwrap_or((rest, ""));
+  if host.is_empty() {
+    return Err(String::from("the host is empty"));
+  }
+  let digits = suffix
+    .find(|c: char| !c.is_ascii_digit())
+    .unwrap_or(suffix.len());
+  let (port, path) = match suffix.split_at(digits) {
+    ("", path) => (None, path),
+    (port, path) if path.is_empty() || path.starts_with('/') => {
+      let port = port
+        .parse::<u16>()
+        .ok()
+        .filter(|&port| port > 0)
+        .ok_or_else(|| format!("`{port}` is not a valid port"))?;
+      (Some(port), path)
+    }
+    _ => (None, suffix),
+  };
+
+  Ok(Destination {
+    user,
+    host: host.to_string(),
+    port,
+    path: Some(PathBuf::from(path)).filter(|path| !path.as_os_str().is_empty()),
+  })
+}
+
+// The password given as `--password=<pwd>`, or the one saved in the keyring,
+// or else one typed in (without echo) at a prompt
+fn password(given: Option<&str>, conf: &Config, host: &str) -> Result<String, String> {
+  if let Some(pwd) = given {
+    warn!(
+      "Passing a password on the command line is deprecated, since it ends up in your shell \
+       history and is visible to other users. Use `--password` on its own."
+    );
+    return Ok(String::from(pwd));
+  }
+  if let Some(pwd) = conf.keyring.then(|| credentials::password(conf)).flatten() {
+    return Ok(pwd);
+  }
+  let user = &conf.user;
+  ask::ask(&format!("{user}@{host}'s password: "), true)
+    .map_err(|e| format!("Couldn't read password: {e}"))
+}
+
+// Standard input is only read once, so hosts added later on get the same password
+fn stdin_password() -> Result<String, String> {
+  static PASSWORD: OnceLock<Result<String, String>> = OnceLock::new();
+  PASSWORD
+    .get_or_init(|| {
+      let mut pwd = String::new();
+      io::stdin()
+        .read_to_string(&mut pwd)
+        .map_err(|e| format!("Couldn't read password from standard input: {e}"))?;
+      Ok(pwd.trim_end_matches(['\r', '\n']).to_string())
+    })
+    .clone()
+}
+
+/// Keyboard-interactive authentication puts the server's questions to the user (see `ask`)
 impl KeyboardInteractivePrompt for Config {
-  fn prompt(
-    &mut self,
-    username: &str,
-    instructions: &str,
-    prompts: &[Prompt],
-  ) -> Vec<String> {
-    let mut responses: Vec<String> = Vec::with_capacity(prompts.len());
-
-    Vec::new()
+  fn prompt(&mut self, _username: &str, instructions: &str, prompts: &[Prompt]) -> Vec<String> {
+    if !instructions.is_empty() {
+      ask::tell(instructions);
+    }
+    prompts
+      .iter()
+      .map(|prompt| ask::ask(&prompt.text, !prompt.echo).unwrap_or_default())
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::settings::Settings;
+
+  // Parses a command line, as though it followed `gsftp`
+  fn parse(args: &[&str]) -> Result<ArgMatches, clap::Error> {
+    command().try_get_matches_from([PROGRAM_NAME].iter().chain(args))
+  }
+
+  // The config for connecting to a server by address, so nothing needs looking up
+  fn config(args: &[&str]) -> Config {
+    let args = parse(args).unwrap();
+    Config::new(args.value_of("DESTINATION").unwrap(), &args).unwrap()
+  }
+
+  #[test]
+  fn command_is_consistent() {
+    command().debug_assert();
+  }
+
+  #[test]
+  fn sizes_take_binary_units() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("1B"), Some(1));
+    assert_eq!(parse_size("500K"), Some(500 << 10));
+    assert_eq!(parse_size(" 64 mb "), Some(64 << 20));
+    assert_eq!(parse_size("1.5G"), Some(3 << 29));
+    assert_eq!(parse_size("2T"), Some(2 << 40));
+    for nonsense in ["", "K", "-1", "1.5.2", "10X", "5 GiB"] {
+      assert_eq!(parse_size(nonsense), None, "{nonsense}");
+    }
+  }
+
+  #[test]
+  fn identity_authenticates_with_that_key() {
+    let conf = config(&["-i", "/keys/deploy", "me@127.0.0.1"]);
+    assert!(matches!(conf.auth_method, AuthMethod::PrivateKey(key) if key == "/keys/deploy"));
+    assert_eq!(conf.identity_files[0], PathBuf::from("/keys/deploy"));
+  }
+
+  #[test]
+  fn identities_are_tried_in_order() {
+    let conf = config(&["--identity=/keys/a", "-i", "/keys/b", "me@127.0.0.1"]);
+    assert!(matches!(conf.auth_method, AuthMethod::PrivateKey(key) if key == "/keys/a"));
+    assert_eq!(
+      conf.identity_files[..2],
+      [PathBuf::from("/keys/a"), PathBuf::from("/keys/b")]
+    );
+  }
+
+  #[test]
+  fn identity_needs_a_file() {
+    assert!(parse(&["me@127.0.0.1", "-i"]).is_err());
+  }
+
+  #[test]
+  fn agent_authenticates_with_the_agent_only() {
+    let conf = config(&["-A", "me@127.0.0.1"]);
+    assert!(matches!(conf.auth_method, AuthMethod::Agent));
+    assert!(matches!(conf.agent_socket, AgentSocket::Default));
+  }
+
+  #[test]
+  fn agent_socket_picks_the_agent() {
+    let conf = config(&["--agent-socket", "/tmp/agent.sock", "me@127.0.0.1"]);
+    assert!(
+      matches!(conf.agent_socket, AgentSocket::Path(path) if path == Path::new("/tmp/agent.sock"))
+    );
+    let conf = config(&["--agent-socket", "none", "me@127.0.0.1"]);
+    assert!(matches!(conf.agent_socket, AgentSocket::Disabled));
+  }
+
+  #[test]
+  fn password_given_with_equals_is_used() {
+    let conf = config(&["--password=hunter2", "me@127.0.0.1"]);
+    assert!(matches!(conf.auth_method, AuthMethod::Password(pwd) if pwd == "hunter2"));
+  }
+
+  #[test]
+  fn password_without_equals_leaves_the_destination() {
+    let args = parse(&["-p", "me@127.0.0.1"]).unwrap();
+    assert!(args.is_present("password"));
+    assert_eq!(args.value_of("password"), None);
+    assert_eq!(args.value_of("DESTINATION"), Some("me@127.0.0.1"));
+  }
+
+  #[test]
+  fn destinations_can_follow_options() {
+    let args = parse(&["me@10.0.0.1", "-A", "you@10.0.0.2:2222"]).unwrap();
+    let destinations: Vec<&str> = args.values_of("DESTINATION").unwrap().collect();
+    assert_eq!(destinations, ["me@10.0.0.1", "you@10.0.0.2:2222"]);
+    assert!(args.subcommand().is_none());
+  }
+
+  #[test]
+  fn nothing_given_tries_each_method() {
+    env::remove_var("GSFTP_PASSWORD");
+    let conf = config(&["me@127.0.0.1"]);
+    assert!(matches!(conf.auth_method, AuthMethod::Manual));
+    assert!(!conf.identity_files.is_empty());
+  }
+
+  #[test]
+  fn auth_methods_conflict() {
+    for args in [
+      ["-A", "-i", "/keys/a"],
+      ["-A", "--password=x", "-C"],
+      ["-i", "/keys/a", "--password-stdin"],
+      ["--agent-socket", "/tmp/agent.sock", "--password=x"],
+    ] {
+      let args: Vec<&str> = args.into_iter().chain(["me@127.0.0.1"]).collect();
+      assert!(parse(&args).is_err(), "{args:?} should conflict");
+    }
+  }
+
+  #[test]
+  fn pubkey_and_passphrase_need_an_identity() {
+    assert!(parse(&["--pubkey", "/keys/a.pub", "me@127.0.0.1"]).is_err());
+    assert!(parse(&["--passphrase", "secret", "me@127.0.0.1"]).is_err());
+    let conf = config(&["-i", "/keys/a", "--passphrase", "secret", "me@127.0.0.1"]);
+    assert_eq!(conf.passphrase.as_deref(), Some("secret"));
+  }
+
+  #[test]
+  fn values_are_checked() {
+    for args in [
+      ["-P", "0"],
+      ["-P", "65536"],
+      ["--timeout", "soon"],
+      ["--confirm-size", "big"],
+      ["--confirm-files", "-1"],
+      ["--chunk-size", "64Q"],
+      ["--proxy", "ftp://proxy"],
+      ["--sort", "colour"],
+      ["--fps", "0"],
+      ["--refresh-interval", "0.1"],
+    ] {
+      let args: Vec<&str> = args.into_iter().chain(["me@127.0.0.1"]).collect();
+      assert!(parse(&args).is_err(), "{args:?} should be refused");
+    }
+    let conf = config(&["-P", "2222", "--timeout", "5", "me@127.0.0.1"]);
+    assert_eq!(
+      (conf.port, conf.timeout),
+      (2222, Some(Duration::from_secs(5)))
+    );
+  }
+
+  #[test]
+  fn destination_port_beats_the_option() {
+    let conf = config(&["-P", "2222", "me@127.0.0.1:2200/srv"]);
+    assert_eq!(conf.port, 2200);
+    assert_eq!(conf.start_dir, Some(PathBuf::from("/srv")));
+  }
+
+  #[test]
+  fn encryption_keys_come_from_the_file_unless_given() {
+    let settings: Settings = toml::from_str(
+      r#"
+      encrypt_to = ["age1first", "age1second"]
+      decrypt_with = "/keys/age.txt"
+      "#,
+    )
+    .unwrap();
+    let options = settings.options(&parse(&["me@127.0.0.1"]).unwrap());
+    assert!(options.contains(&String::from("--encrypt-to=age1second")));
+    assert!(options.contains(&String::from("--decrypt-with=/keys/age.txt")));
+    let given = parse(&["--encrypt-to", "age1mine", "me@127.0.0.1"]).unwrap();
+    assert!(!settings
+      .options(&given)
+      .iter()
+      .any(|o| o.contains("age1first")));
+    // a passphrase takes the place of the file's keys
+    let given = parse(&["--encrypt-passphrase", "me@127.0.0.1"]).unwrap();
+    assert!(!settings.options(&given).iter().any(|o| o.contains("crypt")));
+  }
+
+  #[test]
+  fn a_passphrase_and_keys_conflict() {
+    assert!(parse(&[
+      "--encrypt-passphrase",
+      "--encrypt-to",
+      "age1x",
+      "me@127.0.0.1"
+    ])
+    .is_err());
+    assert!(parse(&[
+      "--encrypt-passphrase",
+      "--decrypt-with",
+      "/k",
+      "me@127.0.0.1"
+    ])
+    .is_err());
   }
 }
@@ -0,0 +1,248 @@
+//! The remote hosts a session is connected to. The remote pane shows one of them at a time.
+use ssh2::{MethodType, Session};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::app::App;
+use crate::app_utils::{self, Entry};
+use crate::config::{AuthMethod, Config};
+use crate::credentials;
+use crate::error::GsftpError;
+use crate::health::Monitor;
+use crate::host_keys;
+use crate::remote_path::Flavor;
+use crate::resume::LastDirs;
+use crate::session::{PooledSftp, SessionPool};
+use crate::sftp;
+
+/// One remote host, with its own sessions and its own place in the remote pane
+pub struct Connection {
+  /// The destination it was opened with, e.g. `deploy@example.com`
+  pub name: String,
+  /// Who's logged in where, e.g. `deploy@example.com:22`, whatever the destination was called
+  pub id: String,
+  pub pool: Arc<SessionPool>,
+  /// The channel the remote pane browses with
+  pub sftp: PooledSftp,
+  /// The remote pane's directory and listing, kept while another connection is showing
+  pub cwd: PathBuf,
+  pub listing: Vec<Entry>,
+  pub info: ServerInfo,
+  /// How it logged in, which reconnects stick with
+  pub auth_method: AuthMethod,
+  /// Whether the server's paths look like Windows ones
+  pub flavor: Flavor,
+  /// Times a ping to the server every few seconds
+  pub health: Monitor,
+}
+
+/// The remote `path` on the connection with this `id` the way scp and rsync take it,
+/// e.g. `deploy@example.com:/srv/www`
+pub fn scp_path(id: &str, path: &Path) -> String {
+  // `id` ends with the port, which an scp path has no room for
+  format!("{}:{}", login(id), path.display())
+}
+
+/// A connection's `id` without the port, e.g. `deploy@example.com`
+pub fn login(id: &str) -> &str {
+  id.rsplit_once(':').map_or(id, |(login, _)| login)
+}
+
+#[derive(Debug, Clone)]
+/// What the server said about itself, and the algorithms negotiated with it
+pub struct ServerInfo {
+  /// The server's address and port
+  pub address: String,
+  /// The server's identification string, e.g. `SSH-2.0-OpenSSH_9.6`
+  pub version: String,
+  /// The message some servers send before authentication, e.g. a legal notice
+  pub banner: Option<String>,
+  pub host_key: String,
+  pub kex: String,
+  pub cipher: String,
+  pub mac: String,
+  pub compression: String,
+}
+
+impl ServerInfo {
+  /// Everything worth knowing about an authenticated session
+  pub fn from(sess: &Session, conf: &Config) -> Self {
+    let method = |kind| sess.methods(kind).unwrap_or("unknown").to_string();
+    // Client-to-server and server-to-client algorithms are negotiated separately
+    let both = |out, inbound| match (method(out), method(inbound)) {
+      (out, inbound) if out == inbound => out,
+      (out, inbound) => format!("{out} (out), {inbound} (in)"),
+    };
+    Self {
+      address: format!("{} ({}), port {}", conf.host, conf.addr, conf.port),
+      version: sess.banner().unwrap_or("unknown").to_string(),
+      banner: sess
+        .userauth_banner()
+        .ok()
+        .flatten()
+        .map(|banner| banner.trim_end().to_string())
+        .filter(|banner| !banner.is_empty()),
+      host_key: format!(
+        "{} {}",
+        method(MethodType::HostKey),
+        host_keys::fingerprint(sess)
+      ),
+      kex: method(MethodType::Kex),
+      cipher: both(MethodType::CryptCs, MethodType::CryptSc),
+      mac: both(MethodType::MacCs, MethodType::MacSc),
+      compression: both(MethodType::CompCs, MethodType::CompSc),
+    }
+  }
+
+  /// One `label: value` line for each, with the banner last
+  pub fn describe(&self) -> String {
+    let mut text = format!(
+      "Address: {}\nServer: {}\nHost key: {}\nKey exchange: {}\nCipher: {}\nMAC: {}\nCompression: {}",
+      self.address, self.version, self.host_key, self.kex, self.cipher, self.mac, self.compression
+    );
+    if let Some(banner) = &self.banner {
+      text.push_str(&format!("\n\n{banner}"));
+    }
+    text
+  }
+}
+
+impl Connection {
+  /// Connect and authenticate, asking questions on the terminal if need be
+  pub fn open(mut conf: Config, name: &str) -> Result<Self, GsftpError> {
+    info!(name, "opening a connection");
+    let (sess, auth_method) = sftp::authenticate(&conf).inspect_err(|_| {
+      if let AuthMethod::Password(pwd) = &conf.auth_method {
+        if conf.keyring && credentials::password(&conf).as_ref() == Some(pwd) {
+          warn!("The password saved in the system keyring didn't work, so it's been forgotten");
+          credentials::forget_password(&conf);
+        }
+      }
+    })?;
+    let info = ServerInfo::from(&sess, &conf);
+    if conf.keyring {
+      credentials::remember(&conf, &auth_method);
+    }
+    // Reconnects go straight to whatever worked
+    conf.auth_method = auth_method.clone();
+    let start_dir = conf.start_dir.clone();
+    let id = format!("{}@{}:{}", conf.user, conf.host, conf.port);
+    // Transfers check their own SFTP channels out of the pool
+    let pool = SessionPool::new(sess, conf);
+    let sftp = pool.get()?;
+    let cwd = app_utils::remote_dir(&pool.session(), &sftp, start_dir.as_deref()).unwrap_or_else(
+      |(home, e)| {
+        warn!("{e}");
+        home
+      },
+    );
+    let flavor = Flavor::detect(&pool.session());
+    let health = Monitor::start(Arc::clone(&pool));
+
+    Ok(Self {
+      name: String::from(name),
+      id,
+      pool,
+      sftp,
+      cwd,
+      listing: Vec::new(),
+      info,
+      auth_method,
+      flavor,
+      health,
+    })
+  }
+}
+
+/// Every open connection, and which one the remote pane is showing
+pub struct Connections {
+  list: Vec<Connection>,
+  active: usize,
+}
+
+impl Connections {
+  pub fn new(first: Connection) -> Self {
+    Self {
+      list: vec![first],
+      active: 0,
+    }
+  }
+
+  /// The connection the remote pane is showing
+  pub fn active(&self) -> &Connection {
+    &self.list[self.active]
+  }
+
+  /// Connection `i`, counting from 0 in the order they were opened
+  pub fn get(&self, i: usize) -> Option<&Connection> {
+    self.list.get(i)
+  }
+
+  /// Which connection the remote pane is showing, counting from 0
+  pub fn active_index(&self) -> usize {
+    self.active
+  }
+
+  /// How many connections are open; there's always at least one
+  pub fn count(&self) -> usize {
+    self.list.len()
+  }
+
+  /// Open another connection and show it
+  pub fn add(&mut self, connection: Connection, app: &mut App) {
+    self.list.push(connection);
+    self.switch(self.list.len() - 1, app);
+  }
+
+  /// Show the next connection, wrapping around after the last
+  pub fn cycle(&mut self, app: &mut App) {
+    self.switch((self.active + 1) % self.list.len(), app);
+  }
+
+  /// Every connection's id with the directories it was left in, for `resume::save`
+  pub fn last_dirs(&self, app: &App) -> Vec<LastDirs> {
+    self
+      .list
+      .iter()
+      .enumerate()
+      .map(|(i, connection)| {
+        let remote = match i == self.active {
+          true => app.buf.remote.clone(),
+          false => connection.cwd.clone(),
+        };
+        (connection.id.clone(), app.buf.local.clone(), remote)
+      })
+      .collect()
+  }
+
+  /// Show connection `i` (counting from 0) in the remote pane, keeping the current one's place.
+  /// Yields false if there's no such connection.
+  pub fn switch(&mut self, i: usize, app: &mut App) -> bool {
+    if i >= self.list.len() {
+      return false;
+    }
+    let current = &mut self.list[self.active];
+    current.cwd = app.buf.remote.clone();
+    current.listing = std::mem::take(&mut app.content.remote);
+    self.attach(i, app);
+    let next = &mut self.list[i];
+    app.buf.remote = next.cwd.clone();
+    app.content.remote = std::mem::take(&mut next.listing);
+    app.content.remote_marks.clear();
+    app.list_remote(&*next.sftp);
+    app.state.remote.select(Some(0));
+    true
+  }
+
+  /// Make connection `i` (counting from 0) the one the remote pane is on, without moving the pane,
+  /// which is already somewhere on that host (e.g. it belongs to a tab being switched to)
+  pub fn attach(&mut self, i: usize, app: &mut App) {
+    self.active = i;
+    let next = &self.list[i];
+    app.remote_flavor = next.flavor;
+    app.remote_id = next.id.clone();
+    app.server_info = next.info.describe();
+    app.hosts = (i, self.list.len());
+  }
+}
@@ -0,0 +1,73 @@
+//! Passwords and key passphrases kept in the system keyring
+//! (Secret Service on Linux, Keychain on macOS, Credential Manager on Windows)
+use keyring::Entry;
+use std::path::Path;
+
+use crate::config::{AuthMethod, Config};
+
+const SERVICE: &str = env!("CARGO_PKG_NAME");
+
+/// The password saved for this user, host and port, if there is one
+pub fn password(conf: &Config) -> Option<String> {
+  get(&password_key(conf))
+}
+
+/// Remember `password` for this user, host and port
+pub fn store_password(conf: &Config, password: &str) {
+  set(&password_key(conf), password);
+}
+
+/// Forget the password saved for this user, host and port, e.g. because it stopped working
+pub fn forget_password(conf: &Config) {
+  if let Ok(entry) = Entry::new(SERVICE, &password_key(conf)) {
+    let _ = entry.delete_password();
+  }
+}
+
+/// The passphrase saved for the private key `identity_file`, if there is one
+pub fn passphrase(identity_file: &Path) -> Option<String> {
+  get(&passphrase_key(identity_file))
+}
+
+/// Remember `passphrase` for the private key `identity_file`
+pub fn store_passphrase(identity_file: &Path, passphrase: &str) {
+  set(&passphrase_key(identity_file), passphrase);
+}
+
+/// Save whatever secret `method` just authenticated with, unless the keyring already has it
+pub fn remember(conf: &Config, method: &AuthMethod) {
+  match method {
+    AuthMethod::Password(pwd) if password(conf).as_ref() != Some(pwd) => store_password(conf, pwd),
+    AuthMethod::PrivateKey(key) => {
+      let key = Path::new(key);
+      match &conf.passphrase {
+        Some(phrase) if passphrase(key).as_ref() != Some(phrase) => store_passphrase(key, phrase),
+        _ => {}
+      }
+    }
+    _ => {}
+  }
+}
+
+fn password_key(conf: &Config) -> String {
+  format!("password:{}@{}:{}", conf.user, conf.addr, conf.port)
+}
+
+fn passphrase_key(identity_file: &Path) -> String {
+  let path = identity_file
+    .canonicalize()
+    .unwrap_or_else(|_| identity_file.to_path_buf());
+  format!("passphrase:{}", path.display())
+}
+
+// Having no keyring (say, on a headless server) just means nothing is ever found
+fn get(key: &str) -> Option<String> {
+  Entry::new(SERVICE, key).ok()?.get_password().ok()
+}
+
+fn set(key: &str, secret: &str) {
+  let stored = Entry::new(SERVICE, key).and_then(|entry| entry.set_password(secret));
+  if let Err(e) = stored {
+    tracing::warn!("Couldn't save to the system keyring: {e}");
+  }
+}
@@ -0,0 +1,130 @@
+//! Everything there is to know about one item, local or remote, for the details popup
+use chrono::{DateTime, Local, TimeZone};
+use ssh2::{Session, Sftp};
+use std::fs;
+use std::path::Path;
+
+use crate::app_utils::{human_size, rwx, thousands};
+use crate::error::GsftpError;
+use crate::sftp;
+
+// e.g. `1.2 MB (1,234,567 bytes)`
+fn size(bytes: u64) -> String {
+  format!("{} ({} bytes)", human_size(bytes), thousands(bytes))
+}
+
+// e.g. `rw-r--r-- (0644)`
+fn permissions(perm: u32) -> String {
+  format!("{} ({:04o})", rwx(perm), perm & 0o7777)
+}
+
+fn time(time: DateTime<Local>) -> String {
+  time.format("%Y-%m-%d %H:%M:%S %Z").to_string()
+}
+
+/// The local item at `path`: its size, permissions, owner, timestamps and, for a symlink,
+/// where it points
+pub fn local(path: &Path) -> Result<String, GsftpError> {
+  let meta = fs::symlink_metadata(path)?;
+  let kind = if meta.is_symlink() {
+    "symlink"
+  } else if meta.is_dir() {
+    "directory"
+  } else if meta.is_file() {
+    "file"
+  } else {
+    "other"
+  };
+  let mut lines = vec![
+    format!("Path:        {}", path.display()),
+    format!("Type:        {kind}"),
+    format!("Size:        {}", size(meta.len())),
+  ];
+  if meta.is_symlink() {
+    let target = fs::read_link(path)?;
+    lines.push(format!("Points to:   {}", target.display()));
+  }
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::MetadataExt;
+    lines.push(format!("Permissions: {}", permissions(meta.mode())));
+    lines.push(format!(
+      "Owner:       uid {}, gid {}",
+      meta.uid(),
+      meta.gid()
+    ));
+  }
+  #[cfg(windows)]
+  lines.push(format!("Read-only:   {}", meta.permissions().readonly()));
+  let times = [
+    ("Modified:   ", meta.modified()),
+    ("Accessed:   ", meta.accessed()),
+    ("Created:    ", meta.created()),
+  ];
+  for (label, when) in times {
+    // Not every filesystem keeps every one of these
+    if let Ok(when) = when {
+      lines.push(format!("{label} {}", time(DateTime::<Local>::from(when))));
+    }
+  }
+  Ok(lines.join("\n"))
+}
+
+/// The remote item at `path`, as `lstat` sees it: the same as for a local item, with owner
+/// names looked up on the server where remote commands are allowed, and the raw `FileStat`
+pub fn remote(sess: &Session, sftp: &Sftp, path: &Path) -> Result<String, GsftpError> {
+  let stat = sftp.lstat(path)?;
+  let file_type = stat.file_type();
+  let kind = if file_type.is_symlink() {
+    "symlink"
+  } else if file_type.is_dir() {
+    "directory"
+  } else if file_type.is_file() {
+    "file"
+  } else {
+    "other"
+  };
+  let mut lines = vec![
+    format!("Path:        {}", path.display()),
+    format!("Type:        {kind}"),
+  ];
+  if let Some(bytes) = stat.size {
+    lines.push(format!("Size:        {}", size(bytes)));
+  }
+  if file_type.is_symlink() {
+    let target = sftp.readlink(path)?;
+    lines.push(format!("Points to:   {}", target.display()));
+  }
+  if let Some(perm) = stat.perm {
+    lines.push(format!("Permissions: {}", permissions(perm)));
+  }
+  if let (Some(uid), Some(gid)) = (stat.uid, stat.gid) {
+    let name = |program: &str, args: &[&str], field: usize| {
+      sftp::exec(sess, program, args)
+        .ok()
+        .and_then(|output| output.trim().split(':').nth(field).map(str::to_string))
+        .filter(|name| !name.is_empty())
+    };
+    let (uid_text, gid_text) = (uid.to_string(), gid.to_string());
+    let user = name("id", &["-nu", &uid_text], 0);
+    let group = name("getent", &["group", &gid_text], 0);
+    let named = |id: u32, name: Option<String>| match name {
+      Some(name) => format!("{name} ({id})"),
+      None => id.to_string(),
+    };
+    lines.push(format!(
+      "Owner:       {}, group {}",
+      named(uid, user),
+      named(gid, group)
+    ));
+  }
+  let times = [("Modified:   ", stat.mtime), ("Accessed:   ", stat.atime)];
+  for (label, secs) in times {
+    if let Some(when) = secs.and_then(|secs| Local.timestamp_opt(secs as i64, 0).single()) {
+      lines.push(format!("{label} {}", time(when)));
+    }
+  }
+  lines.push(String::new());
+  lines.push(format!("{stat:?}"));
+  Ok(lines.join("\n"))
+}
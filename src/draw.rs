@@ -1,237 +1,1103 @@
 //! Drawing items to the terminal
+use chrono::{DateTime, Local};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tui::{
   backend::Backend,
-  layout::{Constraint, Direction, Layout, Rect},
+  buffer::Buffer,
+  layout::{Alignment, Constraint, Direction, Layout, Rect},
   style::{Color, Modifier, Style},
-  widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table},
+  text::{Span, Spans},
+  widgets::{
+    Block, Borders, Clear, List, ListItem, ListState, Paragraph, Row, Table, Tabs, Widget, Wrap,
+  },
   Frame, Terminal,
 };
+use unicode_width::UnicodeWidthStr;
 
-use crate::app::App;
-use crate::app_utils::ActiveState;
+use crate::app::{App, Picker, PickerKind, Viewer};
+use crate::app_utils::{self, ActiveState, Comparison, Entry, FileClass, Totals};
+use crate::connection;
+use crate::file_transfer::{TransferKind, TransferState, THROUGHPUT_SECONDS};
+use crate::health::Health;
+use crate::i18n;
+use crate::tabs;
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How much a message matters, which decides its color and how long it stays up
+pub enum Severity {
+  Info,
+  Success,
+  Error,
+}
+
+impl Severity {
+  fn ttl(&self) -> Duration {
+    match self {
+      Self::Info | Self::Success => Duration::from_secs(4),
+      // Long enough to read something unexpected
+      Self::Error => Duration::from_secs(10),
+    }
+  }
+
+  fn color(&self, theme: &Theme) -> Color {
+    match self {
+      Self::Info => theme.message,
+      Self::Success => theme.success,
+      Self::Error => theme.error,
+    }
+  }
+
+  fn label(&self) -> &'static str {
+    match self {
+      Self::Info => "INFO ",
+      Self::Success => "OK   ",
+      Self::Error => "ERROR",
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+/// A message that was shown, kept in the message log
+pub struct LogEntry {
+  pub time: DateTime<Local>,
+  pub severity: Severity,
+  pub text: String,
+}
+
+// A message shown in the bottom right corner until it expires
+struct Toast {
+  text: String,
+  severity: Severity,
+  expires: Instant,
+}
 
 /// Contains information about window text, allows for drawing to the terminal
 pub struct UiWindow {
-  text: Option<String>,
-  style: Option<TextStyle>,
+  // Messages still showing, oldest first
+  toasts: Vec<Toast>,
+  // Every message shown this session, oldest first, for the message log panel (see `App::log`)
+  log: Vec<LogEntry>,
 }
 
 impl UiWindow {
+  /// The message log forgets its oldest messages past this many
+  const LOG_LIMIT: usize = 1000;
+  /// No more than this many messages are shown at once; older ones make way for newer
+  const MAX_TOASTS: usize = 4;
+
   pub fn new(text: &str) -> Self {
-    let text = Some(text.to_string());
-    let style = Some(TextStyle::default());
-    Self { text, style }
+    let mut window = Self {
+      toasts: Vec::new(),
+      log: Vec::new(),
+    };
+    window.show(text, Severity::Info);
+    window
   }
 
-  pub fn reset(&mut self) {
-    self.text = None;
-    self.style = None;
+  pub fn flashing_text(&mut self, text: &str) {
+    self.show(text, Severity::Info);
+    self.remember(text, Severity::Info);
   }
 
-  pub fn flashing_text(&mut self, text: &str) {
-    self.text = Some(String::from(text));
-    self.style = Some(TextStyle::flash());
+  /// Something the user asked for has finished, e.g. a transfer
+  pub fn success_message(&mut self, text: &str) {
+    self.show(text, Severity::Success);
+    self.remember(text, Severity::Success);
   }
 
   pub fn error_message(&mut self, text: &str) {
-    self.text = Some(String::from(text));
-    self.style = Some(TextStyle::error());
+    tracing::error!("{text}");
+    self.show(text, Severity::Error);
+    self.remember(text, Severity::Error);
   }
 
-  /// Draw UI
-  pub fn draw<B: Backend>(&self, terminal: &mut Terminal<B>, app: &mut App) {
-    match self.text {
-      Some(_) => text_alert(terminal, app, self),
-      None => basic_ui(terminal, app),
+  fn show(&mut self, text: &str, severity: Severity) {
+    // The same message again just stays up for longer
+    self.toasts.retain(|toast| toast.text != text);
+    if self.toasts.len() == Self::MAX_TOASTS {
+      self.toasts.remove(0);
     }
+    self.toasts.push(Toast {
+      text: text.to_string(),
+      severity,
+      expires: Instant::now() + severity.ttl(),
+    });
   }
-}
-
-impl Default for UiWindow {
-  fn default() -> Self {
-    Self::new("Press '?' to toggle help")
-  }
-}
-
-// This struct here reduces code repetition in main.rs and also prevents text styling
-// from being overlooked/changed from the default implementations.
-// Provides default implementations for text styling
-struct TextStyle {
-  color: Color,
-  modifier: Option<Modifier>,
-}
 
-impl Default for TextStyle {
-  fn default() -> Self {
-    Self {
-      color: Color::LightCyan,
-      modifier: None,
+  fn remember(&mut self, text: &str, severity: Severity) {
+    if self.log.len() == Self::LOG_LIMIT {
+      self.log.remove(0);
     }
+    self.log.push(LogEntry {
+      time: Local::now(),
+      severity,
+      text: text.to_string(),
+    });
   }
-}
 
-impl TextStyle {
-  fn from(text_style: &Self) -> Self {
-    let color = text_style.color;
-    let modifier = text_style.modifier.as_ref().copied();
-    Self { color, modifier }
+  /// Writes every message in the log to `path`, one per line, oldest first
+  pub fn save_log(&self, path: &Path) -> io::Result<()> {
+    let text: String = self
+      .log
+      .iter()
+      .map(|entry| {
+        let time = entry.time.format("%Y-%m-%d %H:%M:%S");
+        format!("{time} {} {}\n", entry.severity.label(), entry.text)
+      })
+      .collect();
+    fs::write(path, text)
   }
 
-  fn flash() -> Self {
-    Self {
-      color: Color::Cyan,
-      modifier: Some(Modifier::SLOW_BLINK | Modifier::ITALIC),
+  /// Draw UI. It's an error if the terminal can't be written to.
+  pub fn draw<B: Backend>(&mut self, terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
+    let now = Instant::now();
+    self.toasts.retain(|toast| toast.expires > now);
+    // the app moves the log's highlight around without knowing how many messages there are,
+    // so it's kept to the last of them here
+    if let Some(state) = app.log.as_mut() {
+      let last = self.log.len().saturating_sub(1);
+      state.select(Some(state.selected().unwrap_or(0).min(last)));
     }
+    basic_ui(terminal, app, &self.toasts, &self.log)
   }
+}
 
-  fn error() -> Self {
-    Self {
-      color: Color::Red,
-      modifier: Some(Modifier::BOLD | Modifier::ITALIC),
-    }
+impl Default for UiWindow {
+  fn default() -> Self {
+    Self::new(i18n::tr("Press '?' to toggle help"))
   }
 }
 
 // Draw a windowed terminal for our contents - the left window for our local connection,
-// and the right window for our remote connection.
-// Also draw a help menu (keyboard shortcuts) if the --shortcuts flag was used.
-fn basic_ui<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) {
+// and the right window for our remote connection, with the status bar below and any messages
+// stacked in the bottom right corner of the windows.
+// Also draw the keyboard help while it's open (from the start, with --shortcuts),
+// and the message log under the windows while it's open.
+fn basic_ui<B: Backend>(
+  terminal: &mut Terminal<B>,
+  app: &mut App,
+  toasts: &[Toast],
+  log: &[LogEntry],
+) -> io::Result<()> {
   terminal
     .draw(|f| {
-      if app.show_help {
+      if app.help.is_some() {
         let chunks = Layout::default()
-          .constraints([Constraint::Ratio(3, 4), Constraint::Ratio(1, 4)].as_ref())
+          .constraints(
+            [
+              Constraint::Min(3),
+              Constraint::Length(1),
+              Constraint::Percentage(25),
+            ]
+            .as_ref(),
+          )
           .split(f.size());
-        windows(f, chunks[0], app);
-        help(f, chunks[1]);
+        windows_and_log(f, chunks[0], app, log);
+        status_bar(f, chunks[1], app);
+        help(f, chunks[2], app);
+        toast_stack(f, chunks[0], toasts, &app.theme);
       } else {
         let chunks = Layout::default()
-          .constraints([Constraint::Ratio(24, 25), Constraint::Ratio(1, 25)].as_ref())
+          .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
           .split(f.size());
-        windows(f, chunks[0], app);
+        windows_and_log(f, chunks[0], app, log);
+        status_bar(f, chunks[1], app);
+        toast_stack(f, chunks[0], toasts, &app.theme);
+      }
+      if let Some(question) = &app.confirm {
+        confirm_dialog(f, question);
+      }
+      match &app.prompt {
+        // typed into the status bar instead, so the pane being searched stays visible
+        Some(prompt) if prompt.kind.inline() => {}
+        Some(prompt) => input_dialog(f, &prompt.question, &prompt.shown()),
+        None => {}
+      }
+      if let Some((title, info)) = &app.info {
+        info_dialog(f, title, info);
+      }
+      if let Some(picker) = &app.picker {
+        picker_dialog(f, picker, app.ascii);
+      }
+      if let Some(viewer) = &app.viewer {
+        viewer_dialog(f, viewer);
+      }
+      // Last of all, so that they catch everything else drawn
+      if app.no_color {
+        f.render_widget(NoColor(app.theme.error), f.size());
+      }
+      if app.ascii {
+        f.render_widget(AsciiOnly, f.size());
       }
     })
-    .unwrap_or_else(|e| {
-      eprintln!("Fatal error writing to terminal: {e}");
-      std::process::exit(1);
-    });
+    .map(|_| ())
 }
 
-// Divides an area into two windows & renders them using a helper function `contents_block`
-fn windows<B: Backend>(f: &mut Frame<B>, area: Rect, app: &mut App) {
+// The windows, with the message log underneath them while it's open, and the tab bar above
+// them once there's more than one tab
+fn windows_and_log<B: Backend>(f: &mut Frame<B>, area: Rect, app: &mut App, log: &[LogEntry]) {
+  let area = match app.tabs.is_empty() {
+    true => area,
+    false => {
+      let chunks = Layout::default()
+        .constraints([Constraint::Length(1), Constraint::Min(3)].as_ref())
+        .split(area);
+      tab_bar(f, chunks[0], app);
+      chunks[1]
+    }
+  };
+  if app.log.is_none() {
+    return windows(f, area, app);
+  }
   let chunks = Layout::default()
-    .direction(Direction::Horizontal)
-    .constraints([Constraint::Percentage(50); 2].as_ref())
+    .constraints([Constraint::Min(3), Constraint::Length(12)].as_ref())
+    .split(area);
+  windows(f, chunks[0], app);
+  if let Some(state) = app.log.as_mut() {
+    log_panel(f, chunks[1], &app.theme, log, state);
+  }
+}
+
+// Every tab's title, with the one showing highlighted
+fn tab_bar<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+  let titles = app
+    .tabs
+    .iter()
+    .enumerate()
+    .map(|(i, title)| match i == app.tab {
+      // The active tab's panes may have moved since the titles were made
+      true => Spans::from(tabs::title(i + 1, &app.buf, &app.remote_id)),
+      false => Spans::from(title.as_str()),
+    })
+    .collect();
+  let bar = Tabs::new(titles)
+    .select(app.tab)
+    .style(Style::default().fg(Color::Gray))
+    .highlight_style(
+      Style::default()
+        .fg(app.theme.active_border)
+        .add_modifier(app.theme.active_title),
+    );
+  f.render_widget(bar, area);
+}
+
+// Every message shown this session with the time it was shown, colored by severity
+fn log_panel<B: Backend>(
+  f: &mut Frame<B>,
+  area: Rect,
+  theme: &Theme,
+  entries: &[LogEntry],
+  state: &mut ListState,
+) {
+  let mut items: Vec<ListItem> = entries
+    .iter()
+    .map(|entry| {
+      let color = entry.severity.color(theme);
+      ListItem::new(format!("{} {}", entry.time.format("%H:%M:%S"), entry.text))
+        .style(Style::default().fg(color))
+    })
+    .collect();
+  if items.is_empty() {
+    items.push(ListItem::new(i18n::tr("No messages yet")).style(Style::default().fg(Color::Gray)));
+  }
+  let title = i18n::tr("Messages (s: save to a file, M: close)");
+  let list = List::new(items)
+    .block(Block::default().title(title).borders(Borders::ALL))
+    .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    .highlight_symbol(">>");
+  f.render_stateful_widget(list, area, state);
+}
+
+// Terminals narrower than this have their windows one above the other
+const NARROW: u16 = 80;
+
+// Divides an area into two windows & renders them using a helper function `contents_block`.
+// Running transfers each get a progress row underneath the windows, unless the whole queue is open.
+fn windows<B: Backend>(f: &mut Frame<B>, area: Rect, app: &mut App) {
+  let running = app
+    .transfers
+    .iter()
+    .filter(|t| t.is_running() || t.is_scheduled())
+    .count() as u16;
+  let area = if app.queue.is_some() {
+    let rows = app.transfers.len().clamp(1, 10) as u16;
+    let chunks = Layout::default()
+      .constraints([Constraint::Min(3), Constraint::Length(rows + 2)].as_ref())
+      .split(area);
+    queue_panel(f, chunks[1], app);
+    chunks[0]
+  } else if running > 0 {
+    let chunks = Layout::default()
+      .constraints([Constraint::Min(3), Constraint::Length(running + 2)].as_ref())
+      .split(area);
+    transfer_rows(f, chunks[1], app);
+    chunks[0]
+  } else {
+    area
+  };
+  // Side by side, the windows get too narrow to use, so they're stacked instead
+  let direction = match area.width < NARROW {
+    true => Direction::Vertical,
+    false => Direction::Horizontal,
+  };
+  let (local_width, remote_width) = app.pane_widths();
+  let panes = Layout::default()
+    .direction(direction)
+    .constraints(
+      [
+        Constraint::Percentage(local_width),
+        Constraint::Percentage(remote_width),
+      ]
+      .as_ref(),
+    )
     .split(area);
+  // Each pane has a footer line under it
+  let split = |area: Rect| {
+    Layout::default()
+      .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+      .split(area)
+  };
+  let (local_area, remote_area) = (split(panes[0]), split(panes[1]));
+  let chunks = [local_area[0], remote_area[0]];
+  let footer = |totals: Totals, free: Option<u64>| {
+    let text = match free {
+      Some(free) => format!(
+        " {}, {} free",
+        totals.describe(),
+        app_utils::human_size(free)
+      ),
+      None => format!(" {}", totals.describe()),
+    };
+    Paragraph::new(text).style(Style::default().fg(Color::Gray))
+  };
+  let local_totals = Totals::of(&app.content.local, &app.content.local_marks);
+  f.render_widget(footer(local_totals, app.local_free.1), local_area[1]);
+  let remote_totals = Totals::of(&app.content.remote, &app.content.remote_marks);
+  f.render_widget(footer(remote_totals, app.remote_free.1), remote_area[1]);
 
   let local_is_active = matches!(app.state.active, ActiveState::Local);
-  let local_block = contents_block(local_is_active, &app.buf.local, &app.content.local);
-  f.render_stateful_widget(local_block, chunks[0], &mut app.state.local);
+  let sort = app.content.sort.describe();
+  // e.g. `deploy@example.com:/srv/…/html [name] /log`, with as much of the middle of the path
+  // taken out as it takes to fit between the corners
+  let title = |host: &str, path: &Path, filter: &str, area: Rect| {
+    let suffix = match filter {
+      "" => format!(" [{sort}]"),
+      query => format!(" [{sort}] /{query}"),
+    };
+    let room = (area.width as usize).saturating_sub(host.width() + suffix.width() + 3);
+    let path = app_utils::truncate_middle(&path.display().to_string(), room);
+    format!("{host}:{path}{suffix}")
+  };
+  let local_title = title(
+    &app.local_host,
+    &app.buf.local,
+    &app.content.local_filter,
+    chunks[0],
+  );
+  // Room for each line inside the borders and the highlight symbol
+  let width = |area: Rect| area.width.saturating_sub(4) as usize;
+  // With nothing listed, there's nothing to highlight either
+  if app.content.local.is_empty() {
+    let text = placeholder(&app.content.local_unreadable, &app.content.local_filter);
+    f.render_widget(placeholder_block(local_title, text), chunks[0]);
+  } else {
+    let local_block = contents_block(
+      local_is_active,
+      local_title,
+      &app.content.local,
+      &app.content.local_marks,
+      &app_utils::by_name(app.content.all_remote()),
+      app,
+      width(chunks[0]),
+    );
+    f.render_stateful_widget(local_block, chunks[0], &mut app.state.local);
+  }
 
-  let remote_block = contents_block(!local_is_active, &app.buf.remote, &app.content.remote);
-  f.render_stateful_widget(remote_block, chunks[1], &mut app.state.remote);
+  // which of the connected hosts it is, e.g. `[2/3]`, once there's more than one
+  let remote_host = match app.hosts {
+    (i, count) if count > 1 => format!("[{}/{count}] {}", i + 1, connection::login(&app.remote_id)),
+    _ => connection::login(&app.remote_id).to_string(),
+  };
+  let remote_title = title(
+    &remote_host,
+    &app.buf.remote,
+    &app.content.remote_filter,
+    chunks[1],
+  );
+  if app.content.remote.is_empty() {
+    let text = placeholder(&app.content.remote_unreadable, &app.content.remote_filter);
+    f.render_widget(placeholder_block(remote_title, text), chunks[1]);
+  } else {
+    let remote_block = contents_block(
+      !local_is_active,
+      remote_title,
+      &app.content.remote,
+      &app.content.remote_marks,
+      &app_utils::by_name(app.content.all_local()),
+      app,
+      width(chunks[1]),
+    );
+    f.render_stateful_widget(remote_block, chunks[1], &mut app.state.remote);
+  }
 }
 
-// Draws the contents of each window
-fn contents_block<'a>(active: bool, buf: &'a std::path::Path, contents: &'a [String]) -> List<'a> {
-  let items: Vec<ListItem> = contents.iter().map(|s| ListItem::new(s.as_ref())).collect();
-  let highlight_color = if active { Color::Cyan } else { Color::Blue };
+// What an empty window says instead, given why its directory couldn't be read, if it couldn't
+fn placeholder(unreadable: &Option<String>, filter: &str) -> String {
+  match (unreadable, filter) {
+    (Some(reason), _) => format!("({reason})"),
+    (None, "") => String::from("(empty)"),
+    (None, _) => String::from("(nothing matches)"),
+  }
+}
+
+// An empty window, with `text` in place of its contents
+fn placeholder_block<'a>(title: String, text: String) -> List<'a> {
+  List::new(vec![ListItem::new(text)])
+    .block(Block::default().title(title).borders(Borders::ALL))
+    .style(Style::default().fg(Color::DarkGray))
+}
+
+// Draws the contents of each window. Names too long for the `width` to fill end in `…`, and with
+// details on, each name is followed by its details, lined up on the right, as long as that
+// leaves some room for the name.
+// Names that are also among the `others` in the other window get a `=` after them if the sizes
+// match and `≠` if they don't, and with `compare` every name is coloured by how it compares.
+fn contents_block<'a>(
+  active: bool,
+  title: String,
+  contents: &'a [Entry],
+  marks: &HashSet<String>,
+  others: &HashMap<&OsStr, &Entry>,
+  app: &App,
+  width: usize,
+) -> List<'a> {
+  let theme = &app.theme;
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs();
+  // Anything modified since then stands out, e.g. what a deploy just changed
+  let recent_since = match theme.recent_minutes {
+    0 => None,
+    minutes => Some(now.saturating_sub(minutes * 60)),
+  };
+  let items: Vec<ListItem> = contents
+    .iter()
+    .enumerate()
+    .map(|(i, entry)| {
+      let class = entry.class();
+      let icon = theme.icons.of(class);
+      let (name, style) = match class {
+        FileClass::Dir => (
+          format!("{icon}{}/", entry.name),
+          Style::default().fg(theme.directory),
+        ),
+        FileClass::Symlink => (
+          format!(
+            "{icon}{} -> {}",
+            entry.name,
+            entry.target.as_deref().unwrap_or("?")
+          ),
+          Style::default().fg(theme.symlink),
+        ),
+        FileClass::Executable => (
+          format!("{icon}{}", entry.name),
+          Style::default().fg(theme.executable),
+        ),
+        FileClass::Archive => (
+          format!("{icon}{}", entry.name),
+          Style::default().fg(theme.archive),
+        ),
+        FileClass::Image => (
+          format!("{icon}{}", entry.name),
+          Style::default().fg(theme.image),
+        ),
+        FileClass::Other => (format!("{icon}{}", entry.name), Style::default()),
+      };
+      let style = match (recent_since, entry.mtime) {
+        (Some(since), Some(mtime)) if mtime >= since => style.fg(theme.recent),
+        _ => style,
+      };
+      // Every other row has a background of its own, if the theme says so
+      let style = match theme.stripe {
+        Color::Reset => style,
+        stripe if i % 2 == 1 => style.bg(stripe),
+        _ => style,
+      };
+      let comparison = Comparison::of(entry, others);
+      let name = match comparison {
+        Comparison::Missing => name,
+        Comparison::Differs => format!("{name} ≠"),
+        Comparison::Newer | Comparison::Same => format!("{name} ="),
+      };
+      let style = match (app.compare, comparison) {
+        (false, _) => style,
+        (true, Comparison::Missing) => style.fg(Color::LightGreen),
+        (true, Comparison::Differs) => style.fg(Color::LightRed),
+        (true, Comparison::Newer) => style.fg(Color::LightMagenta),
+        (true, Comparison::Same) => style.fg(Color::DarkGray),
+      };
+      // The `*` in front of marked items takes a column of its own
+      let marked = marks.contains(&entry.name);
+      let width = width.saturating_sub(usize::from(marked));
+      // Narrow windows get just the size, as long as that still leaves room for the name
+      let details = match app.show_details {
+        true if width >= entry.details().len() + 8 => Some(entry.details()),
+        true if width >= entry.short_details().len() + 8 => Some(entry.short_details()),
+        _ => None,
+      };
+      let text = match details {
+        Some(details) => {
+          let room = width - details.len() - 1;
+          let name = app_utils::truncate(&name, room);
+          format!("{} {details}", app_utils::pad(&name, room))
+        }
+        None => app_utils::truncate(&name, width),
+      };
+      // Marked items stand out, with a `*` in front
+      match marked {
+        true => ListItem::new(format!("*{text}")).style(style.fg(Color::LightYellow)),
+        false => ListItem::new(text).style(style),
+      }
+    })
+    .collect();
+  // Color alone isn't enough to tell the active window on every terminal, so its title stands
+  // out and the other window's text is dimmed, as the theme says
+  let (highlight_color, border_style, title_style, text_style) = match active {
+    true => (
+      theme.selection,
+      Style::default().fg(theme.active_border),
+      Style::default()
+        .fg(theme.active_border)
+        .add_modifier(theme.active_title),
+      Style::default().fg(Color::White),
+    ),
+    false if theme.dim_inactive => (
+      theme.inactive_selection,
+      Style::default(),
+      Style::default(),
+      Style::default()
+        .fg(Color::White)
+        .add_modifier(Modifier::DIM),
+    ),
+    false => (
+      theme.inactive_selection,
+      Style::default(),
+      Style::default(),
+      Style::default().fg(Color::White),
+    ),
+  };
 
   List::new(items)
     .block(
       Block::default()
-        .title(buf.to_str().unwrap_or("Remote"))
-        .borders(Borders::ALL),
+        .title(Span::styled(title, title_style))
+        .borders(Borders::ALL)
+        .border_style(border_style),
     )
-    .style(Style::default().fg(Color::White))
+    .style(text_style)
     .highlight_style(
+      // White, so that directories stay readable when highlighted
       Style::default()
+        .fg(Color::White)
         .bg(highlight_color)
         .add_modifier(Modifier::BOLD),
     )
     .highlight_symbol(">>")
 }
 
-// A help text window which appears at the bottom of the screen when you press '?'
-fn help<B: Backend>(f: &mut Frame<B>, area: Rect) {
-  let help_table = Table::new(vec![
-    Row::new(vec![
-      "k or ⬆: move up",
-      "l or ➡: enter directory",
-      "g or Ctrl+⬆: page up",
-    ])
-    .style(Style::default().fg(Color::White)),
-    Row::new(vec![
-      "j or ⬇: move down",
-      "h or ⬅: exit directory",
-      "G or Ctrl+⬇: page down",
-    ])
-    .style(Style::default().fg(Color::White)),
-    Row::new(vec![
-      "y or ↩: download/upload",
-      "w or ↹: switch windows",
-      "a: toggle hidden files",
-    ])
-    .style(Style::default().fg(Color::White)),
-    Row::new(vec!["", "q or Esc: exit", "?: toggle help"]).style(Style::default().fg(Color::White)),
-  ])
-  .style(Style::default().fg(Color::LightYellow))
-  .block(
-    Block::default()
-      .title("Keyboard controls")
-      .borders(Borders::ALL),
-  )
-  .widths([Constraint::Ratio(1, 3); 4].as_ref());
-  f.render_widget(help_table, area);
+// One line per running or scheduled transfer: direction, name and how far along it is, after a
+// sparkline of its throughput over the last minute so stalls stand out
+fn transfer_rows<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+  // Narrow terminals show fewer seconds
+  let spark_width = (area.width as usize / 4).min(THROUGHPUT_SECONDS);
+  let items: Vec<ListItem> = app
+    .transfers
+    .iter()
+    .filter(|t| t.is_running() || t.is_scheduled())
+    .map(|t| {
+      let arrow = match t.kind {
+        TransferKind::Upload => "⬆",
+        TransferKind::Download => "⬇",
+        TransferKind::Relay => "⇄",
+      };
+      if let TransferState::Scheduled(when) = &t.state {
+        return ListItem::new(format!(
+          "{arrow} {} - {} scheduled {when}",
+          t.name,
+          app_utils::human_size(t.total),
+        ));
+      }
+      let percent = (t.bytes * 100)
+        .checked_div(t.total)
+        .unwrap_or_default()
+        .min(100);
+      let bytes = format!(
+        "{} / {}",
+        app_utils::human_size(t.bytes),
+        app_utils::human_size(t.total)
+      );
+      let speed = match t.throughput.back() {
+        Some(&speed) => format!("{}/s", app_utils::human_size(speed)),
+        None => String::new(),
+      };
+      let spark = sparkline(&t.throughput, spark_width);
+      // A directory's row says how many files are done, and which one is being copied
+      match (t.total_files, &t.current) {
+        (total_files, Some(current)) if total_files > 1 => ListItem::new(format!(
+          "{arrow} {spark:<spark_width$} {speed:>10}  {} - ({}/{} files, {bytes}) {percent}% - {current}",
+          t.name,
+          app_utils::thousands(t.files),
+          app_utils::thousands(total_files),
+        )),
+        _ => ListItem::new(format!(
+          "{arrow} {spark:<spark_width$} {speed:>10}  {} - {bytes} ({percent}%)",
+          t.name
+        )),
+      }
+    })
+    .collect();
+  let list = List::new(items)
+    .block(
+      Block::default()
+        .title(i18n::tr("Transfers"))
+        .borders(Borders::ALL),
+    )
+    .style(Style::default().fg(Color::LightCyan));
+  f.render_widget(list, area);
 }
 
-// Just like the normal UI, but with a message in the bottom right corner.
-fn text_alert<B: Backend>(terminal: &mut Terminal<B>, app: &mut App, window: &UiWindow) {
-  terminal
-    .draw(|f| {
-      let style = window
-        .style
-        .as_ref()
-        .map(TextStyle::from)
-        .unwrap_or_default();
-      let text = window.text.as_deref().unwrap_or("[missing text]");
-      if app.show_help {
-        let chunks = Layout::default()
-          .constraints(
-            [
-              Constraint::Percentage(70),
-              Constraint::Percentage(5),
-              Constraint::Percentage(25),
-            ]
-            .as_ref(),
-          )
-          .split(f.size());
-        windows(f, chunks[0], app);
-        right_aligned_text(f, chunks[1], text, style);
-        help(f, chunks[2]);
-      } else {
-        let chunks = Layout::default()
-          .constraints([Constraint::Ratio(24, 25), Constraint::Ratio(1, 25)].as_ref())
-          .split(f.size());
-        windows(f, chunks[0], app);
-        right_aligned_text(f, chunks[1], text, style);
-      }
+// The last `width` of `samples` as bars scaled to the biggest of them. Nothing at all is the
+// lowest bar, and anything more is at least one step above it.
+fn sparkline(samples: &VecDeque<u64>, width: usize) -> String {
+  const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+  let recent = samples.iter().skip(samples.len().saturating_sub(width));
+  let max = recent.clone().copied().max().unwrap_or_default();
+  recent
+    .map(|&sample| match sample {
+      0 => BARS[0],
+      _ => BARS[1 + (sample * 6 / max) as usize],
     })
-    .unwrap_or_else(|e| {
-      eprintln!("Fatal error writing to terminal: {e}");
-      std::process::exit(1);
-    });
+    .collect()
 }
 
-fn right_aligned_text<B: Backend>(f: &mut Frame<B>, area: Rect, text: &str, style: TextStyle) {
-  let paragraph = if let Some(modifier) = style.modifier {
-    Paragraph::new(text)
-      .style(Style::default().fg(style.color).add_modifier(modifier))
-      .alignment(tui::layout::Alignment::Right)
+// Every transfer this session, one per row, with the highlighted one ready to cancel or retry
+fn queue_panel<B: Backend>(f: &mut Frame<B>, area: Rect, app: &mut App) {
+  const BAR_WIDTH: u64 = 20;
+  let mut items: Vec<ListItem> = app
+    .transfers
+    .iter()
+    .map(|t| {
+      let direction = match t.kind {
+        TransferKind::Upload => "⬆ upload  ",
+        TransferKind::Download => "⬇ download",
+        TransferKind::Relay => "⇄ relay   ",
+      };
+      let (state, color) = match &t.state {
+        TransferState::Scheduled(when) => (format!("queued {when}"), Color::Gray),
+        TransferState::Running => (String::from("running"), Color::LightCyan),
+        TransferState::Done => (String::from("done"), Color::Green),
+        TransferState::Failed(message) => (format!("failed: {message}"), app.theme.error),
+        TransferState::Cancelled => (String::from("cancelled"), Color::Yellow),
+      };
+      let percent = match t.state {
+        TransferState::Done => 100,
+        _ => (t.bytes * 100)
+          .checked_div(t.total)
+          .unwrap_or_default()
+          .min(100),
+      };
+      let filled = (percent * BAR_WIDTH / 100) as usize;
+      let bar = format!(
+        "{}{}",
+        "█".repeat(filled),
+        "░".repeat(BAR_WIDTH as usize - filled)
+      );
+      let speed = match t.state {
+        TransferState::Scheduled(_) => String::new(),
+        _ => format!("{}/s", app_utils::human_size(t.speed())),
+      };
+      ListItem::new(format!(
+        "{direction} {bar} {percent:>3}% {speed:>11}  {}  {state}",
+        t.path.display()
+      ))
+      .style(Style::default().fg(color))
+    })
+    .collect();
+  if items.is_empty() {
+    items.push(ListItem::new(i18n::tr("No transfers yet")).style(Style::default().fg(Color::Gray)));
+  }
+  let title = i18n::tr("Transfers (x: cancel, r: retry, T: close)");
+  let list = List::new(items)
+    .block(Block::default().title(title).borders(Borders::ALL))
+    .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    .highlight_symbol(">>");
+  if let Some(state) = app.queue.as_mut() {
+    f.render_stateful_widget(list, area, state);
+  }
+}
+
+// The keyboard help at the bottom of the screen: what every key in the keymap does, laid out
+// across then down, and scrolled a row at a time
+fn help<B: Backend>(f: &mut Frame<B>, area: Rect, app: &mut App) {
+  const COLUMN_WIDTH: u16 = 36;
+  let columns = (area.width.saturating_sub(2) / COLUMN_WIDTH).clamp(1, 3) as usize;
+  let lines: Vec<String> = app
+    .help_lines()
+    .iter()
+    .map(|(keys, description)| format!("{keys}: {description}"))
+    .collect();
+  let rows: Vec<&[String]> = lines.chunks(columns).collect();
+  let per_page = (area.height.saturating_sub(2) as usize).max(1);
+  let Some(state) = app.help.as_mut() else {
+    return;
+  };
+  state.scroll = state.scroll.min(rows.len().saturating_sub(per_page));
+  let page = rows
+    .iter()
+    .skip(state.scroll)
+    .take(per_page)
+    .map(|row| Row::new(row.to_vec()).style(Style::default().fg(Color::White)));
+  let title = if state.searching {
+    i18n::tr_args("Keys matching /{}_", &[&state.query])
+  } else if !state.query.is_empty() {
+    i18n::tr_args(
+      "Keys matching /{} ({} found; / searches again, ? closes)",
+      &[&state.query, &lines.len().to_string()],
+    )
+  } else if rows.len() > per_page {
+    String::from(i18n::tr(
+      "Keyboard controls (j/k scroll, / search, ? close)",
+    ))
   } else {
-    Paragraph::new(text)
-      .style(Style::default().fg(style.color))
-      .alignment(tui::layout::Alignment::Right)
+    String::from(i18n::tr("Keyboard controls (/ search, ? close)"))
+  };
+  let widths = vec![Constraint::Ratio(1, columns as u32); columns];
+  let help_table = Table::new(page)
+    .style(Style::default().fg(Color::LightYellow))
+    .block(Block::default().title(title).borders(Borders::ALL))
+    .widths(&widths);
+  f.render_widget(help_table, area);
+}
+
+// The messages still showing, newest at the bottom, in the bottom right corner of `area`
+fn toast_stack<B: Backend>(f: &mut Frame<B>, area: Rect, toasts: &[Toast], theme: &Theme) {
+  let mut bottom = area.y + area.height;
+  for toast in toasts.iter().rev() {
+    if bottom < area.y + 3 {
+      break;
+    }
+    let width = (toast.text.chars().count() as u16 + 4).min(area.width * 2 / 3);
+    let rect = Rect {
+      x: area.x + area.width - width,
+      y: bottom - 3,
+      width,
+      height: 3,
+    };
+    let color = toast.severity.color(theme);
+    let paragraph = Paragraph::new(toast.text.as_str())
+      .style(Style::default().fg(Color::White))
+      .block(
+        Block::default()
+          .borders(Borders::ALL)
+          .border_style(Style::default().fg(color)),
+      );
+    f.render_widget(Clear, rect);
+    f.render_widget(paragraph, rect);
+    bottom -= 3;
+  }
+}
+
+// One line along the bottom: how the remote host is doing and who we're logged in as there,
+// which window is active and how it's sorted and filtered, whether hidden files are shown,
+// and how many transfers are still to finish. While a filter or name is being typed,
+// the line shows that instead.
+fn status_bar<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+  if let Some(prompt) = app.prompt.as_ref().filter(|p| p.kind.inline()) {
+    let paragraph = Paragraph::new(format!("{}{}_", prompt.question, prompt.input))
+      .style(Style::default().fg(Color::LightYellow));
+    f.render_widget(paragraph, area);
+    return;
+  }
+  // The whole of a name that's too long for its window, scrolled along if even that's too narrow
+  if let Some(name) = app.selected_name().filter(|_| app.show_full_name) {
+    let scroll = app.name_scroll.min(u16::MAX as usize) as u16;
+    let paragraph = Paragraph::new(name)
+      .style(Style::default().fg(Color::White))
+      .scroll((0, scroll));
+    f.render_widget(paragraph, area);
+    return;
+  }
+  let health = app.health;
+  let color = match health {
+    Health::Unknown => Color::Gray,
+    Health::Connected(_) => Color::Green,
+    Health::Slow(_) => Color::Yellow,
+    Health::Disconnected => app.theme.error,
+  };
+  let gray = Style::default().fg(Color::Gray);
+  let separator = || Span::styled(" │ ", Style::default().fg(Color::DarkGray));
+  let mut spans = Vec::new();
+  if let Some(mode) = app.input_mode().describe() {
+    let style = Style::default()
+      .fg(Color::Black)
+      .bg(Color::LightYellow)
+      .add_modifier(Modifier::BOLD);
+    spans.push(Span::styled(format!(" {mode} "), style));
+    spans.push(Span::raw(" "));
+  }
+  spans.push(Span::styled(
+    format!("● {}", health.describe()),
+    Style::default().fg(color),
+  ));
+  // Only worth mentioning once a listing is older than the usual refresh
+  let age = app.content.remote_listed.elapsed().as_secs();
+  if age >= 2 {
+    spans.push(Span::styled(format!(", listed {age}s ago"), gray));
+  }
+  spans.push(separator());
+  spans.push(Span::styled(
+    app.remote_id.clone(),
+    Style::default().fg(Color::White),
+  ));
+  if app.read_only {
+    let style = Style::default()
+      .fg(app.theme.error)
+      .add_modifier(Modifier::BOLD);
+    spans.push(Span::styled(" read-only", style));
+  }
+  spans.push(separator());
+  let pane = match app.state.active {
+    ActiveState::Local => "local",
+    ActiveState::Remote => "remote",
+  };
+  let mut mode = format!("{pane} window, sorted by {}", app.content.sort.describe());
+  if !app.filter().is_empty() {
+    mode.push_str(&format!(", filtered by \"{}\"", app.filter()));
+  }
+  spans.push(Span::styled(mode, gray));
+  spans.push(separator());
+  let hidden = match app.show_hidden {
+    true => "hidden files shown",
+    false => "hidden files hidden",
+  };
+  spans.push(Span::styled(hidden, gray));
+  spans.push(separator());
+  let pending = app
+    .transfers
+    .iter()
+    .filter(|t| t.is_running() || t.is_scheduled())
+    .count();
+  let pending_style = match pending {
+    0 => gray,
+    _ => Style::default().fg(Color::LightCyan),
   };
+  spans.push(Span::styled(format!("{pending} pending"), pending_style));
+  if !app.measuring.is_empty() {
+    spans.push(Span::styled(
+      format!("  {} measuring {}", spinner(), app.measuring.join(", ")),
+      Style::default().fg(Color::LightYellow),
+    ));
+  }
+  if let Some(pattern) = &app.searching {
+    spans.push(Span::styled(
+      format!("  {} searching for {pattern}", spinner()),
+      Style::default().fg(Color::LightYellow),
+    ));
+  }
+  let paragraph = Paragraph::new(Spans::from(spans)).alignment(Alignment::Left);
   f.render_widget(paragraph, area)
 }
+
+// The frame of a spinner to show now, for work going on in the background
+fn spinner() -> char {
+  const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+  let millis = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis();
+  SPINNER[(millis / 100) as usize % SPINNER.len()]
+}
+
+// A yes/no question drawn on top of everything else in the middle of the screen
+fn confirm_dialog<B: Backend>(f: &mut Frame<B>, question: &str) {
+  let area = centered_rect(50, 6, f.size());
+  let text = format!("{question}\n\n(y)es / (n)o");
+  let paragraph = Paragraph::new(text)
+    .style(Style::default().fg(Color::White))
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true })
+    .block(dialog_block(i18n::tr("Confirm")));
+  f.render_widget(Clear, area);
+  f.render_widget(paragraph, area);
+}
+
+// A single line of text being typed, drawn on top of everything else
+fn input_dialog<B: Backend>(f: &mut Frame<B>, question: &str, input: &str) {
+  let area = centered_rect(50, 4, f.size());
+  let text = format!("{question}\n> {input}_");
+  let paragraph = Paragraph::new(text)
+    .style(Style::default().fg(Color::White))
+    .block(dialog_block(i18n::tr("Input")));
+  f.render_widget(Clear, area);
+  f.render_widget(paragraph, area);
+}
+
+// Information about the server, drawn on top of everything else
+fn info_dialog<B: Backend>(f: &mut Frame<B>, title: &str, info: &str) {
+  let height = info.lines().count() as u16 + 4;
+  let area = centered_rect(70, height, f.size());
+  let text = format!("{info}\n\n(press any key)");
+  let paragraph = Paragraph::new(text)
+    .style(Style::default().fg(Color::White))
+    .wrap(Wrap { trim: false })
+    .block(dialog_block(title));
+  f.render_widget(Clear, area);
+  f.render_widget(paragraph, area);
+}
+
+// A file's first lines, scrolled by the user, drawn on top of everything else
+fn viewer_dialog<B: Backend>(f: &mut Frame<B>, viewer: &Viewer) {
+  let area = centered_rect(90, f.size().height.saturating_sub(2), f.size());
+  let paragraph = Paragraph::new(viewer.lines.join("\n"))
+    .style(Style::default().fg(Color::White))
+    .scroll((viewer.scroll.min(u16::MAX as usize) as u16, 0))
+    .block(dialog_block(format!(
+      "{} - j/k scroll, q close",
+      viewer.title
+    )));
+  f.render_widget(Clear, area);
+  f.render_widget(paragraph, area);
+}
+
+// Swaps everything already drawn that isn't ASCII - borders, arrows, bars, the spinner and so
+// on - for the nearest thing that is, for `--ascii`. Names are left as they are, unless they
+// happen to have the same symbols in them.
+struct AsciiOnly;
+
+impl Widget for AsciiOnly {
+  fn render(self, area: Rect, buf: &mut Buffer) {
+    for y in area.top()..area.bottom() {
+      for x in area.left()..area.right() {
+        let cell = buf.get_mut(x, y);
+        if let Some(ascii) = ascii_symbol(&cell.symbol) {
+          cell.set_symbol(ascii);
+        }
+      }
+    }
+  }
+}
+
+// Puts everything already drawn back in the terminal's own colors, for `--no-color`. Whatever
+// had a background color to pick it out, like the highlighted item, is in reverse video instead,
+// and whatever was in the error color is bold.
+struct NoColor(Color);
+
+impl Widget for NoColor {
+  fn render(self, area: Rect, buf: &mut Buffer) {
+    let NoColor(error) = self;
+    for y in area.top()..area.bottom() {
+      for x in area.left()..area.right() {
+        let cell = buf.get_mut(x, y);
+        if cell.bg != Color::Reset {
+          cell.modifier |= Modifier::REVERSED;
+        }
+        if cell.fg == error {
+          cell.modifier |= Modifier::BOLD;
+        }
+        cell.fg = Color::Reset;
+        cell.bg = Color::Reset;
+      }
+    }
+  }
+}
+
+fn ascii_symbol(symbol: &str) -> Option<&'static str> {
+  let ascii = match symbol {
+    "─" | "━" | "═" => "-",
+    "│" | "┃" | "║" => "|",
+    "┌" | "┐" | "└" | "┘" | "╭" | "╮" | "╰" | "╯" | "├" | "┤" | "┬" | "┴" | "┼" => {
+      "+"
+    }
+    "⬆" | "↑" => "^",
+    "⬇" | "↓" => "v",
+    "⬅" => "<",
+    "➡" => ">",
+    "⇄" => "~",
+    "≠" => "!",
+    "…" => "~",
+    "●" => "*",
+    "█" => "#",
+    "░" => ".",
+    "▁" => "_",
+    "▂" => ".",
+    "▃" => ":",
+    "▄" => "-",
+    "▅" => "=",
+    "▆" => "+",
+    "▇" => "*",
+    "⠋" | "⠸" | "⠧" => "|",
+    "⠙" | "⠼" | "⠇" => "/",
+    "⠹" | "⠴" => "-",
+    "⠦" | "⠏" => "\\",
+    _ => return None,
+  };
+  Some(ascii)
+}
+
+// A list to choose from, drawn on top of everything else
+fn picker_dialog<B: Backend>(f: &mut Frame<B>, picker: &Picker, ascii: bool) {
+  let area = centered_rect(60, picker.items.len().max(1) as u16 + 2, f.size());
+  let items: Vec<ListItem> = match picker.items.is_empty() {
+    true => vec![ListItem::new("(nothing here yet)")],
+    false => picker
+      .items
+      .iter()
+      .map(|item| ListItem::new(item.as_str()))
+      .collect(),
+  };
+  let enter = if ascii { "Enter" } else { "↩" };
+  let list = List::new(items)
+    .style(Style::default().fg(Color::White))
+    .highlight_style(
+      Style::default()
+        .bg(Color::Cyan)
+        .add_modifier(Modifier::BOLD),
+    )
+    .block(dialog_block(match picker.kind {
+      PickerKind::Bookmarks => format!("{} - {enter} go, x remove, Esc cancel", picker.title),
+      PickerKind::Found => format!("{} - {enter} go, Esc cancel", picker.title),
+    }));
+  let mut state = ListState::default();
+  if !picker.items.is_empty() {
+    state.select(Some(picker.selected));
+  }
+  f.render_widget(Clear, area);
+  f.render_stateful_widget(list, area, &mut state);
+}
+
+// The frame every modal dialog is drawn in, so they all look alike
+fn dialog_block<'a>(title: impl Into<Spans<'a>>) -> Block<'a> {
+  Block::default()
+    .title(title)
+    .borders(Borders::ALL)
+    .border_style(Style::default().fg(Color::LightYellow))
+}
+
+// A rectangle `percent_x` wide and `height` rows tall, centered in `area`
+fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
+  let width = area.width * percent_x / 100;
+  let height = height.min(area.height);
+  Rect {
+    x: area.x + (area.width - width) / 2,
+    y: area.y + (area.height - height) / 2,
+    width,
+    height,
+  }
+}
@@ -0,0 +1,68 @@
+//! Editing files in the user's own editor: local files in place, remote ones through a temporary
+//! copy which is uploaded again if it was changed
+use ssh2::Sftp;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use gsftp::error::GsftpError;
+
+/// Opens `path` in `$VISUAL` or `$EDITOR`, waiting until the editor exits.
+/// The terminal should be handed over to the editor first.
+pub fn edit(path: &Path) -> Result<(), GsftpError> {
+  let editor = env::var("VISUAL")
+    .or_else(|_| env::var("EDITOR"))
+    .unwrap_or_else(|_| String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+  // e.g. `code --wait`
+  let mut words = editor.split_whitespace();
+  let program = words.next().ok_or("$EDITOR is empty")?;
+  let status = Command::new(program)
+    .args(words)
+    .arg(path)
+    .status()
+    .map_err(|e| format!("Couldn't run {program}: {e}"))?;
+  if !status.success() {
+    return Err(format!("{program} exited with {status}").into());
+  }
+
+  Ok(())
+}
+
+/// Downloads the remote file at `remote`, opens it with `edit`, and uploads it again if it was
+/// changed. Yields whether it was.
+pub fn edit_remote(sftp: &Sftp, remote: &Path) -> Result<bool, GsftpError> {
+  let name = remote.file_name().ok_or("Nothing to edit")?;
+  let mut original = Vec::new();
+  sftp.open(remote)?.read_to_end(&mut original)?;
+  let dir = temp_dir()?;
+  let local = dir.join(name);
+  fs::write(&local, &original)?;
+
+  let edited = edit(&local).and_then(|_| Ok(fs::read(&local)?));
+  // Don't leave a copy of the file lying around, whatever happened
+  let _ = fs::remove_dir_all(&dir);
+  let edited = edited?;
+  if edited == original {
+    return Ok(false);
+  }
+  sftp.create(remote)?.write_all(&edited)?;
+
+  Ok(true)
+}
+
+// A directory only we can read, for the copy being edited
+fn temp_dir() -> Result<PathBuf, GsftpError> {
+  let dir = env::temp_dir().join(format!("{}-edit-{}", env!("CARGO_PKG_NAME"), process::id()));
+  let mut builder = fs::DirBuilder::new();
+  builder.recursive(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::DirBuilderExt;
+    builder.mode(0o700);
+  }
+  builder.create(&dir)?;
+
+  Ok(dir)
+}
@@ -0,0 +1,61 @@
+//! What can go wrong while connecting to a server or moving files, for the interface (or a
+//! program using gsftp as a library) to show or act on
+use std::io;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// Everything connecting, authenticating and transferring can fail with
+pub enum GsftpError {
+  /// The destination, or an option, doesn't make sense
+  #[error("{0}")]
+  Config(String),
+  /// The server's name couldn't be resolved
+  #[error("Couldn't resolve remote server {0} via DNS.")]
+  Dns(String),
+  /// The server (or the proxy in front of it) couldn't be reached
+  #[error("Couldn't connect to {addr}: {source}")]
+  Connect { addr: String, source: io::Error },
+  /// The server didn't accept any of the ways of logging in that were tried
+  #[error("{0}")]
+  Auth(String),
+  /// The server's host key isn't one that's trusted
+  #[error("{0}")]
+  HostKey(String),
+  /// SSH or SFTP said no
+  #[error(transparent)]
+  Sftp(#[from] ssh2::Error),
+  #[error(transparent)]
+  Io(io::Error),
+  /// The user stopped it
+  #[error("Cancelled")]
+  Cancelled,
+  /// Anything else, e.g. a command run on the server that failed
+  #[error("{0}")]
+  Other(String),
+}
+
+/// `Result` with a `GsftpError`
+pub type Result<T, E = GsftpError> = std::result::Result<T, E>;
+
+// A transfer that was cancelled stops with an I/O error carrying `Cancelled`, since that's what
+// copying can fail with (see `Progress::check`)
+impl From<io::Error> for GsftpError {
+  fn from(e: io::Error) -> Self {
+    match e.get_ref().and_then(|inner| inner.downcast_ref::<Self>()) {
+      Some(Self::Cancelled) => Self::Cancelled,
+      _ => Self::Io(e),
+    }
+  }
+}
+
+impl From<String> for GsftpError {
+  fn from(e: String) -> Self {
+    Self::Other(e)
+  }
+}
+
+impl From<&str> for GsftpError {
+  fn from(e: &str) -> Self {
+    Self::Other(e.to_string())
+  }
+}
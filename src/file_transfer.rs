@@ -1,203 +1,1372 @@
 //! File transfer utils
-use ssh2::{Session, Sftp};
-use std::error::Error;
+use crossbeam_channel::Sender;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use ssh2::Session;
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsStr;
 use std::fmt::{self, Formatter};
+use std::io;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::thread;
-use std::time::Duration;
-use std::{fs, io};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 
-use crate::{app::App, app_utils};
+use crate::backend::{self, FsBackend, Local};
+use crate::error::GsftpError;
+use crate::session::{PooledSftp, SessionPool};
+use crate::transform::{self, Transform};
+use crate::{
+  app::App,
+  app_utils::{self, Entry, EntryKind},
+  chunked::{self, Manifest},
+  i18n, remote_path, sftp,
+};
 
-enum TransferKind {
+/// How far back a running transfer's throughput is remembered, for its sparkline
+pub const THROUGHPUT_SECONDS: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What the recursive walkers should do when they come across a symbolic link.
+pub enum SymlinkPolicy {
+  /// Leave links out of the transfer entirely
+  Skip,
+  /// Transfer whatever the link points to, as if it were a regular file or directory
+  Follow,
+  /// Recreate the link itself at the destination, pointing at the same target
+  Recreate,
+}
+
+impl FromStr for SymlinkPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "skip" => Ok(Self::Skip),
+      "follow" => Ok(Self::Follow),
+      "recreate" => Ok(Self::Recreate),
+      _ => Err(format!("Unknown symlink policy: {s}")),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What to do when something already exists where a file or directory is about to land.
+pub enum ConflictPolicy {
+  /// Replace files, and merge into directories, that are already there
+  Overwrite,
+  /// Leave whatever is already there alone and don't transfer the item
+  Skip,
+  /// Never overwrite anything: land the item as `name (1).ext`, `name (2).ext`, ...
+  Rename,
+}
+
+impl FromStr for ConflictPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "overwrite" => Ok(Self::Overwrite),
+      "skip" => Ok(Self::Skip),
+      "rename" | "safe" => Ok(Self::Rename),
+      _ => Err(format!("Unknown conflict policy: {s}")),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Options that are fixed at the moment a transfer is created
+pub struct TransferOptions {
+  pub symlinks: SymlinkPolicy,
+  pub conflicts: ConflictPolicy,
+  /// Gzip file payloads in transit (the remote host needs `gzip`)
+  pub compress: bool,
+  /// Store compressed payloads as `.gz` at the destination instead of unpacking them
+  pub keep_gz: bool,
+  /// Upload plain files bigger than this as separately retried parts
+  pub chunk_size: Option<u64>,
+}
+
+impl Default for TransferOptions {
+  fn default() -> Self {
+    Self {
+      symlinks: SymlinkPolicy::Skip,
+      conflicts: ConflictPolicy::Overwrite,
+      compress: false,
+      keep_gz: false,
+      chunk_size: None,
+    }
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// Totals gathered by a quick walk over everything a transfer is about to copy
+pub struct ScanSummary {
+  pub bytes: u64,
+  pub files: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Transfers larger than either limit have to be confirmed by the user before they start
+pub struct ConfirmThreshold {
+  pub bytes: u64,
+  pub files: u64,
+}
+
+impl ConfirmThreshold {
+  pub fn exceeded_by(&self, summary: &ScanSummary) -> bool {
+    summary.bytes > self.bytes || summary.files > self.files
+  }
+}
+
+impl Default for ConfirmThreshold {
+  fn default() -> Self {
+    Self {
+      bytes: 1 << 30,
+      files: 1000,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
   Upload,
   Download,
+  /// From one remote host to another, streamed through this machine
+  Relay,
 }
 
 #[derive(Debug)]
-pub struct TransferError {
-  message: String,
+/// Sent from transfer threads to the UI thread as soon as anything happens
+pub enum TransferEvent {
+  Progress {
+    id: usize,
+    bytes: u64,
+    /// How many files have been started, including the one being copied now
+    files: u64,
+    /// The name of the file being copied now
+    current: Option<String>,
+  },
+  Finished {
+    id: usize,
+    result: Result<(), String>,
+  },
+  /// A scheduled transfer has just been started
+  Started { id: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferState {
+  /// Waiting for the scheduler; the text says when it'll start
+  Scheduled(String),
+  Running,
+  Done,
+  Failed(String),
+  /// Stopped by the user, before or while it ran
+  Cancelled,
 }
 
-impl fmt::Display for TransferError {
+#[derive(Clone)]
+/// What it takes to run a transfer over again: the hosts it went between, and where to
+pub struct Rerun {
+  to: PathBuf,
+  pool: Arc<SessionPool>,
+  // The receiving host, for relays
+  relay_pool: Option<Arc<SessionPool>>,
+}
+
+impl fmt::Debug for Rerun {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-    write!(f, "{}", self.message)
+    f.debug_struct("Rerun")
+      .field("to", &self.to)
+      .finish_non_exhaustive()
   }
 }
 
-impl From<Box<dyn Error>> for TransferError {
-  fn from(error: Box<dyn Error>) -> TransferError {
-    let message = format!("TRANSFER ERROR: {}", error);
-    TransferError { message }
+#[derive(Debug)]
+/// Everything the UI knows about a transfer: one of these is kept for each transfer
+/// started this session, and the running ones are drawn as progress rows.
+pub struct TransferStatus {
+  pub id: usize,
+  pub kind: TransferKind,
+  pub name: String,
+  pub path: PathBuf,
+  pub bytes: u64,
+  pub total: u64,
+  /// How many files have been started, out of how many there are in all
+  pub files: u64,
+  pub total_files: u64,
+  /// The name of the file being copied now
+  pub current: Option<String>,
+  pub state: TransferState,
+  pub started: Instant,
+  pub elapsed: Option<Duration>,
+  /// Bytes per second in each of the last `THROUGHPUT_SECONDS` seconds it ran, oldest first
+  pub throughput: VecDeque<u64>,
+  // When throughput was last sampled, and how many bytes had been copied by then
+  sampled: (Instant, u64),
+  // Checked by the transfer's thread between chunks
+  cancelled: Arc<AtomicBool>,
+  rerun: Rerun,
+}
+
+impl TransferStatus {
+  pub fn is_running(&self) -> bool {
+    self.state == TransferState::Running
+  }
+
+  pub fn is_scheduled(&self) -> bool {
+    matches!(self.state, TransferState::Scheduled(_))
+  }
+
+  /// Record how far the transfer has got
+  pub fn advance(&mut self, bytes: u64, files: u64, current: Option<String>) {
+    self.bytes = bytes;
+    self.files = files;
+    self.current = current;
+  }
+
+  /// e.g. `Downloaded logs (12.0 MB in 3.2s)`, once the transfer has finished
+  pub fn describe_success(&self) -> Option<String> {
+    if self.state != TransferState::Done {
+      return None;
+    }
+    let verb = match self.kind {
+      TransferKind::Upload => "Uploaded",
+      TransferKind::Download => "Downloaded",
+      TransferKind::Relay => "Copied",
+    };
+    let elapsed = self.elapsed.unwrap_or_default().as_secs_f64();
+    Some(format!(
+      "{verb} {} ({} in {elapsed:.1}s)",
+      self.name,
+      app_utils::human_size(self.bytes)
+    ))
+  }
+
+  /// Whether it's over without having finished, so it's worth starting again
+  pub fn can_retry(&self) -> bool {
+    matches!(
+      self.state,
+      TransferState::Failed(_) | TransferState::Cancelled
+    )
+  }
+
+  /// Average bytes per second since the transfer started
+  pub fn speed(&self) -> u64 {
+    let elapsed = self.elapsed.unwrap_or_else(|| self.started.elapsed());
+    match elapsed.as_secs_f64() {
+      secs if secs > 0.0 => (self.bytes as f64 / secs) as u64,
+      _ => 0,
+    }
+  }
+
+  /// Record how fast the transfer has gone since the last sample, which should be about a
+  /// second ago
+  pub fn sample(&mut self) {
+    let (then, bytes) = self.sampled;
+    let secs = then.elapsed().as_secs_f64();
+    if secs <= 0.0 {
+      return;
+    }
+    let speed = (self.bytes.saturating_sub(bytes) as f64 / secs) as u64;
+    self.throughput.push_back(speed);
+    while self.throughput.len() > THROUGHPUT_SECONDS {
+      self.throughput.pop_front();
+    }
+    self.sampled = (Instant::now(), self.bytes);
+  }
+
+  /// The scheduler has started the transfer, so the clock starts now
+  pub fn start(&mut self) {
+    if self.state != TransferState::Cancelled {
+      self.state = TransferState::Running;
+      self.started = Instant::now();
+      self.sampled = (self.started, self.bytes);
+    }
+  }
+
+  /// Stop the transfer at the end of the chunk it's copying, or before it starts if it's
+  /// still scheduled. Whatever's been copied so far is left where it is.
+  pub fn cancel(&mut self) {
+    if self.is_running() || self.is_scheduled() {
+      info!(id = self.id, "transfer cancelled");
+      self.cancelled.store(true, Ordering::SeqCst);
+      self.elapsed = Some(self.started.elapsed());
+      self.state = TransferState::Cancelled;
+    }
+  }
+
+  /// Record the outcome of the transfer and how long it took
+  pub fn finish(&mut self, result: Result<(), String>) {
+    // A cancelled transfer has already stopped the clock, and fails on its way out
+    if self.state == TransferState::Cancelled {
+      return;
+    }
+    self.elapsed = Some(self.started.elapsed());
+    self.state = match result {
+      Ok(()) => TransferState::Done,
+      Err(message) => TransferState::Failed(message),
+    };
+  }
+}
+
+/// A plain-text table of every transfer in `transfers` - direction, size, duration,
+/// result and source path - suitable for printing once the terminal is restored.
+pub fn summary(transfers: &[TransferStatus]) -> String {
+  let mut table = format!(
+    "{:<9} {:>10} {:>8}  {:<11} {}\n",
+    "DIRECTION", "SIZE", "TIME", "RESULT", "PATH"
+  );
+  for t in transfers {
+    let direction = match t.kind {
+      TransferKind::Upload => "upload",
+      TransferKind::Download => "download",
+      TransferKind::Relay => "relay",
+    };
+    let elapsed = t.elapsed.unwrap_or_else(|| t.started.elapsed());
+    let result = match &t.state {
+      TransferState::Scheduled(_) => "not started",
+      TransferState::Running => "unfinished",
+      TransferState::Done => "ok",
+      TransferState::Failed(_) => "failed",
+      TransferState::Cancelled => "cancelled",
+    };
+    table.push_str(&format!(
+      "{:<9} {:>10} {:>7.1}s  {:<11} {}\n",
+      direction,
+      app_utils::human_size(t.bytes),
+      elapsed.as_secs_f64(),
+      result,
+      t.path.display(),
+    ));
+    if let TransferState::Failed(message) = &t.state {
+      table.push_str(&format!("          {message}\n"));
+    }
+  }
+  table
+}
+
+#[derive(Clone)]
+/// Starts transfers on threads of their own and keeps count of how many are running
+pub struct Runner {
+  tx: Sender<TransferEvent>,
+  running: Arc<AtomicUsize>,
+  handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Runner {
+  pub fn new(tx: Sender<TransferEvent>) -> Self {
+    Self {
+      tx,
+      running: Arc::new(AtomicUsize::new(0)),
+      handles: Arc::new(Mutex::new(Vec::new())),
+    }
+  }
+
+  /// Run `transfer` in the background, reporting as transfer `id`
+  pub fn start(&self, transfer: Transfer, id: usize) {
+    let progress = Progress::new(id, self.tx.clone());
+    let running = Arc::clone(&self.running);
+    running.fetch_add(1, Ordering::SeqCst);
+    let handle = thread::spawn(move || {
+      transfer.execute(progress);
+      running.fetch_sub(1, Ordering::SeqCst);
+    });
+    self.lock_handles().push(handle);
+  }
+
+  /// Tell the UI thread that a transfer it knows about has been started by someone else
+  pub fn announce(&self, id: usize) {
+    let _ = self.tx.send(TransferEvent::Started { id });
+  }
+
+  /// How many transfers are running right now
+  pub fn running(&self) -> usize {
+    self.running.load(Ordering::SeqCst)
+  }
+
+  /// Wait for every transfer started so far to finish
+  pub fn join(&self) {
+    let handles: Vec<JoinHandle<()>> = self.lock_handles().drain(..).collect();
+    for handle in handles {
+      // A panicking transfer has already torn down the terminal with its message
+      let _ = handle.join();
+    }
+  }
+
+  fn lock_handles(&self) -> MutexGuard<'_, Vec<JoinHandle<()>>> {
+    self.handles.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+/// Hands a running transfer's byte count back to the UI thread, at most every `INTERVAL`
+pub struct Progress {
+  id: usize,
+  bytes: u64,
+  files: u64,
+  current: Option<String>,
+  last_sent: Instant,
+  tx: Sender<TransferEvent>,
+  cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+  const INTERVAL: Duration = Duration::from_millis(100);
+
+  pub fn new(id: usize, tx: Sender<TransferEvent>) -> Self {
+    let last_sent = Instant::now();
+    Self {
+      id,
+      bytes: 0,
+      files: 0,
+      current: None,
+      last_sent,
+      tx,
+      cancelled: Arc::new(AtomicBool::new(false)),
+    }
+  }
+
+  /// Fails once the user has cancelled the transfer, so that copying stops where it is
+  pub fn check(&self) -> io::Result<()> {
+    match self.cancelled.load(Ordering::SeqCst) {
+      true => Err(io::Error::other(GsftpError::Cancelled)),
+      false => Ok(()),
+    }
+  }
+
+  pub fn add(&mut self, bytes: u64) {
+    self.bytes += bytes;
+    if self.last_sent.elapsed() >= Self::INTERVAL {
+      self.send();
+    }
+  }
+
+  /// Note that the file at `path` is the one being copied now
+  pub fn start_file(&mut self, path: &Path) {
+    self.files += 1;
+    self.current = path
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string());
+    if self.last_sent.elapsed() >= Self::INTERVAL {
+      self.send();
+    }
+  }
+
+  fn send(&mut self) {
+    self.last_sent = Instant::now();
+    // The UI may already be gone if we're quitting mid-transfer
+    let _ = self.tx.send(TransferEvent::Progress {
+      id: self.id,
+      bytes: self.bytes,
+      files: self.files,
+      current: self.current.clone(),
+    });
+  }
+
+  /// Tell the UI thread the transfer is over
+  fn finish(mut self, result: Result<(), String>) {
+    self.send();
+    match &result {
+      Ok(()) => info!(
+        id = self.id,
+        bytes = self.bytes,
+        files = self.files,
+        "transfer done"
+      ),
+      Err(e) => warn!(
+        id = self.id,
+        bytes = self.bytes,
+        error = e,
+        "transfer failed"
+      ),
+    }
+    let _ = self.tx.send(TransferEvent::Finished {
+      id: self.id,
+      result,
+    });
   }
 }
 
 /// The File tranfer API struct we'll call from main.rs.
 /// We keep track of the source path, destination path, and whether the
 /// transfer is an upload or a download.
+/// Every transfer owns an SFTP channel checked out of the session pool.
 pub struct Transfer {
   from: PathBuf,
   to: PathBuf,
   kind: TransferKind,
+  opts: TransferOptions,
+  // Stages every file's payload passes through, e.g. encryption
+  stages: Vec<Box<dyn Transform>>,
   sess: Session,
-  sftp: Sftp,
+  sftp: PooledSftp,
+  // The receiving host's channel, for relays
+  relay_to: Option<PooledSftp>,
+  cancelled: Arc<AtomicBool>,
+  rerun: Rerun,
 }
 
 impl Transfer {
   /// Create a new upload transfer, ready to be executed
-  pub fn upload(app: &App, sess: &Session) -> Self {
-    let i = app.state.local.selected().unwrap();
-    let from = app.buf.local.join(&app.content.local[i]);
-    let to = app.buf.remote.join(&app.content.local[i]);
-    let kind = TransferKind::Upload;
+  pub fn upload(app: &App, pool: &Arc<SessionPool>) -> Result<Self, GsftpError> {
+    let i = app.state.local.selected().unwrap_or(0);
+    let entry = app.content.local.get(i).ok_or("Nothing to upload here")?;
+    Self::upload_entry(app, entry, pool)
+  }
+
+  /// Create a new upload transfer of `entry` in the local pane, rather than the highlighted one
+  pub fn upload_entry(
+    app: &App,
+    entry: &Entry,
+    pool: &Arc<SessionPool>,
+  ) -> Result<Self, GsftpError> {
+    let from = app.buf.local.join(entry);
+    let to = remote_path::join(&app.buf.remote, entry);
+    let stages = app.encryption.upload_stages()?;
+    Self::new(
+      from,
+      to,
+      TransferKind::Upload,
+      app.transfer_opts,
+      stages,
+      pool,
+    )
+  }
 
-    // TODO: get ride of clone
-    let sess = sess.clone();
-    let sftp = sess.sftp().expect("Failed to create SFTP session.");
+  /// Create a new download transfer, ready to be executed
+  pub fn download(app: &App, pool: &Arc<SessionPool>) -> Result<Self, GsftpError> {
+    let i = app.state.remote.selected().unwrap_or(0);
+    let entry = app
+      .content
+      .remote
+      .get(i)
+      .ok_or("Nothing to download here")?;
+    Self::download_entry(app, entry, pool)
+  }
 
-    Self {
+  /// Create a new download transfer of `entry` in the remote pane, rather than the highlighted one
+  pub fn download_entry(
+    app: &App,
+    entry: &Entry,
+    pool: &Arc<SessionPool>,
+  ) -> Result<Self, GsftpError> {
+    let from = remote_path::join(&app.buf.remote, entry);
+    let to = app.buf.local.join(entry);
+    let stages = app.encryption.download_stages()?;
+    Self::new(
       from,
       to,
-      kind,
-      sess,
-      sftp,
+      TransferKind::Download,
+      app.transfer_opts,
+      stages,
+      pool,
+    )
+  }
+
+  /// Create a new transfer of the highlighted remote item into `to_dir` on another host
+  pub fn relay(
+    app: &App,
+    from_pool: &Arc<SessionPool>,
+    to_pool: &Arc<SessionPool>,
+    to_dir: &Path,
+  ) -> Result<Self, GsftpError> {
+    let i = app.state.remote.selected().unwrap_or(0);
+    let entry = app.content.remote.get(i).ok_or("Nothing to copy here")?;
+    let from = remote_path::join(&app.buf.remote, entry);
+    let to = remote_path::join(to_dir, entry);
+    // Payloads go straight from one host's file to the other's, so stages don't apply
+    let mut transfer = Self::new(
+      from,
+      to,
+      TransferKind::Relay,
+      app.transfer_opts,
+      Vec::new(),
+      from_pool,
+    )?;
+    transfer.relay_to = Some(to_pool.get()?);
+    transfer.rerun.relay_pool = Some(Arc::clone(to_pool));
+
+    Ok(transfer)
+  }
+
+  /// Create the transfer `status` describes over again, between the same hosts. The options
+  /// and encryption set up now apply, rather than the ones it was first started with.
+  pub fn retry(status: &TransferStatus, app: &App) -> Result<Self, GsftpError> {
+    let stages = match status.kind {
+      TransferKind::Upload => app.encryption.upload_stages()?,
+      TransferKind::Download => app.encryption.download_stages()?,
+      TransferKind::Relay => Vec::new(),
+    };
+    let rerun = &status.rerun;
+    let mut transfer = Self::new(
+      status.path.clone(),
+      rerun.to.clone(),
+      status.kind,
+      app.transfer_opts,
+      stages,
+      &rerun.pool,
+    )?;
+    if let Some(to_pool) = &rerun.relay_pool {
+      transfer.relay_to = Some(to_pool.get()?);
+      transfer.rerun.relay_pool = Some(Arc::clone(to_pool));
     }
+
+    Ok(transfer)
   }
 
-  /// Create a new download transfer, ready to be executed
-  pub fn download(app: &App, sess: &Session) -> Self {
-    let i = app.state.remote.selected().unwrap();
-    let from = app.buf.remote.join(&app.content.remote[i]);
-    let to = app.buf.local.join(&app.content.remote[i]);
-    let kind = TransferKind::Download;
+  /// Create a new transfer of `from` to `to` that no pane has anything to do with, e.g. for
+  /// `gsftp get`
+  pub fn between(
+    from: PathBuf,
+    to: PathBuf,
+    kind: TransferKind,
+    opts: TransferOptions,
+    pool: &Arc<SessionPool>,
+  ) -> Result<Self, GsftpError> {
+    Self::new(from, to, kind, opts, Vec::new(), pool)
+  }
 
-    // TODO: get ride of clone
-    let sess = sess.clone();
-    let sftp = sess.sftp().expect("Failed to create SFTP session.");
+  fn new(
+    from: PathBuf,
+    to: PathBuf,
+    kind: TransferKind,
+    opts: TransferOptions,
+    stages: Vec<Box<dyn Transform>>,
+    pool: &Arc<SessionPool>,
+  ) -> Result<Self, GsftpError> {
+    // Check out the channel first: it may have had to reconnect the session
+    let sftp = pool.get()?;
+    let sess = pool.session();
+    let rerun = Rerun {
+      to: to.clone(),
+      pool: Arc::clone(pool),
+      relay_pool: None,
+    };
 
-    Self {
+    Ok(Self {
       from,
       to,
       kind,
+      opts,
+      stages,
       sess,
       sftp,
+      relay_to: None,
+      cancelled: Arc::new(AtomicBool::new(false)),
+      rerun,
+    })
+  }
+
+  /// Whether the user cancelled the transfer while it was waiting to start
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+
+  /// Short status text describing the transfer while it runs
+  pub fn flashing_text(&self) -> &'static str {
+    match self.kind {
+      TransferKind::Upload => "Uploading...",
+      TransferKind::Download => "Downloading...",
+      TransferKind::Relay => "Copying between hosts...",
+    }
+  }
+
+  /// Walk the source of the transfer, totalling the bytes and files that would be copied.
+  /// Symlinks are counted according to the transfer's symlink policy.
+  pub fn scan(&self) -> Result<ScanSummary, GsftpError> {
+    let mut summary = ScanSummary::default();
+    let policy = self.opts.symlinks;
+    let mut visited = HashSet::new();
+    match self.kind {
+      TransferKind::Download | TransferKind::Relay => {
+        scan_tree(&*self.sftp, &self.from, policy, &mut visited, &mut summary)?
+      }
+      TransferKind::Upload => scan_tree(&Local, &self.from, policy, &mut visited, &mut summary)?,
     }
+
+    Ok(summary)
   }
 
-  /// Execute a transfer through an SSH session (either upload or download the file)
-  pub fn execute(self) -> Result<(), TransferError> {
+  /// Start a fresh `TransferStatus` row for this transfer, as big as `total` says
+  pub fn status(&self, id: usize, total: ScanSummary) -> TransferStatus {
+    TransferStatus {
+      id,
+      kind: self.kind,
+      name: self
+        .from
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string(),
+      path: self.from.clone(),
+      bytes: 0,
+      total: total.bytes,
+      files: 0,
+      total_files: total.files,
+      current: None,
+      state: TransferState::Running,
+      started: Instant::now(),
+      elapsed: None,
+      throughput: VecDeque::new(),
+      sampled: (Instant::now(), 0),
+      cancelled: Arc::clone(&self.cancelled),
+      rerun: self.rerun.clone(),
+    }
+  }
+
+  /// Execute a transfer through an SSH session (either upload or download the file),
+  /// reporting progress and the final result through `progress`
+  pub fn execute(self, mut progress: Progress) {
+    info!(
+      id = progress.id,
+      kind = ?self.kind,
+      from = %self.from.display(),
+      to = %self.to.display(),
+      "transfer started"
+    );
+    progress.cancelled = Arc::clone(&self.cancelled);
+    let mut walk = Walk {
+      sess: &self.sess,
+      local: &Local,
+      remote: &*self.sftp,
+      opts: self.opts,
+      stages: &self.stages,
+      relay_to: self.relay_to.as_deref().map(|sftp| sftp as &dyn FsBackend),
+      visited: HashSet::new(),
+      progress,
+    };
     let action = match self.kind {
-      TransferKind::Download => download(&self, &self.sftp),
-      TransferKind::Upload => upload(&self, &self.sess, &self.sftp),
+      TransferKind::Download => download(&self.from, &self.to, &mut walk),
+      TransferKind::Upload => upload(&self.from, &self.to, &mut walk),
+      TransferKind::Relay => relay(&self.from, &self.to, &mut walk),
+    };
+    let result = action.map_err(|e| i18n::tr_args("TRANSFER ERROR: {}", &[&e.to_string()]));
+    walk.progress.finish(result);
+  }
+}
+
+// State shared by the recursive walkers of a single transfer
+struct Walk<'a> {
+  sess: &'a Session,
+  // This machine's files and the host's; only gzipping runs anything on the host through `sess`
+  local: &'a dyn FsBackend,
+  remote: &'a dyn FsBackend,
+  opts: TransferOptions,
+  stages: &'a [Box<dyn Transform>],
+  // Where relays copy to; `remote` is where they copy from
+  relay_to: Option<&'a dyn FsBackend>,
+  // Following links can lead us in circles, so we never enter the same real directory twice
+  visited: HashSet<PathBuf>,
+  progress: Progress,
+}
+
+// What files are created with: readable by everyone, writable by whoever owns them
+const FILE_PERM: u32 = 0o644;
+
+/// Copies everything from `reader` into `writer` in fixed-size chunks, reporting as it goes
+pub fn copy_with_progress(
+  reader: &mut impl Read,
+  writer: &mut impl Write,
+  progress: &mut Progress,
+) -> io::Result<u64> {
+  const CHUNK_SIZE: usize = 32 * 1024;
+  let mut buf = vec![0; CHUNK_SIZE];
+  let mut total = 0;
+  loop {
+    let n = match reader.read(&mut buf) {
+      Ok(0) => break,
+      Ok(n) => n,
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e),
     };
-    if let Err(e) = action {
-      return Err(TransferError::from(e));
+    progress.check()?;
+    writer.write_all(&buf[..n])?;
+    total += n as u64;
+    progress.add(n as u64);
+  }
+
+  Ok(total)
+}
+
+// Adds up what's at `path`, following it if it's a link, and everything under it
+fn scan_tree(
+  fs: &dyn FsBackend,
+  path: &Path,
+  policy: SymlinkPolicy,
+  visited: &mut HashSet<PathBuf>,
+  summary: &mut ScanSummary,
+) -> Result<(), GsftpError> {
+  let entry = fs.stat(path)?;
+  if entry.kind != EntryKind::Dir {
+    summary.files += 1;
+    summary.bytes += entry.size.unwrap_or_default();
+    return Ok(());
+  }
+  if !visited.insert(fs.realpath(path)?) {
+    return Ok(());
+  }
+  for child in fs.list(path)? {
+    let path = fs.join(path, &child.file_name);
+    match child.kind {
+      // Dangling links have nothing to count
+      EntryKind::Symlink if policy == SymlinkPolicy::Follow => {
+        scan_tree(fs, &path, policy, visited, summary).unwrap_or_default()
+      }
+      EntryKind::Symlink => {}
+      EntryKind::Dir => scan_tree(fs, &path, policy, visited, summary)?,
+      _ => {
+        summary.files += 1;
+        summary.bytes += child.size.unwrap_or_default();
+      }
     }
+  }
 
-    Ok(())
+  Ok(())
+}
+
+// Download currently selected item from remote host - directories are downloaded recursively.
+// The selected item itself is always followed unless we've been asked to recreate links.
+fn download(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let (local, remote) = (walk.local, walk.remote);
+  let recreate =
+    walk.opts.symlinks == SymlinkPolicy::Recreate && remote.lstat(from)?.kind == EntryKind::Symlink;
+  if recreate || remote.stat(from)?.kind == EntryKind::Dir {
+    let Some(to) = resolve_conflict(to, walk.opts.conflicts, local) else {
+      return Ok(());
+    };
+    return if recreate {
+      copy_symlink(remote, from, local, &to)
+    } else {
+      download_directory_recursive(from, &to, walk)
+    };
+  }
+  if from.extension() == Some(OsStr::new("manifest")) {
+    if let Some(manifest) = chunked::read_manifest(from, remote)? {
+      return download_chunked(from, to, &manifest, walk);
+    }
   }
+
+  download_file(from, to, walk)
 }
 
-// Download currently selected item from remote host - directories are downloaded recursively
-fn download(transfer: &Transfer, sftp: &Sftp) -> Result<(), Box<dyn Error>> {
-  let from = transfer.from.as_path();
-  let to = transfer.to.as_path();
-  let mut remote_file = sftp.open(from)?;
-  if remote_file.stat().expect("no stats").is_file() {
-    download_file(&mut remote_file, to)?;
-  } else {
-    download_directory_recursive(from, to, sftp)?;
+// The parts of a chunked upload are stitched back together next to `to`, under the name in
+// the manifest, which goes through the stages and the conflict policy like any other file's
+fn download_chunked(
+  from: &Path,
+  to: &Path,
+  manifest: &Manifest,
+  walk: &mut Walk,
+) -> Result<(), GsftpError> {
+  let to = transform::destination(&to.with_file_name(&manifest.name), walk.stages);
+  let Some(to) = resolve_conflict(&to, walk.opts.conflicts, walk.local) else {
+    return Ok(());
+  };
+  walk.progress.start_file(from);
+  let local_file = walk.local.write(&to, FILE_PERM)?;
+  let mut sink = transform::through(local_file, walk.stages)?;
+  chunked::download(from, manifest, walk.remote, &mut sink, &mut walk.progress)?;
+  sink.finish()?;
+
+  Ok(())
+}
+
+// The conflict policy looks at the name the file really lands under, e.g. `notes.txt.gz`
+fn download_file(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let mut to = transform::destination(to, walk.stages);
+  if walk.opts.compress && walk.opts.keep_gz {
+    to = with_gz_extension(&to);
   }
+  let Some(to) = resolve_conflict(&to, walk.opts.conflicts, walk.local) else {
+    return Ok(());
+  };
+  walk.progress.start_file(from);
+  if walk.opts.compress {
+    return download_file_gzipped(from, &to, walk);
+  }
+  let mut remote_file = walk.remote.read(from)?;
+  let local_file = walk.local.write(&to, FILE_PERM)?;
+  let mut sink = transform::through(local_file, walk.stages)?;
+  copy_with_progress(&mut remote_file, &mut sink, &mut walk.progress)?;
+  sink.finish()?;
 
   Ok(())
 }
 
-fn download_file(remote_file: &mut ssh2::File, to: &Path) -> Result<(), Box<dyn Error>> {
-  // "create" opens a file in write-only mode
-  if let Ok(mut local_file) = fs::File::create(to) {
-    let n_bytes: u64 = remote_file.stat()?.size.unwrap_or_default();
-    let mut buf = Vec::with_capacity(n_bytes as usize);
-    remote_file.read_to_end(&mut buf)?; // read contents into buf
-    local_file.write_all(&buf)?; // write contents from buf
+// SFTP can't compress anything itself, so the remote host gzips the file onto an exec
+// channel's stdout; we either unpack it on the fly or keep it as it is, in `to` (which
+// already ends in `.gz` then).
+fn download_file_gzipped(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let from_str = from.to_str().ok_or("Remote path is not valid UTF-8")?;
+  let mut channel = sftp::exec_stream(walk.sess, "gzip", &["-c", "--", from_str])?;
+  let local_file = walk.local.write(to, FILE_PERM)?;
+  if walk.opts.keep_gz {
+    let mut local_file = local_file;
+    copy_with_progress(&mut channel, &mut local_file, &mut walk.progress)?;
+    local_file.finish()?;
+  } else {
+    let mut sink = transform::through(local_file, walk.stages)?;
+    let mut decoder = GzDecoder::new(&mut channel);
+    copy_with_progress(&mut decoder, &mut sink, &mut walk.progress)?;
+    sink.finish()?;
   }
+  sftp::finish_exec(channel, "gzip")?;
 
   Ok(())
 }
 
-fn download_directory_recursive(from: &Path, to: &Path, sftp: &Sftp) -> Result<(), Box<dyn Error>> {
-  if fs::create_dir(to).is_ok() {
-    let readdir_info = sftp.readdir(from).unwrap_or_default();
-    for (buf, stat) in readdir_info {
-      if stat.file_type().is_symlink() {
-        continue;
-      }
-      let new_target = to.join(buf.file_name().unwrap());
-      if stat.is_dir() {
-        download_directory_recursive(&buf, &new_target, sftp)?;
-      } else {
-        let mut f = sftp.open(buf.as_path())?;
-        download_file(&mut f, &new_target)?;
+// Recreates a link from one side on the other, pointing at the same (unmodified) target
+fn copy_symlink(
+  from_fs: &dyn FsBackend,
+  from: &Path,
+  to_fs: &dyn FsBackend,
+  to: &Path,
+) -> Result<(), GsftpError> {
+  let target = from_fs
+    .lstat(from)?
+    .target
+    .ok_or_else(|| format!("Couldn't read the link {}", from.display()))?;
+  to_fs.symlink(Path::new(&target), to)
+}
+
+fn download_directory_recursive(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let (local, remote) = (walk.local, walk.remote);
+  if !walk.visited.insert(remote.realpath(from)?) {
+    return Ok(());
+  }
+  // With the overwrite policy we merge into a directory that's already there
+  backend::mkdir_all(local, to)?;
+  for child in remote.list(from)? {
+    let buf = remote.join(from, &child.file_name);
+    let new_target = local.join(to, &child.file_name);
+    let mut kind = child.kind;
+    if kind == EntryKind::Symlink {
+      match walk.opts.symlinks {
+        SymlinkPolicy::Skip => continue,
+        SymlinkPolicy::Recreate => {
+          if let Some(new_target) = resolve_conflict(&new_target, walk.opts.conflicts, local) {
+            copy_symlink(remote, &buf, local, &new_target)?;
+          }
+          continue;
+        }
+        // Dangling links have nothing to follow
+        SymlinkPolicy::Follow => match remote.stat(&buf) {
+          Ok(target) => kind = target.kind,
+          Err(_) => continue,
+        },
       }
     }
+    if kind != EntryKind::Dir {
+      download_file(&buf, &new_target, walk)?;
+    } else if let Some(new_target) = resolve_conflict(&new_target, walk.opts.conflicts, local) {
+      download_directory_recursive(&buf, &new_target, walk)?;
+    }
+  }
+
+  Ok(())
+}
+
+// Upload currently selected item to remote host - directories are uploaded recursively.
+// The selected item itself is always followed unless we've been asked to recreate links.
+fn upload(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let (local, remote) = (walk.local, walk.remote);
+  let recreate =
+    walk.opts.symlinks == SymlinkPolicy::Recreate && local.lstat(from)?.kind == EntryKind::Symlink;
+  if !recreate && local.stat(from)?.kind != EntryKind::Dir {
+    return upload_file(from, to, walk);
+  }
+  let Some(to) = resolve_conflict(to, walk.opts.conflicts, remote) else {
+    return Ok(());
+  };
+  if recreate {
+    copy_symlink(local, from, remote, &to)
+  } else {
+    upload_directory_recursive(from, &to, walk)
+  }
+}
+
+// As with downloads, the conflict policy looks at the name the file really lands under
+fn upload_file(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  // Chunking only applies to plain payloads, which can be resumed part by part
+  if let Some(chunk_size) = walk.opts.chunk_size {
+    let size = walk.local.stat(from)?.size.unwrap_or_default();
+    if !walk.opts.compress && walk.stages.is_empty() && size > chunk_size {
+      let Some(to) = resolve_conflict(to, walk.opts.conflicts, walk.remote) else {
+        return Ok(());
+      };
+      walk.progress.start_file(from);
+      return chunked::upload(
+        from,
+        &to,
+        chunk_size,
+        walk.sess,
+        walk.local,
+        walk.remote,
+        &mut walk.progress,
+      );
+    }
+  }
+  let mut to = transform::destination(to, walk.stages);
+  if walk.opts.compress && walk.opts.keep_gz {
+    to = with_gz_extension(&to);
   }
+  let Some(to) = resolve_conflict(&to, walk.opts.conflicts, walk.remote) else {
+    return Ok(());
+  };
+  walk.progress.start_file(from);
+  if walk.opts.compress {
+    return upload_file_gzipped(from, &to, walk);
+  }
+  let mut local_file = walk.local.read(from)?;
+  let remote_file = walk.remote.write(&to, FILE_PERM)?;
+  let mut sink = transform::through(remote_file, walk.stages)?;
+  copy_with_progress(&mut local_file, &mut sink, &mut walk.progress)?;
+  sink.finish()?;
 
   Ok(())
 }
 
-// Upload currently selected item to remote host - directories are uploaded recursively
-fn upload(transfer: &Transfer, sess: &Session, sftp: &Sftp) -> Result<(), Box<dyn Error>> {
-  let from = transfer.from.as_path();
-  let to = transfer.to.as_path();
-  if from.is_dir() {
-    upload_directory_recursive(from, to, sess, sftp)?;
+// The payload is gzipped as it's sent and lands as `to.gz`, which the remote host
+// unpacks into `to`, unless we've been asked to keep the compressed copy (in which case
+// `to` already ends in `.gz`). gzip only replaces a file that's there if that's the policy.
+fn upload_file_gzipped(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let gz = if walk.opts.keep_gz {
+    to.to_path_buf()
   } else {
-    upload_file(from, to, sftp)?;
+    with_gz_extension(to)
+  };
+  let mut local_file = walk.local.read(from)?;
+  let remote_file = walk.remote.write(&gz, FILE_PERM)?;
+  let encoder = GzEncoder::new(remote_file, Compression::default());
+  let mut sink = transform::through(Box::new(encoder), walk.stages)?;
+  copy_with_progress(&mut local_file, &mut sink, &mut walk.progress)?;
+  sink.finish()?;
+  if !walk.opts.keep_gz {
+    let gz_str = gz.to_str().ok_or("Remote path is not valid UTF-8")?;
+    let args: &[&str] = match walk.opts.conflicts {
+      ConflictPolicy::Overwrite => &["-d", "-f", "--", gz_str],
+      _ => &["-d", "--", gz_str],
+    };
+    sftp::exec(walk.sess, "gzip", args)?;
   }
 
   Ok(())
 }
 
-fn upload_file(from: &Path, to: &Path, sftp: &Sftp) -> Result<(), io::Error> {
-  if let Ok(mut remote_file) = sftp.create(to) {
-    let buf = fs::read(from).unwrap_or_default();
-    remote_file.write_all(&buf)?;
+// `file.txt` becomes `file.txt.gz`
+fn with_gz_extension(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(".gz");
+  path.with_file_name(name)
+}
+
+fn upload_directory_recursive(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let (local, remote) = (walk.local, walk.remote);
+  if !walk.visited.insert(local.realpath(from)?) {
+    return Ok(());
+  }
+  backend::mkdir_all(remote, to)?;
+  for child in local.list(from)? {
+    let buf = local.join(from, &child.file_name);
+    let new_target_buf = remote.join(to, &child.file_name);
+    let mut kind = child.kind;
+    if kind == EntryKind::Symlink {
+      match walk.opts.symlinks {
+        SymlinkPolicy::Skip => continue,
+        SymlinkPolicy::Recreate => {
+          if let Some(new_target_buf) =
+            resolve_conflict(&new_target_buf, walk.opts.conflicts, remote)
+          {
+            copy_symlink(local, &buf, remote, &new_target_buf)?;
+          }
+          continue;
+        }
+        // Dangling links have nothing to follow
+        SymlinkPolicy::Follow => match local.stat(&buf) {
+          Ok(target) => kind = target.kind,
+          Err(_) => continue,
+        },
+      }
+    }
+    if kind != EntryKind::Dir {
+      upload_file(&buf, &new_target_buf, walk)?;
+    } else if let Some(new_target_buf) =
+      resolve_conflict(&new_target_buf, walk.opts.conflicts, remote)
+    {
+      upload_directory_recursive(&buf, &new_target_buf, walk)?;
+    }
   }
 
   Ok(())
 }
 
-fn upload_directory_recursive(
-  from: &Path,
-  to: &Path,
-  sess: &Session,
-  sftp: &Sftp,
-) -> Result<(), Box<dyn Error>> {
-  // TODO: try and make this more platform-agnostic
-  let mut channel = sess.channel_session()?;
-  let command = format!("mkdir '{}'", to.to_str().unwrap());
-  channel.exec(&command)?;
-  // sftp.mkdir(to, 0o644)?;
-  for buf in &app_utils::read_dir_contents(from) {
-    if buf.is_symlink() {
-      continue;
-    }
-    let new_target_buf = to.join(buf.file_name().unwrap_or_default());
-    if buf.is_dir() {
-      upload_directory_recursive(buf, &new_target_buf, sess, sftp)?;
-    } else {
-      // It can take a second for the remote connection to actually make the directory...
-      for _ in 0..5 {
-        if sftp.opendir(new_target_buf.parent().unwrap()).is_err() {
-          // TODO: This is a bad way to handle this.
-          thread::sleep(Duration::from_millis(5));
+// Copy the selected remote item to another remote host, through this machine.
+// Compression, encryption and chunking don't apply: bytes go from one host's file to the other's.
+fn relay(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let (remote, dest) = (walk.remote, walk.relay_to.ok_or("No host to copy to")?);
+  let to = match resolve_conflict(to, walk.opts.conflicts, dest) {
+    Some(to) => to,
+    None => return Ok(()),
+  };
+  if walk.opts.symlinks == SymlinkPolicy::Recreate && remote.lstat(from)?.kind == EntryKind::Symlink
+  {
+    return copy_symlink(remote, from, dest, &to);
+  }
+  if remote.stat(from)?.kind == EntryKind::Dir {
+    relay_directory_recursive(from, &to, walk)
+  } else {
+    relay_file(from, &to, walk)
+  }
+}
+
+fn relay_file(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  walk.progress.start_file(from);
+  let dest = walk.relay_to.ok_or("No host to copy to")?;
+  let mut source_file = walk.remote.read(from)?;
+  let mut dest_file = dest.write(to, FILE_PERM)?;
+  copy_with_progress(&mut source_file, &mut dest_file, &mut walk.progress)?;
+  dest_file.finish()?;
+
+  Ok(())
+}
+
+fn relay_directory_recursive(from: &Path, to: &Path, walk: &mut Walk) -> Result<(), GsftpError> {
+  let (remote, dest) = (walk.remote, walk.relay_to.ok_or("No host to copy to")?);
+  if !walk.visited.insert(remote.realpath(from)?) {
+    return Ok(());
+  }
+  backend::mkdir_all(dest, to)?;
+  for child in remote.list(from)? {
+    let buf = remote.join(from, &child.file_name);
+    let new_target = dest.join(to, &child.file_name);
+    let new_target = match resolve_conflict(&new_target, walk.opts.conflicts, dest) {
+      Some(new_target) => new_target,
+      None => continue,
+    };
+    let mut kind = child.kind;
+    if kind == EntryKind::Symlink {
+      match walk.opts.symlinks {
+        SymlinkPolicy::Skip => continue,
+        SymlinkPolicy::Recreate => {
+          copy_symlink(remote, &buf, dest, &new_target)?;
           continue;
         }
-        break;
+        // Dangling links have nothing to follow
+        SymlinkPolicy::Follow => match remote.stat(&buf) {
+          Ok(target) => kind = target.kind,
+          Err(_) => continue,
+        },
       }
-      upload_file(buf, &new_target_buf, sftp)?;
+    }
+    if kind == EntryKind::Dir {
+      relay_directory_recursive(&buf, &new_target, walk)?;
+    } else {
+      relay_file(&buf, &new_target, walk)?;
     }
   }
 
   Ok(())
 }
+
+// Where an item should land in `fs` given the conflict policy; `None` means skip it
+fn resolve_conflict(to: &Path, policy: ConflictPolicy, fs: &dyn FsBackend) -> Option<PathBuf> {
+  let exists = |path: &Path| fs.lstat(path).is_ok();
+  if !exists(to) {
+    return Some(to.to_path_buf());
+  }
+  match policy {
+    ConflictPolicy::Overwrite => Some(to.to_path_buf()),
+    ConflictPolicy::Skip => None,
+    ConflictPolicy::Rename => (1..)
+      .map(|n| numbered_copy(to, n))
+      .find(|candidate| !exists(candidate)),
+  }
+}
+
+/// `dir/file.txt` becomes `dir/file (n).txt`, and `a.tar.gz` becomes `a (n).tar.gz`: the number
+/// goes before the first dot. Names without one (including dotfiles such as `.bashrc`, whose
+/// leading dot doesn't count) just get the number appended.
+pub fn numbered_copy(path: &Path, n: u32) -> PathBuf {
+  let name = path.file_name().unwrap_or_default().to_string_lossy();
+  let name = match name.char_indices().skip(1).find(|&(_, c)| c == '.') {
+    Some((dot, _)) => format!("{} ({n}){}", &name[..dot], &name[dot..]),
+    None => format!("{name} ({n})"),
+  };
+  path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::backend::fixtures::{contents, put, tree};
+  use crate::backend::Memory;
+
+  // Copies `from` in `kind`'s direction between `local` and `remote`, as a transfer would
+  fn transfer(
+    kind: TransferKind,
+    local: &Memory,
+    remote: &Memory,
+    from: &str,
+    to: &str,
+    opts: TransferOptions,
+  ) -> Result<(), GsftpError> {
+    let sess = Session::new().unwrap();
+    let (tx, _rx) = crossbeam_channel::unbounded();
+    let mut walk = Walk {
+      sess: &sess,
+      local,
+      remote,
+      opts,
+      stages: &[],
+      relay_to: None,
+      visited: HashSet::new(),
+      progress: Progress::new(0, tx),
+    };
+    let (from, to) = (Path::new(from), Path::new(to));
+    match kind {
+      TransferKind::Upload => upload(from, to, &mut walk),
+      _ => download(from, to, &mut walk),
+    }
+  }
+
+  #[test]
+  fn downloads_copy_the_tree_and_number_what_is_in_the_way() {
+    let (local, remote) = (Memory::new(), tree());
+    let opts = TransferOptions::default();
+    transfer(
+      TransferKind::Download,
+      &local,
+      &remote,
+      "/src",
+      "/src",
+      opts,
+    )
+    .unwrap();
+    assert_eq!(contents(&local, "/src/A.txt"), "ay");
+    assert_eq!(contents(&local, "/src/lib/c.rs"), "fn main() {}");
+    // links are skipped unless asked otherwise
+    assert!(local.lstat(Path::new("/src/link")).is_err());
+    let opts = TransferOptions {
+      conflicts: ConflictPolicy::Rename,
+      ..opts
+    };
+    transfer(
+      TransferKind::Download,
+      &local,
+      &remote,
+      "/src",
+      "/src",
+      opts,
+    )
+    .unwrap();
+    assert_eq!(contents(&local, "/src (1)/lib/c.rs"), "fn main() {}");
+    let opts = TransferOptions {
+      conflicts: ConflictPolicy::Skip,
+      ..opts
+    };
+    put(&remote, "/src/A.txt", "changed");
+    transfer(
+      TransferKind::Download,
+      &local,
+      &remote,
+      "/src/A.txt",
+      "/src/A.txt",
+      opts,
+    )
+    .unwrap();
+    assert_eq!(contents(&local, "/src/A.txt"), "ay");
+  }
+
+  #[test]
+  fn uploads_follow_links_once_or_recreate_them() {
+    let (local, remote) = (tree(), Memory::new());
+    let follow = TransferOptions {
+      symlinks: SymlinkPolicy::Follow,
+      ..TransferOptions::default()
+    };
+    // `link` leads to `lib`, and `up` back to `/src`, which have both been copied already
+    transfer(
+      TransferKind::Upload,
+      &local,
+      &remote,
+      "/src",
+      "/dest",
+      follow,
+    )
+    .unwrap();
+    assert_eq!(contents(&remote, "/dest/lib/c.rs"), "fn main() {}");
+    assert!(remote.lstat(Path::new("/dest/link")).is_err());
+    assert!(remote.lstat(Path::new("/dest/lib/up")).is_err());
+    let recreate = TransferOptions {
+      symlinks: SymlinkPolicy::Recreate,
+      ..TransferOptions::default()
+    };
+    transfer(
+      TransferKind::Upload,
+      &local,
+      &remote,
+      "/src",
+      "/copy",
+      recreate,
+    )
+    .unwrap();
+    let link = remote.lstat(Path::new("/copy/link")).unwrap();
+    assert_eq!(link.target.as_deref(), Some("lib"));
+    assert_eq!(contents(&remote, "/copy/link/c.rs"), "fn main() {}");
+  }
+
+  #[test]
+  fn chunked_uploads_are_joined_again_on_the_way_down() {
+    let (local, remote) = (tree(), Memory::new());
+    // the parts of an upload that the server couldn't join itself
+    put(&remote, "/dest/c.rs.part0000", "fn ma");
+    put(&remote, "/dest/c.rs.part0001", "in() ");
+    put(&remote, "/dest/c.rs.part0002", "{}");
+    let manifest = "gsftp-chunks 1\nname c.rs\nsize 12\nchunk_size 5\n\
+                    5 c.rs.part0000\n5 c.rs.part0001\n2 c.rs.part0002\n";
+    put(&remote, "/dest/c.rs.manifest", manifest);
+    let opts = TransferOptions {
+      conflicts: ConflictPolicy::Rename,
+      ..TransferOptions::default()
+    };
+    let (from, to) = ("/dest/c.rs.manifest", "/src/c.rs.manifest");
+    transfer(TransferKind::Download, &local, &remote, from, to, opts).unwrap();
+    assert_eq!(contents(&local, "/src/c.rs"), "fn main() {}");
+    // which is no more allowed to replace what's there than any other file
+    transfer(TransferKind::Download, &local, &remote, from, to, opts).unwrap();
+    assert_eq!(contents(&local, "/src/c (1).rs"), "fn main() {}");
+  }
+
+  #[test]
+  fn scans_count_what_would_be_copied() {
+    let fs = tree();
+    let scan = |policy| {
+      let mut summary = ScanSummary::default();
+      scan_tree(
+        &fs,
+        Path::new("/src"),
+        policy,
+        &mut HashSet::new(),
+        &mut summary,
+      )
+      .unwrap();
+      (summary.files, summary.bytes)
+    };
+    assert_eq!(scan(SymlinkPolicy::Skip), (4, 20));
+    // `link` is `lib` again, which is only counted once
+    assert_eq!(scan(SymlinkPolicy::Follow), (4, 20));
+  }
+
+  #[test]
+  fn numbered_copies_go_before_the_first_dot() {
+    let copy = |name: &str| numbered_copy(Path::new("dir").join(name).as_path(), 2);
+    assert_eq!(copy("file.txt"), Path::new("dir/file (2).txt"));
+    assert_eq!(copy("a.tar.gz"), Path::new("dir/a (2).tar.gz"));
+    assert_eq!(copy("README"), Path::new("dir/README (2)"));
+    assert_eq!(copy(".bashrc"), Path::new("dir/.bashrc (2)"));
+    assert_eq!(copy(".config.toml"), Path::new("dir/.config (2).toml"));
+  }
+}
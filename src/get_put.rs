@@ -0,0 +1,128 @@
+//! `gsftp get` and `gsftp put`: a single transfer run without the interface, for scripts.
+//! Nothing is printed but errors and, unless `--quiet`, a line saying what was copied;
+//! `--progress` keeps a line on standard error up to date as it goes. Like scp, the exit status
+//! is 0 if the transfer worked, 1 if it didn't, and 255 if the server couldn't be logged in to.
+use clap::ArgMatches;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use gsftp::app_utils::human_size;
+use gsftp::config::Config;
+use gsftp::error::GsftpError;
+use gsftp::file_transfer::{Transfer, TransferKind, TransferStatus};
+use gsftp::headless::{self, FAILED};
+use gsftp::remote_path;
+use gsftp::settings::Settings;
+use gsftp::sftp;
+
+/// Runs `gsftp get` (a download) or `gsftp put` (an upload) with the subcommand's `args`,
+/// and yields the exit status
+pub fn run(kind: TransferKind, args: &ArgMatches, settings: &Settings) -> i32 {
+  match transfer(kind, args, settings) {
+    Ok(()) => 0,
+    Err((status, e)) => {
+      eprintln!("{e}");
+      status
+    }
+  }
+}
+
+fn transfer(
+  kind: TransferKind,
+  args: &ArgMatches,
+  settings: &Settings,
+) -> Result<(), (i32, String)> {
+  let (source, dest) = (
+    args.value_of("SOURCE").unwrap(),
+    args.value_of("DEST").unwrap(),
+  );
+  let (remote, local) = match kind {
+    TransferKind::Upload => (dest, source),
+    _ => (source, dest),
+  };
+  let conf =
+    Config::new(settings.destination(remote), args).map_err(|e| (FAILED, e.to_string()))?;
+  if args.is_present("no-exec") {
+    sftp::disable_exec();
+  }
+  if args.is_present("forward-agent") {
+    sftp::forward_agent();
+  }
+  let pool = headless::connect(conf.clone())?;
+  let failed = |e: GsftpError| (FAILED, e.to_string());
+  let sftp = pool.get().map_err(failed)?;
+  let home = sftp::home_dir(&sftp, &pool.session());
+  // The path after the host's `:` is under the home directory, unless it's absolute
+  let remote_path = conf
+    .start_dir
+    .as_deref()
+    .map_or_else(|| home.clone(), |path| headless::resolve(&home, path));
+  let local_path = PathBuf::from(local);
+  let (from, to) = match kind {
+    TransferKind::Upload => {
+      let name = local_path
+        .file_name()
+        .ok_or_else(|| (FAILED, format!("Can't upload {local}")))?;
+      let into_dir = remote.ends_with('/')
+        || conf.start_dir.is_none()
+        || sftp.stat(&remote_path).is_ok_and(|stat| stat.is_dir());
+      let to = match into_dir {
+        true => remote_path::join(&remote_path, name),
+        false => remote_path,
+      };
+      (local_path, to)
+    }
+    _ => {
+      if conf.start_dir.is_none() {
+        return Err((FAILED, format!("No remote path given in {remote}")));
+      }
+      let name = remote_path
+        .file_name()
+        .ok_or_else(|| (FAILED, format!("Can't download {}", remote_path.display())))?;
+      let to = match fs::metadata(&local_path) {
+        Ok(meta) if meta.is_dir() => local_path.join(name),
+        _ => local_path,
+      };
+      (remote_path, to)
+    }
+  };
+  drop(sftp);
+  let transfer =
+    Transfer::between(from, to, kind, headless::options(args), &pool).map_err(failed)?;
+  let status = execute(transfer, args.is_present("progress"))?;
+  if !args.is_present("quiet") {
+    println!("{}", status.describe_success().unwrap_or_default());
+  }
+  Ok(())
+}
+
+/// Runs `transfer` to the end, keeping a line on standard error up to date with how far it's got
+/// if `progress` is set
+pub fn execute(transfer: Transfer, progress: bool) -> Result<TransferStatus, (i32, String)> {
+  let status = headless::execute(transfer, |status| {
+    if progress {
+      show(status);
+    }
+  });
+  if progress {
+    eprintln!();
+  }
+  status
+}
+
+// e.g. `app.log  45% 12.0 MB of 26.5 MB, 3.1 MB/s`, over the last one
+fn show(status: &TransferStatus) {
+  let name = status.current.as_deref().unwrap_or(&status.name);
+  let percent = match status.total {
+    0 => 100,
+    total => status.bytes * 100 / total,
+  };
+  eprint!(
+    "\r{name}  {percent}% {} of {}, {}/s\x1b[K",
+    human_size(status.bytes),
+    human_size(status.total),
+    human_size(status.speed())
+  );
+  let _ = io::stderr().flush();
+}
@@ -0,0 +1,89 @@
+//! Transfers without the interface, for `gsftp get`, `put` and `batch` and for other programs:
+//! logging in quietly, and running one transfer to the end. Like scp, the exit statuses that go
+//! with the errors are 1 if the transfer failed and 255 if the server couldn't be logged in to.
+use clap::ArgMatches;
+use crossbeam_channel::unbounded;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crate::config::Config;
+use crate::credentials;
+use crate::file_transfer::{
+  Progress, Transfer, TransferEvent, TransferOptions, TransferState, TransferStatus,
+};
+use crate::remote_path;
+use crate::session::SessionPool;
+use crate::sftp;
+
+/// The exit status when the transfer, or anything before it, failed
+pub const FAILED: i32 = 1;
+/// The exit status when the server couldn't be connected or authenticated to
+pub const UNREACHABLE: i32 = 255;
+
+/// `--symlinks` and `--on-conflict`, as given
+pub fn options(args: &ArgMatches) -> TransferOptions {
+  let mut opts = TransferOptions::default();
+  if let Some(policy) = args.value_of("symlinks") {
+    opts.symlinks = policy.parse().unwrap_or(opts.symlinks);
+  }
+  if let Some(policy) = args.value_of("on-conflict") {
+    opts.conflicts = policy.parse().unwrap_or(opts.conflicts);
+  }
+  opts
+}
+
+/// The remote `path`, which is under `dir` unless it's absolute
+pub fn resolve(dir: &Path, path: impl AsRef<Path>) -> PathBuf {
+  let path = path.as_ref();
+  match path.has_root() {
+    true => path.to_path_buf(),
+    false => remote_path::join(dir, path),
+  }
+}
+
+/// Log in to the server `conf` describes, the way the interface would, but quietly
+pub fn connect(mut conf: Config) -> Result<Arc<SessionPool>, (i32, String)> {
+  let (sess, auth_method) = sftp::authenticate(&conf)
+    .map_err(|e| (UNREACHABLE, format!("Error establishing SSH session: {e}")))?;
+  if conf.keyring {
+    credentials::remember(&conf, &auth_method);
+  }
+  conf.auth_method = auth_method;
+  Ok(SessionPool::new(sess, conf))
+}
+
+/// Run `transfer` to the end, on a thread of its own, giving `progress` its status each time it
+/// gets further. Yields its finished status, or the error it failed with.
+pub fn execute(
+  transfer: Transfer,
+  mut progress: impl FnMut(&TransferStatus),
+) -> Result<TransferStatus, (i32, String)> {
+  let total = transfer.scan().unwrap_or_default();
+  let mut status = transfer.status(0, total);
+  let (tx, events) = unbounded();
+  let worker = thread::spawn(move || transfer.execute(Progress::new(0, tx)));
+  for event in events.iter() {
+    match event {
+      TransferEvent::Progress {
+        bytes,
+        files,
+        current,
+        ..
+      } => {
+        status.advance(bytes, files, current);
+        progress(&status);
+      }
+      TransferEvent::Finished { result, .. } => {
+        status.finish(result);
+        break;
+      }
+      TransferEvent::Started { .. } => {}
+    }
+  }
+  let _ = worker.join();
+  match &status.state {
+    TransferState::Failed(message) => Err((FAILED, message.clone())),
+    _ => Ok(status),
+  }
+}
@@ -0,0 +1,87 @@
+//! Keeps an eye on whether the server still answers, and how quickly, by timing a cheap SFTP
+//! request every few seconds in the background
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::session::SessionPool;
+
+/// How often the server is pinged
+const INTERVAL: Duration = Duration::from_secs(5);
+/// Round trips slower than this are shown as slow
+const SLOW: Duration = Duration::from_millis(500);
+/// A ping that hasn't come back after this long means the connection is gone
+const LOST: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the connection to a server is doing, as of the latest ping
+pub enum Health {
+  /// Nothing's been measured yet
+  Unknown,
+  Connected(Duration),
+  Slow(Duration),
+  Disconnected,
+}
+
+impl Health {
+  /// e.g. `connected 42 ms`, for the status bar
+  pub fn describe(&self) -> String {
+    match self {
+      Self::Unknown => String::from("checking connection"),
+      Self::Connected(latency) => format!("connected {} ms", latency.as_millis()),
+      Self::Slow(latency) => format!("slow {} ms", latency.as_millis()),
+      Self::Disconnected => String::from("disconnected"),
+    }
+  }
+}
+
+#[derive(Default)]
+struct Probe {
+  /// How long the last ping took, or `None` if it failed
+  last: Option<Option<Duration>>,
+  /// When the ping that's underway was sent
+  sent: Option<Instant>,
+}
+
+/// Pings one server on a background thread, which stops once the monitor is dropped
+pub struct Monitor {
+  probe: Arc<Mutex<Probe>>,
+}
+
+impl Monitor {
+  pub fn start(pool: Arc<SessionPool>) -> Self {
+    let probe = Arc::new(Mutex::new(Probe::default()));
+    let weak = Arc::downgrade(&probe);
+    thread::spawn(move || {
+      while let Some(probe) = weak.upgrade() {
+        let sent = Instant::now();
+        probe.lock().unwrap_or_else(|e| e.into_inner()).sent = Some(sent);
+        // Don't keep the monitor alive while the server takes its time
+        drop(probe);
+        let answered = pool
+          .get()
+          .is_ok_and(|sftp| sftp.stat(Path::new(".")).is_ok());
+        let Some(probe) = weak.upgrade() else { break };
+        let mut probe = probe.lock().unwrap_or_else(|e| e.into_inner());
+        probe.sent = None;
+        probe.last = Some(answered.then(|| sent.elapsed()));
+        drop(probe);
+        thread::sleep(INTERVAL);
+      }
+    });
+    Self { probe }
+  }
+
+  /// How the connection is doing right now
+  pub fn health(&self) -> Health {
+    let probe = self.probe.lock().unwrap_or_else(|e| e.into_inner());
+    match (probe.sent, probe.last) {
+      (Some(sent), _) if sent.elapsed() > LOST => Health::Disconnected,
+      (_, None) => Health::Unknown,
+      (_, Some(None)) => Health::Disconnected,
+      (_, Some(Some(latency))) if latency > SLOW => Health::Slow(latency),
+      (_, Some(Some(latency))) => Health::Connected(latency),
+    }
+  }
+}
@@ -0,0 +1,118 @@
+//! Checking the server's host key before we authenticate: against `~/.ssh/known_hosts` and
+//! gsftp's own list of hosts accepted on first use, refusing to connect if a key has changed
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use ssh2::{CheckResult, HashType, HostKeyType, KnownHostFileKind, KnownHostKeyFormat, Session};
+use std::fs;
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::ask;
+use crate::config::Config;
+use crate::error::GsftpError;
+
+/// Makes sure the session's host key is one we already trust, or that the user accepts it.
+/// Newly accepted keys are saved to gsftp's own known hosts file, never to OpenSSH's.
+pub fn check(sess: &Session, conf: &Config) -> Result<(), GsftpError> {
+  let (key, key_type) = sess.host_key().ok_or("The server didn't send a host key")?;
+  let fingerprint = fingerprint(sess);
+  let mut known_hosts = sess.known_hosts()?;
+  for file in [openssh_known_hosts(), gsftp_known_hosts()]
+    .into_iter()
+    .flatten()
+  {
+    if file.exists() {
+      known_hosts.read_file(&file, KnownHostFileKind::OpenSSH)?;
+    }
+  }
+  // Known hosts files may list the server by name or by address
+  let by_name = known_hosts.check_port(&conf.host, conf.port, key);
+  let by_addr = known_hosts.check_port(&conf.addr, conf.port, key);
+  match (by_name, by_addr) {
+    (CheckResult::Mismatch, _) | (_, CheckResult::Mismatch) => {
+      return Err(GsftpError::HostKey(format!(
+        "The host key for {} has changed! It's now {} {fingerprint}.\n\
+           Someone could be eavesdropping on you, or the server was reinstalled.\n\
+           If you trust the new key, remove the old one from your known hosts file.",
+        conf.host,
+        key_name(key_type)
+      )))
+    }
+    (CheckResult::Match, _) | (_, CheckResult::Match) => return Ok(()),
+    (CheckResult::Failure, _) | (_, CheckResult::Failure) => {
+      return Err(GsftpError::HostKey(String::from(
+        "Couldn't check the server's host key",
+      )))
+    }
+    (CheckResult::NotFound, CheckResult::NotFound) => {}
+  }
+
+  ask::tell(&format!(
+    "The authenticity of host {} ({}, port {}) can't be established.\n{} key fingerprint is {fingerprint}.",
+    conf.host,
+    conf.addr,
+    conf.port,
+    key_name(key_type)
+  ));
+  let answer = ask::ask(
+    "Are you sure you want to continue connecting (yes/no)? ",
+    false,
+  )?;
+  if !matches!(answer.trim().to_lowercase().as_str(), "yes" | "y") {
+    return Err(GsftpError::HostKey(String::from("Host key not accepted")));
+  }
+
+  // Start from our own file alone, so that nothing from OpenSSH's is copied into it
+  let file = gsftp_known_hosts().ok_or("Couldn't find a data directory to remember the host in")?;
+  let mut ours = sess.known_hosts()?;
+  if file.exists() {
+    ours.read_file(&file, KnownHostFileKind::OpenSSH)?;
+  }
+  let host = match conf.port {
+    22 => conf.host.clone(),
+    port => format!("[{}]:{port}", conf.host),
+  };
+  ours.add(&host, key, "", key_format(key_type))?;
+  if let Some(dir) = file.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  ours.write_file(&file, KnownHostFileKind::OpenSSH)?;
+  info!("Permanently added {host} to {}", file.display());
+
+  Ok(())
+}
+
+/// `SHA256:...`, the way OpenSSH shows fingerprints
+pub fn fingerprint(sess: &Session) -> String {
+  let hash = sess.host_key_hash(HashType::Sha256).unwrap_or_default();
+  format!("SHA256:{}", STANDARD_NO_PAD.encode(hash))
+}
+
+fn openssh_known_hosts() -> Option<PathBuf> {
+  dirs::home_dir().map(|home| home.join(".ssh").join("known_hosts"))
+}
+
+fn gsftp_known_hosts() -> Option<PathBuf> {
+  dirs::data_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("known_hosts"))
+}
+
+fn key_name(key_type: HostKeyType) -> &'static str {
+  match key_type {
+    HostKeyType::Rsa => "RSA",
+    HostKeyType::Dss => "DSA",
+    HostKeyType::Ecdsa256 | HostKeyType::Ecdsa384 | HostKeyType::Ecdsa521 => "ECDSA",
+    HostKeyType::Ed25519 => "ED25519",
+    HostKeyType::Unknown => "Unknown",
+  }
+}
+
+fn key_format(key_type: HostKeyType) -> KnownHostKeyFormat {
+  match key_type {
+    HostKeyType::Rsa => KnownHostKeyFormat::SshRsa,
+    HostKeyType::Dss => KnownHostKeyFormat::SshDss,
+    HostKeyType::Ecdsa256 => KnownHostKeyFormat::Ecdsa256,
+    HostKeyType::Ecdsa384 => KnownHostKeyFormat::Ecdsa384,
+    HostKeyType::Ecdsa521 => KnownHostKeyFormat::Ecdsa521,
+    HostKeyType::Ed25519 => KnownHostKeyFormat::Ed25519,
+    HostKeyType::Unknown => KnownHostKeyFormat::Unknown,
+  }
+}
@@ -0,0 +1,207 @@
+//! What the interface says, in the user's language: the keyboard help, prompts, panel titles,
+//! messages and the prefixes of error messages. The English text in the code is the key; a catalog for
+//! another language pairs each one with its translation, and anything it leaves out stays
+//! English, so a catalog can be added to a bit at a time. A text with `{}` in it is a template
+//! filled in by `tr_args`, and its translation has as many `{}`s, in the same order.
+//!
+//! The language is `language` in `config.toml`, or else the one `$LC_ALL`, `$LC_MESSAGES` or
+//! `$LANG` names (e.g. `de_DE.UTF-8`), or English if there's no catalog for that.
+use std::collections::HashMap;
+use std::env;
+use std::sync::OnceLock;
+
+// (English, translation) pairs
+type Catalog = &'static [(&'static str, &'static str)];
+
+// Every language there's a catalog for, by its ISO 639-1 code. English is the text itself.
+const CATALOGS: [(&str, Catalog); 2] = [("en", &[]), ("de", GERMAN)];
+
+static CATALOG: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+
+/// Picks the catalog for `language` (`config.toml`'s), or else the locale's, once and for all.
+/// A language with no catalog is an error if it was asked for by name.
+pub fn init(language: Option<&str>) -> Result<(), String> {
+  let catalog = match language {
+    Some(language) => catalog(language).ok_or_else(|| {
+      let known: Vec<&str> = CATALOGS.iter().map(|(code, _)| *code).collect();
+      format!(
+        "No translations for language {language}; there are {}",
+        known.join(", ")
+      )
+    })?,
+    None => ["LC_ALL", "LC_MESSAGES", "LANG"]
+      .iter()
+      .find_map(|var| env::var(var).ok().filter(|locale| !locale.is_empty()))
+      .and_then(|locale| catalog(&locale))
+      .unwrap_or_default(),
+  };
+  let _ = CATALOG.set(catalog.iter().copied().collect());
+  Ok(())
+}
+
+// The catalog for a language code or locale, e.g. `de`, `de_AT` or `de_DE.UTF-8`
+fn catalog(locale: &str) -> Option<Catalog> {
+  let code = locale
+    .split(['_', '-', '.', '@'])
+    .next()
+    .unwrap_or_default()
+    .to_lowercase();
+  CATALOGS
+    .iter()
+    .find(|(known, _)| *known == code)
+    .map(|(_, catalog)| *catalog)
+}
+
+/// `text` in the user's language, if there's a translation for it
+pub fn tr(text: &'static str) -> &'static str {
+  CATALOG
+    .get()
+    .and_then(|catalog| catalog.get(text).copied())
+    .unwrap_or(text)
+}
+
+/// The template `text` in the user's language, with each `{}` replaced by the next of `values`
+pub fn tr_args(text: &'static str, values: &[&str]) -> String {
+  let mut pieces = tr(text).split("{}");
+  let mut filled = String::from(pieces.next().unwrap_or_default());
+  for (piece, value) in pieces.zip(values.iter().chain(std::iter::repeat(&""))) {
+    filled.push_str(value);
+    filled.push_str(piece);
+  }
+  filled
+}
+
+#[rustfmt::skip]
+const GERMAN: Catalog = &[
+  // keyboard help
+  ("move up",                       "nach oben"),
+  ("move down",                     "nach unten"),
+  ("enter directory",               "Verzeichnis öffnen"),
+  ("exit directory",                "Verzeichnis verlassen"),
+  ("go to the top",                 "zum Anfang"),
+  ("go to the bottom",              "zum Ende"),
+  ("switch windows",                "Fenster wechseln"),
+  ("download/upload",               "herunter-/hochladen"),
+  ("schedule download/upload",      "Übertragung planen"),
+  ("copy to another host",          "auf anderen Host kopieren"),
+  ("verify against other pane",     "mit anderem Fenster prüfen"),
+  ("copy what differs",             "Unterschiede kopieren"),
+  ("transfer queue",                "Übertragungen"),
+  ("rename",                        "umbenennen"),
+  ("delete",                        "löschen"),
+  ("permissions",                   "Rechte"),
+  ("owner (remote)",                "Besitzer (entfernt)"),
+  ("new directory, or next match",  "neues Verzeichnis, oder nächster Treffer"),
+  ("new file, or previous match",   "neue Datei, oder voriger Treffer"),
+  ("view file",                     "Datei ansehen"),
+  ("edit file",                     "Datei bearbeiten"),
+  ("item details",                  "Details"),
+  ("directory size",                "Verzeichnisgröße"),
+  ("copy path",                     "Pfad kopieren"),
+  ("yank",                          "zum Kopieren merken"),
+  ("paste copy",                    "Kopie einfügen"),
+  ("mark item",                     "markieren"),
+  ("invert marks",                  "Markierung umkehren"),
+  ("mark by pattern",               "nach Muster markieren"),
+  ("mark all",                      "alle markieren"),
+  ("bookmark directory",            "Lesezeichen setzen"),
+  ("bookmarks",                     "Lesezeichen"),
+  ("filter",                        "filtern"),
+  ("jump to a name as you type it", "beim Tippen zum Namen springen"),
+  ("find under remote dir",         "unter entferntem Verzeichnis suchen"),
+  ("sort by next key",              "anders sortieren"),
+  ("reverse sort order",            "Sortierung umkehren"),
+  ("toggle hidden files",           "versteckte Dateien ein/aus"),
+  ("toggle file details",           "Dateidetails ein/aus"),
+  ("compare panes",                 "Fenster vergleichen"),
+  ("follow symlinks",               "symbolischen Links folgen"),
+  ("refresh",                       "neu laden"),
+  ("move the divider left",         "Trennlinie nach links"),
+  ("move the divider right",        "Trennlinie nach rechts"),
+  ("zoom active window",            "aktives Fenster vergrößern"),
+  ("whole name in status bar",      "ganzer Name in der Statusleiste"),
+  ("scroll the whole name back",    "ganzen Namen zurückrollen"),
+  ("scroll the whole name on",      "ganzen Namen weiterrollen"),
+  ("message log",                   "Meldungen"),
+  ("server info",                   "Serverinfo"),
+  ("next host",                     "nächster Host"),
+  ("new tab",                       "neuer Tab"),
+  ("type a command",                "Befehl eingeben"),
+  ("keyboard help",                 "Tastenhilfe"),
+  ("exit",                          "beenden"),
+  ("show that tab",                 "diesen Tab zeigen"),
+  ("next/previous tab",             "nächster/voriger Tab"),
+  ("clear the filter first",        "erst den Filter löschen"),
+  ("go to directory",               "zum Verzeichnis"),
+  ("new directory",                 "neues Verzeichnis"),
+  ("new file",                      "neue Datei"),
+  ("add host",                      "Host hinzufügen"),
+  ("show host n",                   "Host n zeigen"),
+  ("close tab",                     "Tab schließen"),
+  ("read the config files again",   "Konfiguration neu laden"),
+  ("type the encryption passphrase again",
+                                    "Passphrase zur Verschlüsselung neu eingeben"),
+  // panels
+  ("Press '?' to toggle help",      "'?' zeigt die Hilfe"),
+  ("Keys matching /{}_",            "Tasten zu /{}_"),
+  ("Keys matching /{} ({} found; / searches again, ? closes)",
+                                    "Tasten zu /{} ({} gefunden; / sucht erneut, ? schließt)"),
+  ("Keyboard controls (j/k scroll, / search, ? close)",
+                                    "Tastenbelegung (j/k rollen, / suchen, ? schließen)"),
+  ("Keyboard controls (/ search, ? close)",
+                                    "Tastenbelegung (/ suchen, ? schließen)"),
+  ("No messages yet",               "Noch keine Meldungen"),
+  ("Messages (s: save to a file, M: close)",
+                                    "Meldungen (s: in Datei sichern, M: schließen)"),
+  ("No transfers yet",              "Noch keine Übertragungen"),
+  ("Transfers",                     "Übertragungen"),
+  ("Transfers (x: cancel, r: retry, T: close)",
+                                    "Übertragungen (x: abbrechen, r: wiederholen, T: schließen)"),
+  ("Confirm",                       "Bestätigen"),
+  ("Input",                         "Eingabe"),
+  // prompts
+  ("New directory name:",           "Name des neuen Verzeichnisses:"),
+  ("New file name:",                "Name der neuen Datei:"),
+  ("find: ",                        "suchen: "),
+  ("Mark names matching (e.g. *.log):",
+                                    "Namen markieren, die passen auf (z.B. *.log):"),
+  ("Permissions for {}, now {} (e.g. 644, rw-r--r--, g+w):",
+                                    "Rechte für {}, jetzt {} (z.B. 644, rw-r--r--, g+w):"),
+  ("Rename {} to:",                 "{} umbenennen in:"),
+  ("Owner for {}, now {} (user, user:group or :group):",
+                                    "Besitzer für {}, jetzt {} (Benutzer, Benutzer:Gruppe oder :Gruppe):"),
+  ("Start at (HH:MM, +10m, or after the queue):",
+                                    "Start um (HH:MM, +10m, oder nach der Warteschlange):"),
+  ("Copy to host number (1-{}):",   "Auf Host Nummer (1-{}) kopieren:"),
+  ("Find under {} (e.g. *.log):",   "Suchen unter {} (z.B. *.log):"),
+  ("Passphrase to encrypt and decrypt transfers with:",
+                                    "Passphrase zum Ver- und Entschlüsseln von Übertragungen:"),
+  // messages
+  ("Connected to {}",               "Verbunden mit {}"),
+  ("No connection number {}",       "Keine Verbindung Nummer {}"),
+  ("Reloaded the config files",     "Konfigurationsdateien neu geladen"),
+  ("The last tab can't be closed; q quits",
+                                    "Der letzte Tab lässt sich nicht schließen; q beendet"),
+  ("Commands are `cd <path>`, `mkdir <name>`, `touch <name>`, `connect user@host`, `switch <number>`, `tabnew [user@host]`, `tabclose`, `reload-config` and `passphrase`",
+                                    "Befehle sind `cd <Pfad>`, `mkdir <Name>`, `touch <Name>`, `connect benutzer@host`, `switch <Nummer>`, `tabnew [benutzer@host]`, `tabclose`, `reload-config` und `passphrase`"),
+  ("Start gsftp with --encrypt-passphrase to encrypt with a passphrase",
+                                    "Zum Verschlüsseln mit einer Passphrase gsftp mit --encrypt-passphrase starten"),
+  ("The passphrase can't be empty", "Die Passphrase darf nicht leer sein"),
+  ("Transfers will be encrypted with the passphrase",
+                                    "Übertragungen werden mit der Passphrase verschlüsselt"),
+  ("That's not the number of another connected host",
+                                    "Das ist nicht die Nummer eines anderen verbundenen Hosts"),
+  ("Highlight something in the remote window to copy it to another host",
+                                    "Im entfernten Fenster etwas auswählen, um es auf einen anderen Host zu kopieren"),
+  ("Connect to another host first, with :connect user@host",
+                                    "Zuerst mit :connect benutzer@host einen anderen Host verbinden"),
+  ("Nothing differs from the other side",
+                                    "Nichts unterscheidet sich von der anderen Seite"),
+  ("Copying 1 item that differs",   "Kopiere 1 abweichenden Eintrag"),
+  ("Copying {} items that differ",  "Kopiere {} abweichende Einträge"),
+  // error prefixes
+  ("TRANSFER ERROR: {}",            "ÜBERTRAGUNGSFEHLER: {}"),
+  ("VERIFY ERROR: {}",              "PRÜFFEHLER: {}"),
+  ("Read-only: start gsftp without --read-only to make changes",
+                                    "Nur lesen: für Änderungen gsftp ohne --read-only starten"),
+];
@@ -0,0 +1,158 @@
+//! Jobs that would hold the interface up, each on a thread of its own, sending what came of them
+//! back to the event loop
+use crossbeam_channel::Sender;
+use std::sync::Arc;
+use std::thread;
+
+use gsftp::app::App;
+use gsftp::app_utils::{self, ActiveState};
+use gsftp::details;
+use gsftp::i18n;
+use gsftp::register::Register;
+use gsftp::remote_path;
+use gsftp::search;
+use gsftp::session::SessionPool;
+use gsftp::usage;
+use gsftp::verify::{self, Verdict};
+
+/// Adds up the size of `name` in the active pane, which takes a while for big directories
+pub fn spawn_usage_thread(
+  app: &App,
+  name: String,
+  pool: &Arc<SessionPool>,
+  tx: &Sender<(String, Result<String, String>)>,
+) {
+  let (remote, path) = match app.state.active {
+    ActiveState::Local => (false, app.buf.local.join(app.file_name(&name))),
+    ActiveState::Remote => (
+      true,
+      remote_path::join(&app.buf.remote, app.file_name(&name)),
+    ),
+  };
+  let pool = Arc::clone(pool);
+  let tx = tx.clone();
+  thread::spawn(move || {
+    let usage = match remote {
+      false => usage::local(&path).map_err(|e| e.to_string()),
+      true => pool
+        .get()
+        .and_then(|sftp| usage::remote(&pool.session(), &sftp, &path))
+        .map_err(|e| e.to_string()),
+    };
+    let message = usage
+      .map(|usage| format!("{name}: {}", usage.describe()))
+      .map_err(|e| format!("Couldn't measure {name}: {e}"));
+    let _ = tx.send((name, message));
+  });
+}
+
+/// Looks up everything about `name` in the active pane; remote owner names take a command or
+/// two on the server
+pub fn spawn_details_thread(
+  app: &App,
+  name: String,
+  pool: &Arc<SessionPool>,
+  tx: &Sender<(String, Result<String, String>)>,
+) {
+  let (remote, path) = match app.state.active {
+    ActiveState::Local => (false, app.buf.local.join(app.file_name(&name))),
+    ActiveState::Remote => (
+      true,
+      remote_path::join(&app.buf.remote, app.file_name(&name)),
+    ),
+  };
+  let pool = Arc::clone(pool);
+  let tx = tx.clone();
+  thread::spawn(move || {
+    let text = match remote {
+      false => details::local(&path).map_err(|e| e.to_string()),
+      true => pool
+        .get()
+        .and_then(|sftp| details::remote(&pool.session(), &sftp, &path))
+        .map_err(|e| e.to_string()),
+    };
+    let text = text.map_err(|e| format!("Couldn't look up {name}: {e}"));
+    let _ = tx.send((name, text));
+  });
+}
+
+/// Searches under the remote pane's directory for `pattern`, which can take a while on a big tree
+pub fn spawn_search_thread(
+  app: &App,
+  pattern: String,
+  pool: &Arc<SessionPool>,
+  tx: &Sender<(String, Result<Vec<String>, String>)>,
+) {
+  let dir = app.buf.remote.clone();
+  let pool = Arc::clone(pool);
+  let tx = tx.clone();
+  thread::spawn(move || {
+    let found = pool
+      .get()
+      .and_then(|sftp| search::remote(&pool.session(), &sftp, &dir, &pattern))
+      .map(|found| {
+        found
+          .iter()
+          .map(|path| path.display().to_string())
+          .collect()
+      })
+      .map_err(|e| e.to_string());
+    let _ = tx.send((pattern, found));
+  });
+}
+
+/// Copies what was `yanked` into the active pane's directory
+pub fn spawn_paste_thread(
+  app: &App,
+  yanked: Register,
+  pool: &Arc<SessionPool>,
+  tx: &Sender<Result<String, String>>,
+) {
+  let dir = match app.state.active {
+    ActiveState::Local => app.buf.local.clone(),
+    ActiveState::Remote => app.buf.remote.clone(),
+  };
+  let pool = Arc::clone(pool);
+  let tx = tx.clone();
+  thread::spawn(move || {
+    let pasted = match yanked.host {
+      None => yanked.paste_local(&dir),
+      Some(_) => pool
+        .get()
+        .and_then(|sftp| yanked.paste_remote(&pool.session(), &sftp, &dir)),
+    };
+    let message = pasted
+      .map(|_| format!("Pasted {}", yanked.describe()))
+      .map_err(|e| format!("Couldn't paste {}: {e}", yanked.describe()));
+    let _ = tx.send(message);
+  });
+}
+
+/// Compares `name` in both panes, by checksum if it has to, which takes a while for big files
+pub fn spawn_verify_thread(
+  app: &App,
+  name: String,
+  pool: &Arc<SessionPool>,
+  tx: &Sender<Result<String, String>>,
+) {
+  let local = app.buf.local.join(app.file_name(&name));
+  let remote = remote_path::join(&app.buf.remote, app.file_name(&name));
+  let pool = Arc::clone(pool);
+  let tx = tx.clone();
+  thread::spawn(move || {
+    let verdict = pool
+      .get()
+      .and_then(|sftp| verify::verify(&local, &remote, &pool.session(), &sftp));
+    let message = match verdict {
+      Ok(Verdict::Identical) => Ok(format!("{name}: identical")),
+      Ok(Verdict::SizeDiffers { local, remote }) => Err(format!(
+        "{name}: different (local {}, remote {})",
+        app_utils::human_size(local),
+        app_utils::human_size(remote)
+      )),
+      Ok(Verdict::ContentDiffers) => Err(format!("{name}: different (checksums don't match)")),
+      Err(e) => Err(i18n::tr_args("VERIFY ERROR: {}", &[&e.to_string()])),
+    };
+    let _ = tx.send(message);
+  });
+}
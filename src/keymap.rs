@@ -0,0 +1,422 @@
+//! Which key does what in the panes, and the help the keys are listed in. Keys can be changed in
+//! the `[keys]` table of `config.toml` (see `settings`), e.g.
+//!
+//! ```toml
+//! [keys]
+//! delete = ["x", "Delete"]
+//! yank = "ctrl+y"
+//! queue = "none"
+//! ```
+//!
+//! Each line gives an action all the keys it's bound to, as a list or separated by spaces in a
+//! string, taking them from
+//! any other action they were bound to; `none` leaves it with no keys at all. Keys are a single
+//! character (`G` is Shift+g), or one of `enter`, `tab`, `esc`, `space`, `backspace`, `delete`,
+//! `up`, `down`, `left`, `right`, `home`, `end`, `pageup`, `pagedown` or `f1` to `f12`, with any
+//! of `ctrl+`, `alt+` and `shift+` in front.
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::app_utils::ActiveState;
+use crate::i18n;
+use crate::settings::{Setting, Settings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Everything a key can do in the panes
+pub enum Action {
+  Up,
+  Down,
+  EnterDir,
+  LeaveDir,
+  Top,
+  Bottom,
+  SwitchWindow,
+  Transfer,
+  Schedule,
+  Relay,
+  Verify,
+  CopyDifferences,
+  Queue,
+  Rename,
+  Delete,
+  Chmod,
+  Chown,
+  /// Makes a new directory, or goes to the next match while there's a filter
+  NewDir,
+  /// Makes a new file, or goes to the previous match while there's a filter
+  NewFile,
+  View,
+  Edit,
+  Details,
+  Usage,
+  CopyPath,
+  Yank,
+  Paste,
+  Mark,
+  InvertMarks,
+  MarkPattern,
+  MarkAll,
+  Bookmark,
+  Bookmarks,
+  Filter,
+  Find,
+  Search,
+  Sort,
+  Reverse,
+  Hidden,
+  FileDetails,
+  Compare,
+  FollowSymlinks,
+  Refresh,
+  SplitLeft,
+  SplitRight,
+  Zoom,
+  WholeName,
+  NameLeft,
+  NameRight,
+  Log,
+  ServerInfo,
+  NextHost,
+  NewTab,
+  Command,
+  Help,
+  Quit,
+}
+
+impl Action {
+  /// Whether it changes files, here or on the server, which `--read-only` won't let it do.
+  /// Transfers only do from the local window, since downloads are left alone.
+  pub fn writes(self, pane: ActiveState) -> bool {
+    match self {
+      Self::Transfer | Self::Schedule | Self::CopyDifferences => {
+        matches!(pane, ActiveState::Local)
+      }
+      Self::Relay
+      | Self::Rename
+      | Self::Delete
+      | Self::Chmod
+      | Self::Chown
+      | Self::NewDir
+      | Self::NewFile
+      | Self::Edit
+      | Self::Paste => true,
+      _ => false,
+    }
+  }
+}
+
+/// A key with the modifiers held down with it. Shift is left out for characters, which are
+/// upper case already if it was held.
+pub type Key = (KeyCode, KeyModifiers);
+
+// Every action in the order the help lists them, with the name the config file calls it by,
+// what the help says it does, and the keys it has unless the config file says otherwise
+#[rustfmt::skip]
+const DEFAULTS: [(Action, &str, &str, &str); 55] = [
+  (Action::Up,              "up",               "move up",                       "k up"),
+  (Action::Down,            "down",             "move down",                     "j down"),
+  (Action::EnterDir,        "enter_dir",        "enter directory",               "l right"),
+  (Action::LeaveDir,        "leave_dir",        "exit directory",                "h left"),
+  (Action::Top,             "top",              "go to the top",                 "home ctrl+up"),
+  (Action::Bottom,          "bottom",           "go to the bottom",              "G ctrl+down"),
+  (Action::SwitchWindow,    "switch_window",    "switch windows",                "w tab ctrl+w"),
+  (Action::Transfer,        "transfer",         "download/upload",               "y enter"),
+  (Action::Schedule,        "schedule",         "schedule download/upload",      "S"),
+  (Action::Relay,           "relay",            "copy to another host",          "H"),
+  (Action::Verify,          "verify",           "verify against other pane",     "V"),
+  (Action::CopyDifferences, "copy_differences", "copy what differs",             "Y"),
+  (Action::Queue,           "queue",            "transfer queue",                "T"),
+  (Action::Rename,          "rename",           "rename",                        "r"),
+  (Action::Delete,          "delete",           "delete",                        "d delete"),
+  (Action::Chmod,           "chmod",            "permissions",                   "m"),
+  (Action::Chown,           "chown",            "owner (remote)",                "O"),
+  (Action::NewDir,          "new_dir",          "new directory, or next match",  "n"),
+  (Action::NewFile,         "new_file",         "new file, or previous match",   "N"),
+  (Action::View,            "view",             "view file",                     "v"),
+  (Action::Edit,            "edit",             "edit file",                     "e"),
+  (Action::Details,         "details",          "item details",                  "i"),
+  (Action::Usage,           "usage",            "directory size",                "u"),
+  (Action::CopyPath,        "copy_path",        "copy path",                     "c"),
+  (Action::Yank,            "yank",             "yank",                          "x"),
+  (Action::Paste,           "paste",            "paste copy",                    "p"),
+  (Action::Mark,            "mark",             "mark item",                     "space"),
+  (Action::InvertMarks,     "invert_marks",     "invert marks",                  "*"),
+  (Action::MarkPattern,     "mark_pattern",     "mark by pattern",               "+"),
+  (Action::MarkAll,         "mark_all",         "mark all",                      "ctrl+a"),
+  (Action::Bookmark,        "bookmark",         "bookmark directory",            "b"),
+  (Action::Bookmarks,       "bookmarks",        "bookmarks",                     "B"),
+  (Action::Filter,          "filter",           "filter",                        "/"),
+  (Action::Find,            "find",             "jump to a name as you type it", "f"),
+  (Action::Search,          "search",           "find under remote dir",         "F"),
+  (Action::Sort,            "sort",             "sort by next key",              "s"),
+  (Action::Reverse,         "reverse",          "reverse sort order",            "o"),
+  (Action::Hidden,          "hidden",           "toggle hidden files",           "a"),
+  (Action::FileDetails,     "file_details",     "toggle file details",           "D"),
+  (Action::Compare,         "compare",          "compare panes",                 "="),
+  (Action::FollowSymlinks,  "follow_symlinks",  "follow symlinks",               "L"),
+  (Action::Refresh,         "refresh",          "refresh",                       "R ctrl+r"),
+  (Action::SplitLeft,       "split_left",       "move the divider left",         "< ctrl+left"),
+  (Action::SplitRight,      "split_right",      "move the divider right",        "> ctrl+right"),
+  (Action::Zoom,            "zoom",             "zoom active window",            "z"),
+  (Action::WholeName,       "whole_name",       "whole name in status bar",      "W"),
+  (Action::NameLeft,        "name_left",        "scroll the whole name back",    "shift+left"),
+  (Action::NameRight,       "name_right",       "scroll the whole name on",      "shift+right"),
+  (Action::Log,             "log",              "message log",                   "M"),
+  (Action::ServerInfo,      "server_info",      "server info",                   "I"),
+  (Action::NextHost,        "next_host",        "next host",                     "C"),
+  (Action::NewTab,          "new_tab",          "new tab",                       "ctrl+t"),
+  (Action::Command,         "command",          "type a command",                ":"),
+  (Action::Help,            "help",             "keyboard help",                 "?"),
+  (Action::Quit,            "quit",             "exit",                          "q esc ctrl+c"),
+];
+
+// Keys that can't be changed, and the commands typed after `:`, for the end of the help
+const FIXED: [(&str, &str); 12] = [
+  ("1-9", "show that tab"),
+  ("gt or gT", "next/previous tab"),
+  ("Esc", "clear the filter first"),
+  (":cd path", "go to directory"),
+  (":mkdir name", "new directory"),
+  (":touch name", "new file"),
+  (":connect user@host", "add host"),
+  (":switch n", "show host n"),
+  (":tabnew [user@host]", "new tab"),
+  (":tabclose", "close tab"),
+  (":reload-config", "read the config files again"),
+  (":passphrase", "type the encryption passphrase again"),
+];
+
+#[derive(Debug, Clone)]
+/// The key each action is bound to
+pub struct Keymap {
+  bindings: Vec<(Key, Action)>,
+}
+
+impl Default for Keymap {
+  fn default() -> Self {
+    let bindings = DEFAULTS
+      .iter()
+      .flat_map(|(action, _, _, keys)| {
+        keys
+          .split_whitespace()
+          .map(|key| (parse_key(key).expect("default keys parse"), *action))
+      })
+      .collect();
+    Self { bindings }
+  }
+}
+
+impl Keymap {
+  /// The keys set up in `config.toml`, or the default ones if it changes none. Anything it says
+  /// that can't be understood is an error, saying where it is.
+  pub fn load(toml: &Settings) -> Result<Self, String> {
+    Self::from_settings(&toml.section("keys")?)
+  }
+
+  /// The default keys, changed by the settings from a `[keys]` table in order
+  pub fn from_settings(settings: &[Setting]) -> Result<Self, String> {
+    let mut keymap = Self::default();
+    for (at, name, value) in settings {
+      let action = DEFAULTS
+        .iter()
+        .find(|(_, action_name, ..)| *action_name == name.as_str())
+        .map(|(action, ..)| *action)
+        .ok_or_else(|| format!("{at}: unknown action {name}"))?;
+      let keys = match value.to_lowercase().as_str() {
+        "none" => Vec::new(),
+        _ => value
+          .split_whitespace()
+          .map(|key| parse_key(key).ok_or_else(|| format!("{at}: unknown key {key}")))
+          .collect::<Result<Vec<Key>, String>>()?,
+      };
+      keymap
+        .bindings
+        .retain(|(key, bound)| *bound != action && !keys.contains(key));
+      keymap
+        .bindings
+        .extend(keys.into_iter().map(|key| (key, action)));
+    }
+    Ok(keymap)
+  }
+
+  /// What the key pressed does, if anything
+  pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+    let key = normalize(event.code, event.modifiers);
+    self
+      .bindings
+      .iter()
+      .find(|(bound, _)| *bound == key)
+      .map(|(_, action)| *action)
+  }
+
+  /// Every action that has keys, in order, as e.g. `("k or ⬆", "move up")`, followed by the keys
+  /// that can't be changed and the commands. With `ascii`, keys are named without arrows.
+  pub fn help(&self, ascii: bool) -> Vec<(String, &'static str)> {
+    let mut lines: Vec<(String, &'static str)> = DEFAULTS
+      .iter()
+      .filter_map(|(action, _, description, _)| {
+        let keys: Vec<String> = self
+          .bindings
+          .iter()
+          .filter(|(_, bound)| bound == action)
+          .map(|(key, _)| describe_key(*key, ascii))
+          .collect();
+        (!keys.is_empty()).then(|| (keys.join(" or "), i18n::tr(description)))
+      })
+      .collect();
+    lines.extend(
+      FIXED
+        .iter()
+        .map(|(keys, description)| (keys.to_string(), i18n::tr(description))),
+    );
+    lines
+  }
+}
+
+// Shift only matters for keys that aren't characters: terminals disagree over whether they
+// report it along with e.g. `?`, but the character is the same either way
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> Key {
+  match code {
+    KeyCode::Char(c) => (KeyCode::Char(c), modifiers - KeyModifiers::SHIFT),
+    code => (code, modifiers),
+  }
+}
+
+/// e.g. `k`, `G`, `ctrl+a` or `shift+left` (see the module docs)
+pub fn parse_key(text: &str) -> Option<Key> {
+  let mut modifiers = KeyModifiers::NONE;
+  let mut rest = text;
+  // `+` on its own, or after a modifier, is the key itself
+  while let Some((modifier, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+    modifiers |= match modifier.to_lowercase().as_str() {
+      "ctrl" | "control" => KeyModifiers::CONTROL,
+      "alt" => KeyModifiers::ALT,
+      "shift" => KeyModifiers::SHIFT,
+      _ => return None,
+    };
+    rest = key;
+  }
+  let mut chars = rest.chars();
+  let code = match (chars.next(), chars.next()) {
+    (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+      KeyCode::Char(c.to_ascii_uppercase())
+    }
+    (Some(c), None) => KeyCode::Char(c),
+    _ => match rest.to_lowercase().as_str() {
+      "enter" | "return" => KeyCode::Enter,
+      "tab" => KeyCode::Tab,
+      "esc" | "escape" => KeyCode::Esc,
+      "space" => KeyCode::Char(' '),
+      "backspace" => KeyCode::Backspace,
+      "delete" | "del" => KeyCode::Delete,
+      "up" => KeyCode::Up,
+      "down" => KeyCode::Down,
+      "left" => KeyCode::Left,
+      "right" => KeyCode::Right,
+      "home" => KeyCode::Home,
+      "end" => KeyCode::End,
+      "pageup" => KeyCode::PageUp,
+      "pagedown" => KeyCode::PageDown,
+      name => KeyCode::F(
+        name
+          .strip_prefix('f')?
+          .parse()
+          .ok()
+          .filter(|n| (1..=12).contains(n))?,
+      ),
+    },
+  };
+  Some(normalize(code, modifiers))
+}
+
+/// e.g. `k`, `Ctrl+a` or `Shift+⬅` (`Shift+Left` with `ascii`), the way the help shows it
+pub fn describe_key((code, modifiers): Key, ascii: bool) -> String {
+  let mut text = String::new();
+  if modifiers.contains(KeyModifiers::CONTROL) {
+    text.push_str("Ctrl+");
+  }
+  if modifiers.contains(KeyModifiers::ALT) {
+    text.push_str("Alt+");
+  }
+  if modifiers.contains(KeyModifiers::SHIFT) {
+    text.push_str("Shift+");
+  }
+  let name = match code {
+    KeyCode::Char(' ') => String::from("space"),
+    KeyCode::Char(c) => c.to_string(),
+    KeyCode::Up
+    | KeyCode::Down
+    | KeyCode::Left
+    | KeyCode::Right
+    | KeyCode::Enter
+    | KeyCode::Tab
+      if ascii =>
+    {
+      format!("{code:?}")
+    }
+    KeyCode::Up => String::from("⬆"),
+    KeyCode::Down => String::from("⬇"),
+    KeyCode::Left => String::from("⬅"),
+    KeyCode::Right => String::from("➡"),
+    KeyCode::Enter => String::from("↩"),
+    KeyCode::Tab => String::from("↹"),
+    KeyCode::Esc => String::from("Esc"),
+    KeyCode::F(n) => format!("F{n}"),
+    code => format!("{code:?}"),
+  };
+  text.push_str(&name);
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keys_are_characters_or_names_after_modifiers() {
+    assert_eq!(
+      parse_key("k"),
+      Some((KeyCode::Char('k'), KeyModifiers::NONE))
+    );
+    assert_eq!(
+      parse_key("G"),
+      Some((KeyCode::Char('G'), KeyModifiers::NONE))
+    );
+    // shift+ on a character is the capital, however the terminal reports it
+    assert_eq!(parse_key("shift+g"), parse_key("G"));
+    assert_eq!(
+      parse_key("Ctrl+a"),
+      Some((KeyCode::Char('a'), KeyModifiers::CONTROL))
+    );
+    assert_eq!(
+      parse_key("ctrl+alt+delete"),
+      Some((KeyCode::Delete, KeyModifiers::CONTROL | KeyModifiers::ALT))
+    );
+    assert_eq!(
+      parse_key("shift+left"),
+      Some((KeyCode::Left, KeyModifiers::SHIFT))
+    );
+    assert_eq!(
+      parse_key("space"),
+      Some((KeyCode::Char(' '), KeyModifiers::NONE))
+    );
+    assert_eq!(parse_key("F12"), Some((KeyCode::F(12), KeyModifiers::NONE)));
+    assert_eq!(
+      parse_key("+"),
+      Some((KeyCode::Char('+'), KeyModifiers::NONE))
+    );
+    assert_eq!(
+      parse_key("ctrl++"),
+      Some((KeyCode::Char('+'), KeyModifiers::CONTROL))
+    );
+    for nonsense in ["", "f0", "f13", "hyper+a", "ctrl+", "enterr"] {
+      assert_eq!(parse_key(nonsense), None, "{nonsense}");
+    }
+  }
+
+  #[test]
+  fn every_key_shown_in_the_help_reads_back() {
+    for (key, _) in Keymap::default().bindings {
+      assert_eq!(parse_key(&describe_key(key, true)), Some(key));
+    }
+  }
+}
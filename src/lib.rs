@@ -1,6 +1,84 @@
+//! gsftp's engine, which the `gsftp` binary puts a terminal interface on, and which other
+//! programs can use to log in to SFTP servers and move files around without it.
+//!
+//! - Connecting: [`config::Config`] says where and how to log in, and
+//!   [`headless::connect`] authenticates (see [`sftp::authenticate`]) and yields a
+//!   [`session::SessionPool`], which hands out SFTP channels to whatever needs them.
+//!   [`connection::Connection::open`] does the same for the interface, and finds out what the
+//!   remote pane needs to know about the server.
+//! - Transfers: a [`file_transfer::Transfer`] copies a file or directory one way or the other
+//!   (or between two servers), reporting its progress over a channel as
+//!   [`file_transfer::TransferEvent`]s; [`headless::execute`] runs one to the end.
+//! - Directories: [`app_utils::Entry`] is an item in a directory listing, local
+//!   ([`app_utils::Entry::from_path`]) or remote ([`sftp::ls`]), and [`remote_path`] works with
+//!   remote paths whatever the server's platform. [`backend::FsBackend`] lists, reads and writes
+//!   files the same way on either side, or in memory ([`backend::Memory`]) for tests.
+//! - Errors: connecting, logging in and transferring fail with an [`error::GsftpError`], which
+//!   says which of those went wrong (e.g. [`error::GsftpError::Auth`]), so a caller can tell a
+//!   wrong password from a server that's down, or from a transfer the user cancelled.
+//!
+//! ```no_run
+//! use gsftp::config::Config;
+//! use gsftp::file_transfer::{Transfer, TransferKind, TransferOptions};
+//! use gsftp::headless;
+//!
+//! let conf = Config::from_destination("deploy@example.com")?;
+//! let pool = headless::connect(conf).map_err(|(_, e)| e)?;
+//! let transfer = Transfer::between(
+//!   "site.tar.gz".into(),
+//!   "/srv/site.tar.gz".into(),
+//!   TransferKind::Upload,
+//!   TransferOptions::default(),
+//!   &pool,
+//! )?;
+//! let status = headless::execute(transfer, |_| {}).map_err(|(_, e)| e)?;
+//! println!("{}", status.describe_success().unwrap_or_default());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The rest ([`app`], [`draw`], [`keymap`], [`theme`], [`tabs`] and so on) is the interface's.
+//! The command line itself (reading it, `gsftp get`, `put` and `batch`, and the questions asked
+//! when it's empty), the terminal, the editor and the event loop belong to the binary.
+//!
+//! Nothing here exits the process or reads or writes the terminal: errors come back as values,
+//! and warnings go to [`tracing`]. What logging in has to ask the user (e.g. a password, or
+//! whether to trust a new host key) goes to whatever [`ask::set`] was given, and without one it
+//! fails instead; the binary asks on the terminal, which is why the interface logs in before it
+//! starts. Only `--password-stdin` reads standard input, where a script has piped the password.
 pub mod app;
 pub mod app_utils;
+pub mod ask;
+pub mod backend;
+pub mod bookmarks;
+pub mod chunked;
 pub mod config;
+pub mod connection;
+pub mod credentials;
+pub mod details;
 pub mod draw;
+pub mod error;
 pub mod file_transfer;
+pub mod headless;
+pub mod health;
+pub mod host_keys;
+pub mod i18n;
+pub mod keymap;
+pub mod logging;
+pub mod owner;
+pub mod proxy;
+pub mod register;
+pub mod remote_path;
+pub mod resume;
+pub mod scheduler;
+pub mod search;
+pub mod session;
+pub mod settings;
 pub mod sftp;
+pub mod split;
+pub mod ssh_config;
+pub mod tabs;
+pub mod theme;
+pub mod transform;
+pub mod usage;
+pub mod verify;
+pub mod watch;
@@ -0,0 +1,37 @@
+//! Diagnostics to read after the fact. With `--log-file <path>`, SSH negotiation, SFTP
+//! operations, transfers and the errors the interface shows are logged there with `tracing`;
+//! `-v` adds the details and `-vv` everything. Standard output belongs to the interface, so
+//! nothing's logged without a file, except that `gsftp get`, `put` and `batch` log to standard
+//! error with `-v`.
+use clap::ArgMatches;
+use std::fs::OpenOptions;
+use std::io;
+use std::sync::Mutex;
+use tracing::Level;
+
+/// Start logging as `args` ask for, if they do. `interactive` is whether the interface is
+/// about to start.
+pub fn init(args: &ArgMatches, interactive: bool) -> Result<(), String> {
+  let verbosity = args.occurrences_of("verbose");
+  let level = match verbosity {
+    0 => Level::INFO,
+    1 => Level::DEBUG,
+    _ => Level::TRACE,
+  };
+  let logger = tracing_subscriber::fmt()
+    .with_max_level(level)
+    .with_ansi(false);
+  match args.value_of("log-file") {
+    Some(path) => {
+      let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Couldn't open log file {path}: {e}"))?;
+      logger.with_writer(Mutex::new(file)).init();
+    }
+    None if !interactive && verbosity > 0 => logger.with_writer(io::stderr).init(),
+    None => {}
+  }
+  Ok(())
+}
@@ -1,72 +1,185 @@
-use crossbeam_channel::{bounded, select, tick, unbounded, Receiver};
-use crossterm::{
-  cursor,
-  event::{Event, KeyCode, KeyModifiers},
-  execute,
-  terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
-};
+use clap::ArgMatches;
+use crossbeam_channel::{never, select, tick, unbounded, Receiver};
+use crossterm::event::Event;
 use std::{
-  cmp, error, io,
-  thread::{self, JoinHandle},
-  time::Duration,
+  error, io,
+  sync::atomic::{AtomicBool, Ordering},
+  sync::Arc,
+  time::{Duration, Instant},
 };
 use tui::{backend::CrosstermBackend, Terminal};
 
 use gsftp::{
-  app::App,
-  app_utils::ActiveState,
-  config::{self, AuthMethod, Config},
+  app::{App, Effect, Handled, Picker, PickerKind},
+  app_utils::{self, ActiveState},
+  ask,
+  config::{self, Config},
+  connection::{Connection, Connections},
   draw::UiWindow,
-  file_transfer::Transfer,
+  error::GsftpError,
+  file_transfer::{
+    self, Runner, ScanSummary, Transfer, TransferEvent, TransferKind, TransferState,
+  },
+  headless, i18n, logging, owner, resume,
+  scheduler::Scheduler,
+  search,
+  session::SessionPool,
+  settings::Settings,
   sftp,
+  tabs::Tabs,
+  usage,
+  watch::LocalWatcher,
+};
+
+mod batch;
+mod cli;
+mod editor;
+mod get_put;
+mod jobs;
+mod tty;
+mod wizard;
+
+use jobs::{
+  spawn_details_thread, spawn_paste_thread, spawn_search_thread, spawn_usage_thread,
+  spawn_verify_thread,
 };
+use tty::{cleanup_terminal, setup_terminal, setup_ui_events, Tty};
 
 fn main() -> Result<(), Box<dyn error::Error>> {
   // Command line arguments
-  let args = config::args();
-  // Set up static immutable Config
-  let conf = Config::from(&args);
-  // SSH session
-  println!("Connecting to client...");
-  let sess = match &conf.auth_method {
-    AuthMethod::Password(pwd) => sftp::get_session_with_password(pwd, &conf),
-    AuthMethod::PrivateKey(sk) => sftp::get_session_with_identity_file(sk, &conf),
-    AuthMethod::Agent => sftp::get_session_with_user_auth_agent(&conf),
-    AuthMethod::Manual => unimplemented!(),
+  // (and settings from config.toml, which fill in any that aren't given)
+  let (mut args, mut settings) = cli::args().unwrap_or_else(|e| {
+    eprintln!("{e}");
+    std::process::exit(1);
+  });
+  // Logging in asks whatever it has to on the terminal, before any interface starts
+  ask::set(Box::new(Tty));
+  // `gsftp get`, `put` and `batch` never start the interface
+  if let Some((command, command_args)) = args.subcommand() {
+    if let Err(e) = logging::init(command_args, false) {
+      eprintln!("{e}");
+      std::process::exit(headless::FAILED);
+    }
+    std::process::exit(match command {
+      "batch" => batch::run(command_args, &settings),
+      "get" => get_put::run(TransferKind::Download, command_args, &settings),
+      _ => get_put::run(TransferKind::Upload, command_args, &settings),
+    });
+  }
+  if let Err(e) = logging::init(&args, true) {
+    eprintln!("{e}");
+    std::process::exit(1);
+  }
+  if let Err(e) = i18n::init(settings.language.as_deref()) {
+    eprintln!("{e}");
+    std::process::exit(1);
+  }
+  if args.is_present("no-exec") {
+    sftp::disable_exec();
+  }
+  if args.is_present("forward-agent") {
+    sftp::forward_agent();
   }
-  .unwrap_or_else(|e| {
-    eprintln!("Error establishing SSH session: {e}");
+  // SSH session, and SFTP connection via SSH
+  let destination = args.value_of("DESTINATION").unwrap();
+  let connection = open_connection(destination, &settings, &args).unwrap_or_else(|e| {
+    eprintln!("{e}");
     std::process::exit(1);
   });
-  // Establish SFTP connection via SSH
-  let sftp = sess.sftp()?;
   // Setup static mutable App
-  let mut app = App::from(&sess, &sftp, args);
+  let mut app = App::from(&connection, args.clone()).unwrap_or_else(|e| {
+    eprintln!("{e}");
+    std::process::exit(1);
+  });
+  if let Err(e) = app.configure(&settings) {
+    eprintln!("{e}");
+    std::process::exit(1);
+  }
+  if args.is_present("resume") {
+    if let Some((local, remote)) = resume::load(&connection.id) {
+      app.restore_dirs(local, remote, &*connection.sftp);
+    }
+  }
+  let (local_dir, remote_dir) = (args.value_of("local-dir"), args.value_of("remote-dir"));
+  if let Err(e) = app.start_in(local_dir, remote_dir, &*connection.sftp) {
+    eprintln!("{e}");
+    std::process::exit(1);
+  }
+  // More connections can be opened later on, with `:connect`
+  let mut connections = Connections::new(connection);
+  // and more tabs, each with panes of their own, with `:tabnew` or Ctrl+T
+  let mut tabs = Tabs::new(&connections);
+  // with a tab for each destination after the first, logged in to one after the other
+  for destination in args.values_of("DESTINATION").unwrap().skip(1) {
+    let connection = open_connection(destination, &settings, &args).unwrap_or_else(|e| {
+      eprintln!("{e}");
+      std::process::exit(1);
+    });
+    tabs.open(&mut app, &mut connections);
+    connections.add(connection, &mut app);
+  }
+  tabs.switch(0, &mut app, &mut connections);
+  // a passphrase is typed in right away, before anything can be transferred
+  if app.encryption.needs_passphrase() {
+    app.ask_passphrase();
+  }
   // Cleanup & close the Alternate Screen before logging error messages
   std::panic::set_hook(Box::new(|panic_info| {
-    cleanup_terminal().unwrap();
+    let _ = cleanup_terminal();
+    tracing::error!("panicked: {panic_info}");
     eprintln!("Application error: {panic_info}");
   }));
   // Initializing backend, terminal, & receivers before we attempt to establish a session
   setup_terminal()?;
   let backend = CrosstermBackend::new(io::stdout());
   let mut terminal = Terminal::new(backend)?;
-  // variables related to our tick receiver
-  const FPS: f64 = 60.0;
-  let mut ticks_elapsed: u8 = 0;
+  // how often the screen is redrawn, and how often the panes are read again (both checked by clap)
+  let fps: f64 = args.value_of("fps").unwrap().parse().unwrap_or(60.0);
+  let refresh_interval: f64 = args
+    .value_of("refresh-interval")
+    .unwrap()
+    .parse()
+    .unwrap_or(1.0);
+  let refresh_interval = Duration::from_secs_f64(refresh_interval);
+  let (mut last_refresh, mut last_sample) = (Instant::now(), Instant::now());
   // receivers
-  let ui_events_receiver = setup_ui_events();
+  let ui_events_paused = Arc::new(AtomicBool::new(false));
+  let ui_events_receiver = setup_ui_events(Arc::clone(&ui_events_paused));
   let ctrl_c_events = setup_ctrl_c();
-  let ticker = tick(Duration::from_secs_f64(1.0 / FPS));
-  // vector to store our thread handles
-  let mut handles: Vec<JoinHandle<()>> = vec![];
-  // vector to store receivers from threads
-  let mut receivers: Vec<Receiver<String>> = vec![];
+  let ticker = tick(Duration::from_secs_f64(1.0 / fps));
+  // every transfer thread reports back through this one channel
+  let (transfer_tx, transfer_events) = unbounded();
+  // starts transfers now, or later on
+  let runner = Runner::new(transfer_tx);
+  let scheduler = Scheduler::new(runner.clone());
+  // background jobs other than transfers report a message (or an error) through this one
+  let (message_tx, messages) = unbounded::<Result<String, String>>();
+  // directory sizes come back through this one, along with the directory's name
+  let (usage_tx, usages) = unbounded::<(String, Result<String, String>)>();
+  let (details_tx, details) = unbounded::<(String, Result<String, String>)>();
+  // and search results through this one, along with the pattern searched for
+  let (search_tx, searches) = unbounded::<(String, Result<Vec<String>, String>)>();
   // User Interface struct
   let mut window = UiWindow::default();
   // app stuff
-  let mut user_has_pressed_buttons = false;
-  let mut completed_transfers = 0;
+  // big transfers waiting for the user to confirm them, along with their sizes
+  let mut pending_transfers: Vec<(Transfer, ScanSummary)> = Vec::new();
+  // held for the whole session, since on X11 whatever's copied only lasts as long as this does
+  let mut clipboard = arboard::Clipboard::new().map_err(|e| e.to_string());
+
+  // the local directory is re-read when it changes, or polled if it can't be watched
+  let mut watcher = LocalWatcher::new().ok();
+  let local_changes = watcher.as_ref().map_or_else(never, |w| w.changes.clone());
+  // and the config files are read again when they're saved, e.g. while trying out colors
+  let mut config_watcher = LocalWatcher::new().ok();
+  let config_changes = match (config_watcher.as_mut(), config::dir(&args)) {
+    (Some(w), Some(dir)) => match w.watch(&dir) {
+      true => w.changes.clone(),
+      false => never(),
+    },
+    _ => never(),
+  };
+  let mut config_stamp = config::stamp(&args);
 
   loop {
     select! {
@@ -74,167 +187,285 @@ fn main() -> Result<(), Box<dyn error::Error>> {
         break;
       }
       recv(ticker) -> _ => {
-        // Check for updates once every second (at 60 fps)
-        ticks_elapsed = (ticks_elapsed + 1) % FPS as u8;
-        if ticks_elapsed == 0 {
-          app.content.update_local(&app.buf.local, app.show_hidden);
-          app.content.update_remote(&sftp, &app.buf.remote, app.show_hidden);
-          // Reset window periodically when there's no info to show
-          if user_has_pressed_buttons && receivers.len() == completed_transfers {
-            window.reset();
+        let watched = watcher.as_mut().is_some_and(|w| w.watch(&app.buf.local));
+        // Transfers' speeds are sampled every second, however often the panes are refreshed
+        if last_sample.elapsed() >= Duration::from_secs(1) {
+          last_sample = Instant::now();
+          for transfer in app.transfers.iter_mut().filter(|t| t.is_running()) {
+            transfer.sample();
+          }
+        }
+        // Check for updates once every refresh interval (a second, unless --refresh-interval)
+        if last_refresh.elapsed() >= refresh_interval {
+          last_refresh = Instant::now();
+          if !watched {
+            app.list_local();
+          }
+          app.list_remote(&*connections.active().sftp);
+          // Free space only needs asking about again once the directory changes
+          if app.local_free.0 != app.buf.local {
+            app.local_free = (app.buf.local.clone(), usage::local_free(&app.buf.local));
           }
-          // Check if any of our receivers completed
-          for receiver in &receivers {
-            if let Ok(message) = receiver.try_recv() { if message.is_empty() {
-              completed_transfers +=1;
-            } else {
-              window.error_message(message.as_str());
-              completed_transfers += 1;
-            }}
+          if app.remote_free.0 != app.buf.remote {
+            let free = usage::remote_free(&connections.active().pool.session(), &app.buf.remote);
+            app.remote_free = (app.buf.remote.clone(), free);
+          }
+        }
+        app.health = connections.active().health.health();
+        window.draw(&mut terminal, &mut app)?;
+      }
+      recv(local_changes) -> _ => {
+        // one refresh covers a whole burst of changes
+        while local_changes.try_recv().is_ok() {}
+        app.list_local();
+      }
+      recv(config_changes) -> _ => {
+        while config_changes.try_recv().is_ok() {}
+        // bookmarks and the like are kept in the same directory
+        if config::stamp(&args) != config_stamp {
+          config_stamp = config::stamp(&args);
+          match reload_config(&mut app) {
+            Ok((new_args, new_settings)) => {
+              (args, settings) = (new_args, new_settings);
+              window.flashing_text(i18n::tr("Reloaded the config files"));
+            }
+            Err(e) => window.error_message(&e),
+          }
+        }
+      }
+      recv(messages) -> message => {
+        // we hold a sender ourselves, so this channel never disconnects
+        match message.unwrap() {
+          Ok(text) => window.flashing_text(&text),
+          Err(text) => window.error_message(&text),
+        }
+      }
+      recv(usages) -> usage => {
+        // we hold a sender ourselves, so this channel never disconnects
+        let (name, usage) = usage.unwrap();
+        if let Some(i) = app.measuring.iter().position(|n| *n == name) {
+          app.measuring.remove(i);
+        }
+        match usage {
+          Ok(text) => window.flashing_text(&text),
+          Err(text) => window.error_message(&text),
+        }
+      }
+      recv(details) -> found => {
+        // we hold a sender ourselves, so this channel never disconnects
+        match found.unwrap() {
+          (name, Ok(text)) => app.info = Some((name, text)),
+          (_, Err(text)) => window.error_message(&text),
+        }
+      }
+      recv(searches) -> search => {
+        // we hold a sender ourselves, so this channel never disconnects
+        let (pattern, found) = search.unwrap();
+        app.searching = None;
+        match found {
+          Ok(found) if found.is_empty() => window.flashing_text(&format!("Nothing matches {pattern}")),
+          Ok(found) => {
+            let title = match found.len() {
+              search::MAX_RESULTS => format!("First {} matching {pattern}", search::MAX_RESULTS),
+              n => format!("{n} matching {pattern}"),
+            };
+            app.picker = Some(Picker::new(PickerKind::Found, &title, found));
+          }
+          Err(e) => window.error_message(&format!("Couldn't search for {pattern}: {e}")),
+        }
+      }
+      recv(transfer_events) -> event => {
+        // we hold a sender ourselves, so this channel never disconnects
+        match event.unwrap() {
+          TransferEvent::Progress { id, bytes, files, current } => app.transfers[id].advance(bytes, files, current),
+          TransferEvent::Started { id } => app.transfers[id].start(),
+          TransferEvent::Finished { id, result } => {
+            if let Err(message) = &result {
+              // a cancelled transfer fails on its way out, which the user knows about already
+              if app.transfers[id].state != TransferState::Cancelled {
+                window.error_message(message);
+              }
+            }
+            app.transfers[id].finish(result);
+            if let Some(text) = app.transfers[id].describe_success() {
+              window.success_message(&text);
+            }
+            // show the new files right away rather than on the next refresh
+            app.list_local();
+            app.list_remote(&*connections.active().sftp);
           }
         }
-        window.draw(&mut terminal, &mut app);
       }
       recv(ui_events_receiver) -> message => {
-        if let Event::Key(key_event) = message.unwrap() {
-          user_has_pressed_buttons = true;
-          if key_event.modifiers.is_empty() {
-            match key_event.code {
-              // quit
-              KeyCode::Char('q') | KeyCode::Esc => break,
-              // Show/hide help
-              KeyCode::Char('?') => {
-                if receivers.len() == completed_transfers {
-                  window.reset();
+        // once the terminal can't be read there's no way to go on, but the screen is put back
+        let event = match message {
+          Ok(Ok(event)) => event,
+          Ok(Err(e)) => {
+            tracing::error!("couldn't read the terminal: {e}");
+            break;
+          }
+          // only a failed read stops the thread sending these
+          Err(_) => break,
+        };
+        if let Event::Key(key_event) = event {
+          // the app does what it can by itself, and the rest is done here
+          let effect = match app.handle_key(key_event, &*connections.active().sftp) {
+            Handled::Done => continue,
+            Handled::Message(text) => {
+              window.flashing_text(&text);
+              continue;
+            }
+            Handled::Error(text) => {
+              window.error_message(&text);
+              continue;
+            }
+            Handled::Effect(effect) => effect,
+          };
+          match effect {
+            Effect::Quit => break,
+            Effect::Details(name) => spawn_details_thread(&app, name, &connections.active().pool, &details_tx),
+            Effect::OpenEditor(path) => {
+              // The editor gets the terminal to itself until it exits
+              ui_events_paused.store(true, Ordering::SeqCst);
+              cleanup_terminal()?;
+              let edited = match app.state.active {
+                ActiveState::Local => editor::edit(&path).map(|_| false),
+                ActiveState::Remote => editor::edit_remote(&connections.active().sftp, &path),
+              };
+              setup_terminal()?;
+              terminal.clear()?;
+              ui_events_paused.store(false, Ordering::SeqCst);
+              match edited {
+                Ok(true) => {
+                  window.flashing_text(&format!("Uploaded {}", path.display()));
+                  app.list_remote(&*connections.active().sftp);
                 }
-                app.show_help = !app.show_help;
-              },
-              // toggle hidden files
-              KeyCode::Char('a') => {
-                app.show_hidden = !app.show_hidden;
-                app.content.update_local(&app.buf.local, app.show_hidden);
-                app.content.update_remote(&sftp, &app.buf.remote, app.show_hidden);
+                Ok(false) => {}
+                Err(e) => window.error_message(&e.to_string()),
+              }
+            }
+            Effect::SetClipboard(text) => {
+              let copied = match clipboard.as_mut() {
+                Ok(clipboard) => clipboard.set_text(text.clone()).map_err(|e| e.to_string()),
+                Err(e) => Err(e.clone()),
+              };
+              match copied {
+                Ok(()) => window.flashing_text(&format!("Copied {text}")),
+                Err(e) => window.error_message(&format!("Couldn't copy to the clipboard: {e}")),
+              }
+            }
+            Effect::Paste(yanked) => {
+              window.flashing_text(&format!("Pasting {}...", yanked.describe()));
+              spawn_paste_thread(&app, yanked, &connections.active().pool, &message_tx);
+            }
+            Effect::MeasureUsage(name) => spawn_usage_thread(&app, name, &connections.active().pool, &usage_tx),
+            Effect::Verify(name) => {
+              window.flashing_text("Verifying...");
+              spawn_verify_thread(&app, name, &connections.active().pool, &message_tx);
+            }
+            Effect::Search(pattern) => spawn_search_thread(&app, pattern, &connections.active().pool, &search_tx),
+            Effect::SaveLog(path) => match window.save_log(&path) {
+              Ok(()) => window.flashing_text(&format!("Saved the messages to {}", path.display())),
+              Err(e) => window.error_message(&format!("Couldn't save the messages: {e}")),
+            },
+            Effect::Transfer => match new_transfer(&app, &connections.active().pool) {
+              Ok(transfer) => begin_transfers(vec![transfer], &mut app, &mut window, &runner, &mut pending_transfers),
+              Err(e) => window.error_message(&i18n::tr_args("TRANSFER ERROR: {}", &[&e.to_string()])),
+            },
+            Effect::CopyDifferences(differences) => {
+              match differences.len() {
+                1 => window.flashing_text(i18n::tr("Copying 1 item that differs")),
+                n => window.flashing_text(&i18n::tr_args("Copying {} items that differ", &[&n.to_string()])),
               }
-              // down
-              KeyCode::Char('j') | KeyCode::Down => match app.state.active {
-                ActiveState::Local => {
-                  // the continue prevents the function from breaking in empty dirs
-                  if app.content.local.is_empty() { continue }
-                  let curr = app.state.local.selected().unwrap();
-                  let next = cmp::min(curr + 1, app.content.local.len() - 1);
-                  app.state.local.select(Some(next));
-                },
-                ActiveState::Remote => {
-                  // the continue prevents the function from breaking in empty dirs
-                  if app.content.remote.is_empty() { continue }
-                  let curr = app.state.remote.selected().unwrap();
-                  let next = cmp::min(curr + 1, app.content.remote.len() - 1);
-                  app.state.remote.select(Some(next));
-                },
-              },
-              // up
-              KeyCode::Char('k') | KeyCode::Up => match app.state.active {
-                ActiveState::Local => {
-                  let curr = app.state.local.selected().unwrap();
-                  let next = if curr > 0 { curr - 1 } else { curr };
-                  app.state.local.select(Some(next));
-                },
-                ActiveState::Remote => {
-                  let curr = app.state.remote.selected().unwrap();
-                  let next = if curr > 0 { curr - 1 } else { curr };
-                  app.state.remote.select(Some(next));
-                },
-              },
-              // page up
-              KeyCode::Char('g') | KeyCode::Char('t') => match app.state.active {
-                ActiveState::Local =>  app.state.local.select(Some(0)),
-                ActiveState::Remote =>  app.state.remote.select(Some(0)),
-              },
-              // page down
-              KeyCode::Char('b') => match app.state.active {
-                ActiveState::Local => {
-                  let i = app.content.local.len() - 1;
-                  app.state.local.select(Some(i));
-                },
-                ActiveState::Remote => {
-                  let i = app.content.remote.len() - 1;
-                  app.state.remote.select(Some(i));
-                },
-              },
-              // switch tabs
-              KeyCode::Tab  | KeyCode::Char('w') => {
-                app.state.active = match app.state.active {
-                  ActiveState::Local => ActiveState::Remote,
-                  ActiveState::Remote => ActiveState::Local,
+              let pool = &connections.active().pool;
+              let transfers = differences
+                .iter()
+                .map(|entry| match app.state.active {
+                  ActiveState::Local => Transfer::upload_entry(&app, entry, pool),
+                  ActiveState::Remote => Transfer::download_entry(&app, entry, pool),
+                })
+                .collect::<Result<Vec<_>, _>>();
+              // the scans are added up, so that copying lots of small differences asks first too
+              match transfers {
+                Ok(transfers) => begin_transfers(transfers, &mut app, &mut window, &runner, &mut pending_transfers),
+                Err(e) => window.error_message(&i18n::tr_args("TRANSFER ERROR: {}", &[&e.to_string()])),
+              }
+            }
+            Effect::Schedule(start) => match new_transfer(&app, &connections.active().pool) {
+              Ok(transfer) => {
+                let total = transfer.scan().unwrap_or_default();
+                let id = app.transfers.len();
+                let mut status = transfer.status(id, total);
+                status.state = TransferState::Scheduled(start.describe());
+                window.flashing_text(&format!("Scheduled {} {}", status.name, start.describe()));
+                app.transfers.push(status);
+                scheduler.schedule(transfer, id, start);
+              }
+              Err(e) => window.error_message(&i18n::tr_args("TRANSFER ERROR: {}", &[&e.to_string()])),
+            },
+            Effect::Relay(i) => match relay_transfer(&app, &connections, i) {
+              Ok(transfer) => begin_transfers(vec![transfer], &mut app, &mut window, &runner, &mut pending_transfers),
+              Err(e) => window.error_message(&i18n::tr_args("TRANSFER ERROR: {}", &[&e.to_string()])),
+            },
+            Effect::Connect { destination, new_tab } => {
+              // Authentication may ask questions, so step out of the TUI while connecting
+              ui_events_paused.store(true, Ordering::SeqCst);
+              cleanup_terminal()?;
+              let opened = open_connection(&destination, &settings, &args);
+              setup_terminal()?;
+              terminal.clear()?;
+              ui_events_paused.store(false, Ordering::SeqCst);
+              match opened {
+                Ok(connection) => {
+                  if new_tab {
+                    tabs.open(&mut app, &mut connections);
+                  }
+                  connections.add(connection, &mut app);
+                  window.flashing_text(&i18n::tr_args("Connected to {}", &[&destination]));
                 }
-              },
-              // navigate into child directory
-              KeyCode::Char('l') | KeyCode::Right => match app.state.active {
-                ActiveState::Local => app.cd_into_local(),
-                ActiveState::Remote => app.cd_into_remote(&sftp),
-              },
-              // navigate into parent directory (out of local directory)
-              KeyCode::Char('h') | KeyCode::Left => match app.state.active {
-                ActiveState::Local => app.cd_out_of_local(),
-                ActiveState::Remote => app.cd_out_of_remote(&sftp),
-              },
-              // file transfer
-              KeyCode::Enter | KeyCode::Char('y') => match app.state.active {
-                // upload
-                ActiveState::Local => {
-                  window.flashing_text("Uploading...");
-                  let transfer = Transfer::upload(&app, &sess);
-                  spawn_transfer_thread(transfer, &mut handles, &mut receivers);
-                  app.content.update_remote(&sftp, &app.buf.remote, app.show_hidden);
-                },
-                // download
-                ActiveState::Remote => {
-                  window.flashing_text("Downloading...");
-                  let transfer = Transfer::download(&app, &sess);
-                  spawn_transfer_thread(transfer, &mut handles, &mut receivers);
-                  app.content.update_local(&app.buf.local, app.show_hidden);
-                },
-              },
-              _ => {}
+                Err(e) => window.error_message(&e.to_string()),
+              }
+            }
+            Effect::SwitchHost(i) => {
+              connections.switch(i, &mut app);
             }
-          } else if key_event.modifiers == KeyModifiers::CONTROL {
-            match key_event.code {
-              // quit
-              KeyCode::Char('c') => break,
-              // switch tabs
-              KeyCode::Char('w') => app.state.active = match app.state.active {
-                ActiveState::Local => ActiveState::Remote,
-                ActiveState::Remote => ActiveState::Local,
-              },
-              // page up
-              KeyCode::Up => match app.state.active {
-                ActiveState::Local =>  app.state.local.select(Some(0)),
-                ActiveState::Remote =>  app.state.remote.select(Some(0)),
-              },
-              // page down
-              KeyCode::Down => match app.state.active {
-                ActiveState::Local => {
-                  let i = app.content.local.len() - 1;
-                  app.state.local.select(Some(i));
-                },
-                ActiveState::Remote => {
-                  let i = app.content.remote.len() - 1;
-                  app.state.remote.select(Some(i));
-                },
-              },
-              _ => {}
+            Effect::NextHost => connections.cycle(&mut app),
+            Effect::Chown { name, owner } => {
+              let conn = connections.active();
+              let changed = owner::resolve(&conn.pool.session(), &owner)
+                .map_err(|e| e.to_string())
+                .and_then(|(uid, gid)| app.chown(&name, uid, gid, &conn.sftp));
+              match changed {
+                Ok(()) => window.flashing_text(&format!("{name} now belongs to {owner}")),
+                Err(e) => window.error_message(&e),
+              }
+            }
+            Effect::ReloadConfig => match reload_config(&mut app) {
+              Ok((new_args, new_settings)) => {
+                (args, settings) = (new_args, new_settings);
+                config_stamp = config::stamp(&args);
+                window.flashing_text(i18n::tr("Reloaded the config files"));
+              }
+              Err(e) => window.error_message(&e),
+            },
+            Effect::NewTab => tabs.open(&mut app, &mut connections),
+            Effect::CloseTab => {
+              tabs.close(&mut app, &mut connections);
+            }
+            Effect::StartPending => {
+              start_transfers(std::mem::take(&mut pending_transfers), &mut app, &mut window, &runner)
+            }
+            Effect::DropPending => pending_transfers.clear(),
+            Effect::Retry(i) => match Transfer::retry(&app.transfers[i], &app) {
+              Ok(transfer) => begin_transfers(vec![transfer], &mut app, &mut window, &runner, &mut pending_transfers),
+              Err(e) => window.error_message(&format!("Couldn't retry {}: {e}", app.transfers[i].name)),
+            },
+            Effect::CycleTab(step) => tabs.cycle(step, &mut app, &mut connections),
+            Effect::SwitchTab(i) => {
+              if !tabs.switch(i, &mut app, &mut connections) {
+                window.error_message(&format!("No tab number {}", i + 1));
+              }
             }
-          } else if key_event.modifiers == KeyModifiers::SHIFT {
-            if let KeyCode::Char('G') = key_event.code { match app.state.active {
-              ActiveState::Local => {
-                let i = app.content.local.len() - 1;
-                app.state.local.select(Some(i));
-              },
-              ActiveState::Remote => {
-                let i = app.content.remote.len() - 1;
-                app.state.remote.select(Some(i));
-              },
-            } }
           }
         }
       }
@@ -242,82 +473,138 @@ fn main() -> Result<(), Box<dyn error::Error>> {
   }
 
   cleanup_terminal()?;
-
-  for handle in handles {
-    handle.join().unwrap();
+  if let Err(e) = resume::save(&connections.last_dirs(&app)) {
+    eprintln!("Couldn't remember the last directories: {e}");
   }
 
-  Ok(())
-}
-
-fn setup_terminal() -> Result<(), io::Error> {
-  let mut stdout = io::stdout();
-  execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
-  // TTYs don't actually have an alternate screen, so you need to
-  //  clear the screen in this case.
-  // We have to execute this *after* entering the alternate screen so that
-  //  the main screen is cleared iff we're running in a TTY.
-  execute!(
-    stdout,
-    terminal::Clear(terminal::ClearType::All),
-    terminal::SetTitle("gsftp")
-  )?;
+  // Transfers that are still waiting for their start time never get to run
+  drop(scheduler);
+  runner.join();
 
-  terminal::enable_raw_mode()?;
+  // Everything's finished now, so we can tell the user how it all went
+  for event in transfer_events.try_iter() {
+    match event {
+      TransferEvent::Progress {
+        id,
+        bytes,
+        files,
+        current,
+      } => app.transfers[id].advance(bytes, files, current),
+      TransferEvent::Started { id } => app.transfers[id].start(),
+      TransferEvent::Finished { id, result } => app.transfers[id].finish(result),
+    }
+  }
+  if !app.transfers.is_empty() {
+    println!("Transfers this session:");
+    print!("{}", file_transfer::summary(&app.transfers));
+  }
 
   Ok(())
 }
 
-fn cleanup_terminal() -> Result<(), io::Error> {
-  let mut stdout = io::stdout();
-  // TTYs don't actually have an alternate screen, so you need to
-  //  clear the screen in this case.
-  // We have to execute this *before* leaving the alternate screen so that
-  //  the main screen is cleared iff we're running in a TTY.
-  execute!(
-    stdout,
-    cursor::MoveTo(0, 0),
-    terminal::Clear(terminal::ClearType::All)
-  )?;
-  execute!(stdout, LeaveAlternateScreen, cursor::Show)?;
+// If the handler can't be set, nothing ever arrives here and the interface goes on without it
+fn setup_ctrl_c() -> Receiver<()> {
+  let (tx, rx) = unbounded();
+  match ctrlc::set_handler(move || {
+    let _ = tx.send(());
+  }) {
+    Ok(()) => rx,
+    Err(e) => {
+      tracing::warn!("couldn't handle Ctrl+C: {e}");
+      never()
+    }
+  }
+}
 
-  terminal::disable_raw_mode()?;
+// Logs in to `destination`, saying how it's going on the terminal, which the TUI is out of
+fn open_connection(
+  destination: &str,
+  settings: &Settings,
+  args: &ArgMatches,
+) -> Result<Connection, GsftpError> {
+  println!("Connecting to {destination}...");
+  let conf = Config::new(settings.destination(destination), args)?;
+  let connection = Connection::open(conf, destination)?;
+  if let Some(banner) = &connection.info.banner {
+    println!("{banner}");
+  }
+  println!("Authenticated with {}.", connection.auth_method);
+  Ok(connection)
+}
 
-  Ok(())
+// Uploads whatever's selected in the local pane, or downloads from the remote pane
+fn new_transfer(app: &App, pool: &Arc<SessionPool>) -> Result<Transfer, GsftpError> {
+  match app.state.active {
+    ActiveState::Local => Transfer::upload(app, pool),
+    ActiveState::Remote => Transfer::download(app, pool),
+  }
 }
 
-// TODO: Figure out how to handle these unwraps in the tx.send(...unwrap()).unwrap()
-fn setup_ui_events() -> Receiver<Event> {
-  let (tx, rx) = unbounded();
-  thread::spawn(move || loop {
-    tx.send(crossterm::event::read().unwrap()).unwrap()
-  });
+// Copies the highlighted remote item into connection `i`'s current directory
+fn relay_transfer(app: &App, connections: &Connections, i: usize) -> Result<Transfer, GsftpError> {
+  let target = connections.get(i).ok_or("No such connection")?;
+  Transfer::relay(app, &connections.active().pool, &target.pool, &target.cwd)
+}
 
-  rx
+// Starts transfers right away, unless together they're big enough to need confirming first
+fn begin_transfers(
+  transfers: Vec<Transfer>,
+  app: &mut App,
+  window: &mut UiWindow,
+  runner: &Runner,
+  pending_transfers: &mut Vec<(Transfer, ScanSummary)>,
+) {
+  let scanned: Vec<(Transfer, ScanSummary)> = transfers
+    .into_iter()
+    .map(|transfer| {
+      let summary = transfer.scan().unwrap_or_default();
+      (transfer, summary)
+    })
+    .collect();
+  let total = scanned
+    .iter()
+    .fold(ScanSummary::default(), |total, (_, summary)| ScanSummary {
+      bytes: total.bytes + summary.bytes,
+      files: total.files + summary.files,
+    });
+  if app.confirm_threshold.exceeded_by(&total) {
+    app.confirm = Some(format!(
+      "{} in {} files - proceed?",
+      app_utils::human_size(total.bytes),
+      app_utils::thousands(total.files),
+    ));
+    *pending_transfers = scanned;
+  } else {
+    start_transfers(scanned, app, window, runner);
+  }
 }
 
-fn setup_ctrl_c() -> Receiver<()> {
-  let (tx, rx) = unbounded();
-  ctrlc::set_handler(move || {
-    tx.send(()).unwrap();
-  })
-  .unwrap();
+// A lone transfer says what it's doing; several leave that to whatever started them
+fn start_transfers(
+  transfers: Vec<(Transfer, ScanSummary)>,
+  app: &mut App,
+  window: &mut UiWindow,
+  runner: &Runner,
+) {
+  if let [(transfer, _)] = transfers.as_slice() {
+    window.flashing_text(transfer.flashing_text());
+  }
+  for (transfer, total) in transfers {
+    start_transfer(transfer, total, app, runner);
+  }
+}
 
-  rx
+// Reads the config files again and applies them, yielding the command line and settings as they
+// now stand; nothing changes if the files are wrong
+fn reload_config(app: &mut App) -> Result<(ArgMatches, Settings), String> {
+  let (args, settings) = cli::reload()?;
+  app.reload_config(&args, &settings)?;
+  Ok((args, settings))
 }
 
-fn spawn_transfer_thread(
-  transfer: Transfer,
-  handles: &mut Vec<JoinHandle<()>>,
-  receivers: &mut Vec<Receiver<String>>,
-) {
-  let (tx, rx) = bounded(1);
-  handles.push(thread::spawn(move || {
-    tx.send(match transfer.execute() {
-      Ok(_) => String::new(),
-      Err(err) => format!("{}", err),
-    })
-    .unwrap();
-  }));
-  receivers.push(rx);
+// Every transfer gets its own thread, SFTP channel and progress row
+fn start_transfer(transfer: Transfer, total: ScanSummary, app: &mut App, runner: &Runner) {
+  let id = app.transfers.len();
+  app.transfers.push(transfer.status(id, total));
+  runner.start(transfer, id);
 }
@@ -0,0 +1,54 @@
+//! Who owns remote files. SFTP only deals in numeric ids, so user and group names are looked up on
+//! the server, which needs remote commands.
+use ssh2::Session;
+
+use crate::error::GsftpError;
+use crate::sftp;
+
+/// The uid and gid that `spec` asks for, the way chown takes them: `deploy`, `deploy:www-data`,
+/// `:www-data`, or numbers like `1000:33`. Whichever is left out stays as it is.
+pub fn resolve(sess: &Session, spec: &str) -> Result<(Option<u32>, Option<u32>), GsftpError> {
+  let spec = spec.trim();
+  let (user, group) = spec.split_once(':').unwrap_or((spec, ""));
+  if user.is_empty() && group.is_empty() {
+    return Err("Give a user, a group, or both, like user:group".into());
+  }
+  let uid = match user {
+    "" => None,
+    user => Some(uid(sess, user)?),
+  };
+  let gid = match group {
+    "" => None,
+    group => Some(gid(sess, group)?),
+  };
+  Ok((uid, gid))
+}
+
+fn uid(sess: &Session, user: &str) -> Result<u32, GsftpError> {
+  if let Ok(uid) = user.parse() {
+    return Ok(uid);
+  }
+  let output = sftp::exec(sess, "id", &["-u", "--", user])
+    .map_err(|e| format!("Couldn't look up user {user}: {e}"))?;
+  output
+    .trim()
+    .parse()
+    .map_err(|_| GsftpError::Other(format!("`id` gave no uid for {user}")))
+}
+
+fn gid(sess: &Session, group: &str) -> Result<u32, GsftpError> {
+  if let Ok(gid) = group.parse() {
+    return Ok(gid);
+  }
+  // `name:password:gid:members`
+  let output = sftp::exec(sess, "getent", &["group", group])
+    .map_err(|e| format!("Couldn't look up group {group}: {e}"))?;
+  let gid = output
+    .split(':')
+    .nth(2)
+    .ok_or_else(|| format!("No such group: {group}"))?;
+  gid
+    .trim()
+    .parse()
+    .map_err(|_| GsftpError::Other(format!("getent gave no gid for {group}")))
+}
@@ -0,0 +1,64 @@
+//! Copying items from one directory to another on the same side, local or remote: they're yanked
+//! into a register, then pasted wherever the pane has got to
+use ssh2::{Session, Sftp};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::{self, Local};
+use crate::error::GsftpError;
+use crate::remote_path;
+use crate::sftp;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Items yanked from one directory, waiting to be pasted
+pub struct Register {
+  /// The id of the host they're on, or `None` if they're local
+  pub host: Option<String>,
+  pub dir: PathBuf,
+  pub names: Vec<OsString>,
+}
+
+impl Register {
+  /// e.g. `notes.txt` or `3 items`
+  pub fn describe(&self) -> String {
+    match self.names.as_slice() {
+      [name] => name.to_string_lossy().to_string(),
+      names => format!("{} items", names.len()),
+    }
+  }
+
+  /// Copies everything in the register into the local `dir`, refusing to replace anything
+  pub fn paste_local(&self, dir: &Path) -> Result<(), GsftpError> {
+    for name in &self.names {
+      let to = dir.join(name);
+      if fs::symlink_metadata(&to).is_ok() {
+        return Err(format!("{} already exists", to.display()).into());
+      }
+      backend::copy_all(&Local, &self.dir.join(name), &Local, &to)?;
+    }
+    Ok(())
+  }
+
+  /// Copies everything in the register into the remote `dir`. The server's own `cp` does the
+  /// copying when remote commands are allowed; otherwise every file goes there and back over SFTP.
+  pub fn paste_remote(&self, sess: &Session, sftp: &Sftp, dir: &Path) -> Result<(), GsftpError> {
+    for name in &self.names {
+      let (from, to) = (
+        remote_path::join(&self.dir, name),
+        remote_path::join(dir, name),
+      );
+      if sftp.lstat(&to).is_ok() {
+        return Err(format!("{} already exists", to.display()).into());
+      }
+      let server_side = match (from.to_str(), to.to_str()) {
+        (Some(from), Some(to)) => sftp::exec(sess, "cp", &["-Rp", "--", from, to]).is_ok(),
+        _ => false,
+      };
+      if !server_side {
+        backend::copy_all(sftp, &from, sftp, &to)?;
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,74 @@
+//! Paths on the server. SFTP paths are always separated by `/`, whichever OS gsftp runs on,
+//! and Windows servers (Win32-OpenSSH) show their drives as `/C:/`, which is as far up as
+//! their paths go.
+use ssh2::Session;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Which conventions the server's paths follow
+pub enum Flavor {
+  #[default]
+  Posix,
+  Windows,
+}
+
+impl Flavor {
+  /// Windows OpenSSH says so in its version banner, e.g. `SSH-2.0-OpenSSH_for_Windows_8.1`
+  pub fn detect(sess: &Session) -> Self {
+    match sess.banner() {
+      Some(banner) if banner.to_lowercase().contains("windows") => Self::Windows,
+      _ => Self::Posix,
+    }
+  }
+}
+
+/// `dir` with `name` added on the end, separated by `/` even when the local OS uses `\`
+pub fn join(dir: &Path, name: impl AsRef<Path>) -> PathBuf {
+  let name = name.as_ref();
+  if name.as_os_str().is_empty() {
+    return dir.to_path_buf();
+  }
+  let mut path = OsString::from(dir.as_os_str());
+  if !path.is_empty() && !path.to_string_lossy().ends_with('/') {
+    path.push("/");
+  }
+  path.push(name.as_os_str());
+  PathBuf::from(path)
+}
+
+/// The directory `dir` is in. The root of a Windows drive is its own parent.
+pub fn parent(dir: &Path, flavor: Flavor) -> PathBuf {
+  if flavor == Flavor::Windows {
+    let text = dir.to_string_lossy();
+    let trimmed = text.trim_end_matches('/');
+    // `/C:/Users` goes up to `/C:/`, and `/C:/` stays where it is
+    match trimmed.rfind('/') {
+      _ if is_drive_root(trimmed) => return PathBuf::from(format!("{trimmed}/")),
+      Some(i) if is_drive_root(&trimmed[..i]) => {
+        return PathBuf::from(format!("{}/", &trimmed[..i]))
+      }
+      _ => {}
+    }
+  }
+  // `Path::parent` splits on `/` everywhere, and keeps names that aren't UTF-8 intact
+  dir
+    .parent()
+    .map_or_else(|| dir.to_path_buf(), Path::to_path_buf)
+}
+
+/// A path the way Windows' own shell prints it, e.g. `C:\Users\me`, made into the
+/// `/C:/Users/me` that its SFTP server expects
+pub fn from_windows(path: &str) -> PathBuf {
+  let path = path.trim().replace('\\', "/");
+  match path.starts_with('/') {
+    true => PathBuf::from(path),
+    false => PathBuf::from(format!("/{path}")),
+  }
+}
+
+// `/C:`
+fn is_drive_root(path: &str) -> bool {
+  let bytes = path.as_bytes();
+  bytes.len() == 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':'
+}
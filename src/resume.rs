@@ -0,0 +1,56 @@
+//! Where each pane was when gsftp last quit, per host, kept in gsftp's data directory so that
+//! `--resume` can start there again
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// A host's id (e.g. `deploy@example.com:22`), with the local and remote directories last open
+pub type LastDirs = (String, PathBuf, PathBuf);
+
+fn file() -> Option<PathBuf> {
+  dirs::data_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("last_dirs"))
+}
+
+// One `id<TAB>local<TAB>remote` per line
+fn read() -> Vec<LastDirs> {
+  let text = file()
+    .and_then(|file| fs::read_to_string(file).ok())
+    .unwrap_or_default();
+  text
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.split('\t');
+      let (id, local, remote) = (fields.next()?, fields.next()?, fields.next()?);
+      Some((id.to_string(), PathBuf::from(local), PathBuf::from(remote)))
+    })
+    .collect()
+}
+
+/// The local and remote directories last open with the host `id`, if it's been connected to
+pub fn load(id: &str) -> Option<(PathBuf, PathBuf)> {
+  read()
+    .into_iter()
+    .find(|(host, ..)| host == id)
+    .map(|(_, local, remote)| (local, remote))
+}
+
+/// Remembers where each of `hosts` was left, keeping what's saved for any others
+pub fn save(hosts: &[LastDirs]) -> io::Result<()> {
+  let file = file().ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::NotFound,
+      "no data directory to remember directories in",
+    )
+  })?;
+  let mut saved = read();
+  saved.retain(|(id, ..)| !hosts.iter().any(|(host, ..)| host == id));
+  saved.extend(hosts.iter().cloned());
+  if let Some(dir) = file.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  let text: String = saved
+    .iter()
+    .map(|(id, local, remote)| format!("{id}\t{}\t{}\n", local.display(), remote.display()))
+    .collect();
+  fs::write(file, text)
+}
@@ -0,0 +1,160 @@
+//! Deferring transfers until a given time, or until everything else has finished
+use chrono::{DateTime, Local, NaiveTime, TimeDelta, TimeZone};
+use crossbeam_channel::{unbounded, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::file_transfer::{Runner, Transfer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// When a scheduled transfer should be started
+pub enum Start {
+  At(DateTime<Local>),
+  /// Once no other transfers are running; these start one at a time, in order
+  AfterQueue,
+}
+
+impl Start {
+  /// Understands `HH:MM` (the next time the clock says so), `+30s`/`+10m`/`+2h`,
+  /// and `after` (or nothing at all) for "once the queue drains". A delay that's negative,
+  /// or too far off for the clock to reach, is no time at all.
+  pub fn parse(input: &str) -> Option<Self> {
+    let input = input.trim().to_lowercase();
+    if input.is_empty() || input == "after" {
+      return Some(Self::AfterQueue);
+    }
+    if let Some(delay) = input.strip_prefix('+') {
+      let (last, _) = delay.char_indices().last()?;
+      let (number, unit) = delay.split_at(last);
+      let number: i64 = number.parse().ok().filter(|n| *n >= 0)?;
+      let delay = match unit {
+        "s" => TimeDelta::try_seconds(number),
+        "m" => TimeDelta::try_minutes(number),
+        "h" => TimeDelta::try_hours(number),
+        _ => None,
+      }?;
+      return Local::now().checked_add_signed(delay).map(Self::At);
+    }
+    let time = NaiveTime::parse_from_str(&input, "%H:%M").ok()?;
+    let now = Local::now();
+    let mut at = Local
+      .from_local_datetime(&now.date_naive().and_time(time))
+      .earliest()?;
+    if at <= now {
+      at = at.checked_add_signed(TimeDelta::days(1))?;
+    }
+    Some(Self::At(at))
+  }
+
+  /// Short description for the transfer queue, e.g. `at 02:00`
+  pub fn describe(&self) -> String {
+    match self {
+      Self::At(at) if at.date_naive() == Local::now().date_naive() => {
+        format!("at {}", at.format("%H:%M"))
+      }
+      Self::At(at) => format!("at {}", at.format("%a %H:%M")),
+      Self::AfterQueue => String::from("after queue"),
+    }
+  }
+
+  fn is_due(&self, runner: &Runner) -> bool {
+    match self {
+      Self::At(at) => Local::now() >= *at,
+      Self::AfterQueue => runner.running() == 0,
+    }
+  }
+}
+
+/// A small thread that holds on to scheduled transfers and hands them to the `Runner`
+/// when they're due. Anything still waiting when the scheduler is dropped never starts.
+pub struct Scheduler {
+  tx: Sender<(Transfer, usize, Start)>,
+}
+
+impl Scheduler {
+  pub fn new(runner: Runner) -> Self {
+    let (tx, rx) = unbounded::<(Transfer, usize, Start)>();
+    thread::spawn(move || {
+      let mut queue = Vec::new();
+      loop {
+        match rx.recv_timeout(Duration::from_secs(1)) {
+          Ok(scheduled) => queue.push(scheduled),
+          Err(RecvTimeoutError::Timeout) => {}
+          Err(RecvTimeoutError::Disconnected) => break,
+        }
+        // Anything cancelled while it waited is forgotten about
+        queue.retain(|(transfer, ..)| !transfer.is_cancelled());
+        // Checked one at a time, so a transfer we start holds back any that wait for the queue
+        let mut i = 0;
+        while i < queue.len() {
+          if queue[i].2.is_due(&runner) {
+            let (transfer, id, _) = queue.remove(i);
+            runner.announce(id);
+            runner.start(transfer, id);
+          } else {
+            i += 1;
+          }
+        }
+      }
+    });
+    Self { tx }
+  }
+
+  /// Hold `transfer` (known to the UI as transfer `id`) until `start`
+  pub fn schedule(&self, transfer: Transfer, id: usize, start: Start) {
+    // The scheduler thread only stops once we're dropped
+    let _ = self.tx.send((transfer, id, start));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // How far off `input` starts, in seconds, or `None` for after the queue
+  fn delay(input: &str) -> Option<Option<i64>> {
+    Start::parse(input).map(|start| match start {
+      Start::At(at) => Some((at - Local::now()).num_seconds()),
+      Start::AfterQueue => None,
+    })
+  }
+
+  #[test]
+  fn delays_start_that_long_from_now() {
+    assert_eq!(delay(""), Some(None));
+    assert_eq!(delay(" After "), Some(None));
+    // a second can go by while parsing
+    assert!(matches!(delay("+30s"), Some(Some(29..=30))));
+    assert!(matches!(delay("+10M"), Some(Some(599..=600))));
+    assert!(matches!(delay("+2h"), Some(Some(7199..=7200))));
+    assert!(matches!(delay("+0s"), Some(Some(-1..=0))));
+  }
+
+  #[test]
+  fn times_of_day_are_the_next_time_the_clock_says_so() {
+    let Some(Some(seconds)) = delay("02:00") else {
+      panic!("02:00 is a time");
+    };
+    assert!((0..=24 * 60 * 60).contains(&seconds));
+  }
+
+  #[test]
+  fn anything_else_is_no_time_at_all() {
+    for input in [
+      "+",
+      "+m",
+      "+5",
+      "+5d",
+      "+-5m",
+      "+1.5h",
+      "25:00",
+      "2pm",
+      "soon",
+      // too far off for the clock, rather than a panic
+      "+99999999999999h",
+      "+9223372036854775807s",
+    ] {
+      assert_eq!(delay(input), None, "{input}");
+    }
+  }
+}
@@ -0,0 +1,83 @@
+//! Finding names under a remote directory, however deep, like `find dir -iname pattern`
+use ssh2::{Session, Sftp};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::app_utils;
+use crate::error::GsftpError;
+use crate::sftp;
+
+/// How many directories down a search goes below where it started
+pub const MAX_DEPTH: usize = 8;
+/// Searches stop once they've found this many matches
+pub const MAX_RESULTS: usize = 1000;
+
+/// Everything under the remote `dir` whose name fits `pattern` (see `app_utils::wildcard`).
+/// `find` on the server does the work when remote commands are allowed, which is far quicker
+/// than listing every directory over SFTP. Symlinks are never followed.
+pub fn remote(
+  sess: &Session,
+  sftp: &Sftp,
+  dir: &Path,
+  pattern: &str,
+) -> Result<Vec<PathBuf>, GsftpError> {
+  match remote_find(sess, dir, pattern) {
+    Some(found) => Ok(found),
+    None => {
+      let mut found = Vec::new();
+      remote_walk(sftp, dir, pattern, MAX_DEPTH, &mut found)?;
+      Ok(found)
+    }
+  }
+}
+
+fn remote_find(sess: &Session, dir: &Path, pattern: &str) -> Option<Vec<PathBuf>> {
+  let dir = dir.to_str()?;
+  let depth = MAX_DEPTH.to_string();
+  let args = [
+    dir,
+    "-mindepth",
+    "1",
+    "-maxdepth",
+    &depth,
+    "-iname",
+    pattern,
+  ];
+  let mut channel = sftp::exec_stream(sess, "find", &args).ok()?;
+  // Everything `find` prints has to be read for it to exit, even past the matches that are kept
+  let mut found: Vec<PathBuf> = BufReader::new(&mut channel)
+    .lines()
+    .map_while(Result::ok)
+    .map(PathBuf::from)
+    .collect();
+  found.truncate(MAX_RESULTS);
+  // `find` exits with 1 after finding what it could when some directories can't be read, which
+  // is only worth falling back over if it found nothing at all (e.g. it isn't installed)
+  match sftp::finish_exec(channel, "find") {
+    Err(_) if found.is_empty() => None,
+    _ => Some(found),
+  }
+}
+
+fn remote_walk(
+  sftp: &Sftp,
+  dir: &Path,
+  pattern: &str,
+  depth: usize,
+  found: &mut Vec<PathBuf>,
+) -> Result<(), GsftpError> {
+  for (child, stat) in sftp.readdir(dir)? {
+    if found.len() >= MAX_RESULTS {
+      break;
+    }
+    let name = child.file_name().unwrap_or_default().to_string_lossy();
+    if app_utils::wildcard(pattern.as_bytes(), name.as_bytes()) {
+      found.push(child.clone());
+    }
+    // Whatever can't be read is left out
+    if depth > 1 && stat.file_type().is_dir() {
+      let _ = remote_walk(sftp, &child, pattern, depth - 1, found);
+    }
+  }
+  Ok(())
+}
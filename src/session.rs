@@ -0,0 +1,95 @@
+//! Hands out SFTP channels so that concurrent transfers never share one
+use ssh2::{Session, Sftp};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::GsftpError;
+use crate::{config::Config, sftp};
+
+/// Owns the SSH session and a pool of idle SFTP channels opened on it.
+/// Every transfer checks out its own channel, which is handed back when it's dropped.
+/// If the session stops handing out channels, the pool reconnects with the original config.
+pub struct SessionPool {
+  conf: Config,
+  inner: Mutex<PoolInner>,
+}
+
+struct PoolInner {
+  sess: Session,
+  idle: Vec<Sftp>,
+  // Bumped on every reconnect so channels from a dead session aren't returned to the pool
+  generation: u64,
+}
+
+impl SessionPool {
+  /// Create a new pool around an already authenticated session
+  pub fn new(sess: Session, conf: Config) -> Arc<Self> {
+    let inner = Mutex::new(PoolInner {
+      sess,
+      idle: Vec::new(),
+      generation: 0,
+    });
+    Arc::new(Self { conf, inner })
+  }
+
+  /// The current SSH session, e.g. for opening exec channels
+  pub fn session(&self) -> Session {
+    self.lock().sess.clone()
+  }
+
+  /// Check out an SFTP channel, reusing an idle one when possible.
+  /// If the session can't open a new channel, it's re-established once before giving up.
+  pub fn get(self: &Arc<Self>) -> Result<PooledSftp, GsftpError> {
+    let mut inner = self.lock();
+    let sftp = match inner.idle.pop() {
+      Some(sftp) => sftp,
+      None => match inner.sess.sftp() {
+        Ok(sftp) => sftp,
+        Err(_) => {
+          inner.sess = sftp::connect(&self.conf)?;
+          inner.idle.clear();
+          inner.generation += 1;
+          inner.sess.sftp()?
+        }
+      },
+    };
+
+    Ok(PooledSftp {
+      sftp: Some(sftp),
+      pool: Arc::clone(self),
+      generation: inner.generation,
+    })
+  }
+
+  fn lock(&self) -> MutexGuard<'_, PoolInner> {
+    // A panicking transfer thread can't leave the pool in an inconsistent state
+    self.inner.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+/// An SFTP channel checked out of a `SessionPool`
+pub struct PooledSftp {
+  sftp: Option<Sftp>,
+  pool: Arc<SessionPool>,
+  generation: u64,
+}
+
+impl Deref for PooledSftp {
+  type Target = Sftp;
+
+  fn deref(&self) -> &Sftp {
+    // Only ever `None` while being dropped
+    self.sftp.as_ref().unwrap()
+  }
+}
+
+impl Drop for PooledSftp {
+  fn drop(&mut self) {
+    let mut inner = self.pool.lock();
+    if inner.generation == self.generation {
+      if let Some(sftp) = self.sftp.take() {
+        inner.idle.push(sftp);
+      }
+    }
+  }
+}
@@ -0,0 +1,290 @@
+//! Settings from gsftp's `config.toml`, which stand in for command line options that aren't
+//! given, e.g.
+//!
+//! ```toml
+//! show_hidden = true
+//! sort = "natural"
+//! on_conflict = "rename"
+//! confirm_size = "2G"
+//! encrypt_to = ["age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"]
+//! decrypt_with = "~/.config/age/key.txt"
+//! timeout = 10
+//!
+//! [theme]
+//! preset = "solarized"
+//! stripe = 235
+//!
+//! [keys]
+//! delete = ["x", "Delete"]
+//!
+//! [profiles.www]
+//! destination = "deploy@example.com:/var/www"
+//! identity = "~/.ssh/deploy"
+//! local_dir = "~/src/site"
+//! read_only = true
+//! ```
+//!
+//! Anything given on the command line beats the file. `[theme]` changes the colors (see `theme`)
+//! and `[keys]` the keys (see `keymap`). A profile's name can be given instead of a destination,
+//! which also starts with its options.
+use clap::ArgMatches;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// Any one of these on the command line says how to authenticate, so a profile's identity is left out
+const AUTH_ARGS: [&str; 5] = [
+  "identity",
+  "agent",
+  "password",
+  "password-stdin",
+  "agent-socket",
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// Everything `config.toml` can set; each is the default for the command line option of the
+/// same name (with `-` for `_`)
+pub struct Settings {
+  /// `--all`
+  pub show_hidden: bool,
+  pub sort: Option<String>,
+  pub symlinks: Option<String>,
+  pub on_conflict: Option<String>,
+  pub confirm_size: Option<String>,
+  pub confirm_files: Option<u64>,
+  pub chunk_size: Option<String>,
+  /// age recipients, or files of them, to encrypt uploads for
+  pub encrypt_to: Vec<String>,
+  /// An age identity file to decrypt downloads with
+  pub decrypt_with: Option<String>,
+  /// Encrypt and decrypt with a passphrase instead, typed in once the interface starts
+  pub encrypt_passphrase: bool,
+  pub gzip: bool,
+  pub trash: bool,
+  pub no_exec: bool,
+  pub forward_agent: bool,
+  pub read_only: bool,
+  /// What the interface speaks, if not the locale's language (see `i18n`)
+  pub language: Option<String>,
+  pub fps: Option<f64>,
+  /// Seconds between reading the windows again
+  pub refresh_interval: Option<f64>,
+  /// Seconds to wait for a server while connecting
+  pub timeout: Option<u64>,
+  pub profiles: BTreeMap<String, Profile>,
+  theme: toml::Table,
+  keys: toml::Table,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// A destination with a name of its own, and where to start out when it's connected to
+pub struct Profile {
+  pub destination: String,
+  pub identity: Option<String>,
+  pub local_dir: Option<String>,
+  pub remote_dir: Option<String>,
+  /// `--read-only`, e.g. for production servers
+  #[serde(default)]
+  pub read_only: bool,
+}
+
+/// Where a setting came from (e.g. `config.toml [theme] stripe`), its key in lower case, and its
+/// value
+pub type Setting = (String, String, String);
+
+/// `config.toml` in gsftp's config directory (`$XDG_CONFIG_HOME/gsftp`, or `~/.config/gsftp`, on
+/// Linux)
+pub fn file() -> Option<PathBuf> {
+  dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+}
+
+// `~/...` is under the home directory, as a shell would have it
+fn expand(path: &str) -> String {
+  match (path.strip_prefix("~/"), dirs::home_dir()) {
+    (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+    _ => path.to_string(),
+  }
+}
+
+impl Settings {
+  /// The settings in `path` (`--config`), or else in `file()`, which needn't exist.
+  /// Anything in it that can't be understood is an error.
+  pub fn load(path: Option<&Path>) -> Result<Self, String> {
+    // the theme and keys used to be kept in a file of their own, which is no longer read
+    let old = file().map(|file| file.with_file_name("config"));
+    if let Some(old) = old.filter(|old| old.exists()) {
+      warn!(
+        "{} is no longer read: move its [theme] and [keys] to config.toml",
+        old.display()
+      );
+    }
+    let text = match path {
+      Some(path) => fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read config file {}: {e}", path.display()))?,
+      None => match file().and_then(|file| fs::read_to_string(file).ok()) {
+        Some(text) => text,
+        None => return Ok(Self::default()),
+      },
+    };
+    let name = path.map(PathBuf::from).or_else(file).unwrap_or_default();
+    toml::from_str(&text).map_err(|e| format!("In {}: {e}", name.display()))
+  }
+
+  /// The destination the profile called `name` stands for, or else `name` itself
+  pub fn destination<'a>(&'a self, name: &'a str) -> &'a str {
+    self
+      .profiles
+      .get(name)
+      .map_or(name, |profile| profile.destination.as_str())
+  }
+
+  /// The `[theme]` or `[keys]` table, as settings, with a list of strings joined by spaces
+  pub fn section(&self, name: &str) -> Result<Vec<Setting>, String> {
+    let table = match name {
+      "theme" => &self.theme,
+      "keys" => &self.keys,
+      _ => return Ok(Vec::new()),
+    };
+    table
+      .iter()
+      .map(|(key, value)| {
+        let at = format!("config.toml [{name}] {key}");
+        let value = match value {
+          toml::Value::String(text) => text.clone(),
+          toml::Value::Integer(n) => n.to_string(),
+          toml::Value::Boolean(yes) => yes.to_string(),
+          toml::Value::Array(items) => items
+            .iter()
+            .map(|item| {
+              item
+                .as_str()
+                .ok_or_else(|| format!("{at}: expected strings"))
+            })
+            .collect::<Result<Vec<&str>, String>>()?
+            .join(" "),
+          _ => return Err(format!("{at}: expected a string")),
+        };
+        Ok((at, key.to_lowercase(), value))
+      })
+      .collect()
+  }
+
+  /// Command line options for whatever the file sets that the command line `given` doesn't,
+  /// to be parsed after it
+  pub fn options(&self, given: &ArgMatches) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut option = |id: &str, value: Option<String>| {
+      if let Some(value) = value.filter(|_| given.occurrences_of(id) == 0) {
+        options.push(format!("--{id}={value}"));
+      }
+    };
+    option("sort", self.sort.clone());
+    option("symlinks", self.symlinks.clone());
+    option("on-conflict", self.on_conflict.clone());
+    option("confirm-size", self.confirm_size.clone());
+    option("confirm-files", self.confirm_files.map(|n| n.to_string()));
+    option("chunk-size", self.chunk_size.clone());
+    // a passphrase on the command line takes the place of any keys in the file
+    if !given.is_present("encrypt-passphrase") {
+      option("decrypt-with", self.decrypt_with.as_deref().map(expand));
+    }
+    option("timeout", self.timeout.map(|secs| secs.to_string()));
+    option("fps", self.fps.map(|fps| fps.to_string()));
+    option(
+      "refresh-interval",
+      self.refresh_interval.map(|secs| secs.to_string()),
+    );
+    let profile = given
+      .value_of("DESTINATION")
+      .and_then(|name| self.profiles.get(name));
+    if let Some(profile) = profile {
+      option("local-dir", profile.local_dir.as_deref().map(expand));
+      option("remote-dir", profile.remote_dir.clone());
+      if !AUTH_ARGS.iter().any(|id| given.is_present(id)) {
+        option("identity", profile.identity.as_deref().map(expand));
+      }
+    }
+    if !given.is_present("encrypt-to") && !given.is_present("encrypt-passphrase") {
+      for recipient in &self.encrypt_to {
+        options.push(format!("--encrypt-to={}", expand(recipient)));
+      }
+    }
+    let keys_given = ["encrypt-to", "decrypt-with"]
+      .iter()
+      .any(|id| given.is_present(id));
+    let flags = [
+      ("all", self.show_hidden),
+      ("trash", self.trash),
+      ("no-exec", self.no_exec),
+      ("forward-agent", self.forward_agent),
+      (
+        "read-only",
+        self.read_only || profile.is_some_and(|p| p.read_only),
+      ),
+      // --gzip can't be used with --no-exec
+      ("gzip", self.gzip && !given.is_present("no-exec")),
+      // nor a passphrase with keys
+      ("encrypt-passphrase", self.encrypt_passphrase && !keys_given),
+    ];
+    for (id, set) in flags {
+      if set && !given.is_present(id) {
+        options.push(format!("--{id}"));
+      }
+    }
+    options
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::keymap::{Action, Keymap};
+  use crate::theme::Theme;
+  use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+  use tui::style::Color;
+
+  #[test]
+  fn theme_and_keys_come_from_their_tables() {
+    let settings: Settings = toml::from_str(
+      r##"
+      [theme]
+      preset = "mono"
+      directory = "#268bd2"
+      stripe = 235
+      dim_inactive = false
+
+      [keys]
+      delete = ["x", "Delete"]
+      quit = "ctrl+q"
+      "##,
+    )
+    .unwrap();
+    let theme = Theme::load(&settings).unwrap();
+    assert_eq!(theme.directory, Color::Rgb(0x26, 0x8b, 0xd2));
+    assert_eq!(theme.stripe, Color::Indexed(235));
+    assert!(!theme.dim_inactive);
+    let keys = Keymap::load(&settings).unwrap();
+    let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+    assert_eq!(keys.action(&key(KeyCode::Char('x'))), Some(Action::Delete));
+    assert_eq!(keys.action(&key(KeyCode::Delete)), Some(Action::Delete));
+    let ctrl_q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL);
+    assert_eq!(keys.action(&ctrl_q), Some(Action::Quit));
+  }
+
+  #[test]
+  fn theme_and_keys_that_cant_be_understood_say_where() {
+    let settings: Settings = toml::from_str("[theme]\ndirectory = \"plaid\"").unwrap();
+    let error = Theme::load(&settings).unwrap_err();
+    assert!(
+      error.starts_with("config.toml [theme] directory"),
+      "{error}"
+    );
+    let settings: Settings = toml::from_str("[keys]\ndelete = [1]").unwrap();
+    let error = Keymap::load(&settings).unwrap_err();
+    assert!(error.starts_with("config.toml [keys] delete"), "{error}");
+  }
+}
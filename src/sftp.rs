@@ -1,115 +1,396 @@
 //! SFTP utils
-use ssh2::{Prompt, Session, Sftp};
-use std::error::Error;
-use std::io::{Read, Write};
+use ssh2::{Channel, MethodType, Session, Sftp};
+use std::env;
+use std::io::Read;
+use std::iter;
 use std::net::{SocketAddr, TcpStream};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::Duration;
+use tracing::{debug, info, warn};
 
-use crate::config::Config;
+use crate::app_utils::Entry;
+use crate::ask;
+use crate::backend;
+use crate::config::{AgentSocket, AuthMethod, Config};
+use crate::credentials;
+use crate::error::GsftpError;
+use crate::host_keys;
+use crate::proxy;
+use crate::remote_path::{self, Flavor};
 
-/// Establish SFTP session with a password, given as an argument
-pub fn get_session_with_password(password: &str, conf: &Config) -> Result<Session, Box<dyn Error>> {
+/// Establish an authenticated SSH session using whichever method the config asks for
+pub fn connect(conf: &Config) -> Result<Session, GsftpError> {
+  authenticate(conf).map(|(sess, _)| sess)
+}
+
+/// Like `connect`, but also says which method worked, so the caller can stick with it
+pub fn authenticate(conf: &Config) -> Result<(Session, AuthMethod), GsftpError> {
+  info!(user = conf.user, method = %conf.auth_method, "authenticating");
+  let authenticated = match &conf.auth_method {
+    AuthMethod::Password(pwd) => {
+      get_session_with_password(pwd, conf).map(|sess| (sess, conf.auth_method.clone()))
+    }
+    AuthMethod::PrivateKey(sk) => get_session_with_identity_files(sk, conf),
+    AuthMethod::Agent => get_session_with_user_auth_agent(conf),
+    AuthMethod::Manual => get_session_with_fallback(conf),
+  };
+  match &authenticated {
+    Ok((_, method)) => info!(user = conf.user, %method, "authenticated"),
+    Err(e) => warn!(user = conf.user, error = %e, "authentication failed"),
+  }
+
+  authenticated
+}
+
+// TCP connection and SSH handshake, ready for authentication. `--timeout` beats the `timeout` given.
+fn handshake(conf: &Config, timeout: Duration) -> Result<Session, GsftpError> {
+  let timeout = conf.timeout.unwrap_or(timeout);
+  info!(
+    host = conf.host,
+    addr = conf.addr,
+    port = conf.port,
+    proxy = conf.proxy.is_some(),
+    "connecting"
+  );
   let mut sess = Session::new()?;
-  let addr = SocketAddr::from_str(format!("{}:{}", conf.addr, conf.port).as_str())?;
-  let stream = TcpStream::connect_timeout(&addr, Duration::from_millis(5000))?;
+  let stream = match &conf.proxy {
+    Some(via) => proxy::connect(via, conf, timeout)?,
+    None => {
+      let addr = format!("{}:{}", conf.addr, conf.port);
+      let socket = SocketAddr::from_str(&addr)
+        .map_err(|e| GsftpError::Config(format!("{addr} isn't an address: {e}")))?;
+      TcpStream::connect_timeout(&socket, timeout)
+        .map_err(|source| GsftpError::Connect { addr, source })?
+    }
+  };
   sess.set_tcp_stream(stream);
+  // Only takes effect if it's asked for before the handshake
+  sess.set_compress(conf.compress);
   sess.handshake()?;
-  sess.userauth_password(&conf.user, password)?;
+  debug!(
+    banner = sess.banner(),
+    kex = sess.methods(MethodType::Kex),
+    host_key = sess.methods(MethodType::HostKey),
+    cipher = sess.methods(MethodType::CryptCs),
+    mac = sess.methods(MethodType::MacCs),
+    "handshake done"
+  );
+  host_keys::check(&sess, conf)?;
+
+  Ok(sess)
+}
+
+/// Establish SFTP session with a password, given as an argument
+pub fn get_session_with_password(password: &str, conf: &Config) -> Result<Session, GsftpError> {
+  let sess = handshake(conf, Duration::from_millis(5000))?;
+  sess
+    .userauth_password(&conf.user, password)
+    .map_err(|e| GsftpError::Auth(e.to_string()))?;
 
   Ok(sess)
 }
 
-/// Establish SFTP session with a public key file, given as an argument
-pub fn get_session_with_identity_file(
+/// Establish SFTP session with the private key `identity_file` (and `--pubkey`, if given), then
+/// each of the other identity files in turn, and yield whichever key worked
+pub fn get_session_with_identity_files(
   identity_file: &str,
   conf: &Config,
-) -> Result<Session, Box<dyn Error>> {
-  let mut sess = Session::new()?;
-  let addr = SocketAddr::from_str(format!("{}:{}", conf.addr, conf.port).as_str())?;
-  let stream = TcpStream::connect_timeout(&addr, Duration::from_millis(7000))?;
-  sess.set_tcp_stream(stream);
-  sess.handshake()?;
-  let private_key = Path::new(identity_file);
-  let pubkey = conf.pubkey.as_deref();
-  let passphrase = conf.passphrase.as_deref();
-  sess.userauth_pubkey_file(&conf.user, pubkey, private_key, passphrase)?;
+) -> Result<(Session, AuthMethod), GsftpError> {
+  let sess = handshake(conf, Duration::from_millis(7000))?;
+  let first = PathBuf::from(identity_file);
+  let others = conf
+    .identity_files
+    .iter()
+    .filter(|key| **key != first && key.exists());
+  let mut last_err = None;
+  for key in iter::once(&first).chain(others) {
+    let pubkey = conf.pubkey.as_deref().filter(|_| *key == first);
+    let passphrase = passphrase(conf, key);
+    match sess.userauth_pubkey_file(&conf.user, pubkey, key, passphrase.as_deref()) {
+      Ok(()) => {
+        let key = key.to_string_lossy().into_owned();
+        return Ok((sess, AuthMethod::PrivateKey(key)));
+      }
+      Err(e) => last_err = Some(e),
+    }
+  }
 
-  Ok(sess)
+  Err(GsftpError::Auth(match last_err {
+    Some(e) => e.to_string(),
+    None => String::from("No identity file worked"),
+  }))
 }
 
-#[allow(unreachable_code, unused_variables, unused_mut)]
-/// Gets credentials via an interactive prompt
-/// (NOT IMPLEMENTED)
-pub fn get_session_with_keyboard_interactive(conf: &Config) -> Result<Session, Box<dyn Error>> {
-  let mut sess = Session::new()?;
-  let addr = SocketAddr::from_str(format!("{}:{}", conf.addr, conf.port).as_str())?;
-  let stream = TcpStream::connect_timeout(&addr, Duration::from_millis(5000))?;
-  sess.set_tcp_stream(stream);
-  sess.handshake()?;
-  let mut password_prompt = Prompt {
-    text: std::borrow::Cow::Borrowed("Password:"),
-    echo: true,
-  };
-  // sess.user_auth_keyboard_interactive(&conf.user, &mut prompter);
+// --passphrase, or else whatever the keyring has for `key`
+fn passphrase(conf: &Config, key: &Path) -> Option<String> {
+  conf
+    .passphrase
+    .clone()
+    .or_else(|| conf.keyring.then(|| credentials::passphrase(key))?)
+}
+
+/// Gets credentials via an interactive prompt on the terminal
+pub fn get_session_with_keyboard_interactive(conf: &Config) -> Result<Session, GsftpError> {
+  let sess = handshake(conf, Duration::from_millis(5000))?;
+  let mut prompter = conf.clone();
+  sess
+    .userauth_keyboard_interactive(&conf.user, &mut prompter)
+    .map_err(|e| GsftpError::Auth(e.to_string()))?;
 
   Ok(sess)
 }
 
 /// Establish SFTP session automatically with a user auth agent.
-/// With no password or identity file arguments, this is used as the default; if it fails
-/// it will attempt to establish an interactive keyboard session to authenticate (not implemented).
-pub fn get_session_with_user_auth_agent(conf: &Config) -> Result<Session, Box<dyn Error>> {
-  let mut sess = Session::new()?;
-  let addr = SocketAddr::from_str(format!("{}:{}", conf.addr, conf.port).as_str())?;
-  let stream = TcpStream::connect_timeout(&addr, Duration::from_millis(5000))?;
-  sess.set_tcp_stream(stream);
-  sess.handshake()?;
-  if sess.userauth_agent(&conf.user).is_err() {
-    return get_session_with_keyboard_interactive(conf);
+/// If the agent has no key the server accepts, says which methods the server does allow and
+/// falls back to keyboard-interactive and password prompts, yielding whichever worked.
+pub fn get_session_with_user_auth_agent(
+  conf: &Config,
+) -> Result<(Session, AuthMethod), GsftpError> {
+  let sess = handshake(conf, Duration::from_millis(5000))?;
+  let agent_err = match agent_auth(&sess, conf) {
+    Ok(()) if sess.authenticated() => return Ok((sess, AuthMethod::Agent)),
+    Ok(()) => String::from("the server didn't accept any of its keys"),
+    Err(e) => e.to_string(),
+  };
+  let allowed = sess
+    .auth_methods(&conf.user)
+    .map(String::from)
+    .unwrap_or_default();
+  warn!(allowed, "The SSH agent couldn't log you in: {agent_err}");
+  match fallback(sess, conf, &allowed, &["keyboard-interactive", "password"])? {
+    Some(authenticated) => Ok(authenticated),
+    None => Err(GsftpError::Auth(format!(
+      "Neither the SSH agent nor a password worked (the server allows {allowed})"
+    ))),
   }
+}
 
-  Ok(sess)
+// Held while asking the agent, so that one connection's $SSH_AUTH_SOCK is never another's
+static AGENT_SOCKET: Mutex<()> = Mutex::new(());
+
+// libssh2 finds the agent through $SSH_AUTH_SOCK (falling back to Pageant and then the OpenSSH
+// agent's named pipe on Windows), and ssh2 has no other way to point it elsewhere. Setting an
+// environment variable isn't thread-safe: anything else reading the environment at the same
+// moment (e.g. a `getenv` inside libc) can see it half-written. So `IdentityAgent` is only
+// set for the one `userauth_agent` call, with the lock held so that connections opened by
+// other threads can't overlap, and the old value is put back straight afterwards.
+fn agent_auth(sess: &Session, conf: &Config) -> Result<(), GsftpError> {
+  if matches!(conf.agent_socket, AgentSocket::Disabled) {
+    return Err(GsftpError::Auth(String::from(
+      "The SSH agent is disabled (IdentityAgent none)",
+    )));
+  }
+  let _lock = AGENT_SOCKET
+    .lock()
+    .unwrap_or_else(|poisoned| poisoned.into_inner());
+  let authenticated = match &conf.agent_socket {
+    AgentSocket::Path(path) => {
+      let previous = env::var_os("SSH_AUTH_SOCK");
+      env::set_var("SSH_AUTH_SOCK", path);
+      let authenticated = sess.userauth_agent(&conf.user);
+      match previous {
+        Some(previous) => env::set_var("SSH_AUTH_SOCK", previous),
+        None => env::remove_var("SSH_AUTH_SOCK"),
+      }
+      authenticated
+    }
+    _ => sess.userauth_agent(&conf.user),
+  };
+  authenticated.map_err(|e| GsftpError::Auth(e.to_string()))
+}
+
+/// Tries every method the server allows, in the order given by `PreferredAuthentications`
+/// (by default the SSH agent and identity files, then keyboard-interactive, then a password
+/// prompt), and yields the first that works.
+pub fn get_session_with_fallback(conf: &Config) -> Result<(Session, AuthMethod), GsftpError> {
+  let sess = handshake(conf, Duration::from_millis(5000))?;
+  let allowed = match sess.auth_methods(&conf.user) {
+    Ok(methods) => methods.to_string(),
+    // The server let us in without authenticating at all
+    Err(_) if sess.authenticated() => return Ok((sess, AuthMethod::Manual)),
+    Err(e) => return Err(e.into()),
+  };
+  let order = if conf.preferred_auth.is_empty() {
+    vec!["publickey", "keyboard-interactive", "password"]
+  } else {
+    conf.preferred_auth.iter().map(String::as_str).collect()
+  };
+  match fallback(sess, conf, &allowed, &order)? {
+    Some(authenticated) => Ok(authenticated),
+    None => Err(GsftpError::Auth(format!(
+      "No authentication method worked (the server allows {allowed})"
+    ))),
+  }
+}
+
+// Tries each method in `order` that's `allowed`, on a session that isn't authenticated yet.
+// Yields nothing (having dropped the session) if none of them worked.
+fn fallback(
+  sess: Session,
+  conf: &Config,
+  allowed: &str,
+  order: &[&str],
+) -> Result<Option<(Session, AuthMethod)>, GsftpError> {
+  for &method in order {
+    if !allowed.split(',').any(|m| m == method) {
+      continue;
+    }
+    debug!(method, "trying");
+    match method {
+      "publickey" => {
+        if agent_auth(&sess, conf).is_ok() {
+          return Ok(Some((sess, AuthMethod::Agent)));
+        }
+        for key in conf.identity_files.iter().filter(|key| key.exists()) {
+          let passphrase = passphrase(conf, key);
+          if sess
+            .userauth_pubkey_file(&conf.user, None, key, passphrase.as_deref())
+            .is_ok()
+          {
+            let key = key.to_string_lossy().into_owned();
+            return Ok(Some((sess, AuthMethod::PrivateKey(key))));
+          }
+        }
+      }
+      "keyboard-interactive" => {
+        let mut prompter = conf.clone();
+        if sess
+          .userauth_keyboard_interactive(&conf.user, &mut prompter)
+          .is_ok()
+        {
+          return Ok(Some((sess, AuthMethod::Manual)));
+        }
+      }
+      "password" => {
+        if let Some(password) = conf.keyring.then(|| credentials::password(conf)).flatten() {
+          if sess.userauth_password(&conf.user, &password).is_ok() {
+            return Ok(Some((sess, AuthMethod::Password(password))));
+          }
+          warn!("The password saved in the system keyring didn't work, so it's been forgotten");
+          credentials::forget_password(conf);
+        }
+        let prompt = format!("{}@{}'s password: ", conf.user, conf.addr);
+        let password = ask::ask(&prompt, true)?;
+        if sess.userauth_password(&conf.user, &password).is_ok() {
+          return Ok(Some((sess, AuthMethod::Password(password))));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  Ok(None)
 }
 
 /// Mimics the behavior of `ls` in a terminal, yielding the contents of a directory.
 /// The implied files `.` and `..` are ignored.
-pub fn ls(sftp: &Sftp, buf: &Path, show_hidden: bool) -> Vec<String> {
-  let mut items: Vec<String> = sftp
-    .readdir(buf)
-    .unwrap_or_default()
-    .iter()
-    .map(|(buf, _)| {
-      buf
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap_or_default()
-        .to_string()
-    })
-    .filter(|s| {
-      if show_hidden {
-        true
-      } else {
-        !s.starts_with('.')
-      }
-    })
-    .collect();
-  items.sort_by(|s1, s2| s1.to_lowercase().partial_cmp(&s2.to_lowercase()).unwrap());
-  items
+pub fn ls(sftp: &Sftp, buf: &Path, show_hidden: bool) -> Vec<Entry> {
+  try_ls(sftp, buf, show_hidden).unwrap_or_default()
+}
+
+/// Like `ls`, but fails if the directory can't be read, e.g. for lack of permission
+pub fn try_ls(sftp: &Sftp, buf: &Path, show_hidden: bool) -> Result<Vec<Entry>, GsftpError> {
+  backend::ls(sftp, buf, show_hidden)
 }
 
 /// Gets the base directory ($HOME) of the remote client, i.e. `/home/user/` on Linux
-/// or `C:\Users\user` on Windows
-pub fn home_dir(sess: &Session) -> PathBuf {
-  let mut channel = sess.channel_session().unwrap();
-  channel.exec("pwd").unwrap_or_else(|e| {
-    eprintln!("Failure to execute command pwd: {e}");
-    eprintln!("Perhaps client does not have the permissions to read their own home directory?");
-    channel.write_all(b"ERROR").unwrap();
+/// or `C:\Users\user` on Windows.
+/// SFTP sessions start out in the home directory, so this is just where `.` resolves to;
+/// running `pwd` is only a fallback, since SFTP-only servers won't execute commands.
+pub fn home_dir(sftp: &Sftp, sess: &Session) -> PathBuf {
+  if let Ok(home) = sftp.realpath(Path::new(".")) {
+    return home;
+  }
+  // Windows' shell is `cmd`, where `cd` on its own prints the current directory
+  let (program, windows) = match Flavor::detect(sess) {
+    Flavor::Windows => ("cd", true),
+    Flavor::Posix => ("pwd", false),
+  };
+  let pwd = exec(sess, program, &[]).unwrap_or_else(|e| {
+    warn!("Couldn't run {program} to find the home directory (is it readable?): {e}");
+    String::new()
   });
-  let mut s = String::new();
-  channel.read_to_string(&mut s).unwrap_or_default();
-  PathBuf::from(s.strip_suffix('\n').unwrap_or_default())
+  let pwd = pwd.trim_end_matches(['\r', '\n']);
+  match windows {
+    true => remote_path::from_windows(pwd),
+    false => PathBuf::from(pwd),
+  }
+}
+
+/// Stops `exec` and `exec_stream` from ever opening a shell channel again, for servers which
+/// only allow SFTP (e.g. `ForceCommand internal-sftp` in a chroot)
+pub fn disable_exec() {
+  EXEC_DISABLED.store(true, Ordering::SeqCst);
+}
+
+static EXEC_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Asks the server to forward the SSH agent, like `ssh -A`, to every command `exec` and
+/// `exec_stream` run from now on. libssh2 only answers the connections those commands then make
+/// back to the agent if an agent callback has been set on the session, which `ssh2` can't do.
+pub fn forward_agent() {
+  AGENT_FORWARDED.store(true, Ordering::SeqCst);
+}
+
+static AGENT_FORWARDED: AtomicBool = AtomicBool::new(false);
+// Servers refusing to forward the agent (`AllowAgentForwarding no`) are only warned about once
+static FORWARDING_REFUSED: AtomicBool = AtomicBool::new(false);
+
+/// Quotes `arg` so that a POSIX shell treats it as one literal word, no matter which
+/// quotes, `$`, backticks or newlines it contains.
+pub fn shell_quote(arg: &str) -> String {
+  format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Runs `program` on the remote host with every argument quoted by `shell_quote`,
+/// waits for it to finish and yields whatever it wrote to stdout.
+/// Along with `exec_stream`, this is the only place gsftp should ever call `channel.exec`,
+/// and both refuse once `disable_exec` has been called. Callers fall back to plain SFTP, or fail.
+pub fn exec(sess: &Session, program: &str, args: &[&str]) -> Result<String, GsftpError> {
+  let mut channel = exec_stream(sess, program, args)?;
+  let mut stdout = String::new();
+  channel.read_to_string(&mut stdout)?;
+  finish_exec(channel, program)?;
+
+  Ok(stdout)
+}
+
+/// Like `exec`, but hands back the channel straight away so that large outputs can be
+/// streamed from it; pass it to `finish_exec` once stdout has been read.
+pub fn exec_stream(sess: &Session, program: &str, args: &[&str]) -> Result<Channel, GsftpError> {
+  if EXEC_DISABLED.load(Ordering::SeqCst) {
+    return Err(format!("Can't run `{program}`: remote commands are disabled (--no-exec)").into());
+  }
+  let mut command = String::from(program);
+  for arg in args {
+    command.push(' ');
+    command.push_str(&shell_quote(arg));
+  }
+  debug!(command, "exec");
+  let mut channel = sess.channel_session()?;
+  if AGENT_FORWARDED.load(Ordering::SeqCst) {
+    if let Err(e) = channel.request_auth_agent_forwarding() {
+      if !FORWARDING_REFUSED.swap(true, Ordering::SeqCst) {
+        warn!("The server won't forward your SSH agent: {e}");
+      }
+    }
+  }
+  channel.exec(&command)?;
+
+  Ok(channel)
+}
+
+/// Waits for a command started by `exec_stream` to exit, turning a non-zero exit status
+/// into an error that includes whatever the command wrote to stderr
+pub fn finish_exec(mut channel: Channel, program: &str) -> Result<(), GsftpError> {
+  let mut stderr = String::new();
+  channel.stderr().read_to_string(&mut stderr)?;
+  channel.wait_close()?;
+  match channel.exit_status()? {
+    0 => Ok(()),
+    code => Err(format!("`{program}` exited with status {code}: {}", stderr.trim()).into()),
+  }
 }
@@ -0,0 +1,35 @@
+//! How the screen's width is shared between the two windows, kept in gsftp's data directory
+//! so that it's the same next time
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The share of the width the local window gets when nothing's been saved, in percent
+pub const DEFAULT: u16 = 50;
+/// Neither window gets less than this much of the width, in percent
+pub const MIN: u16 = 20;
+
+fn file() -> Option<PathBuf> {
+  dirs::data_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("split"))
+}
+
+/// The local window's share of the width when gsftp last quit, if it was ever changed
+pub fn load() -> Option<u16> {
+  let text = fs::read_to_string(file()?).ok()?;
+  let percent: u16 = text.trim().parse().ok()?;
+  Some(percent.clamp(MIN, 100 - MIN))
+}
+
+/// Remembers the local window's share of the width, in percent
+pub fn save(percent: u16) -> io::Result<()> {
+  let file = file().ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::NotFound,
+      "no data directory to remember the split in",
+    )
+  })?;
+  if let Some(dir) = file.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  fs::write(file, format!("{percent}\n"))
+}
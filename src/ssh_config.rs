@@ -0,0 +1,119 @@
+//! Just enough of an OpenSSH client config (`~/.ssh/config`) to resolve a host alias.
+//! `Match` blocks and `Include` directives aren't supported and are skipped.
+//! One keyword is gsftp's own: `RemoteDirectory`, which OpenSSH needs to be told to ignore with
+//! `IgnoreUnknown RemoteDirectory`.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::app_utils::wildcard;
+
+#[derive(Debug, Default, Clone)]
+/// The settings in `~/.ssh/config` which apply to one host
+pub struct HostConfig {
+  pub hostname: Option<String>,
+  pub user: Option<String>,
+  pub port: Option<u16>,
+  pub identity_files: Vec<PathBuf>,
+  /// `IdentityAgent`: an agent socket, `SSH_AUTH_SOCK`, or `none`
+  pub identity_agent: Option<String>,
+  /// `PreferredAuthentications`, e.g. `publickey`, `password`, in order
+  pub preferred_auth: Vec<String>,
+  /// `ProxyCommand`, with its `%h`, `%p` and `%r` tokens left for when it's run
+  pub proxy_command: Option<String>,
+  /// `Compression yes` or `no`
+  pub compression: Option<bool>,
+  /// `RemoteDirectory`: where to start on the server; relative paths are under the home directory
+  pub remote_dir: Option<PathBuf>,
+}
+
+impl HostConfig {
+  /// Settings for `host` from the user's `~/.ssh/config`, or nothing at all if there isn't one
+  pub fn load(host: &str) -> Self {
+    let text = home()
+      .map(|home| home.join(".ssh").join("config"))
+      .and_then(|path| fs::read_to_string(path).ok())
+      .unwrap_or_default();
+    Self::parse(&text, host)
+  }
+
+  /// Like OpenSSH, the first value found for each keyword wins, so specific `Host` blocks
+  /// belong above general ones. `IdentityFile`s accumulate.
+  pub fn parse(text: &str, host: &str) -> Self {
+    let mut conf = Self::default();
+    // Settings before the first `Host` line apply to every host
+    let mut active = true;
+    for line in text.lines() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let split = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+      let (keyword, value) = line.split_at(split);
+      let value = value
+        .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+        .trim_matches('"');
+      match keyword.to_lowercase().as_str() {
+        "host" => active = host_matches(value, host),
+        "match" => active = false,
+        _ if !active => {}
+        "hostname" if conf.hostname.is_none() => conf.hostname = Some(value.replace("%h", host)),
+        "user" if conf.user.is_none() => conf.user = Some(value.to_string()),
+        "port" if conf.port.is_none() => conf.port = value.parse().ok(),
+        "identityfile" => conf.identity_files.push(expand(value, host)),
+        "identityagent" if conf.identity_agent.is_none() => {
+          conf.identity_agent = Some(expand(value, host).to_string_lossy().into_owned());
+        }
+        "preferredauthentications" if conf.preferred_auth.is_empty() => {
+          conf.preferred_auth = value.split(',').map(|m| m.trim().to_string()).collect();
+        }
+        "proxycommand" if conf.proxy_command.is_none() => {
+          conf.proxy_command = Some(value.to_string());
+        }
+        "compression" if conf.compression.is_none() => {
+          conf.compression = Some(value.eq_ignore_ascii_case("yes"));
+        }
+        "remotedirectory" if conf.remote_dir.is_none() => {
+          // `~` is the remote home directory, which relative paths already start from
+          let dir = value.trim_start_matches('~').trim_start_matches('/');
+          conf.remote_dir = Some(match value.starts_with('~') {
+            true => PathBuf::from(dir),
+            false => PathBuf::from(value),
+          });
+        }
+        _ => {}
+      }
+    }
+    conf
+  }
+}
+
+fn home() -> Option<PathBuf> {
+  env::var_os("HOME").map(PathBuf::from)
+}
+
+// `~` and the `%d` (home directory) and `%h` (host) tokens in an `IdentityFile`
+fn expand(path: &str, host: &str) -> PathBuf {
+  let home = home().unwrap_or_default();
+  let home = home.to_string_lossy();
+  let path = match path.strip_prefix('~') {
+    Some(rest) => format!("{home}{rest}"),
+    None => path.to_string(),
+  };
+  PathBuf::from(path.replace("%d", &home).replace("%h", host))
+}
+
+// A `Host` line holds whitespace-separated patterns; any `!pattern` that matches rules the host out
+fn host_matches(patterns: &str, host: &str) -> bool {
+  let mut matched = false;
+  for pattern in patterns.split_whitespace() {
+    match pattern.strip_prefix('!') {
+      Some(negated) if wildcard(negated.as_bytes(), host.as_bytes()) => return false,
+      Some(_) => {}
+      None => matched |= wildcard(pattern.as_bytes(), host.as_bytes()),
+    }
+  }
+  matched
+}
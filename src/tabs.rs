@@ -0,0 +1,169 @@
+//! Tabs, each with a local and a remote pane of its own, possibly on different hosts. The panes
+//! on screen belong to the active tab; the others are kept here until they're switched to.
+use std::mem;
+use std::path::Path;
+
+use crate::app::App;
+use crate::app_utils::{AppBuf, AppContent, AppState};
+use crate::connection::{self, Connections};
+
+// A tab's panes, while another tab is showing
+struct Panes {
+  buf: AppBuf,
+  content: AppContent,
+  state: AppState,
+}
+
+struct Tab {
+  // Which connection its remote pane is on, as of when it was last showing
+  connection: usize,
+  // `None` for the active tab, whose panes are `App`'s
+  panes: Option<Panes>,
+}
+
+/// Every open tab, and which one is showing
+pub struct Tabs {
+  list: Vec<Tab>,
+  active: usize,
+}
+
+/// e.g. `2 www ⇄ deploy@example.com:html`: a tab's number, its local directory's name, and its
+/// remote directory's name with who it's logged in as (`id` is a connection's id)
+pub fn title(n: usize, buf: &AppBuf, id: &str) -> String {
+  let name = |path: &Path| {
+    path.file_name().map_or_else(
+      || path.display().to_string(),
+      |name| name.to_string_lossy().to_string(),
+    )
+  };
+  // The port would only make the title longer
+  let login = connection::login(id);
+  format!("{n} {} ⇄ {login}:{}", name(&buf.local), name(&buf.remote))
+}
+
+impl Tabs {
+  /// Just the one tab, whose panes are on the active connection
+  pub fn new(connections: &Connections) -> Self {
+    Self {
+      list: vec![Tab {
+        connection: connections.active_index(),
+        panes: None,
+      }],
+      active: 0,
+    }
+  }
+
+  /// Open a tab on the same directories and host as the active one, and show it
+  pub fn open(&mut self, app: &mut App, connections: &mut Connections) {
+    let buf = AppBuf {
+      local: app.buf.local.clone(),
+      remote: app.buf.remote.clone(),
+    };
+    let mut content = AppContent::from(
+      &buf,
+      &*app.local_fs,
+      &*connections.active().sftp,
+      app.show_hidden,
+    );
+    content.sort = app.content.sort;
+    content.refilter();
+    let state = AppState {
+      active: app.state.active,
+      ..AppState::default()
+    };
+    self.stash(app, connections, buf, content, state);
+    self.list.push(Tab {
+      connection: connections.active_index(),
+      panes: None,
+    });
+    self.active = self.list.len() - 1;
+    self.label(app, connections);
+  }
+
+  /// Close the active tab and show the one before it. Yields false if it's the only one.
+  pub fn close(&mut self, app: &mut App, connections: &mut Connections) -> bool {
+    if self.list.len() == 1 {
+      return false;
+    }
+    let closing = self.active;
+    let next = closing.checked_sub(1).unwrap_or(1);
+    // The closing tab's panes are stashed on the way out, then thrown away with it
+    self.switch(next, app, connections);
+    self.list.remove(closing);
+    if self.active > closing {
+      self.active -= 1;
+    }
+    self.label(app, connections);
+    true
+  }
+
+  /// Show tab `i` (counting from 0), keeping the active one's place.
+  /// Yields false if there's no such tab.
+  pub fn switch(&mut self, i: usize, app: &mut App, connections: &mut Connections) -> bool {
+    if i >= self.list.len() {
+      return false;
+    }
+    if i == self.active {
+      return true;
+    }
+    let Some(next) = self.list[i].panes.take() else {
+      return false;
+    };
+    self.stash(app, connections, next.buf, next.content, next.state);
+    self.active = i;
+    let connection = self.list[i].connection;
+    if connection != connections.active_index() {
+      connections.attach(connection, app);
+    }
+    // It may have changed while another tab was showing
+    app.list_local();
+    app.list_remote(&*connections.active().sftp);
+    self.label(app, connections);
+    true
+  }
+
+  /// Show the tab `step` tabs along, wrapping around at either end
+  pub fn cycle(&mut self, step: isize, app: &mut App, connections: &mut Connections) {
+    let len = self.list.len() as isize;
+    let i = (self.active as isize + step).rem_euclid(len) as usize;
+    self.switch(i, app, connections);
+  }
+
+  // Put the active tab's panes away, and `App` takes the ones given instead
+  fn stash(
+    &mut self,
+    app: &mut App,
+    connections: &Connections,
+    buf: AppBuf,
+    content: AppContent,
+    state: AppState,
+  ) {
+    let active = &mut self.list[self.active];
+    active.connection = connections.active_index();
+    active.panes = Some(Panes {
+      buf: mem::replace(&mut app.buf, buf),
+      content: mem::replace(&mut app.content, content),
+      state: mem::replace(&mut app.state, state),
+    });
+  }
+
+  // Tell `App` what the tab bar says, which it only shows once there's more than one tab
+  fn label(&self, app: &mut App, connections: &Connections) {
+    app.tab = self.active;
+    app.tabs = match self.list.len() {
+      1 => Vec::new(),
+      _ => self
+        .list
+        .iter()
+        .enumerate()
+        .map(|(i, tab)| {
+          let id = connections
+            .get(tab.connection)
+            .map_or("", |c| c.id.as_str());
+          let buf = tab.panes.as_ref().map_or(&app.buf, |panes| &panes.buf);
+          title(i + 1, buf, id)
+        })
+        .collect(),
+    };
+  }
+}
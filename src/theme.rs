@@ -0,0 +1,309 @@
+//! The colors gsftp draws with, taken from the `[theme]` table of `config.toml` (see
+//! `settings`), e.g.
+//!
+//! ```toml
+//! [theme]
+//! preset = "solarized"
+//! directory = "#268bd2"
+//! selection = 33
+//! ```
+//!
+//! A `preset` is applied first, whatever line it's on, and the other keys change single colors.
+//! Colors are names like `lightblue`, numbers from the terminal's 256-color palette, or
+//! `#rrggbb` for terminals that support truecolor. `icons = ascii` or `icons = nerd` puts an
+//! icon in front of each name (`nerd` needs a Nerd Font). So the active window stands out without
+//! color too, its title is drawn `active_title = "bold reverse"` (or any of `bold`, `reverse`,
+//! `underline` and `italic`, or `none`), and with `dim_inactive = true` the other window's text
+//! is dimmed. `stripe = 235` gives every other row that background, and `recent_minutes = 10`
+//! puts names modified in the last ten minutes in the `recent` color.
+use std::str::FromStr;
+use tui::style::{Color, Modifier};
+
+use crate::app_utils::FileClass;
+use crate::settings::{Setting, Settings};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// What goes in front of each name to show what sort of file it is
+pub enum Icons {
+  /// Nothing, which is right for any terminal
+  #[default]
+  None,
+  /// A letter or symbol, e.g. `d` for directories
+  Ascii,
+  /// Nerd Font symbols, which need a patched font to show up
+  Nerd,
+}
+
+impl FromStr for Icons {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "none" | "off" => Ok(Self::None),
+      "ascii" => Ok(Self::Ascii),
+      "nerd" | "nerdfont" => Ok(Self::Nerd),
+      _ => Err(format!("unknown icons {s} (try none, ascii or nerd)")),
+    }
+  }
+}
+
+impl Icons {
+  /// The icon for files of `class`, with a space after it, if there are icons at all
+  pub fn of(&self, class: FileClass) -> &'static str {
+    match (self, class) {
+      (Self::None, _) => "",
+      (Self::Ascii, FileClass::Dir) => "d ",
+      (Self::Ascii, FileClass::Symlink) => "@ ",
+      (Self::Ascii, FileClass::Executable) => "x ",
+      (Self::Ascii, FileClass::Archive) => "z ",
+      (Self::Ascii, FileClass::Image) => "i ",
+      (Self::Ascii, FileClass::Other) => "- ",
+      (Self::Nerd, FileClass::Dir) => "\u{f115} ",
+      (Self::Nerd, FileClass::Symlink) => "\u{f0c1} ",
+      (Self::Nerd, FileClass::Executable) => "\u{f489} ",
+      (Self::Nerd, FileClass::Archive) => "\u{f410} ",
+      (Self::Nerd, FileClass::Image) => "\u{f1c5} ",
+      (Self::Nerd, FileClass::Other) => "\u{f15b} ",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Which color each part of the UI is drawn in
+pub struct Theme {
+  /// The border around the window that has the keyboard
+  pub active_border: Color,
+  /// Behind the highlighted item in the active window
+  pub selection: Color,
+  /// Behind the highlighted item in the other window
+  pub inactive_selection: Color,
+  pub directory: Color,
+  pub symlink: Color,
+  /// Files anyone may run
+  pub executable: Color,
+  /// Zip files, tarballs and so on, by extension
+  pub archive: Color,
+  /// Pictures, by extension
+  pub image: Color,
+  /// Error messages, failed transfers and lost connections
+  pub error: Color,
+  /// Transfers and other jobs that have finished
+  pub success: Color,
+  /// Every other message
+  pub message: Color,
+  pub icons: Icons,
+  /// How the active window's title stands out, e.g. bold and in reverse video
+  pub active_title: Modifier,
+  /// Whether the text in the window without the keyboard is dimmed
+  pub dim_inactive: bool,
+  /// Behind every other row, or `Color::Reset` for no stripes
+  pub stripe: Color,
+  /// Names modified in the last `recent_minutes` minutes, if that's more than 0
+  pub recent: Color,
+  pub recent_minutes: u64,
+}
+
+/// The built-in themes, by name
+pub const PRESETS: [&str; 3] = ["default", "solarized", "mono"];
+
+impl Default for Theme {
+  fn default() -> Self {
+    Self {
+      active_border: Color::LightCyan,
+      selection: Color::Cyan,
+      inactive_selection: Color::Blue,
+      directory: Color::LightBlue,
+      symlink: Color::LightCyan,
+      executable: Color::LightGreen,
+      archive: Color::LightRed,
+      image: Color::LightMagenta,
+      error: Color::Red,
+      success: Color::LightGreen,
+      message: Color::LightCyan,
+      icons: Icons::None,
+      active_title: Modifier::BOLD | Modifier::REVERSED,
+      dim_inactive: true,
+      stripe: Color::Reset,
+      recent: Color::Yellow,
+      recent_minutes: 0,
+    }
+  }
+}
+
+impl Theme {
+  /// One of the built-in themes in `PRESETS`
+  pub fn preset(name: &str) -> Option<Self> {
+    match name.to_lowercase().as_str() {
+      "default" => Some(Self::default()),
+      // Ethan Schoonover's palette, which needs truecolor
+      "solarized" => Some(Self {
+        active_border: Color::Rgb(0x2a, 0xa1, 0x98),
+        selection: Color::Rgb(0x26, 0x8b, 0xd2),
+        inactive_selection: Color::Rgb(0x07, 0x36, 0x42),
+        directory: Color::Rgb(0x26, 0x8b, 0xd2),
+        symlink: Color::Rgb(0x2a, 0xa1, 0x98),
+        executable: Color::Rgb(0x85, 0x99, 0x00),
+        archive: Color::Rgb(0xcb, 0x4b, 0x16),
+        image: Color::Rgb(0xd3, 0x36, 0x82),
+        error: Color::Rgb(0xdc, 0x32, 0x2f),
+        success: Color::Rgb(0x85, 0x99, 0x00),
+        message: Color::Rgb(0xb5, 0x89, 0x00),
+        ..Self::default()
+      }),
+      // For terminals with few colors, or people who'd rather not have them
+      "mono" => Some(Self {
+        active_border: Color::White,
+        selection: Color::Gray,
+        inactive_selection: Color::DarkGray,
+        directory: Color::White,
+        symlink: Color::Gray,
+        executable: Color::White,
+        archive: Color::White,
+        image: Color::White,
+        error: Color::White,
+        success: Color::White,
+        message: Color::Gray,
+        icons: Icons::Ascii,
+        ..Self::default()
+      }),
+      _ => None,
+    }
+  }
+
+  /// The theme set up in `config.toml`, or the default one if it has none. Anything it says
+  /// that can't be understood is an error, saying where it is.
+  pub fn load(toml: &Settings) -> Result<Self, String> {
+    Self::from_settings(&toml.section("theme")?)
+  }
+
+  /// The theme the settings from a `[theme]` table describe, where any setting given twice
+  /// takes the later value
+  pub fn from_settings(settings: &[Setting]) -> Result<Self, String> {
+    let mut theme = Self::default();
+    if let Some((at, _, name)) = settings.iter().rfind(|(_, key, _)| key == "preset") {
+      theme = Self::preset(name)
+        .ok_or_else(|| format!("{at}: unknown preset {name} (try {})", PRESETS.join(", ")))?;
+    }
+    if let Some((at, _, icons)) = settings.iter().rfind(|(_, key, _)| key == "icons") {
+      theme.icons = icons.parse().map_err(|e| format!("{at}: {e}"))?;
+    }
+    if let Some((at, _, title)) = settings.iter().rfind(|(_, key, _)| key == "active_title") {
+      theme.active_title =
+        parse_modifier(title).ok_or_else(|| format!("{at}: {title} isn't a style"))?;
+    }
+    if let Some((at, _, dim)) = settings.iter().rfind(|(_, key, _)| key == "dim_inactive") {
+      theme.dim_inactive = match dim.to_lowercase().as_str() {
+        "yes" | "true" | "on" => true,
+        "no" | "false" | "off" => false,
+        _ => return Err(format!("{at}: dim_inactive should be yes or no")),
+      };
+    }
+    if let Some((at, _, minutes)) = settings.iter().rfind(|(_, key, _)| key == "recent_minutes") {
+      theme.recent_minutes = minutes
+        .parse()
+        .map_err(|_| format!("{at}: recent_minutes should be a number"))?;
+    }
+    const NOT_COLORS: [&str; 5] = [
+      "preset",
+      "icons",
+      "active_title",
+      "dim_inactive",
+      "recent_minutes",
+    ];
+    let colors = settings
+      .iter()
+      .filter(|(_, key, _)| !NOT_COLORS.contains(&key.as_str()));
+    for (at, key, value) in colors {
+      let color = parse_color(value).ok_or_else(|| format!("{at}: {value} isn't a color"))?;
+      let slot = match key.as_str() {
+        "active_border" => &mut theme.active_border,
+        "selection" => &mut theme.selection,
+        "inactive_selection" => &mut theme.inactive_selection,
+        "directory" => &mut theme.directory,
+        "symlink" => &mut theme.symlink,
+        "executable" => &mut theme.executable,
+        "archive" => &mut theme.archive,
+        "image" => &mut theme.image,
+        "error" => &mut theme.error,
+        "success" => &mut theme.success,
+        "message" => &mut theme.message,
+        "stripe" => &mut theme.stripe,
+        "recent" => &mut theme.recent,
+        _ => return Err(format!("{at}: unknown theme key {key}")),
+      };
+      *slot = color;
+    }
+    Ok(theme)
+  }
+}
+
+/// Any of `bold`, `reverse`, `underline` and `italic`, separated by spaces or `+`, or `none`
+pub fn parse_modifier(text: &str) -> Option<Modifier> {
+  let mut modifier = Modifier::empty();
+  for word in text.split(|c: char| c == '+' || c.is_whitespace()) {
+    modifier |= match word.to_lowercase().as_str() {
+      "" | "none" => Modifier::empty(),
+      "bold" => Modifier::BOLD,
+      "reverse" | "reversed" => Modifier::REVERSED,
+      "underline" | "underlined" => Modifier::UNDERLINED,
+      "italic" => Modifier::ITALIC,
+      _ => return None,
+    };
+  }
+  Some(modifier)
+}
+
+/// `lightblue` (or `light_blue`), `33` from the 256-color palette, or `#268bd2`
+pub fn parse_color(text: &str) -> Option<Color> {
+  let text = text.trim().to_lowercase().replace(['_', '-', ' '], "");
+  if let Some(hex) = text.strip_prefix('#') {
+    if hex.len() != 6 {
+      return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+  }
+  if let Ok(index) = text.parse::<u8>() {
+    return Some(Color::Indexed(index));
+  }
+  let color = match text.as_str() {
+    "reset" | "none" => Color::Reset,
+    "black" => Color::Black,
+    "red" => Color::Red,
+    "green" => Color::Green,
+    "yellow" => Color::Yellow,
+    "blue" => Color::Blue,
+    "magenta" => Color::Magenta,
+    "cyan" => Color::Cyan,
+    "gray" | "grey" => Color::Gray,
+    "darkgray" | "darkgrey" => Color::DarkGray,
+    "lightred" => Color::LightRed,
+    "lightgreen" => Color::LightGreen,
+    "lightyellow" => Color::LightYellow,
+    "lightblue" => Color::LightBlue,
+    "lightmagenta" => Color::LightMagenta,
+    "lightcyan" => Color::LightCyan,
+    "white" => Color::White,
+    _ => return None,
+  };
+  Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn colors_are_names_palette_numbers_or_hex() {
+    assert_eq!(parse_color("lightblue"), Some(Color::LightBlue));
+    assert_eq!(parse_color(" Light_Blue "), Some(Color::LightBlue));
+    assert_eq!(parse_color("dark-grey"), Some(Color::DarkGray));
+    assert_eq!(parse_color("none"), Some(Color::Reset));
+    assert_eq!(parse_color("33"), Some(Color::Indexed(33)));
+    assert_eq!(parse_color("255"), Some(Color::Indexed(255)));
+    assert_eq!(parse_color("#268BD2"), Some(Color::Rgb(0x26, 0x8b, 0xd2)));
+    for nonsense in ["", "256", "#fff", "#aé8bd", "#2g8bd2", "#268bd2ff", "plaid"] {
+      assert_eq!(parse_color(nonsense), None, "{nonsense}");
+    }
+  }
+}
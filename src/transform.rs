@@ -0,0 +1,350 @@
+//! Pluggable stages that file payloads pass through on their way to the destination
+use flate2::write::GzEncoder;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use crate::error::GsftpError;
+
+/// Somewhere the bytes of a file end up. Unlike a plain `Write`, a sink has to be
+/// finished explicitly so that failures further down the pipeline aren't lost.
+pub trait Sink: Write + Send {
+  fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl Sink for fs::File {
+  fn finish(mut self: Box<Self>) -> io::Result<()> {
+    self.flush()
+  }
+}
+
+impl Sink for ssh2::File {
+  fn finish(mut self: Box<Self>) -> io::Result<()> {
+    self.flush()
+  }
+}
+
+impl Sink for GzEncoder<Box<dyn Sink>> {
+  fn finish(self: Box<Self>) -> io::Result<()> {
+    GzEncoder::finish(*self)?.finish()
+  }
+}
+
+/// A stage in the transfer pipeline: it wraps the sink the file is being written to,
+/// and may change the name the file ends up with.
+pub trait Transform: Send + Sync {
+  fn wrap(&self, sink: Box<dyn Sink>) -> io::Result<Box<dyn Sink>>;
+
+  fn destination(&self, to: &Path) -> PathBuf {
+    to.to_path_buf()
+  }
+}
+
+/// Feeds `sink` through every stage, so that bytes written to the result pass through
+/// `stages` in order before they arrive
+pub fn through(sink: Box<dyn Sink>, stages: &[Box<dyn Transform>]) -> io::Result<Box<dyn Sink>> {
+  stages
+    .iter()
+    .rev()
+    .try_fold(sink, |sink, stage| stage.wrap(sink))
+}
+
+/// Where a file bound for `to` really ends up once every stage has had its say
+pub fn destination(to: &Path, stages: &[Box<dyn Transform>]) -> PathBuf {
+  stages
+    .iter()
+    .fold(to.to_path_buf(), |to, stage| stage.destination(&to))
+}
+
+/// Encrypts payloads for one or more age recipients (public keys, or files of them)
+/// using the `age` binary; encrypted files get an `.age` extension.
+pub struct AgeEncrypt {
+  pub recipients: Vec<String>,
+}
+
+impl Transform for AgeEncrypt {
+  fn wrap(&self, sink: Box<dyn Sink>) -> io::Result<Box<dyn Sink>> {
+    let mut command = Command::new("age");
+    for recipient in &self.recipients {
+      // Anything that exists on disk is treated as a recipients file
+      let flag = if Path::new(recipient).exists() {
+        "-R"
+      } else {
+        "-r"
+      };
+      command.args([flag, recipient]);
+    }
+    ChildSink::spawn(command, sink)
+  }
+
+  fn destination(&self, to: &Path) -> PathBuf {
+    with_age_extension(to)
+  }
+}
+
+/// Decrypts age-encrypted payloads with an identity file using the `age` binary,
+/// dropping the `.age` extension if the file has one.
+pub struct AgeDecrypt {
+  pub identity: PathBuf,
+}
+
+impl Transform for AgeDecrypt {
+  fn wrap(&self, sink: Box<dyn Sink>) -> io::Result<Box<dyn Sink>> {
+    let mut command = Command::new("age");
+    command.arg("-d").arg("-i").arg(&self.identity);
+    ChildSink::spawn(command, sink)
+  }
+
+  fn destination(&self, to: &Path) -> PathBuf {
+    without_age_extension(to)
+  }
+}
+
+/// Encrypts payloads with a passphrase, or decrypts payloads encrypted with one, using the
+/// `age` binary and its `batchpass` plugin (age 1.3 and later), which takes the passphrase
+/// from `$AGE_PASSPHRASE`. That's only set for the child, so gsftp's own environment and
+/// command line never hold it. Encrypted files get an `.age` extension, as with `AgeEncrypt`.
+pub struct AgePassphrase {
+  pub passphrase: String,
+  pub decrypt: bool,
+}
+
+impl Transform for AgePassphrase {
+  fn wrap(&self, sink: Box<dyn Sink>) -> io::Result<Box<dyn Sink>> {
+    let mut command = Command::new("age");
+    command
+      .arg(if self.decrypt { "-d" } else { "-e" })
+      .args(["-j", "batchpass"])
+      .env("AGE_PASSPHRASE", &self.passphrase);
+    ChildSink::spawn(command, sink)
+  }
+
+  fn destination(&self, to: &Path) -> PathBuf {
+    if self.decrypt {
+      without_age_extension(to)
+    } else {
+      with_age_extension(to)
+    }
+  }
+}
+
+// `notes.txt` becomes `notes.txt.age`
+fn with_age_extension(to: &Path) -> PathBuf {
+  let mut name = to.file_name().unwrap_or_default().to_os_string();
+  name.push(".age");
+  to.with_file_name(name)
+}
+
+// `notes.txt.age` becomes `notes.txt`, and anything else stays as it is
+fn without_age_extension(to: &Path) -> PathBuf {
+  match to.extension() {
+    Some(ext) if ext == OsStr::new("age") => to.with_extension(""),
+    _ => to.to_path_buf(),
+  }
+}
+
+// Pipes everything written to it through a child process, whose output is copied into
+// the wrapped sink on a helper thread. What it says on stderr is kept for the error, if it fails.
+struct ChildSink {
+  program: String,
+  child: Child,
+  stdin: Option<ChildStdin>,
+  pump: Option<JoinHandle<io::Result<Box<dyn Sink>>>>,
+  stderr: Option<JoinHandle<String>>,
+}
+
+impl ChildSink {
+  fn spawn(mut command: Command, mut sink: Box<dyn Sink>) -> io::Result<Box<dyn Sink>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(|e| io::Error::new(e.kind(), format!("Couldn't run {program}: {e}")))?;
+    let stdin = child.stdin.take();
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let pump = thread::spawn(move || {
+      io::copy(&mut stdout, &mut sink)?;
+      Ok(sink)
+    });
+    // Read on a thread of its own, so that a child with a lot to say never blocks on it
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let complaints = thread::spawn(move || {
+      let mut text = String::new();
+      let _ = stderr.read_to_string(&mut text);
+      text
+    });
+    Ok(Box::new(Self {
+      program,
+      child,
+      stdin,
+      pump: Some(pump),
+      stderr: Some(complaints),
+    }))
+  }
+}
+
+impl Write for ChildSink {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match &mut self.stdin {
+      Some(stdin) => stdin.write(buf),
+      None => Err(io::ErrorKind::BrokenPipe.into()),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match &mut self.stdin {
+      Some(stdin) => stdin.flush(),
+      None => Ok(()),
+    }
+  }
+}
+
+impl Sink for ChildSink {
+  fn finish(mut self: Box<Self>) -> io::Result<()> {
+    // Closing stdin tells the child there's nothing more to come
+    drop(self.stdin.take());
+    let pumped = match self.pump.take() {
+      Some(pump) => pump
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("pipe thread panicked"))),
+      None => Err(io::ErrorKind::BrokenPipe.into()),
+    };
+    let status = self.child.wait()?;
+    let complaints = self
+      .stderr
+      .take()
+      .and_then(|stderr| stderr.join().ok())
+      .unwrap_or_default();
+    if !status.success() {
+      let program = &self.program;
+      return Err(io::Error::other(match complaints.trim() {
+        "" => format!("{program} exited with {status}"),
+        complaints => format!("{program} exited with {status}: {complaints}"),
+      }));
+    }
+    pumped?.finish()
+  }
+}
+
+#[derive(Clone, Default)]
+/// Client-side encryption settings: uploads are encrypted for `recipients` and
+/// downloads are decrypted with `identity`, when they're given, or both are done with a
+/// passphrase if `with_passphrase` says so.
+pub struct Encryption {
+  pub recipients: Vec<String>,
+  pub identity: Option<PathBuf>,
+  /// `--encrypt-passphrase`: the passphrase is typed into the interface, rather than kept anywhere
+  pub with_passphrase: bool,
+  /// The passphrase, once it's been typed in
+  pub passphrase: Option<String>,
+}
+
+// The passphrase stays out of anything printed or logged
+impl fmt::Debug for Encryption {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Encryption")
+      .field("recipients", &self.recipients)
+      .field("identity", &self.identity)
+      .field("with_passphrase", &self.with_passphrase)
+      .field("passphrase", &self.passphrase.as_ref().map(|_| "..."))
+      .finish()
+  }
+}
+
+impl Encryption {
+  /// Whether transfers have to wait for a passphrase to be typed in
+  pub fn needs_passphrase(&self) -> bool {
+    self.with_passphrase && self.passphrase.is_none()
+  }
+
+  pub fn upload_stages(&self) -> Result<Vec<Box<dyn Transform>>, GsftpError> {
+    let mut stages: Vec<Box<dyn Transform>> = Vec::new();
+    if !self.recipients.is_empty() {
+      let recipients = self.recipients.clone();
+      stages.push(Box::new(AgeEncrypt { recipients }));
+    }
+    if let Some(passphrase) = self.passphrase()? {
+      stages.push(Box::new(AgePassphrase {
+        passphrase,
+        decrypt: false,
+      }));
+    }
+    Ok(stages)
+  }
+
+  pub fn download_stages(&self) -> Result<Vec<Box<dyn Transform>>, GsftpError> {
+    let mut stages: Vec<Box<dyn Transform>> = Vec::new();
+    if let Some(identity) = &self.identity {
+      let identity = identity.clone();
+      stages.push(Box::new(AgeDecrypt { identity }));
+    }
+    if let Some(passphrase) = self.passphrase()? {
+      stages.push(Box::new(AgePassphrase {
+        passphrase,
+        decrypt: true,
+      }));
+    }
+    Ok(stages)
+  }
+
+  // The passphrase to encrypt with, if there's meant to be one; transfers can't start without it
+  fn passphrase(&self) -> Result<Option<String>, GsftpError> {
+    match (self.with_passphrase, &self.passphrase) {
+      (false, _) => Ok(None),
+      (true, Some(passphrase)) => Ok(Some(passphrase.clone())),
+      (true, None) => Err(GsftpError::Other(String::from(
+        "No passphrase to encrypt with yet: type one in with :passphrase",
+      ))),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  #[cfg(unix)]
+  fn a_failing_stage_says_why() {
+    let path = std::env::temp_dir().join(format!("gsftp-transform-{}", std::process::id()));
+    let file = fs::File::create(&path).unwrap();
+    let mut command = Command::new("sh");
+    command.args(["-c", "cat >/dev/null; echo 'bad passphrase' >&2; exit 1"]);
+    let mut sink = ChildSink::spawn(command, Box::new(file)).unwrap();
+    sink.write_all(b"secret").unwrap();
+    let error = sink.finish().unwrap_err().to_string();
+    let _ = fs::remove_file(&path);
+    assert!(error.starts_with("sh exited with"), "{error}");
+    assert!(error.ends_with(": bad passphrase"), "{error}");
+  }
+
+  #[test]
+  fn passphrases_name_files_like_keys_do() {
+    let stage = |decrypt| AgePassphrase {
+      passphrase: String::from("correct horse"),
+      decrypt,
+    };
+    assert_eq!(
+      stage(false).destination(Path::new("a/b.txt")),
+      Path::new("a/b.txt.age")
+    );
+    assert_eq!(
+      stage(true).destination(Path::new("a/b.txt.age")),
+      Path::new("a/b.txt")
+    );
+    assert!(!format!(
+      "{:?}",
+      Encryption {
+        passphrase: Some(String::from("correct horse")),
+        ..Default::default()
+      }
+    )
+    .contains("horse"));
+  }
+}
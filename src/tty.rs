@@ -0,0 +1,97 @@
+//! The terminal: taken over for the interface and given back, its keys read, and questions asked
+//! on it while the interface isn't there (e.g. for logging in)
+use crossbeam_channel::{unbounded, Receiver};
+use crossterm::{
+  cursor,
+  event::Event,
+  execute,
+  terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use gsftp::ask::Ask;
+
+/// Asks on the terminal, so only while the interface isn't using it
+pub struct Tty;
+
+impl Ask for Tty {
+  fn tell(&self, text: &str) {
+    println!("{text}");
+  }
+
+  fn ask(&self, question: &str, secret: bool) -> io::Result<String> {
+    if secret {
+      return rpassword::prompt_password(question);
+    }
+    print!("{question}");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+  }
+}
+
+/// Takes the terminal over for the interface
+pub fn setup_terminal() -> Result<(), io::Error> {
+  let mut stdout = io::stdout();
+  execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+  // TTYs don't actually have an alternate screen, so you need to
+  //  clear the screen in this case.
+  // We have to execute this *after* entering the alternate screen so that
+  //  the main screen is cleared iff we're running in a TTY.
+  execute!(
+    stdout,
+    terminal::Clear(terminal::ClearType::All),
+    terminal::SetTitle("gsftp")
+  )?;
+
+  terminal::enable_raw_mode()?;
+
+  Ok(())
+}
+
+/// Gives the terminal back the way it was found
+pub fn cleanup_terminal() -> Result<(), io::Error> {
+  let mut stdout = io::stdout();
+  // TTYs don't actually have an alternate screen, so you need to
+  //  clear the screen in this case.
+  // We have to execute this *before* leaving the alternate screen so that
+  //  the main screen is cleared iff we're running in a TTY.
+  execute!(
+    stdout,
+    cursor::MoveTo(0, 0),
+    terminal::Clear(terminal::ClearType::All)
+  )?;
+  execute!(stdout, LeaveAlternateScreen, cursor::Show)?;
+
+  terminal::disable_raw_mode()?;
+
+  Ok(())
+}
+
+/// While `paused`, key presses are left for whoever else is reading the terminal.
+/// The first error reading it is passed on, and is the last thing sent.
+pub fn setup_ui_events(paused: Arc<AtomicBool>) -> Receiver<io::Result<Event>> {
+  let (tx, rx) = unbounded();
+  thread::spawn(move || loop {
+    if paused.load(Ordering::SeqCst) {
+      thread::sleep(Duration::from_millis(50));
+      continue;
+    }
+    let event = match crossterm::event::poll(Duration::from_millis(50)) {
+      Ok(false) => continue,
+      Ok(true) => crossterm::event::read(),
+      Err(e) => Err(e),
+    };
+    let failed = event.is_err();
+    if tx.send(event).is_err() || failed {
+      break;
+    }
+  });
+
+  rx
+}
@@ -0,0 +1,130 @@
+//! Adding up how much is in a directory, like `du -s`, and how much room is left, like `df`
+use ssh2::{Session, Sftp};
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::app_utils;
+use crate::error::GsftpError;
+use crate::sftp;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// The total size of the files under a directory, and how many there are
+pub struct Usage {
+  pub bytes: u64,
+  pub files: u64,
+}
+
+impl Usage {
+  /// e.g. `1.2 GB in 3,401 files`
+  pub fn describe(&self) -> String {
+    let files = match self.files {
+      1 => String::from("1 file"),
+      n => format!("{} files", app_utils::thousands(n)),
+    };
+    format!("{} in {files}", app_utils::human_size(self.bytes))
+  }
+}
+
+/// Everything under the local `path`; symlinks are counted as files, never followed
+pub fn local(path: &Path) -> io::Result<Usage> {
+  let meta = fs::symlink_metadata(path)?;
+  if !meta.is_dir() {
+    return Ok(Usage {
+      bytes: meta.len(),
+      files: 1,
+    });
+  }
+  let mut usage = Usage::default();
+  for entry in fs::read_dir(path)? {
+    // Whatever can't be read is left out, like `du` does after complaining
+    if let Ok(inner) = entry.and_then(|entry| local(&entry.path())) {
+      usage.bytes += inner.bytes;
+      usage.files += inner.files;
+    }
+  }
+  Ok(usage)
+}
+
+/// Space left for an unprivileged user on the filesystem holding the local `path`
+#[cfg(unix)]
+pub fn local_free(path: &Path) -> Option<u64> {
+  use std::ffi::CString;
+  use std::os::unix::ffi::OsStrExt;
+  let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+  // Safe: `path` is NUL-terminated and `stat` is ours to write to
+  if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+    return None;
+  }
+  Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn local_free(_path: &Path) -> Option<u64> {
+  None
+}
+
+/// Space left on the filesystem holding the remote `path`, from `df` on the server.
+/// There's no way to ask over SFTP alone, so this is `None` if remote commands are disabled.
+pub fn remote_free(sess: &Session, path: &Path) -> Option<u64> {
+  let output = sftp::exec(sess, "df", &["-Pk", "--", path.to_str()?]).ok()?;
+  // A header line, then `Filesystem 1024-blocks Used Available Capacity Mounted on`
+  let available: u64 = output
+    .lines()
+    .nth(1)?
+    .split_whitespace()
+    .nth(3)?
+    .parse()
+    .ok()?;
+  Some(available * 1024)
+}
+
+/// Everything under the remote `path`, counted by `du` and `find` on the server when remote
+/// commands are allowed, which is far quicker than listing every directory over SFTP
+pub fn remote(sess: &Session, sftp: &Sftp, path: &Path) -> Result<Usage, GsftpError> {
+  match remote_du(sess, path) {
+    Some(usage) => Ok(usage),
+    None => remote_walk(sftp, path),
+  }
+}
+
+// `du` reports the space used on disk, which can differ a little from the files' sizes
+fn remote_du(sess: &Session, path: &Path) -> Option<Usage> {
+  let path = path.to_str()?;
+  let output = sftp::exec(sess, "du", &["-sk", "--", path]).ok()?;
+  let kilobytes: u64 = output.split_whitespace().next()?.parse().ok()?;
+  let mut channel = sftp::exec_stream(sess, "find", &[path, "-type", "f"]).ok()?;
+  let files = BufReader::new(&mut channel).lines().count() as u64;
+  sftp::finish_exec(channel, "find").ok()?;
+  Some(Usage {
+    bytes: kilobytes * 1024,
+    files,
+  })
+}
+
+fn remote_walk(sftp: &Sftp, path: &Path) -> Result<Usage, GsftpError> {
+  let stat = sftp.lstat(path)?;
+  if !stat.file_type().is_dir() {
+    return Ok(Usage {
+      bytes: stat.size.unwrap_or_default(),
+      files: 1,
+    });
+  }
+  let mut usage = Usage::default();
+  for (child, stat) in sftp.readdir(path)? {
+    let inner = match stat.file_type().is_dir() {
+      true => match remote_walk(sftp, &child) {
+        Ok(inner) => inner,
+        Err(_) => continue,
+      },
+      false => Usage {
+        bytes: stat.size.unwrap_or_default(),
+        files: 1,
+      },
+    };
+    usage.bytes += inner.bytes;
+    usage.files += inner.files;
+  }
+  Ok(usage)
+}
@@ -0,0 +1,63 @@
+//! Comparing a local file with its remote counterpart without transferring it again
+use sha2::{Digest, Sha256};
+use ssh2::{Session, Sftp};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::error::GsftpError;
+use crate::sftp;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+  Identical,
+  SizeDiffers { local: u64, remote: u64 },
+  ContentDiffers,
+}
+
+/// Compares the sizes of `local` and `remote`, then their SHA-256 checksums.
+/// The remote checksum is computed by `sha256sum` on the server when it's available;
+/// otherwise the file is streamed over SFTP and hashed locally.
+pub fn verify(
+  local: &Path,
+  remote: &Path,
+  sess: &Session,
+  sftp: &Sftp,
+) -> Result<Verdict, GsftpError> {
+  let local_size = fs::metadata(local)?.len();
+  let remote_size = sftp.stat(remote)?.size.unwrap_or_default();
+  if local_size != remote_size {
+    return Ok(Verdict::SizeDiffers {
+      local: local_size,
+      remote: remote_size,
+    });
+  }
+  let local_sum = sha256(&mut fs::File::open(local)?)?;
+  let remote_sum = match remote_sha256sum(remote, sess) {
+    Some(sum) => sum,
+    None => sha256(&mut sftp.open(remote)?)?,
+  };
+  if local_sum == remote_sum {
+    Ok(Verdict::Identical)
+  } else {
+    Ok(Verdict::ContentDiffers)
+  }
+}
+
+// Hex-encoded SHA-256 of everything `reader` yields
+fn sha256(reader: &mut impl Read) -> io::Result<String> {
+  let mut hasher = Sha256::new();
+  io::copy(reader, &mut hasher)?;
+  Ok(hex(&hasher.finalize()))
+}
+
+// Asks the server to checksum the file itself, which saves sending it over the wire
+fn remote_sha256sum(remote: &Path, sess: &Session) -> Option<String> {
+  let output = sftp::exec(sess, "sha256sum", &["--", remote.to_str()?]).ok()?;
+  let sum = output.split_whitespace().next()?;
+  Some(sum.to_lowercase())
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,45 @@
+//! Noticing changes to the local pane's directory as they happen, rather than re-reading it
+//! every second
+use crossbeam_channel::{unbounded, Receiver};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+
+/// Watches one directory at a time, sending on `changes` whenever anything in it changes
+pub struct LocalWatcher {
+  watcher: RecommendedWatcher,
+  dir: Option<PathBuf>,
+  // Whether `dir` could be watched
+  watching: bool,
+  pub changes: Receiver<()>,
+}
+
+impl LocalWatcher {
+  pub fn new() -> notify::Result<Self> {
+    let (tx, changes) = unbounded();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+      if event.is_ok() {
+        let _ = tx.send(());
+      }
+    })?;
+    Ok(Self {
+      watcher,
+      dir: None,
+      watching: false,
+      changes,
+    })
+  }
+
+  /// Watches `dir` instead of whichever directory was watched before.
+  /// Yields false if it can't be watched, in which case it has to be polled.
+  pub fn watch(&mut self, dir: &Path) -> bool {
+    if self.dir.as_deref() == Some(dir) {
+      return self.watching;
+    }
+    if let Some(old) = self.dir.take().filter(|_| self.watching) {
+      let _ = self.watcher.unwatch(&old);
+    }
+    self.watching = self.watcher.watch(dir, RecursiveMode::NonRecursive).is_ok();
+    self.dir = Some(dir.to_path_buf());
+    self.watching
+  }
+}
@@ -0,0 +1,182 @@
+//! The questions gsftp asks when it's started with no arguments at all, for anyone who would
+//! rather answer them than look up the options: where to connect, how to log in, and whether to
+//! keep the answers as a profile in `config.toml`, so that next time `gsftp <profile>` is enough.
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use gsftp::config;
+use gsftp::settings::{self, Settings};
+use gsftp::ssh_config::HostConfig;
+
+// The ways to log in that can be picked, and what each adds to the command line
+#[rustfmt::skip]
+const AUTH_METHODS: [(&str, &[&str]); 4] = [
+  ("try each method the server allows, in turn", &[]),
+  ("with a private key (identity file)",         &["-i"]),
+  ("with your SSH agent only",                   &["-A"]),
+  ("with a password",                            &["-p"]),
+];
+
+/// Asks where to connect and how, and yields the command line that does it (starting with the
+/// program's name, like `env::args_os`). A profile is saved on the way if asked for.
+pub fn run() -> Result<Vec<OsString>, String> {
+  println!(
+    "No destination given, so let's set one up (Ctrl+C quits; see gsftp --help for options)."
+  );
+  println!();
+  let host = loop {
+    let host = ask(
+      "Host (a name, an address, or a Host from ~/.ssh/config)",
+      None,
+    )?;
+    if !host.is_empty() && !host.contains(['@', ':', ' ']) {
+      break host;
+    }
+    println!("Just the host, please: the user and port come next.");
+  };
+  let ssh = HostConfig::load(&host);
+  let user = loop {
+    let local = ssh.user.clone().or_else(config::local_user);
+    let user = ask("User", local.as_deref())?;
+    if !user.is_empty() {
+      break user;
+    }
+  };
+  let port = loop {
+    let default = ssh.port.unwrap_or(22).to_string();
+    match ask("Port", Some(&default))?.parse::<u16>() {
+      Ok(port) if port > 0 => break port,
+      _ => println!("That isn't a port: give a number from 1 to 65535."),
+    }
+  };
+  println!("How do you log in?");
+  for (i, (method, _)) in AUTH_METHODS.iter().enumerate() {
+    println!("  {}) {method}", i + 1);
+  }
+  let (_, auth) = loop {
+    let choice = ask("Choice", Some("1"))?;
+    match choice
+      .parse::<usize>()
+      .ok()
+      .and_then(|n| AUTH_METHODS.get(n.wrapping_sub(1)))
+    {
+      Some(method) => break method,
+      None => println!("Pick one of 1 to {}.", AUTH_METHODS.len()),
+    }
+  };
+  let identity = match auth.first() {
+    Some(&"-i") => Some(loop {
+      let default = ["id_ed25519", "id_ecdsa", "id_rsa"]
+        .iter()
+        .filter_map(|key| dirs::home_dir().map(|home| home.join(".ssh").join(key)))
+        .find(|key| key.exists())
+        .map(|key| key.to_string_lossy().into_owned());
+      let key = ask("Private key", default.as_deref())?;
+      let path = match (key.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => key.clone().into(),
+      };
+      match path.is_file() {
+        true => break path.to_string_lossy().into_owned(),
+        false => println!("There's no file at {key}."),
+      }
+    }),
+    _ => None,
+  };
+  let destination = match port {
+    22 => format!("{user}@{host}"),
+    port => format!("{user}@{host}:{port}"),
+  };
+  save(&destination, identity.as_deref())?;
+  println!();
+
+  let mut args = vec![OsString::from(env!("CARGO_PKG_NAME"))];
+  match identity {
+    Some(key) => args.extend([OsString::from("-i"), OsString::from(key)]),
+    None => args.extend(auth.iter().map(OsString::from)),
+  }
+  args.push(OsString::from(destination));
+  Ok(args)
+}
+
+// Offers to keep the destination as a profile, and appends it to `config.toml` if wanted.
+// Trouble saving it is only reported, since the connection can go ahead regardless.
+fn save(destination: &str, identity: Option<&str>) -> Result<(), String> {
+  let Some(file) = settings::file() else {
+    return Ok(());
+  };
+  let profiles = match Settings::load(None) {
+    Ok(settings) => settings.profiles,
+    Err(e) => {
+      println!("Not offering to save a profile: {e}");
+      return Ok(());
+    }
+  };
+  let name = loop {
+    let name = ask(
+      "Save as a profile? Give it a name, or leave it empty not to",
+      None,
+    )?;
+    if name.is_empty() {
+      return Ok(());
+    }
+    if !name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+      println!("Profile names are letters, digits, - and _.");
+    } else if profiles.contains_key(&name) {
+      println!("There's already a profile called {name}.");
+    } else {
+      break name;
+    }
+  };
+  let quote = |text: &str| toml::Value::String(text.to_string()).to_string();
+  let mut profile = format!(
+    "\n[profiles.{name}]\ndestination = {}\n",
+    quote(destination)
+  );
+  if let Some(key) = identity {
+    profile.push_str(&format!("identity = {}\n", quote(key)));
+  }
+  match append(&file, &profile) {
+    Ok(()) => println!("Saved in {}: next time, run gsftp {name}", file.display()),
+    Err(e) => println!("Couldn't save the profile in {}: {e}", file.display()),
+  }
+  Ok(())
+}
+
+fn append(file: &Path, text: &str) -> io::Result<()> {
+  if let Some(dir) = file.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(file)?
+    .write_all(text.as_bytes())
+}
+
+// Asks `question` on the terminal, and yields the answer, trimmed, or else `default`.
+// Running out of input is an error, since there's no one left to answer.
+fn ask(question: &str, default: Option<&str>) -> Result<String, String> {
+  match default {
+    Some(default) => print!("{question} [{default}]: "),
+    None => print!("{question}: "),
+  }
+  let mut line = String::new();
+  let read = io::stdout()
+    .flush()
+    .and_then(|_| io::stdin().read_line(&mut line))
+    .map_err(|e| format!("Couldn't read the answer: {e}"))?;
+  if read == 0 {
+    println!();
+    return Err(String::from("No destination given"));
+  }
+  Ok(match (line.trim(), default) {
+    ("", Some(default)) => default.to_string(),
+    (answer, _) => answer.to_string(),
+  })
+}